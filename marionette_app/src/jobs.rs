@@ -0,0 +1,261 @@
+// Purpose: runs slow analyses on worker threads so the window never waits on them. A job is
+// queued with `JobManager::spawn`, reports progress and log lines through its `JobContext`, and
+// can be cancelled while queued or between the steps of its work. Everything a job reports comes
+// back to the UI as `JobMessage`s on the receiver returned by `JobManager::new`.
+
+use std::collections::HashMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use futures_channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use serde::Serialize;
+use serde_json::Value;
+
+pub type JobId = u64;
+
+type Work = Box<dyn FnOnce(&JobContext) -> Result<Value, String> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub name: String,
+    pub state: JobState,
+    /// From 0 to 1.
+    pub progress: f32,
+    /// What the job is doing right now, or why it failed.
+    pub message: String,
+}
+
+/// What the interface is told about jobs, as `{ event, data }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum JobEvent {
+    /// A job was queued, or its state or progress changed.
+    Job(JobInfo),
+    /// A line for the log widget.
+    JobLog { job: JobId, message: String },
+}
+
+#[derive(Debug)]
+pub enum JobOutcome {
+    Finished(Value),
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub enum JobMessage {
+    Event(JobEvent),
+    /// Sent once per job, after its last event.
+    Done { name: String, request: Option<u64>, outcome: JobOutcome },
+}
+
+struct Job {
+    info: JobInfo,
+    cancelled: Arc<AtomicBool>,
+}
+
+type Jobs = Arc<Mutex<HashMap<JobId, Job>>>;
+
+struct Queued {
+    id: JobId,
+    request: Option<u64>,
+    work: Work,
+}
+
+/// Handed to a job's work to report progress and notice cancellation.
+pub struct JobContext {
+    id: JobId,
+    cancelled: Arc<AtomicBool>,
+    jobs: Jobs,
+    messages: UnboundedSender<JobMessage>,
+}
+
+impl JobContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Fails once the job is cancelled, so work can stop with `?` between its steps.
+    pub fn check(&self) -> Result<(), String> {
+        match self.is_cancelled() {
+            true => Err("cancelled".to_string()),
+            false => Ok(()),
+        }
+    }
+
+    pub fn progress(&self, progress: f32, message: &str) {
+        let info = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(job) = jobs.get_mut(&self.id) else { return };
+            job.info.progress = progress.clamp(0.0, 1.0);
+            job.info.message = message.to_string();
+            job.info.clone()
+        };
+        let _ = self.messages.unbounded_send(JobMessage::Event(JobEvent::Job(info)));
+    }
+
+    pub fn log(&self, message: &str) {
+        let _ = self.messages.unbounded_send(JobMessage::Event(JobEvent::JobLog {
+            job: self.id,
+            message: message.to_string(),
+        }));
+    }
+}
+
+/// Cheap to clone; clones share the same queue and workers.
+#[derive(Clone)]
+pub struct JobManager {
+    queue: mpsc::Sender<Queued>,
+    jobs: Jobs,
+    messages: UnboundedSender<JobMessage>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl JobManager {
+    /// Starts `workers` threads, which stop once every clone of the manager is dropped.
+    pub fn new(workers: usize) -> (Self, UnboundedReceiver<JobMessage>) {
+        let (queue, queued) = mpsc::channel::<Queued>();
+        let (messages, received) = unbounded();
+        let manager = JobManager {
+            queue,
+            jobs: Arc::default(),
+            messages,
+            next_id: Arc::new(AtomicU64::new(1)),
+        };
+
+        let queued = Arc::new(Mutex::new(queued));
+        for worker in 0..workers.max(1) {
+            let queued = queued.clone();
+            let jobs = manager.jobs.clone();
+            let messages = manager.messages.clone();
+            thread::Builder::new()
+                .name(format!("marionette-job-{}", worker))
+                .spawn(move || loop {
+                    // hold the lock only while waiting, so other workers can take the next job
+                    let next = queued.lock().unwrap().recv();
+                    match next {
+                        Ok(job) => run(job, &jobs, &messages),
+                        Err(_) => break,
+                    }
+                })
+                .expect("failed to start a job worker");
+        }
+
+        (manager, received)
+    }
+
+    /// One worker per core, keeping one core free for the window.
+    pub fn with_default_workers() -> (Self, UnboundedReceiver<JobMessage>) {
+        let cores = thread::available_parallelism().map(|cores| cores.get()).unwrap_or(2);
+        JobManager::new(cores.saturating_sub(1))
+    }
+
+    /// Queues `work`. `request` is the id of the RPC request waiting on it, if any, and is
+    /// handed back in the job's `JobMessage::Done`.
+    pub fn spawn<F>(&self, name: &str, request: Option<u64>, work: F) -> JobId
+    where
+        F: FnOnce(&JobContext) -> Result<Value, String> + Send + 'static,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let info = JobInfo {
+            id,
+            name: name.to_string(),
+            state: JobState::Queued,
+            progress: 0.0,
+            message: String::new(),
+        };
+        self.jobs.lock().unwrap().insert(id, Job { info: info.clone(), cancelled: Arc::default() });
+        let _ = self.messages.unbounded_send(JobMessage::Event(JobEvent::Job(info)));
+
+        if self.queue.send(Queued { id, request, work: Box::new(work) }).is_err() {
+            // no workers left to run it
+            finish(id, request, JobOutcome::Failed("no job workers are running".to_string()), &self.jobs, &self.messages);
+        }
+        id
+    }
+
+    /// Asks a job to stop. Queued jobs never start; running ones stop at their next check.
+    /// Returns false if there is no such job, e.g. because it already ended.
+    pub fn cancel(&self, id: JobId) -> bool {
+        match self.jobs.lock().unwrap().get(&id) {
+            Some(job) => {
+                job.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Jobs that have not ended yet, oldest first.
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.jobs.lock().unwrap().values().map(|job| job.info.clone()).collect();
+        jobs.sort_by_key(|job| job.id);
+        jobs
+    }
+}
+
+fn run(queued: Queued, jobs: &Jobs, messages: &UnboundedSender<JobMessage>) {
+    let (cancelled, info) = {
+        let mut jobs = jobs.lock().unwrap();
+        let Some(job) = jobs.get_mut(&queued.id) else { return };
+        job.info.state = JobState::Running;
+        (job.cancelled.clone(), job.info.clone())
+    };
+
+    if cancelled.load(Ordering::Relaxed) {
+        finish(queued.id, queued.request, JobOutcome::Cancelled, jobs, messages);
+        return;
+    }
+    let _ = messages.unbounded_send(JobMessage::Event(JobEvent::Job(info)));
+
+    let context = JobContext {
+        id: queued.id,
+        cancelled: cancelled.clone(),
+        jobs: jobs.clone(),
+        messages: messages.clone(),
+    };
+    let work = queued.work;
+    // a panicking analysis fails its job instead of taking a worker down with it
+    let result = catch_unwind(AssertUnwindSafe(|| work(&context)))
+        .unwrap_or_else(|_| Err("the job panicked".to_string()));
+
+    let outcome = match result {
+        _ if cancelled.load(Ordering::Relaxed) => JobOutcome::Cancelled,
+        Ok(value) => JobOutcome::Finished(value),
+        Err(error) => JobOutcome::Failed(error),
+    };
+    finish(queued.id, queued.request, outcome, jobs, messages);
+}
+
+fn finish(id: JobId, request: Option<u64>, outcome: JobOutcome, jobs: &Jobs, messages: &UnboundedSender<JobMessage>) {
+    let Some(job) = jobs.lock().unwrap().remove(&id) else { return };
+    let mut info = job.info;
+    match &outcome {
+        JobOutcome::Finished(_) => {
+            info.state = JobState::Finished;
+            info.progress = 1.0;
+        }
+        JobOutcome::Failed(error) => {
+            info.state = JobState::Failed;
+            info.message = error.clone();
+        }
+        JobOutcome::Cancelled => info.state = JobState::Cancelled,
+    }
+
+    let name = info.name.clone();
+    let _ = messages.unbounded_send(JobMessage::Event(JobEvent::Job(info)));
+    let _ = messages.unbounded_send(JobMessage::Done { name, request, outcome });
+}
@@ -1,17 +1,21 @@
 mod welcome;
 mod page_not_found;
+mod settings_page;
 mod selector_service;
 mod tool;
 mod msgbox;
 mod states;
 mod plugin;
+mod rpc;
+mod jobs;
+mod updates;
 
 use std::fs;
 use std::env;
-use dioxus::desktop::tao::platform::windows::WindowBuilderExtWindows;
-use marionette_util::lexer_service::LexerService;
-use serde_json::{json, Value};
-use futures::{executor, FutureExt};
+use std::cell::RefCell;
+use std::rc::Rc;
+use futures::{executor, FutureExt, StreamExt};
+use serde::Serialize;
 use dioxus::{
     html::p, prelude::*
 };
@@ -21,8 +25,12 @@ use dioxus::desktop::{
 };
 
 use crate::{
+    jobs::{JobManager, JobMessage},
+    rpc::{RpcRegistry, RpcResponse},
     welcome::Welcome, 
     selector_service::OpenTab, 
+    settings_page::SettingsPage,
+    states::settings::{SettingsStore, Theme},
     tool::Tool, 
     page_not_found::NotFound
 };
@@ -38,33 +46,16 @@ pub enum Route {
     #[route("/tool")]
     Tool {},
 
+    #[route("/settings")]
+    SettingsPage {},
+
     #[route("")]
     NotFound {},
 }
 
-fn dispatch(method: String, data: Value) -> Result<String, String> {
-    let mut result = "".to_string();
-
-    result = match method.as_str() {
-        "lex" => {
-            let content = data["text"].as_str().unwrap().to_string();
-            let lexer_choice = data["lexer"].as_str().unwrap().to_string();
-
-            let result = LexerService::lex(content, lexer_choice);
-            if result.is_err() {
-                return Err(result.err().unwrap().to_string());
-            }
-
-            result.unwrap().to_string()
-        }
-        _ => format!("Error, no method found: {}", method)
-    };
-
-    Ok(result)
-}
-
-fn respond(response: Value, eval: UseEval) {
-    if eval.send(response.to_string().into()).is_ok() { 
+fn respond<T: Serialize>(message: T, eval: UseEval) {
+    let response = serde_json::to_value(&message).unwrap();
+    if eval.send(response.clone()).is_ok() { 
         return;
     }
     
@@ -87,36 +78,58 @@ fn main() {
 #[component]
 fn portal() -> Element {
     let mut eval = eval(include_str!("resources/scripts/interop_connector.js"));
-    
+
+    let settings = use_context_provider(|| Signal::new(SettingsStore::new()));
+
+    // loaded once, the settings decide which plugins run
+    let plugins = use_context_provider(|| {
+        let enabled = settings.peek().settings.plugins.enabled.clone();
+        let mut plugins_vec = vec![];
+        let current_exe = env::current_exe().unwrap();
+        let mut plugin_dir = current_exe.parent().unwrap().to_path_buf();
+        plugin_dir.push("plugins");
+        let plugins_dir = fs::read_dir(plugin_dir);
+
+        if let Ok(plugins_dir) = plugins_dir {
+            for entry in plugins_dir.filter_map(Result::ok) {
+                let path = entry.path();
+                if path.extension() == Some(std::ffi::OsStr::new("py")) {
+                    if let Ok(mut plugin) = plugin::Plugin::new(path.to_str().unwrap()) {
+                        plugin.enabled = enabled.get(&plugin.name).copied().unwrap_or(plugin.enabled);
+                        if plugin.enabled {
+                            plugin.init();
+                        }
+                        plugins_vec.push(plugin);
+                    }
+                }
+            }
+        }
+        Signal::new(plugins_vec)
+    });
+    let selector_state = use_context_provider(|| Signal::new(states::selector::SelectorState::new()));
+    let project_state = use_context_provider(|| Signal::new(states::project::ProjectState::new()));
+
+    let (registry, job_messages) = use_hook(|| {
+        let (jobs, job_messages) = JobManager::with_default_workers();
+        let mut registry = RpcRegistry::new(jobs);
+        rpc::hex::register(&mut registry, project_state);
+        rpc::jobs::register(&mut registry);
+        rpc::lexer::register(&mut registry);
+        rpc::lua::register(&mut registry);
+        rpc::plugins::register(&mut registry, plugins);
+        rpc::project::register(&mut registry, project_state);
+        rpc::settings::register(&mut registry, settings);
+        (Rc::new(registry), Rc::new(RefCell::new(Some(job_messages))))
+    });
+
     let task = use_future(move || {
+        to_owned![registry];
         async move {
             loop {
                 to_owned![eval];
                 if let Ok(message) = eval.recv().await {
-                    let mut response = json!({
-                        "status": "ok",
-                        "data": ""
-                    });
-
-                    if let Some(message) = message.as_object() {
-                        if message.contains_key("method") && message.contains_key("data") {
-                            let res = dispatch(
-                                message["method"].as_str().unwrap().to_string(), 
-                                Value::Object(message["data"].as_object().unwrap().clone())
-                            );
-
-                            if let Ok(res) = res {
-                                response["data"] = res.clone().into();
-                                respond(response, eval);
-                            } else {
-                                response["status"] = "err".into();
-                                response["data"] = res.clone().err().unwrap().into();
-                                respond(response, eval);
-                            }
-                        }
-                    } else {
-                        response["status"] = "err".into();
-                        response["data"] = format!("Error: {}", message).into();
+                    // requests answered by a job get their response from the loop below
+                    if let Some(response) = registry.handle(message) {
                         respond(response, eval);
                     }
                 }
@@ -124,26 +137,22 @@ fn portal() -> Element {
         }
     });
 
-    let mut plugins_vec = vec![];
-    let current_exe = env::current_exe().unwrap();
-    let mut plugin_dir = current_exe.parent().unwrap().to_path_buf();
-    plugin_dir.push("plugins");
-    let plugins_dir = fs::read_dir(plugin_dir);
-
-    if let Ok(plugins_dir) = plugins_dir {
-        for entry in plugins_dir.filter_map(Result::ok) {
-            let path = entry.path();
-            if path.extension() == Some(std::ffi::OsStr::new("py")) {
-                if let Ok(plugin) = plugin::Plugin::new(path.to_str().unwrap()) {
-                    plugin.init();
-                    plugins_vec.push(plugin);
+    // forwards job progress to the interface and answers requests whose jobs ended
+    use_future(move || {
+        let job_messages = job_messages.borrow_mut().take();
+        async move {
+            let Some(mut job_messages) = job_messages else { return };
+            while let Some(message) = job_messages.next().await {
+                match message {
+                    JobMessage::Event(event) => respond(event, eval),
+                    JobMessage::Done { name, request: Some(request), outcome } => {
+                        respond(RpcResponse::from_job(request, &name, outcome), eval)
+                    }
+                    JobMessage::Done { .. } => {}
                 }
             }
         }
-    }
-
-    let plugins = use_context_provider(|| Signal::new(plugins_vec));
-    let selector_state = use_context_provider(|| Signal::new(states::selector::SelectorState::new()));
+    });
 
     rsx!(
         link {
@@ -152,6 +161,18 @@ fn portal() -> Element {
         }
         style { {include_str!("resources/styles/misc/file-icons.min.css")} }
         script { {include_str!("resources/scripts/interop.js")} }
+        ThemeStyle {}
         Router::<Route> { }
     )
+}
+
+// a component of its own, so changing the theme does not rerender the portal
+#[component]
+fn ThemeStyle() -> Element {
+    let settings = use_context::<Signal<SettingsStore>>();
+    let theme = settings.read().settings.theme;
+    match theme {
+        Theme::Dark => rsx!(),
+        Theme::Light => rsx!( style { {include_str!("resources/styles/misc/light.css")} } ),
+    }
 }
\ No newline at end of file
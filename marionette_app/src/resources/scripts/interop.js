@@ -1,26 +1,62 @@
-window.internalRequest = async function(method, data, no_response = false, avoid_log = false) {
-    if (!avoid_log) window.requested({ method: method, data: data });
+// Responses come back on a single channel, possibly out of order, so every request carries an
+// id and waits for the response with the same id. Resolves with the method's result and
+// rejects with the backend's error ({ method, description, error_type }).
+window.pendingRequests = new Map();
+window.nextRequestId = 1;
+window.receiving = false;
+
+window.receiveResponses = async function() {
+    if (window.receiving) return;
+    window.receiving = true;
+
+    while (true) {
+        let response = await window.dioxus.recv();
+        if (response.event) {
+            // job notifications answer no request
+            if (response.event == "job") window.jobChanged(response.data);
+            if (response.event == "job_log") window.jobLogged(response.data);
+            continue;
+        }
+
+        let pending = window.pendingRequests.get(response.id);
+        if (!pending) {
+            // no_response requests and malformed requests have nobody waiting
+            if (response.status != "ok") window.error(response);
+            continue;
+        }
+
+        window.pendingRequests.delete(response.id);
+        if (!pending.avoid_log) window.received(response);
+        if (response.status == "ok") {
+            pending.resolve(response.data);
+        } else {
+            pending.reject(response.data);
+        }
+    }
+}
+
+window.internalRequest = async function(method, params = {}, no_response = false, avoid_log = false) {
+    let id = window.nextRequestId++;
+    if (!avoid_log) window.requested({ id: id, method: method, params: params });
+
+    window.receiveResponses();
+
+    let response = no_response ? null : new Promise((resolve, reject) => {
+        window.pendingRequests.set(id, { resolve: resolve, reject: reject, avoid_log: avoid_log });
+    });
 
     window.dioxus.send({
+        id: id,
         method: method,
-        data: data
+        params: params
     });
 
-    if (no_response) return;
-
-    let response = await window.dioxus.recv();
-    if (!avoid_log) window.received(response);
-    return JSON.parse(response);
+    return response;
 }
 
 // THE CODE BELOW IS 100% GOING TO BE OVERRIDEN
 window.received = function(data) {
-    let content = JSON.parse(data);
-    if (content.status == "ok") {
-        console.log("RECV", content.status, data);
-    } else {
-        console.log("RECV", content.status, data);
-    }
+    console.log("RECV", data.id, data.status, JSON.stringify(data.data));
 }
 
 window.requested = function(data) {
@@ -28,6 +64,20 @@ window.requested = function(data) {
     console.log("CREQ", data);
 }
 
+// data is { id, name, state, progress, message }, sent whenever a background job changes
+window.jobChanged = function(data) {
+    console.log("JOB", data.id, data.name, data.state, data.progress);
+}
+
+window.jobLogged = function(data) {
+    console.log("JLOG", data.job, data.message);
+}
+
+// the outcome of something the user did, like an undo or a save
+window.statusMessage = function(message, failed = false) {
+    console.log("STAT", message);
+}
+
 window.error = function(data) {
     // TODO: behavior is undefined currently.
     data = JSON.stringify(data);
@@ -1,19 +1,26 @@
+// (* the widgets of one document; only the active document's canvas is shown       *)
 class Canvas {
-    constructor() {
+    constructor(workspace, doc) {
         //this.element = document.getElementById('tool-canvas')[0];
         this.element = document.createElement('div');
         this.element.id = 'tool-canvas';
         this.element.className = 'canvas'
         document.getElementById('main').appendChild(this.element);
 
+        this.workspace = workspace;
+        this.document = doc;
+
         this.widgets = [];
         this.grid_size = 20;
     }
 
-    addWidget(widget) {
+    // (* state is what the widget's state() returned when the layout was saved     *)
+    addWidget(widget, state = null) {
         this.widgets.push(widget);
         widget.setCanvas(this);
+        widget.openDocument(this.document, state);
         this.widgetFocused(widget);
+        this.changed();
     }
 
     highestZIndexWidget() {
@@ -36,7 +43,7 @@ class Canvas {
     widgetFocused(widget) {
         this.widgets.forEach(w => w.flags.focused = false);
         widget.flags.focused = true;
-        
+
         const indexes = this.widgets.map(w => parseInt(w.element.style.zIndex)).filter(z => z > parseInt(widget.element.style.zIndex));
         indexes.forEach(z => {
             const w = this.widgets.find(w => parseInt(w.element.style.zIndex) === z);
@@ -55,5 +62,34 @@ class Canvas {
             this.widgetFocused(maxWidget);
         }
         widget.element.remove();
+        this.changed();
+    }
+
+    // (* widgets call this whenever they move, resize, open or close               *)
+    changed() {
+        this.workspace.changed();
+    }
+
+    show(visible) {
+        this.element.style.display = visible ? '' : 'none';
+        if (visible) this.relayout();
+    }
+
+    // (* docked widgets follow the size of the window                             *)
+    relayout() {
+        this.widgets.filter(w => w.docked !== null).forEach(w => w.dock(w.docked));
+    }
+
+    // (* bottom to top, so the saved order restores the same stacking             *)
+    layout() {
+        return [...this.widgets]
+            .sort((a, b) => parseInt(a.element.style.zIndex) - parseInt(b.element.style.zIndex))
+            .map(w => Object.assign({ type: Workspace.typeOf(w) }, w.layout()));
+    }
+
+    destroy() {
+        this.widgets.forEach(w => w.cleanup());
+        this.widgets = [];
+        this.element.remove();
     }
-}
\ No newline at end of file
+}
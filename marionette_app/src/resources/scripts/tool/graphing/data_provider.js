@@ -49,4 +49,36 @@ class DebugDataProvider extends DataProvider {
             node.content.push(line);
         }
     }
-}
\ No newline at end of file
+}
+
+// (* renders the blocks served by the lua.cfg method, one line per instruction with  *)
+// (* every token colored by its kind                                                 *)
+class LuaDataProvider extends DataProvider {
+    static colors = {
+        pc: "#4EF196",
+        opcode: "#B686C1",
+        register: "#9b9b9b",
+        constant: "#AD9764",
+        upvalue: "#D7BA7D",
+        number: "#4EF196",
+        target: "#569CD6",
+        comment: "#5f5f5f"
+    };
+
+    constructor() {
+        super();
+    }
+
+    provide(node) {
+        node.content = [];
+        if (!node.data) return;
+
+        node.data.instructions.forEach((tokens) => {
+            const line = new Line("Consolas", "#9b9b9b");
+            tokens.forEach((token) => {
+                line.add(`${token.text}\t`, LuaDataProvider.colors[token.kind]);
+            });
+            node.content.push(line);
+        });
+    }
+}
@@ -1,12 +1,14 @@
 class Graph {
-    constructor() {
+    constructor(provider) {
+        // (* fills the vertices with content, GraphVertex.provider if undefined           *)
+        this.provider = provider;
         this.nodes = [];
         this.edges = [];
         this.root = new GraphVertex(this);
     }
 
-    static fromJSON(json) {
-        let graph = new Graph();
+    static fromJSON(json, provider) {
+        let graph = new Graph(provider);
         graph.nodes = json.nodes.map((node) => GraphVertex.fromJSON(graph, node));
         graph.edges = json.edges.map((edge) => GraphEdge.fromJSON(graph, edge));
         graph.root = graph.nodes[0];
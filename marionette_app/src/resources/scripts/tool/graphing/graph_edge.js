@@ -1,11 +1,16 @@
 class GraphEdge {
-    constructor(source, target) {
+    constructor(source, target, kind) {
         this.source = source;
         this.target = target;
+
+        // (* "unconditional", "true" or "false", undefined if unknown                     *)
+        this.kind = kind;
     }
 
     static fromJSON(graph, json) {
-        return new GraphEdge(graph.nodes[json.source], graph.nodes[json.target]);
+        let source = graph.nodes.find((node) => node.id == json.source);
+        let target = graph.nodes.find((node) => node.id == json.target);
+        return new GraphEdge(source, target, json.kind);
     }
 
     getSource() {
@@ -1,10 +1,13 @@
 class GraphVertex {
     static provider = new DebugDataProvider();
 
-    constructor(graph) {
+    constructor(graph, data) {
+        // (* data the provider turns into content, e.g. a block from the backend          *)
+        this.data = data;
+
         // (* content that will be rendered in the vertex                                  *)
         this.content = [];
-        GraphVertex.provider.provide(this);
+        (graph.provider || GraphVertex.provider).provide(this);
 
         // (* unique identifier for the vertex                                             *)
         this.id = -1;
@@ -16,7 +19,7 @@ class GraphVertex {
     }
 
     static fromJSON(graph, json) {
-        let vertex = new GraphVertex(graph);
+        let vertex = new GraphVertex(graph, json);
         vertex.id = json.id;
         return vertex;
     }
@@ -28,6 +28,7 @@ class BoxRenderer extends GraphRenderer {
                 this.drawingData = { vertexData: [], edgeData: [] };
                 this.vertexRenderer.drawingData = [];
                 this.edgeRenderer.drawingData = [];
+                this.edgeRenderer.vertexInfo = [];
                 this.graph.updateIdentifiers();
             }
         };
@@ -87,7 +88,7 @@ class BoxRenderer extends GraphRenderer {
                 let edge = this.graph.edges.find((edge) => edge.source.id == parseInt(e.v) && edge.target.id == parseInt(e.w));
                 let source = this.drawingData.vertexData.find((v) => v.vertex.id == edge.source.id);
                 let target = this.drawingData.vertexData.find((v) => v.vertex.id == edge.target.id);
-                this.drawingData.edgeData.push({source: source, target: target});
+                this.drawingData.edgeData.push({source: source, target: target, kind: edge.kind});
             });
 
             this.edgeRenderer.preprocess(ctx, this.drawingData.edgeData);
@@ -168,6 +169,7 @@ class BoxEdgeRenderer extends EdgeRenderer {
                     targets: [
                         {
                             target: edge.target,
+                            kind: edge.kind,
 
                             targetMidX: edge.target.x,
                             targetMidY: edge.target.y,
@@ -182,6 +184,7 @@ class BoxEdgeRenderer extends EdgeRenderer {
             } else {
                 sourceInfo.targets.push({
                     target: edge.target,
+                    kind: edge.kind,
 
                     targetMidX: edge.target.x,
                     targetMidY: edge.target.y,
@@ -240,7 +243,14 @@ class BoxEdgeRenderer extends EdgeRenderer {
             sourceInfo.targets.sort((a, b) => a.targetMidX - b.targetMidX);
 
             sourceInfo.targets.forEach((target, index) => {
-                const color = index < half ? config.COLORS.COLOR_TRUE : (index >= numTargets - half ? config.COLORS.COLOR_FALSE : config.COLORS.COLOR_DIRECT);
+                const kindColors = {
+                    unconditional: config.COLORS.COLOR_DIRECT,
+                    true: config.COLORS.COLOR_TRUE,
+                    false: config.COLORS.COLOR_FALSE
+                };
+
+                // (* typed edges get their own color, others are colored by position  *)
+                const color = target.kind in kindColors ? kindColors[target.kind] : (index < half ? config.COLORS.COLOR_TRUE : (index >= numTargets - half ? config.COLORS.COLOR_FALSE : config.COLORS.COLOR_DIRECT));
 
                 drawLine(
                     sourceInfo.source,
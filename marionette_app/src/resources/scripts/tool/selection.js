@@ -0,0 +1,27 @@
+// (* the bytes of a file selected in one widget, so other widgets showing the same    *)
+// (* file can follow along, e.g. the hex view scrolling to a block clicked in the graph *)
+class Selection {
+    static EVENT = 'marionette-selection';
+
+    // (* start and end are file offsets, end exclusive; fn is the function holding    *)
+    // (* them, when the widget knows it, so views of code can switch to it             *)
+    static select(source, path, start, end, fn = null) {
+        window.dispatchEvent(new CustomEvent(Selection.EVENT, {
+            detail: { source: source, path: path, start: start, end: end, function: fn }
+        }));
+    }
+
+    // (* calls callback for selections made by other widgets, returns the listener     *)
+    // (* to pass to unlisten                                                           *)
+    static listen(widget, callback) {
+        let listener = (e) => {
+            if (e.detail.source !== widget) callback(e.detail);
+        };
+        window.addEventListener(Selection.EVENT, listener);
+        return listener;
+    }
+
+    static unlisten(listener) {
+        window.removeEventListener(Selection.EVENT, listener);
+    }
+}
@@ -1,28 +1,133 @@
 toolbar = new Toolbar(document.getElementById('toolbar'));
-ToolCanvas = new Canvas();
+ToolWorkspace = new Workspace(document.getElementById('tabs'));
+
+// (* the status line: what the last undo, redo or save did, and whether the        *)
+// (* project has changes that are not saved yet                                    *)
+statusElement = document.getElementById('status');
+statusText = statusElement.appendChild(document.createElement('span'));
+statusText.classList.add('status-text');
+statusUnsaved = statusElement.appendChild(document.createElement('span'));
+statusUnsaved.classList.add('status-unsaved');
+statusUnsaved.textContent = 'unsaved changes';
+statusUnsaved.style.display = 'none';
+statusElement.style.display = 'none';
+
+function showStatus() {
+    let unsaved = statusUnsaved.style.display != 'none';
+    statusElement.style.display = statusText.textContent || unsaved ? '' : 'none';
+}
+
+window.statusMessage = function(message, failed = false) {
+    statusText.textContent = message;
+    statusText.classList.toggle('status-error', failed);
+    showStatus();
+}
+
+function refreshStatus() {
+    window.internalRequest('project.info', {}, false, true).then((info) => {
+        statusUnsaved.style.display = info.dirty ? '' : 'none';
+        showStatus();
+    }).catch(window.error);
+}
+
+function statusError(error) {
+    window.statusMessage(error.description || String(error), true);
+    window.error(error);
+}
+
+// (* widgets showing project data listen for this to read it again; source is the  *)
+// (* widget that made the change, if any                                            *)
+document.addEventListener('project-changed', refreshStatus);
+function projectChanged() {
+    document.dispatchEvent(new CustomEvent('project-changed', { detail: { source: null } }));
+}
+
+function undo() {
+    window.internalRequest('project.undo').then((result) => {
+        window.statusMessage(result.description || 'Nothing to undo');
+        if (result.description) projectChanged();
+    }).catch(statusError);
+}
+
+function redo() {
+    window.internalRequest('project.redo').then((result) => {
+        window.statusMessage(result.description || 'Nothing to redo');
+        if (result.description) projectChanged();
+    }).catch(statusError);
+}
+
+function save() {
+    window.internalRequest('project.save').then(() => {
+        window.statusMessage('Project saved');
+        refreshStatus();
+    }).catch(statusError);
+}
+
+// (* ctrl+z undoes, ctrl+shift+z and ctrl+y redo, ctrl+s saves. Text fields keep    *)
+// (* their own undo                                                                  *)
+document.getElementById('tool').addEventListener('keydown', (e) => {
+    if (!(e.ctrlKey || e.metaKey)) return;
+    let editing = e.target.closest('input, textarea, [contenteditable="true"]');
+
+    if (e.code == 'KeyS') {
+        save();
+    } else if (editing) {
+        return;
+    } else if (e.code == 'KeyZ' && e.shiftKey) {
+        redo();
+    } else if (e.code == 'KeyZ') {
+        undo();
+    } else if (e.code == 'KeyY') {
+        redo();
+    } else {
+        return;
+    }
+    e.preventDefault();
+});
 
 file = new ToolbarCategory('File');
 {
+    file.components.push(new ToolbarTool('Save', save));
+    file.components.push(new ToolbarTool('Close Tab', function() {
+        if (ToolWorkspace.active) ToolWorkspace.closeDocument(ToolWorkspace.active);
+    }));
     file.components.push(new ToolbarTool('Exit', function() {
-        window.close();
+        window.internalRequest('project.info', {}, false, true).then((info) => {
+            if (info.dirty && !confirm('The project has unsaved changes. Exit without saving?')) return;
+            window.close();
+        }).catch(window.error);
     }));
 }
 
+edit = new ToolbarCategory('Edit');
+{
+    edit.components.push(new ToolbarTool('Undo', undo));
+    edit.components.push(new ToolbarTool('Redo', redo));
+}
+
 widgets = new ToolbarCategory('Widgets');
 {
     {
         analysis = new ToolbarCategory('Analysis');
 
         analysis.components.push(new ToolbarTool('Graph View', function() {
-            ToolCanvas.addWidget(new GraphWidget('Graph View', 601, 400));
+            ToolWorkspace.addWidget('graph');
+        }));
+
+        analysis.components.push(new ToolbarTool('Listing View', function() {
+            ToolWorkspace.addWidget('listing');
         }));
 
         analysis.components.push(new ToolbarTool('Text View', function() {
-            ToolCanvas.addWidget(new TextEditorWidget('Text View', 401, 400));
+            ToolWorkspace.addWidget('text');
         }));
 
         analysis.components.push(new ToolbarTool('Log View', function() {
-            ToolCanvas.addWidget(new LogWidget('Log View', 401, 400));
+            ToolWorkspace.addWidget('log');
+        }));
+
+        analysis.components.push(new ToolbarTool('Hex View', function() {
+            ToolWorkspace.addWidget('hex');
         }));
 
         widgets.components.push(analysis);
@@ -30,12 +135,15 @@ widgets = new ToolbarCategory('Widgets');
     {
         misc = new ToolbarCategory('Miscellaneous');
         misc.components.push(new ToolbarTool('Clock View', function() {
-            ToolCanvas.addWidget(new ClockWidget('Clock', 201, 200));
+            ToolWorkspace.addWidget('clock');
         }));
         widgets.components.push(misc);
     }
 }
 
 toolbar.components.push(file);
+toolbar.components.push(edit);
 toolbar.components.push(widgets);
-toolbar.create();
\ No newline at end of file
+toolbar.create();
+
+ToolWorkspace.load();
\ No newline at end of file
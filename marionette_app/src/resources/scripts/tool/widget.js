@@ -1,7 +1,11 @@
 class Widget {
+    // (* how close to an edge of the window a widget must be dropped to dock there *)
+    static DOCK_DISTANCE = 40;
+
     constructor(title, width, height) {
-        let toolBar = document.getElementById('toolbar');
-        this.startY = toolBar.clientHeight + 1;
+        // (* widgets live below the toolbar and the document tabs                    *)
+        let tabs = document.getElementById('tabs');
+        this.startY = tabs.offsetTop + tabs.offsetHeight + 1;
 
         this.height = height;
         this.width = width;
@@ -13,7 +17,7 @@ class Widget {
             this.element.style.width = this.width + 'px';
             this.element.style.position = 'absolute';
             this.element.style.left = '0px';
-            this.element.style.top = (toolBar.clientHeight + 1) + 'px';
+            this.element.style.top = this.startY + 'px';
             
             this.header = this.element.appendChild(document.createElement('div'));
             this.header.id = 'header';
@@ -64,6 +68,10 @@ class Widget {
             oldZIndex: 0
         }
 
+        // (* 'left', 'right' or 'bottom' while docked, and the size to go back to    *)
+        this.docked = null;
+        this.floatingSize = null;
+
         this.flags = {
             hasBindedEvents: false,
             expanded: false,
@@ -84,6 +92,104 @@ class Widget {
 
     cleanup() {}
 
+    // (* called once the widget is on its canvas; widgets showing a file open the   *)
+    // (* document's here, and put back the state saved in a layout                  *)
+    openDocument(doc, state) {}
+
+    // (* what openDocument needs to restore the widget, saved with the layout       *)
+    state() {
+        return null;
+    }
+
+    layout() {
+        return {
+            x: this.positionInfo.movement.current.x,
+            y: this.positionInfo.movement.current.y,
+            width: this.positionInfo.resizing.currentSize.width,
+            height: this.positionInfo.resizing.currentSize.height,
+            expanded: this.flags.expanded,
+            docked: this.docked,
+            floatingSize: this.floatingSize,
+            state: this.state()
+        };
+    }
+
+    // (* puts the widget back where a layout saved it, once it is on its canvas     *)
+    restoreLayout(layout) {
+        this.setGeometry(layout.x, layout.y, layout.width, layout.height);
+        if (layout.expanded !== this.flags.expanded) this.dropClick();
+        if (layout.docked) {
+            this.dock(layout.docked);
+            this.floatingSize = layout.floatingSize;
+        }
+    }
+
+    setGeometry(x, y, width, height) {
+        this.positionInfo.movement.current = { x: x, y: y };
+        this.element.style.left = x + 'px';
+        this.element.style.top = y + 'px';
+
+        this.positionInfo.resizing.current = { x: width - this.width, y: height - this.height + 20 };
+        this.positionInfo.resizing.currentSize = { width: width, height: height };
+        this.element.style.width = width + 'px';
+        if (this.flags.expanded) this.element.style.height = height + 'px';
+    }
+
+    // (* fills an edge of the window, or floats again at its old size if side is   *)
+    // (* null                                                                       *)
+    dock(side) {
+        // (* moving to another edge starts over from the floating size                 *)
+        const switching = this.docked !== null && side !== this.docked;
+        const size = switching && this.floatingSize ? this.floatingSize : this.positionInfo.resizing.currentSize;
+        if (side === null) {
+            if (this.docked !== null && this.floatingSize !== null) {
+                const current = this.positionInfo.movement.current;
+                this.setGeometry(current.x, current.y, this.floatingSize.width, this.floatingSize.height);
+            }
+            this.docked = null;
+            this.floatingSize = null;
+            return;
+        }
+
+        if (this.docked === null) {
+            this.floatingSize = { width: size.width, height: size.height };
+        }
+        this.docked = side;
+
+        const [right, bottom] = [window.innerWidth - 2, window.innerHeight - 2];
+        switch (side) {
+            case 'left':
+                this.setGeometry(0, this.startY, size.width, bottom - this.startY);
+                break;
+            case 'right':
+                this.setGeometry(right - size.width, this.startY, size.width, bottom - this.startY);
+                break;
+            case 'bottom':
+                this.setGeometry(0, bottom - size.height, right, size.height);
+                break;
+        }
+    }
+
+    // (* docks the widget to the edge it was dropped next to, if any; a click on   *)
+    // (* the header without moving changes nothing                                *)
+    dockAt(e) {
+        const pressed = this.positionInfo.movement.pressed;
+        if (Math.abs(e.clientX - pressed.x) + Math.abs(e.clientY - pressed.y) < this.canvas.grid_size) {
+            return;
+        }
+
+        const distance = Widget.DOCK_DISTANCE;
+        if (e.clientX < distance) {
+            this.dock('left');
+        } else if (window.innerWidth - e.clientX < distance) {
+            this.dock('right');
+        } else if (window.innerHeight - e.clientY < distance) {
+            this.dock('bottom');
+        } else {
+            this.dock(null);
+        }
+    }
+
     closeClick(e) {
         this.cleanup();
         
@@ -132,6 +238,7 @@ class Widget {
         if (this.flags.expanded) {
             this.drag.addEventListener('mousedown', this.binds.dragMouseDown);
         }
+        this.canvas.changed();
     }
 
     headerMouseDown(e) {
@@ -139,6 +246,7 @@ class Widget {
         this.element.style.position = 'absolute';
         this.positionInfo.oldZIndex = this.element.style.zIndex;
         this.element.style.zIndex = 1000;
+        this.positionInfo.movement.pressed = { x: e.clientX, y: e.clientY };
 
         this.positionInfo.movement.start.x = e.clientX - this.positionInfo.movement.current.x;
         this.positionInfo.movement.start.y = this.startY + (e.clientY - this.positionInfo.movement.current.y);
@@ -172,12 +280,14 @@ class Widget {
         this.moveAt(e);
     }
 
-    headerMouseUp() {
+    headerMouseUp(e) {
         this.canvas.element.removeEventListener('mousemove', this.binds.headerMouseMove);
         this.header.removeEventListener('mouseup', this.binds.headerMouseUp);
         this.flags.hasBindedEvents = false;
         this.flags.moving = false;
         this.element.style.zIndex = this.positionInfo.oldZIndex;
+        this.dockAt(e);
+        this.canvas.changed();
     }
 
     dragMouseDown(e) {
@@ -214,6 +324,7 @@ class Widget {
         this.canvas.element.removeEventListener('mousemove', this.binds.dragMouseMove);
         this.drag.removeEventListener('mouseup', this.binds.dragMouseUp);
         this.flags.dragging = false;
+        this.canvas.changed();
     }
 
     setCanvas(canvas) {
@@ -9,6 +9,14 @@ class GraphWidget extends Widget {
         this.centerButton.id = 'graph-center-button';
         this.centerButton.innerHTML = '󰆤';
 
+        this.functionSelect = this.element.appendChild(document.createElement('select'));
+        this.functionSelect.id = 'graph-function-select';
+
+        this.tabButton = this.element.appendChild(document.createElement('div'));
+        this.tabButton.id = 'graph-tab-button';
+        this.tabButton.innerHTML = '󰓩';
+        this.tabButton.title = 'Open this function in a new tab';
+
         this.ctx = this.container.getContext('2d');
 
         this.binds['containerMouseClick'] = this.containerMouseClick.bind(this);
@@ -17,6 +25,8 @@ class GraphWidget extends Widget {
         this.binds['containerMouseUp'] = this.containerMouseUp.bind(this);
         this.binds['containerWheel'] = this.containerMouseWheel.bind(this);
         this.binds['centerButtonClick'] = this.centerButtonClick.bind(this);
+        this.binds['functionSelectChange'] = this.functionSelectChange.bind(this);
+        this.binds['tabButtonClick'] = this.tabButtonClick.bind(this);
         this.binds['containerTouchStart'] = this.containerTouchStart.bind(this);
         this.binds['containerTouchEnd'] = this.containerTouchEnd.bind(this);
         this.binds['containerTouchMove'] = this.containerTouchMove.bind(this);
@@ -47,13 +57,20 @@ class GraphWidget extends Widget {
         }
 
         this.renderer = new BoxRenderer(this.camera);
-        this.graph = Graph.fromJSON(Graph.testJSON());
+
+        // (* empty until a file is opened                                       *)
+        this.path = null;
+        this.function = null;
+        this.selectionListener = Selection.listen(this, (selection) => this.selectOffset(selection).catch(window.error));
+        this.graph = Graph.fromJSON({ nodes: [], edges: [] });
 
         $(this.container).click(this.binds.containerMouseClick);
 
         this.onExpand['graph'] = () => {
             this.container.style.visibility = this.flags.expanded ? 'visible' : 'hidden';
             this.centerButton.style.visibility = this.flags.expanded ? 'visible' : 'hidden';
+            this.functionSelect.style.visibility = this.flags.expanded ? 'visible' : 'hidden';
+            this.tabButton.style.visibility = this.flags.expanded ? 'visible' : 'hidden';
             
             this.centerButton.style.opacity = this.flags.expanded ? '1' : '0';
             this.centerButton.style.width = this.flags.expanded ? '40px' : '0px';
@@ -70,6 +87,8 @@ class GraphWidget extends Widget {
                 this.container.addEventListener('touchmove', this.binds.containerTouchMove);
 
                 this.centerButton.addEventListener('click', this.binds.centerButtonClick);
+                this.functionSelect.addEventListener('change', this.binds.functionSelectChange);
+                this.tabButton.addEventListener('click', this.binds.tabButtonClick);
             } else {
                 this.container.removeEventListener('mousedown', this.binds.containerMouseDown);
                 this.container.removeEventListener('mousemove', this.binds.containerMouseMove);
@@ -81,6 +100,8 @@ class GraphWidget extends Widget {
                 this.container.removeEventListener('touchmove', this.binds.containerTouchMove);
                 
                 this.centerButton.removeEventListener('click', this.binds.centerButtonClick);
+                this.functionSelect.removeEventListener('change', this.binds.functionSelectChange);
+                this.tabButton.removeEventListener('click', this.binds.tabButtonClick);
             }
         }
 
@@ -91,6 +112,92 @@ class GraphWidget extends Widget {
         this.draw();
     }
 
+    // (* a function document starts at its function, and a restored graph    *)
+    // (* where it was left                                                   *)
+    async openDocument(doc, state) {
+        if (!doc.path) return;
+        await this.open(doc.path, state ? state.function : doc.function);
+        if (state && state.camera) {
+            Object.assign(this.camera, state.camera);
+            this.camera.lastZoom = this.camera.zoom;
+            this.states.updated.flag = true;
+        }
+    }
+
+    state() {
+        return {
+            function: this.function,
+            camera: { x: this.camera.x, y: this.camera.y, zoom: this.camera.zoom }
+        };
+    }
+
+    // (* lists the functions of a Lua chunk and shows the graph of fn, or of  *)
+    // (* the first function                                                  *)
+    async open(path, fn = null) {
+        try {
+            let functions = await window.internalRequest('lua.open', { path: path });
+            this.path = path;
+
+            this.functionSelect.innerHTML = '';
+            functions.forEach((info) => {
+                let option = this.functionSelect.appendChild(document.createElement('option'));
+                option.value = info.id;
+                option.text = info.first_line ? `function ${info.id} (line ${info.first_line})` : `function ${info.id}`;
+            });
+
+            await this.showFunction(fn === null || fn === undefined ? 0 : fn);
+        } catch (error) {
+            window.error(error);
+        }
+    }
+
+    async showFunction(id) {
+        let cfg = await window.internalRequest('lua.cfg', { path: this.path, function: id });
+        this.graph = Graph.fromJSON({ nodes: cfg.blocks, edges: cfg.edges }, new LuaDataProvider());
+        this.function = id;
+        this.functionSelect.value = id;
+        this.states.selected.vertex = null;
+        this.states.updated.flag = true;
+        this.canvas.changed();
+        return cfg;
+    }
+
+    // (* selects the block holding the first selected byte, switching to its  *)
+    // (* function if the selection names another one                         *)
+    async selectOffset(selection) {
+        if (selection.path !== this.path) return;
+        if (selection.function !== null && selection.function !== this.function) {
+            await this.showFunction(selection.function);
+        }
+
+        let found = null;
+        this.graph.nodes.forEach((node) => {
+            node.selected = node.data && node.data.start <= selection.start && selection.start < node.data.end;
+            if (node.selected) found = node;
+        });
+
+        this.states.selected.vertex = found;
+        this.states.updated.flag = true;
+    }
+
+    cleanup() {
+        Selection.unlisten(this.selectionListener);
+    }
+
+    // (* the other views follow to the entry of the function                 *)
+    functionSelectChange(e) {
+        let id = parseInt(this.functionSelect.value);
+        this.showFunction(id).then((cfg) => {
+            let entry = cfg.blocks[0];
+            if (entry) Selection.select(this, this.path, entry.start, entry.end, id);
+        }).catch(window.error);
+    }
+
+    tabButtonClick(e) {
+        if (this.path === null) return;
+        this.canvas.workspace.openDocument(this.path, this.function);
+    }
+
     draw() {
         if (this.flags.expanded && !this.flags.dragging && !this.flags.moving && this.states.updated.flag) {
             this.states.updated.flag = false;
@@ -151,6 +258,11 @@ class GraphWidget extends Widget {
         if (this.graph) {
             this.states.selected.vertex = this.renderer.select(this.ctx, {x: canvasSpace.x, y: canvasSpace.y});
             this.states.updated.flag = true;
+
+            let data = this.states.selected.vertex ? this.states.selected.vertex.data : null;
+            if (data && data.start !== undefined) {
+                Selection.select(this, this.path, data.start, data.end, this.function);
+            }
         }
     }
 
@@ -0,0 +1,310 @@
+class HexEditorWidget extends Widget {
+    static ROW_SIZE = 16;
+    static PAGE_SIZE = 0x400;
+
+    constructor(title, width, height) {
+        super(title, width, height);
+
+        this.toolbar = this.element.appendChild(document.createElement('div'));
+        this.toolbar.classList.add('hex-toolbar');
+
+        this.previousButton = this.toolbar.appendChild(document.createElement('div'));
+        this.previousButton.classList.add('hex-button');
+        this.previousButton.innerHTML = '󰅁';
+
+        this.nextButton = this.toolbar.appendChild(document.createElement('div'));
+        this.nextButton.classList.add('hex-button');
+        this.nextButton.innerHTML = '󰅂';
+
+        this.offsetInput = this.toolbar.appendChild(document.createElement('input'));
+        this.offsetInput.classList.add('hex-offset-input');
+        this.offsetInput.placeholder = 'go to offset';
+        this.offsetInput.spellcheck = false;
+
+        this.info = this.toolbar.appendChild(document.createElement('div'));
+        this.info.classList.add('hex-info');
+
+        this.view = this.element.appendChild(document.createElement('div'));
+        this.view.classList.add('hex-view');
+        this.view.tabIndex = 0;
+
+        this.inspector = this.element.appendChild(document.createElement('div'));
+        this.inspector.classList.add('hex-inspector');
+
+        this.endianSelect = this.inspector.appendChild(document.createElement('select'));
+        this.endianSelect.classList.add('hex-endian');
+        ['little', 'big'].forEach((endian) => {
+            let option = this.endianSelect.appendChild(document.createElement('option'));
+            option.value = endian;
+            option.text = `${endian} endian`;
+        });
+
+        this.inspectorTable = this.inspector.appendChild(document.createElement('table'));
+        this.inspectorTable.classList.add('hex-inspector-table');
+
+        // (* the open file, see the hex.open method                            *)
+        this.path = null;
+        this.file = null;
+        this.page = { offset: 0, bytes: [], patched: [] };
+        this.structures = [];
+
+        // (* selected bytes as file offsets, end exclusive                    *)
+        this.selection = { start: null, end: null, anchor: null, selecting: false };
+        // (* the high nibble typed so far when editing                        *)
+        this.pendingNibble = null;
+
+        this.binds['previousClick'] = () => this.showPage(this.page.offset - HexEditorWidget.PAGE_SIZE);
+        this.binds['nextClick'] = () => this.showPage(this.page.offset + HexEditorWidget.PAGE_SIZE);
+        this.binds['offsetKeyDown'] = this.offsetKeyDown.bind(this);
+        this.binds['viewMouseDown'] = this.viewMouseDown.bind(this);
+        this.binds['viewMouseOver'] = this.viewMouseOver.bind(this);
+        this.binds['viewMouseUp'] = this.viewMouseUp.bind(this);
+        this.binds['viewKeyDown'] = this.viewKeyDown.bind(this);
+        this.binds['endianChange'] = () => this.inspect();
+        // (* patches change with undo and redo, so the page is read again      *)
+        this.binds['projectChanged'] = (e) => {
+            if (e.detail.source !== this) this.showPage(this.page.offset).catch(window.error);
+        };
+
+        this.previousButton.addEventListener('click', this.binds.previousClick);
+        this.nextButton.addEventListener('click', this.binds.nextClick);
+        this.offsetInput.addEventListener('keydown', this.binds.offsetKeyDown);
+        this.view.addEventListener('mousedown', this.binds.viewMouseDown);
+        this.view.addEventListener('mouseover', this.binds.viewMouseOver);
+        this.view.addEventListener('keydown', this.binds.viewKeyDown);
+        window.addEventListener('mouseup', this.binds.viewMouseUp);
+        this.endianSelect.addEventListener('change', this.binds.endianChange);
+        document.addEventListener('project-changed', this.binds.projectChanged);
+
+        this.selectionListener = Selection.listen(this, (selection) => {
+            if (selection.path !== this.path) return;
+            this.select(selection.start, selection.end, false);
+        });
+
+        this.onExpand['hex'] = () => {
+            const visibility = this.flags.expanded ? 'visible' : 'hidden';
+            this.toolbar.style.visibility = visibility;
+            this.view.style.visibility = visibility;
+            this.inspector.style.visibility = visibility;
+        };
+    }
+
+    async openDocument(doc, state) {
+        if (!doc.path) return;
+        await this.open(doc.path);
+        if (state && state.offset) await this.showPage(state.offset);
+    }
+
+    state() {
+        return { offset: this.page.offset };
+    }
+
+    cleanup() {
+        Selection.unlisten(this.selectionListener);
+        window.removeEventListener('mouseup', this.binds.viewMouseUp);
+        document.removeEventListener('project-changed', this.binds.projectChanged);
+    }
+
+    async open(path) {
+        try {
+            this.file = await window.internalRequest('hex.open', { path: path });
+            this.path = path;
+
+            let details = [`${this.file.size} bytes`, this.file.format || 'unknown format'];
+            if (!this.file.project_file) details.push('read only');
+            this.info.textContent = details.join(', ');
+
+            await this.showPage(0);
+        } catch (error) {
+            window.error(error);
+        }
+    }
+
+    async showPage(offset) {
+        if (!this.file) return;
+
+        // (* pages start at multiples of the page size                         *)
+        offset = Math.max(0, Math.min(offset, Math.max(0, this.file.size - 1)));
+        offset -= offset % HexEditorWidget.PAGE_SIZE;
+
+        let params = { path: this.path, offset: offset, length: HexEditorWidget.PAGE_SIZE };
+        let [page, structures] = await Promise.all([
+            window.internalRequest('hex.read', params, false, true),
+            window.internalRequest('hex.structures', params, false, true)
+        ]);
+
+        this.page = page;
+        this.structures = structures;
+        this.render();
+    }
+
+    // (* the structures covering offset, outermost first                      *)
+    structuresAt(offset) {
+        return this.structures
+            .filter((structure) => structure.start <= offset && offset < structure.end)
+            .sort((a, b) => a.depth - b.depth);
+    }
+
+    isPatched(offset) {
+        return this.page.patched.some((span) => span.start <= offset && offset < span.end);
+    }
+
+    isSelected(offset) {
+        return this.selection.start !== null && this.selection.start <= offset && offset < this.selection.end;
+    }
+
+    render() {
+        this.view.innerHTML = '';
+        const rowSize = HexEditorWidget.ROW_SIZE;
+
+        for (let row = 0; row < this.page.bytes.length; row += rowSize) {
+            let rowElement = this.view.appendChild(document.createElement('div'));
+            rowElement.classList.add('hex-row');
+
+            let offsetElement = rowElement.appendChild(document.createElement('span'));
+            offsetElement.classList.add('hex-row-offset');
+            offsetElement.textContent = (this.page.offset + row).toString(16).padStart(8, '0');
+
+            let bytesElement = rowElement.appendChild(document.createElement('span'));
+            bytesElement.classList.add('hex-bytes');
+            let asciiElement = rowElement.appendChild(document.createElement('span'));
+            asciiElement.classList.add('hex-ascii');
+
+            this.page.bytes.slice(row, row + rowSize).forEach((byte, column) => {
+                let offset = this.page.offset + row + column;
+
+                let byteElement = bytesElement.appendChild(document.createElement('span'));
+                byteElement.textContent = byte.toString(16).padStart(2, '0');
+                let charElement = asciiElement.appendChild(document.createElement('span'));
+                charElement.textContent = byte >= 0x20 && byte < 0x7f ? String.fromCharCode(byte) : '.';
+
+                [byteElement, charElement].forEach((element) => {
+                    element.classList.add('hex-byte');
+                    element.dataset.offset = offset;
+
+                    // (* the innermost structure colors the byte, all of them name it *)
+                    let structures = this.structuresAt(offset);
+                    if (structures.length > 0) {
+                        let depth = Math.min(structures[structures.length - 1].depth, 3);
+                        element.classList.add(`hex-depth-${depth}`);
+                        element.title = structures.map((structure) => structure.text).join('\n');
+                    }
+
+                    if (this.isPatched(offset)) element.classList.add('hex-patched');
+                    if (this.isSelected(offset)) element.classList.add('hex-selected');
+                });
+            });
+        }
+    }
+
+    // (* selects [start, end), paging to it if needed; widgets that did not   *)
+    // (* make the selection are told about it when notify is set               *)
+    async select(start, end, notify = true) {
+        this.selection.start = start;
+        this.selection.end = Math.max(end, start + 1);
+        this.pendingNibble = null;
+
+        if (start < this.page.offset || start >= this.page.offset + HexEditorWidget.PAGE_SIZE) {
+            await this.showPage(start);
+        } else {
+            this.render();
+        }
+
+        if (notify) Selection.select(this, this.path, this.selection.start, this.selection.end);
+        this.inspect();
+    }
+
+    async inspect() {
+        if (this.selection.start === null) return;
+
+        let inspections = await window.internalRequest('hex.inspect', {
+            path: this.path,
+            offset: this.selection.start,
+            endian: this.endianSelect.value
+        }, false, true);
+
+        this.inspectorTable.innerHTML = '';
+        inspections.forEach((inspection) => {
+            let row = this.inspectorTable.appendChild(document.createElement('tr'));
+            let name = row.appendChild(document.createElement('td'));
+            name.classList.add('hex-inspector-name');
+            name.textContent = inspection.name;
+
+            let value = row.appendChild(document.createElement('td'));
+            value.classList.add('hex-inspector-value');
+            value.textContent = inspection.value === null ? '-' : inspection.value;
+        });
+    }
+
+    offsetKeyDown(e) {
+        if (e.key !== 'Enter') return;
+
+        let offset = parseInt(this.offsetInput.value, 16);
+        if (!isNaN(offset) && this.file && offset < this.file.size) {
+            this.select(offset, offset + 1);
+        }
+    }
+
+    byteOffset(e) {
+        let offset = e.target.dataset ? e.target.dataset.offset : undefined;
+        return offset === undefined ? null : parseInt(offset);
+    }
+
+    viewMouseDown(e) {
+        let offset = this.byteOffset(e);
+        if (offset === null) return;
+
+        this.selection.anchor = offset;
+        this.selection.selecting = true;
+        this.selection.start = offset;
+        this.selection.end = offset + 1;
+        this.render();
+    }
+
+    viewMouseOver(e) {
+        let offset = this.byteOffset(e);
+        if (!this.selection.selecting || offset === null) return;
+
+        this.selection.start = Math.min(this.selection.anchor, offset);
+        this.selection.end = Math.max(this.selection.anchor, offset) + 1;
+        this.render();
+    }
+
+    viewMouseUp(e) {
+        if (!this.selection.selecting) return;
+
+        this.selection.selecting = false;
+        this.select(this.selection.start, this.selection.end);
+    }
+
+    // (* typing two hex digits overwrites the selected byte and moves on     *)
+    async viewKeyDown(e) {
+        if (this.selection.start === null || !/^[0-9a-fA-F]$/.test(e.key)) return;
+        e.preventDefault();
+
+        if (!this.file.project_file) {
+            window.error({ description: 'add the file to the project to edit it' });
+            return;
+        }
+
+        if (this.pendingNibble === null) {
+            this.pendingNibble = parseInt(e.key, 16);
+            return;
+        }
+
+        let byte = (this.pendingNibble << 4) | parseInt(e.key, 16);
+        let offset = this.selection.start;
+        this.pendingNibble = null;
+
+        try {
+            await window.internalRequest('hex.write', { path: this.path, offset: offset, bytes: [byte] });
+            await this.showPage(this.page.offset);
+            document.dispatchEvent(new CustomEvent('project-changed', { detail: { source: this } }));
+            if (offset + 1 < this.file.size) {
+                await this.select(offset + 1, offset + 2);
+            }
+        } catch (error) {
+            window.error(error);
+        }
+    }
+}
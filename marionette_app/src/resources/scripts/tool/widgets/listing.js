@@ -0,0 +1,261 @@
+// (* the instructions of one function as text, block by block. Selecting a block   *)
+// (* here selects it in the graph and hex views, and the other way around          *)
+class ListingWidget extends Widget {
+    constructor(title, width, height) {
+        super(title, width, height);
+
+        this.toolbar = this.element.appendChild(document.createElement('div'));
+        this.toolbar.classList.add('listing-toolbar');
+
+        this.functionSelect = this.toolbar.appendChild(document.createElement('select'));
+        this.functionSelect.classList.add('listing-function-select');
+
+        // (* annotations of the selected block, or of the function for renames   *)
+        this.renameButton = this.addButton('Rename', 'Rename the function');
+        this.commentButton = this.addButton('Comment', 'Comment the selected block');
+        this.bookmarkButton = this.addButton('Bookmark', 'Bookmark the selected block');
+        this.colorInput = this.toolbar.appendChild(document.createElement('input'));
+        this.colorInput.type = 'color';
+        this.colorInput.title = 'Color tag of the selected block';
+        this.colorInput.classList.add('listing-color');
+
+        this.view = this.element.appendChild(document.createElement('div'));
+        this.view.classList.add('listing-view');
+
+        // (* the shown function, see the lua.cfg method                         *)
+        this.path = null;
+        this.function = null;
+        this.blocks = [];
+        this.selected = -1;
+        // (* see the project.annotations method                                  *)
+        this.annotations = { renames: [], comments: [], bookmarks: [], color_tags: [] };
+
+        this.binds['functionSelectChange'] = this.functionSelectChange.bind(this);
+        this.binds['viewClick'] = this.viewClick.bind(this);
+        this.binds['renameClick'] = () => this.rename().catch(window.error);
+        this.binds['commentClick'] = () => this.annotateBlock('comment', 'text', 'Comment').catch(window.error);
+        this.binds['bookmarkClick'] = () => this.annotateBlock('bookmark', 'label', 'Bookmark').catch(window.error);
+        this.binds['colorChange'] = () => this.colorBlock().catch(window.error);
+        this.binds['projectChanged'] = (e) => {
+            if (e.detail.source !== this) this.loadAnnotations().catch(window.error);
+        };
+
+        this.functionSelect.addEventListener('change', this.binds.functionSelectChange);
+        this.view.addEventListener('click', this.binds.viewClick);
+        this.renameButton.addEventListener('click', this.binds.renameClick);
+        this.commentButton.addEventListener('click', this.binds.commentClick);
+        this.bookmarkButton.addEventListener('click', this.binds.bookmarkClick);
+        this.colorInput.addEventListener('change', this.binds.colorChange);
+        document.addEventListener('project-changed', this.binds.projectChanged);
+
+        this.selectionListener = Selection.listen(this, (selection) => this.selectOffset(selection).catch(window.error));
+
+        this.onExpand['listing'] = () => {
+            const visibility = this.flags.expanded ? 'visible' : 'hidden';
+            this.toolbar.style.visibility = visibility;
+            this.view.style.visibility = visibility;
+        };
+    }
+
+    cleanup() {
+        Selection.unlisten(this.selectionListener);
+        document.removeEventListener('project-changed', this.binds.projectChanged);
+    }
+
+    addButton(text, title) {
+        let button = this.toolbar.appendChild(document.createElement('button'));
+        button.classList.add('listing-button');
+        button.textContent = text;
+        button.title = title;
+        return button;
+    }
+
+    async openDocument(doc, state) {
+        if (!doc.path) return;
+        await this.open(doc.path, state ? state.function : doc.function);
+    }
+
+    state() {
+        return { function: this.function };
+    }
+
+    async open(path, fn = null) {
+        try {
+            let functions = await window.internalRequest('lua.open', { path: path });
+            this.path = path;
+
+            this.functionSelect.innerHTML = '';
+            functions.forEach((info) => {
+                let option = this.functionSelect.appendChild(document.createElement('option'));
+                option.value = info.id;
+                option.text = info.first_line ? `function ${info.id} (line ${info.first_line})` : `function ${info.id}`;
+            });
+
+            await this.loadAnnotations();
+            await this.showFunction(fn === null || fn === undefined ? 0 : fn);
+        } catch (error) {
+            window.error(error);
+        }
+    }
+
+    async showFunction(id) {
+        let cfg = await window.internalRequest('lua.cfg', { path: this.path, function: id });
+        this.function = id;
+        this.functionSelect.value = id;
+        this.blocks = cfg.blocks;
+        this.selected = -1;
+        this.render();
+        this.canvas.changed();
+        return cfg;
+    }
+
+    async loadAnnotations() {
+        this.annotations = await window.internalRequest('project.annotations', { path: this.path }, false, true);
+        this.render();
+    }
+
+    // (* the annotation of kind at address, if any                             *)
+    annotation(kind, address) {
+        return this.annotations[kind].find((item) => item.address === address && (kind !== 'renames' || item.register === null));
+    }
+
+    render() {
+        let scroll = this.view.scrollTop;
+        this.view.innerHTML = '';
+        if (this.blocks.length === 0) return;
+
+        // (* functions are renamed at the address of their entry block           *)
+        let rename = this.annotation('renames', this.blocks[0].start);
+        let title = this.view.appendChild(document.createElement('div'));
+        title.classList.add('listing-function-name');
+        title.textContent = `function ${rename ? rename.name : this.function}`;
+
+        this.blocks.forEach((block, index) => {
+            let element = this.view.appendChild(document.createElement('div'));
+            element.classList.add('listing-block');
+            element.classList.toggle('listing-block-selected', index === this.selected);
+            element.dataset.index = index;
+
+            let tag = this.annotations.color_tags.find((tag) => tag.function === this.function && tag.block === block.id);
+            if (tag) element.style.borderLeftColor = '#' + tag.color.toString(16).padStart(6, '0');
+
+            let header = element.appendChild(document.createElement('div'));
+            header.classList.add('listing-block-header');
+            header.textContent = `block ${block.id}  0x${block.start.toString(16)}..0x${block.end.toString(16)}`;
+
+            let bookmark = this.annotation('bookmarks', block.start);
+            if (bookmark) {
+                let label = header.appendChild(document.createElement('span'));
+                label.classList.add('listing-bookmark');
+                label.textContent = `  ${bookmark.label}`;
+            }
+
+            let comment = this.annotation('comments', block.start);
+            if (comment) {
+                let line = element.appendChild(document.createElement('div'));
+                line.classList.add('listing-comment');
+                line.textContent = `; ${comment.text}`;
+            }
+
+            block.instructions.forEach((tokens) => {
+                let line = element.appendChild(document.createElement('div'));
+                line.classList.add('listing-line');
+                tokens.forEach((token) => {
+                    let span = line.appendChild(document.createElement('span'));
+                    span.textContent = token.text;
+                    span.style.color = LuaDataProvider.colors[token.kind];
+                });
+            });
+        });
+        this.view.scrollTop = scroll;
+    }
+
+    // (* highlights the block at index and scrolls to it, -1 for none          *)
+    highlight(index) {
+        this.selected = index;
+        let tag = index >= 0 ? this.annotations.color_tags.find((tag) => tag.function === this.function && tag.block === this.blocks[index].id) : null;
+        this.colorInput.value = tag ? '#' + tag.color.toString(16).padStart(6, '0') : '#000000';
+
+        this.view.querySelectorAll('.listing-block').forEach((element) => {
+            let selected = parseInt(element.dataset.index) === index;
+            element.classList.toggle('listing-block-selected', selected);
+            if (selected) element.scrollIntoView({ block: 'nearest' });
+        });
+    }
+
+    async selectOffset(selection) {
+        if (selection.path !== this.path) return;
+        if (selection.function !== null && selection.function !== this.function) {
+            await this.showFunction(selection.function);
+        }
+        this.highlight(this.blocks.findIndex((block) => block.start <= selection.start && selection.start < block.end));
+    }
+
+    viewClick(e) {
+        let element = e.target.closest('.listing-block');
+        if (!element) return;
+
+        let index = parseInt(element.dataset.index);
+        let block = this.blocks[index];
+        this.highlight(index);
+        Selection.select(this, this.path, block.start, block.end, this.function);
+    }
+
+    // (* sets an annotation of the file; the result can be undone like any change *)
+    async annotate(annotation) {
+        let result = await window.internalRequest('project.annotate', { path: this.path, annotation: annotation });
+        window.statusMessage(result.description);
+        document.dispatchEvent(new CustomEvent('project-changed', { detail: { source: this } }));
+        await this.loadAnnotations();
+    }
+
+    // (* asks for the new text, empty removes the annotation, cancel keeps it    *)
+    ask(title, current) {
+        let text = prompt(title, current || '');
+        if (text === null) return undefined;
+        return text.trim() === '' ? null : text;
+    }
+
+    async rename() {
+        if (this.blocks.length === 0) return;
+        let address = this.blocks[0].start;
+        let current = this.annotation('renames', address);
+        let name = this.ask('Function name', current ? current.name : '');
+        if (name === undefined) return;
+        await this.annotate({ kind: 'rename', address: address, register: null, name: name });
+    }
+
+    async annotateBlock(kind, field, title) {
+        let block = this.blocks[this.selected];
+        if (!block) {
+            window.statusMessage('Select a block first', true);
+            return;
+        }
+
+        let current = this.annotation(kind + 's', block.start);
+        let value = this.ask(title, current ? current[field] : '');
+        if (value === undefined) return;
+        await this.annotate({ kind: kind, address: block.start, [field]: value });
+    }
+
+    async colorBlock() {
+        let block = this.blocks[this.selected];
+        if (!block) {
+            window.statusMessage('Select a block first', true);
+            return;
+        }
+
+        // (* black clears the tag                                               *)
+        let color = parseInt(this.colorInput.value.slice(1), 16);
+        await this.annotate({ kind: 'color_tag', function: this.function, block: block.id, color: color === 0 ? null : color });
+    }
+
+    // (* the other views follow to the entry of the function                 *)
+    functionSelectChange(e) {
+        let id = parseInt(this.functionSelect.value);
+        this.showFunction(id).then((cfg) => {
+            let entry = cfg.blocks[0];
+            if (entry) Selection.select(this, this.path, entry.start, entry.end, id);
+        }).catch(window.error);
+    }
+}
@@ -32,6 +32,12 @@ class LogWidget extends Widget {
         this.original_received = null;
         this.original_requested = null;
         this.original_error = null;
+        this.original_job_changed = null;
+        this.original_job_logged = null;
+        this.original_status = null;
+
+        // (* jobs that have not ended, shown in the bottom bar                 *)
+        this.jobs = new Map();
 
         this.cleanup = () => {
             if (this.original_received) {
@@ -46,6 +52,18 @@ class LogWidget extends Widget {
                 restore(window, 'error', this.original_error);
                 this.original_error = null;
             }
+            if (this.original_job_changed) {
+                restore(window, 'jobChanged', this.original_job_changed);
+                this.original_job_changed = null;
+            }
+            if (this.original_job_logged) {
+                restore(window, 'jobLogged', this.original_job_logged);
+                this.original_job_logged = null;
+            }
+            if (this.original_status) {
+                restore(window, 'statusMessage', this.original_status);
+                this.original_status = null;
+            }
         }
 
         this.onExpand['log'] = () => {
@@ -55,36 +73,88 @@ class LogWidget extends Widget {
             if (this.flags.expanded) {
 
                 this.original_received = hook(window, 'received', (ret, args) => {
-                    let content = JSON.parse(args[0]);
-                    this.createLog("RECV", content.status, content.data);
+                    let content = args[0];
+                    this.createLog("RECV", content.status, JSON.stringify(content.data));
                     return ret;
                 });
 
                 this.original_requested = hook(window, 'requested', (ret, args) => {
-                    this.createLog("CREQ", args[0].method, args[0].data);
+                    this.createLog("CREQ", args[0].method, JSON.stringify(args[0].params));
                     return ret;
                 });
 
                 this.original_error = hook(window, 'error', (ret, args) => {
                     // TODO: behavior is undefined currently.
-                    this.createLog("CERR", "error", args[0]);
+                    this.createLog("CERR", "error", JSON.stringify(args[0]));
+                    return ret;
+                });
+
+                this.original_job_changed = hook(window, 'jobChanged', (ret, args) => {
+                    this.jobChanged(args[0]);
+                    return ret;
+                });
+
+                this.original_job_logged = hook(window, 'jobLogged', (ret, args) => {
+                    let job = this.jobs.get(args[0].job);
+                    this.createLog("JLOG", job ? job.name : args[0].job, args[0].message);
+                    return ret;
+                });
+
+                this.original_status = hook(window, 'statusMessage', (ret, args) => {
+                    this.createLog("STAT", args[1] ? "error" : "info", args[0]);
                     return ret;
                 });
             } else {
                 restore(window, 'received', this.original_received);
                 restore(window, 'requested', this.original_requested);
                 restore(window, 'error', this.original_error);
+                restore(window, 'jobChanged', this.original_job_changed);
+                restore(window, 'jobLogged', this.original_job_logged);
+                restore(window, 'statusMessage', this.original_status);
+                this.jobs.clear();
+                this.renderJobs();
             }
         }
     }
 
+    jobChanged(job) {
+        if (job.state == 'queued' || job.state == 'running') {
+            this.jobs.set(job.id, job);
+        } else {
+            this.jobs.delete(job.id);
+            let message = job.state == 'failed' ? job.message : job.name;
+            this.createLog("JOB", job.state, message);
+        }
+        this.renderJobs();
+    }
+
+    // (* one entry per unfinished job: its name, progress and a cancel button *)
+    renderJobs() {
+        this.bottomBar.innerHTML = '';
+        this.jobs.forEach((job) => {
+            let jobObject = this.bottomBar.appendChild(document.createElement('div'));
+            jobObject.classList.add('log-job');
+
+            let progress = job.state == 'queued' ? 'queued' : `${Math.round(job.progress * 100)}%`;
+            jobObject.textContent = `${job.name} ${progress}`;
+            if (job.message) jobObject.title = job.message;
+
+            let cancelObject = jobObject.appendChild(document.createElement('span'));
+            cancelObject.classList.add('log-job-cancel');
+            cancelObject.textContent = '✕';
+            cancelObject.addEventListener('click', () => {
+                window.internalRequest('jobs.cancel', { id: job.id }).catch(window.error);
+            });
+        });
+    }
+
     logColor(detail) {
         let color = 'log-default';
-        if (detail == 'error') {
+        if (detail == 'error' || detail == 'failed') {
             color = 'log-error';
         } else if (detail == 'ok') {
             color = 'log-info';
-        } else if (detail == 'info') {
+        } else if (detail == 'info' || detail == 'finished') {
             color = 'log-info';
         }
         return color;
@@ -31,6 +31,12 @@ class TextEditorWidget extends Widget {
 
             "raw_curr_lines": [""]
         };
+
+        // (* highlight with the lexer picked in the settings                   *)
+        this.lexer = 'lua';
+        window.internalRequest('settings.get', {}, false, true)
+            .then((settings) => this.lexer = settings.editor.default_lexer)
+            .catch(window.error);
         
         $(this.text).on({
             'scroll': () => this.syncScrollPositions(),
@@ -127,8 +133,7 @@ class TextEditorWidget extends Widget {
     }
 
     async lintLine(lineContent) {
-        var data = await window.internalRequest('lex', {"lexer":"lua", "text": lineContent}, false, true);
-        data = JSON.parse(data["data"]);
+        var data = await window.internalRequest('lex', {"lexer": this.lexer, "text": lineContent}, false, true);
 
         let edits = [];
         for (let i = 0; i < data.length; i++) {
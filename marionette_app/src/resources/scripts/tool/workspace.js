@@ -0,0 +1,248 @@
+// (* the documents open in the tool, a file or one function of it each, shown as   *)
+// (* tabs with a canvas of widgets per tab. The layout of every tab is saved in the *)
+// (* project whenever it changes and restored when the project is opened again      *)
+class Workspace {
+    // (* the widgets a layout can hold, by the type saved for them                  *)
+    static WIDGETS = {
+        graph: { widget: GraphWidget, title: 'Graph View', width: 601, height: 400 },
+        listing: { widget: ListingWidget, title: 'Listing View', width: 421, height: 400 },
+        text: { widget: TextEditorWidget, title: 'Text View', width: 401, height: 400 },
+        log: { widget: LogWidget, title: 'Log View', width: 401, height: 400 },
+        hex: { widget: HexEditorWidget, title: 'Hex View', width: 761, height: 400 },
+        clock: { widget: ClockWidget, title: 'Clock', width: 201, height: 200 }
+    };
+
+    // (* what a new document opens with, and the edge each one is docked to         *)
+    static DEFAULT_WIDGETS = [['listing', 'left'], ['hex', 'bottom'], ['graph', 'right']];
+
+    // (* how long the layout has to stay the same before it is saved               *)
+    static SAVE_DELAY = 500;
+
+    static typeOf(widget) {
+        return Object.keys(Workspace.WIDGETS).find((type) => widget.constructor === Workspace.WIDGETS[type].widget);
+    }
+
+    constructor(element) {
+        this.element = element;
+        this.documents = [];
+        this.active = null;
+        this.nextId = 1;
+
+        // (* nothing is saved until the saved layout is back                          *)
+        this.restoring = true;
+        this.saveTimer = null;
+
+        this.tabs = this.element.appendChild(document.createElement('div'));
+        this.tabs.classList.add('tabs-list');
+
+        this.addButton = this.element.appendChild(document.createElement('div'));
+        this.addButton.classList.add('tab-add');
+        this.addButton.innerHTML = '󰐕';
+        this.addButton.title = 'Open a project file';
+
+        this.menu = null;
+
+        this.binds = {
+            addClick: this.addClick.bind(this),
+            windowClick: () => this.closeMenu(),
+            windowResize: () => { if (this.active) this.active.canvas.relayout(); }
+        };
+        this.addButton.addEventListener('click', this.binds.addClick);
+        window.addEventListener('click', this.binds.windowClick);
+        window.addEventListener('resize', this.binds.windowResize);
+    }
+
+    // (* restores the project's saved layout, or opens each of its files if it has  *)
+    // (* none                                                                         *)
+    async load() {
+        let saved = null;
+        try {
+            saved = await window.internalRequest('project.workspace', {}, false, true);
+            if (saved) {
+                this.restore(JSON.parse(saved));
+            } else {
+                let info = await window.internalRequest('project.info', {}, false, true);
+                info.paths.forEach((path) => this.openDocument(path, null));
+                if (this.documents.length > 0) this.activate(this.documents[0]);
+            }
+        } catch (error) {
+            window.error(error);
+        } finally {
+            this.restoring = false;
+        }
+
+        // (* a project opened for the first time gets the layout it was given        *)
+        if (!saved) this.changed();
+    }
+
+    restore(layout) {
+        layout.documents.forEach((saved) => {
+            let doc = this.createDocument(saved.path, saved.function);
+            saved.widgets.forEach((widgetLayout) => {
+                let widget = this.addWidget(widgetLayout.type, doc, widgetLayout.state);
+                if (widget) widget.restoreLayout(widgetLayout);
+            });
+        });
+
+        let active = this.documents[layout.active] || this.documents[0];
+        if (active) this.activate(active);
+    }
+
+    layout() {
+        return {
+            active: this.documents.indexOf(this.active),
+            documents: this.documents.map((doc) => ({
+                path: doc.path,
+                function: doc.function,
+                widgets: doc.canvas.layout()
+            }))
+        };
+    }
+
+    // (* saves the layout once it stops changing, e.g. at the end of a drag         *)
+    changed() {
+        if (this.restoring) return;
+
+        clearTimeout(this.saveTimer);
+        this.saveTimer = setTimeout(() => {
+            window.internalRequest('project.set_workspace', { layout: JSON.stringify(this.layout()) }, false, true)
+                .catch(window.error);
+        }, Workspace.SAVE_DELAY);
+    }
+
+    static title(path, fn) {
+        let name = path ? path.split(/[\\/]/).pop() : 'Workspace';
+        return fn === null || fn === undefined ? name : `${name}: function ${fn}`;
+    }
+
+    createDocument(path, fn) {
+        let doc = { id: this.nextId++, path: path, function: fn };
+        doc.canvas = new Canvas(this, doc);
+        doc.canvas.show(false);
+
+        doc.tab = this.tabs.appendChild(document.createElement('div'));
+        doc.tab.classList.add('tab');
+        doc.tab.title = path || '';
+
+        let title = doc.tab.appendChild(document.createElement('span'));
+        title.classList.add('tab-title');
+        title.textContent = Workspace.title(path, fn);
+
+        let close = doc.tab.appendChild(document.createElement('span'));
+        close.classList.add('tab-close');
+        close.innerHTML = '';
+
+        doc.tab.addEventListener('click', () => this.activate(doc));
+        close.addEventListener('click', (e) => {
+            e.stopPropagation();
+            this.closeDocument(doc);
+        });
+
+        this.documents.push(doc);
+        return doc;
+    }
+
+    // (* shows the document of path and fn, opening it with the default widgets    *)
+    // (* if it is not open yet; fn is null for the whole file                       *)
+    openDocument(path, fn) {
+        let doc = this.documents.find((doc) => doc.path === path && doc.function === fn);
+        if (!doc) {
+            doc = this.createDocument(path, fn);
+            Workspace.DEFAULT_WIDGETS.forEach(([type, side]) => {
+                let widget = this.addWidget(type, doc);
+                widget.dropClick();
+                if (side) widget.dock(side);
+            });
+        }
+        this.activate(doc);
+        return doc;
+    }
+
+    activate(doc) {
+        this.documents.forEach((other) => {
+            other.canvas.show(other === doc);
+            other.tab.classList.toggle('tab-active', other === doc);
+        });
+        this.active = doc;
+        this.changed();
+    }
+
+    closeDocument(doc) {
+        let index = this.documents.indexOf(doc);
+        doc.canvas.destroy();
+        doc.tab.remove();
+        this.documents.splice(index, 1);
+
+        if (this.active === doc) {
+            this.active = null;
+            let next = this.documents[Math.min(index, this.documents.length - 1)];
+            if (next) this.activate(next);
+        }
+        this.changed();
+    }
+
+    // (* adds a widget of type to doc, the active document by default, or to a new  *)
+    // (* empty one if nothing is open                                               *)
+    addWidget(type, doc = null, state = null) {
+        let info = Workspace.WIDGETS[type];
+        if (!info) return null;
+
+        if (doc === null) {
+            doc = this.active || this.openEmpty();
+        }
+        let widget = new info.widget(info.title, info.width, info.height);
+        doc.canvas.addWidget(widget, state);
+        return widget;
+    }
+
+    openEmpty() {
+        let doc = this.createDocument(null, null);
+        this.activate(doc);
+        return doc;
+    }
+
+    async addClick(e) {
+        e.stopPropagation();
+        if (this.menu) {
+            this.closeMenu();
+            return;
+        }
+
+        this.menu = this.element.appendChild(document.createElement('div'));
+        this.menu.classList.add('tab-menu');
+        let rect = this.addButton.getBoundingClientRect();
+        this.menu.style.left = rect.left + 'px';
+        this.menu.style.top = rect.bottom + 'px';
+
+        try {
+            let info = await window.internalRequest('project.info', {}, false, true);
+            if (!this.menu) return;
+            if (info.paths.length === 0) {
+                this.addMenuItem('The project has no files', null);
+            }
+            info.paths.forEach((path, index) => this.addMenuItem(info.files[index], () => this.openDocument(path, null)));
+        } catch (error) {
+            window.error(error);
+        }
+    }
+
+    addMenuItem(text, onClick) {
+        let item = this.menu.appendChild(document.createElement('div'));
+        item.classList.add(onClick ? 'tab-menu-item' : 'tab-menu-note');
+        item.textContent = text;
+        if (onClick) {
+            item.addEventListener('click', (e) => {
+                e.stopPropagation();
+                this.closeMenu();
+                onClick();
+            });
+        }
+    }
+
+    closeMenu() {
+        if (this.menu) {
+            this.menu.remove();
+            this.menu = null;
+        }
+    }
+}
@@ -0,0 +1,202 @@
+// Purpose: requests from the user interface to the backend. The interface sends
+// `{ id, method, params }` through `window.internalRequest` and gets back `{ id, status, data }`,
+// where `data` is the method's result, or an `RpcError` when `status` is "err". The id is
+// echoed so concurrent requests can each be matched to their own response.
+//
+// Methods are registered by name with typed parameters and results, so handlers never touch
+// raw JSON and bad input becomes an `invalid_params` error instead of a panic. Slow methods are
+// registered as jobs: their work runs on a worker thread and they answer when it ends, so other
+// requests are served meanwhile.
+
+pub mod hex;
+pub mod jobs;
+pub mod lexer;
+pub mod lua;
+pub mod plugins;
+pub mod project;
+pub mod settings;
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::jobs::{JobContext, JobManager, JobOutcome};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcRequest {
+    pub id: u64,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcResponse {
+    /// The id of the request, None if the request was too malformed to read one.
+    pub id: Option<u64>,
+    #[serde(flatten)]
+    pub result: RpcResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", content = "data", rename_all = "snake_case")]
+pub enum RpcResult {
+    Ok(Value),
+    Err(RpcError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcErrorType {
+    MalformedRequest,
+    UnknownMethod,
+    InvalidParams,
+    Failure,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcError {
+    pub method: String,
+    pub description: String,
+    pub error_type: RpcErrorType,
+}
+
+impl RpcError {
+    pub fn new(method: &str, description: String, error_type: RpcErrorType) -> Self {
+        RpcError {
+            method: method.to_string(),
+            description,
+            error_type,
+        }
+    }
+}
+
+impl Display for RpcError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} in {}: {}", self.error_type, self.method, self.description)
+    }
+}
+
+impl RpcResponse {
+    /// The answer to the request a job method queued, once the job has ended.
+    pub fn from_job(request: u64, method: &str, outcome: JobOutcome) -> Self {
+        RpcResponse {
+            id: Some(request),
+            result: match outcome {
+                JobOutcome::Finished(value) => RpcResult::Ok(value),
+                JobOutcome::Failed(error) => RpcResult::Err(RpcError::new(method, error, RpcErrorType::Failure)),
+                JobOutcome::Cancelled => RpcResult::Err(RpcError::new(method, "cancelled".to_string(), RpcErrorType::Cancelled)),
+            },
+        }
+    }
+}
+
+type Work = Box<dyn FnOnce(&JobContext) -> Result<Value, String> + Send>;
+
+enum Reply {
+    Now(Value),
+    /// Work to queue as a job, which answers the request when it ends.
+    Later(Work),
+}
+
+type Handler = Box<dyn Fn(Value) -> Result<Reply, RpcError>>;
+
+pub struct RpcRegistry {
+    handlers: HashMap<String, Handler>,
+    jobs: JobManager,
+}
+
+impl RpcRegistry {
+    pub fn new(jobs: JobManager) -> Self {
+        RpcRegistry {
+            handlers: HashMap::new(),
+            jobs,
+        }
+    }
+
+    pub fn jobs(&self) -> &JobManager {
+        &self.jobs
+    }
+
+    /// Adds a method, replacing any earlier one with the same name. A failing handler's
+    /// message is returned to the interface as a `failure` error.
+    pub fn register<P, R, F>(&mut self, method: &str, handler: F)
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: Fn(P) -> Result<R, String> + 'static,
+    {
+        let name = method.to_string();
+        self.handlers.insert(name.clone(), Box::new(move |params| {
+            let params = serde_json::from_value(params)
+                .map_err(|error| RpcError::new(&name, error.to_string(), RpcErrorType::InvalidParams))?;
+            let result = handler(params)
+                .map_err(|error| RpcError::new(&name, error, RpcErrorType::Failure))?;
+            serde_json::to_value(result)
+                .map(Reply::Now)
+                .map_err(|error| RpcError::new(&name, error.to_string(), RpcErrorType::Failure))
+        }));
+    }
+
+    /// Adds a method that runs as a job. `prepare` runs first, on the UI thread, so it can read
+    /// state the workers cannot; the work it returns runs on a worker thread.
+    pub fn register_job<P, R, F, W>(&mut self, method: &str, prepare: F)
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+        F: Fn(P) -> Result<W, String> + 'static,
+        W: FnOnce(&JobContext) -> Result<R, String> + Send + 'static,
+    {
+        let name = method.to_string();
+        self.handlers.insert(name.clone(), Box::new(move |params| {
+            let params = serde_json::from_value(params)
+                .map_err(|error| RpcError::new(&name, error.to_string(), RpcErrorType::InvalidParams))?;
+            let work = prepare(params)
+                .map_err(|error| RpcError::new(&name, error, RpcErrorType::Failure))?;
+            Ok(Reply::Later(Box::new(move |context: &JobContext| {
+                serde_json::to_value(work(context)?).map_err(|error| error.to_string())
+            })))
+        }));
+    }
+
+    pub fn methods(&self) -> Vec<&str> {
+        let mut methods: Vec<&str> = self.handlers.keys().map(String::as_str).collect();
+        methods.sort();
+        methods
+    }
+
+    /// Answers a request, or returns None if it was queued as a job, whose `JobMessage::Done`
+    /// carries the answer later.
+    pub fn call(&self, request: RpcRequest) -> Option<RpcResponse> {
+        let result = match self.handlers.get(&request.method) {
+            Some(handler) => handler(request.params),
+            None => Err(RpcError::new(&request.method, "no such method".to_string(), RpcErrorType::UnknownMethod)),
+        };
+
+        let result = match result {
+            Ok(Reply::Now(value)) => RpcResult::Ok(value),
+            Ok(Reply::Later(work)) => {
+                self.jobs.spawn(&request.method, Some(request.id), work);
+                return None;
+            }
+            Err(error) => RpcResult::Err(error),
+        };
+        Some(RpcResponse { id: Some(request.id), result })
+    }
+
+    /// Handles a raw message from the interface.
+    pub fn handle(&self, message: Value) -> Option<RpcResponse> {
+        // keep the id of a request whose other fields are wrong, so the caller still gets its answer
+        let id = message.get("id").and_then(Value::as_u64);
+        match serde_json::from_value::<RpcRequest>(message) {
+            Ok(request) => self.call(request),
+            Err(error) => Some(RpcResponse {
+                id,
+                result: RpcResult::Err(RpcError::new("", error.to_string(), RpcErrorType::MalformedRequest)),
+            }),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+// Purpose: backs the hex editor widget. Files are read in pages with the project's patches
+// applied, the structures a loader finds in them are served for highlighting, and edits
+// become `Patch` commands, so they can be undone and are saved with the project. Opening a file
+// runs its loader, so `hex.open` is a job.
+
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+
+use dioxus::prelude::*;
+use marionette_core::assembly::Range;
+use marionette_core::byte_stream::{inspect::inspect, Endian};
+use marionette_core::interval::IntervalMap;
+use marionette_core::loader::LoaderRegistry;
+use marionette_core::mproj::history::Command;
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobContext;
+use crate::states::project::ProjectState;
+use super::RpcRegistry;
+
+/// Largest page `hex.read` returns.
+const MAX_PAGE: u64 = 0x10000;
+/// Bytes the data inspector decodes from, enough for any of its strings.
+const INSPECT_WINDOW: u64 = 0x400;
+
+struct Structure {
+    kind: &'static str,
+    text: String,
+    depth: usize,
+}
+
+struct HexFile {
+    /// The file as it is on disk, without patches.
+    bytes: Vec<u8>,
+    format: Option<String>,
+    structures: IntervalMap<Structure>,
+}
+
+type OpenFiles = Arc<Mutex<HashMap<String, Arc<HexFile>>>>;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileParams {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageParams {
+    pub path: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InspectParams {
+    pub path: String,
+    pub offset: u64,
+    pub endian: ByteOrder,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WriteParams {
+    pub path: String,
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HexInfo {
+    pub size: u64,
+    /// Name of the loader that understood the file, None for unknown formats.
+    pub format: Option<String>,
+    /// Path of the file inside the project, None if it is not part of it and so read only.
+    pub project_file: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Span {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HexPage {
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+    /// Parts of the page the project patches.
+    pub patched: Vec<Span>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StructureInfo {
+    pub start: u64,
+    pub end: u64,
+    /// "region" for regions of the assembly, "data" for the items inside them.
+    pub kind: &'static str,
+    pub text: String,
+    /// Number of regions around this one.
+    pub depth: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InspectionInfo {
+    pub name: &'static str,
+    pub value: Option<String>,
+    pub size: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WriteResult {
+    pub description: String,
+}
+
+fn load(path: &str) -> Result<HexFile, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+
+    let mut loaders = LoaderRegistry::new();
+    marionette_lua::loader::register(&mut loaders).map_err(|error| error.to_string())?;
+
+    // files no loader understands, or that make it fail, are still shown, just without structures
+    let mut structures = Vec::new();
+    let mut format = None;
+    let loaded = std::panic::catch_unwind(AssertUnwindSafe(|| loaders.load(bytes.clone(), None)));
+    if let Ok(Ok((name, assembly))) = loaded {
+        let regions = IntervalMap::from_regions(assembly.as_ref());
+        for region in assembly.regions() {
+            let range = region.range();
+            let depth = regions.overlapping(&range).into_iter()
+                .filter(|(other, _)| other.contains_range(&range) && **other != range)
+                .count();
+            // the first line of a region's text names it, the rest lists its contents
+            let title = region.text().lines().next().unwrap_or_default().trim_start_matches("; ").to_string();
+            structures.push((range, Structure { kind: "region", text: title, depth }));
+            for data in region.data() {
+                structures.push((data.range(), Structure { kind: "data", text: data.text().to_string(), depth: depth + 1 }));
+            }
+        }
+        format = Some(name);
+    }
+
+    Ok(HexFile { bytes, format, structures: structures.into_iter().collect() })
+}
+
+/// A file opened by `hex.open`. The other methods run on the UI thread, so they never load one.
+fn file(files: &OpenFiles, path: &str) -> Result<Arc<HexFile>, String> {
+    files.lock().unwrap().get(path).cloned().ok_or_else(|| format!("{} is not open", path))
+}
+
+/// The bytes of `range` with the project's patches applied.
+fn window(file: &HexFile, state: &ProjectState, path: &str, range: &Range) -> (Vec<u8>, Vec<Range>) {
+    let end = range.end.min(file.bytes.len() as u64);
+    let start = range.start.min(end);
+    let mut bytes = file.bytes[start as usize..end as usize].to_vec();
+    let patched = match state.project_file(path) {
+        Some(name) => state.project.apply_patches(&name, start, &mut bytes),
+        None => Vec::new(),
+    };
+    (bytes, patched)
+}
+
+pub fn register(registry: &mut RpcRegistry, project_state: Signal<ProjectState>) {
+    let files: OpenFiles = Arc::default();
+
+    // (re)reads the file, so changes on disk are picked up
+    let open = files.clone();
+    registry.register_job("hex.open", move |params: FileParams| {
+        let open = open.clone();
+        // the project lives on the UI thread, so look the file up before the job starts
+        let project_file = project_state.read().project_file(&params.path);
+        Ok(move |context: &JobContext| {
+            context.progress(0.0, &format!("loading {}", params.path));
+            let file = Arc::new(load(&params.path)?);
+            open.lock().unwrap().insert(params.path.clone(), file.clone());
+            Ok(HexInfo {
+                size: file.bytes.len() as u64,
+                format: file.format.clone(),
+                project_file,
+            })
+        })
+    });
+
+    let close = files.clone();
+    registry.register("hex.close", move |params: FileParams| {
+        Ok::<_, String>(close.lock().unwrap().remove(&params.path).is_some())
+    });
+
+    let read = files.clone();
+    registry.register("hex.read", move |params: PageParams| {
+        let file = file(&read, &params.path)?;
+        let range = Range::new(params.offset, params.offset.saturating_add(params.length.min(MAX_PAGE)));
+        let (bytes, patched) = window(&file, &project_state.read(), &params.path, &range);
+        Ok::<_, String>(HexPage {
+            offset: params.offset,
+            bytes,
+            patched: patched.into_iter().map(|range| Span { start: range.start, end: range.end }).collect(),
+        })
+    });
+
+    let structures = files.clone();
+    registry.register("hex.structures", move |params: PageParams| {
+        let file = file(&structures, &params.path)?;
+        let range = Range::new(params.offset, params.offset.saturating_add(params.length.min(MAX_PAGE)));
+        Ok::<_, String>(file.structures.overlapping(&range).into_iter().map(|(range, structure)| StructureInfo {
+            start: range.start,
+            end: range.end,
+            kind: structure.kind,
+            text: structure.text.clone(),
+            depth: structure.depth,
+        }).collect::<Vec<_>>())
+    });
+
+    let inspected = files.clone();
+    registry.register("hex.inspect", move |params: InspectParams| {
+        let file = file(&inspected, &params.path)?;
+        let range = Range::new(params.offset, params.offset.saturating_add(INSPECT_WINDOW));
+        let (bytes, _) = window(&file, &project_state.read(), &params.path, &range);
+        let endian = match params.endian {
+            ByteOrder::Little => Endian::Little,
+            ByteOrder::Big => Endian::Big,
+        };
+        Ok::<_, String>(inspect(&bytes, endian).into_iter().map(|inspection| InspectionInfo {
+            name: inspection.name,
+            value: inspection.value,
+            size: inspection.size,
+        }).collect::<Vec<_>>())
+    });
+
+    registry.register("hex.write", move |params: WriteParams| {
+        let file = file(&files, &params.path)?;
+        if params.bytes.is_empty() || params.offset.saturating_add(params.bytes.len() as u64) > file.bytes.len() as u64 {
+            return Err(format!("0x{:x}: edit does not fit in the file", params.offset));
+        }
+
+        // handlers are `Fn`, so write through a copy of the signal
+        let mut state = project_state;
+        let name = state.read().project_file(&params.path)
+            .ok_or_else(|| format!("{} is not part of the project, add it to edit it", params.path))?;
+        let description = state.write().execute(Command::Patch {
+            file: name,
+            offset: params.offset,
+            before: None,
+            after: Some(params.bytes),
+        });
+        Ok(WriteResult { description })
+    });
+}
@@ -0,0 +1,19 @@
+// Purpose: lets the interface see the background jobs and cancel them.
+
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::jobs::JobId;
+use super::RpcRegistry;
+
+#[derive(Debug, Deserialize)]
+pub struct CancelParams {
+    pub id: JobId,
+}
+
+pub fn register(registry: &mut RpcRegistry) {
+    let jobs = registry.jobs().clone();
+    registry.register("jobs.list", move |_: IgnoredAny| Ok::<_, String>(jobs.list()));
+
+    let jobs = registry.jobs().clone();
+    registry.register("jobs.cancel", move |params: CancelParams| Ok::<_, String>(jobs.cancel(params.id)));
+}
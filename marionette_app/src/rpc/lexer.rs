@@ -0,0 +1,37 @@
+// Purpose: syntax highlighting for the text editor widget.
+
+use marionette_util::lexer_service::LexerService;
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobContext;
+use super::RpcRegistry;
+
+#[derive(Debug, Deserialize)]
+pub struct LexParams {
+    /// "lua", "python" or "general"; anything else gives no tokens.
+    pub lexer: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Token {
+    pub token: String,
+    pub slice: String,
+    pub span: Span,
+}
+
+fn lex(params: LexParams) -> Result<Vec<Token>, String> {
+    let tokens = LexerService::lex(params.text, params.lexer)?;
+    serde_json::from_str(&tokens).map_err(|error| error.to_string())
+}
+
+pub fn register(registry: &mut RpcRegistry) {
+    // large files take a while to lex, so it never runs on the UI thread
+    registry.register_job("lex", |params: LexParams| Ok(move |_: &JobContext| lex(params)));
+}
@@ -0,0 +1,207 @@
+// Purpose: analysis of Lua 5.1 chunks on disk. Files are parsed once by `lua.open` and kept
+// until `lua.close`, so widgets asking for one function after another do not reparse them.
+// Everything that parses or analyses runs as a job.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use marionette_core::byte_stream::{ByteStream, ByteStreamRead};
+use marionette_lua::{cfg::{edge_kind, get_graph}, lua_binary::LuaBinary};
+use serde::{Deserialize, Serialize};
+
+use crate::jobs::JobContext;
+use super::RpcRegistry;
+
+type OpenFiles = Arc<Mutex<HashMap<String, Arc<LuaBinary>>>>;
+
+#[derive(Debug, Deserialize)]
+pub struct FileParams {
+    pub path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FunctionParams {
+    pub path: String,
+    pub function: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DecompileParams {
+    pub path: String,
+    /// Only decompile the function with this id.
+    #[serde(default)]
+    pub function: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FunctionInfo {
+    pub id: usize,
+    pub name: String,
+    pub first_line: u64,
+    pub last_line: u64,
+    pub parameters: u8,
+    pub instructions: usize,
+    pub constants: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecompileResult {
+    pub functions: usize,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Token {
+    pub text: String,
+    /// "pc", "opcode", "register", "constant", "upvalue", "number", "target" or "comment".
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CfgBlock {
+    /// The pc of the block's first instruction.
+    pub id: usize,
+    /// Offsets of the block's bytes in the file.
+    pub start: u64,
+    pub end: u64,
+    pub instructions: Vec<Vec<Token>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CfgEdge {
+    pub source: usize,
+    pub target: usize,
+    /// "unconditional", "true" or "false".
+    pub kind: &'static str,
+    /// Whether the edge goes back to an earlier pc, i.e. closes a loop.
+    pub back: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CfgResult {
+    pub function: usize,
+    pub entry: Option<usize>,
+    /// Ordered by pc, so the entry block comes first.
+    pub blocks: Vec<CfgBlock>,
+    pub edges: Vec<CfgEdge>,
+}
+
+fn read(path: &str) -> Result<LuaBinary, String> {
+    let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    LuaBinary::read(&mut ByteStream::new(bytes)).map_err(|error| format!("{}: {}", path, error))
+}
+
+/// The open file at `path`, opening it first if needed.
+fn binary(files: &OpenFiles, path: &str) -> Result<Arc<LuaBinary>, String> {
+    if let Some(binary) = files.lock().unwrap().get(path) {
+        return Ok(binary.clone());
+    }
+    // parse without holding the lock, so other jobs can use the files already open
+    let binary = Arc::new(read(path)?);
+    files.lock().unwrap().insert(path.to_string(), binary.clone());
+    Ok(binary)
+}
+
+fn functions(binary: &LuaBinary) -> Vec<FunctionInfo> {
+    binary.functions.iter().enumerate().map(|(id, function)| FunctionInfo {
+        id,
+        name: function.name.trim_end_matches('\0').to_string(),
+        first_line: function.first_line,
+        last_line: function.last_line,
+        parameters: function.num_parameters,
+        instructions: function.code.len(),
+        constants: function.constants.len(),
+    }).collect()
+}
+
+fn cfg(binary: &LuaBinary, id: usize) -> Result<CfgResult, String> {
+    let function = binary.functions.get(id).ok_or_else(|| format!("no function {}", id))?;
+    let (graph, entry) = get_graph(function.clone())?;
+
+    let mut blocks: Vec<CfgBlock> = graph.node_indices().map(|node| {
+        let block = &graph[node];
+        let instructions = (block.id..block.id + block.instructions.len())
+            .map(|pc| function.listing(pc).unwrap_or_default().into_iter()
+                .map(|token| Token { text: token.text, kind: token.kind.name() })
+                .collect())
+            .collect();
+        let start = block.instructions.first().map(|instruction| instruction.range.start).unwrap_or_default();
+        let end = block.instructions.last().map(|instruction| instruction.range.end).unwrap_or_default();
+        CfgBlock { id: block.id, start, end, instructions }
+    }).collect();
+    blocks.sort_by_key(|block| block.id);
+
+    let edges = graph.edge_indices().filter_map(|edge| {
+        let (source, target) = graph.edge_endpoints(edge)?;
+        let (source, target) = (&graph[source], &graph[target]);
+        Some(CfgEdge {
+            source: source.id,
+            target: target.id,
+            kind: edge_kind(source, target).name(),
+            back: target.id <= source.id,
+        })
+    }).collect();
+
+    Ok(CfgResult { function: id, entry: entry.map(|node| graph[node].id), blocks, edges })
+}
+
+/// Every function's pseudocode, like `LuaBinary::decompile`, reporting progress as it goes.
+fn decompile(binary: &LuaBinary, context: &JobContext) -> Result<String, String> {
+    let count = binary.functions.len();
+    let mut code = Vec::with_capacity(count);
+    for id in 0..count {
+        context.check()?;
+        context.progress(id as f32 / count as f32, &format!("function {} of {}", id + 1, count));
+        code.extend(binary.decompile_function(id));
+    }
+    Ok(code.join("\n"))
+}
+
+pub fn register(registry: &mut RpcRegistry) {
+    let files: OpenFiles = Arc::default();
+
+    // (re)reads the file, so changes on disk are picked up
+    let open = files.clone();
+    registry.register_job("lua.open", move |params: FileParams| {
+        let open = open.clone();
+        Ok(move |context: &JobContext| {
+            context.progress(0.0, &format!("parsing {}", params.path));
+            open.lock().unwrap().remove(&params.path);
+            let binary = binary(&open, &params.path)?;
+            context.log(&format!("{}: {} functions", params.path, binary.functions.len()));
+            Ok(functions(&binary))
+        })
+    });
+
+    let close = files.clone();
+    registry.register("lua.close", move |params: FileParams| {
+        Ok::<_, String>(close.lock().unwrap().remove(&params.path).is_some())
+    });
+
+    let list = files.clone();
+    registry.register_job("lua.functions", move |params: FileParams| {
+        let list = list.clone();
+        Ok(move |_: &JobContext| binary(&list, &params.path).map(|binary| functions(&binary)))
+    });
+
+    let graph = files.clone();
+    registry.register_job("lua.cfg", move |params: FunctionParams| {
+        let graph = graph.clone();
+        Ok(move |_: &JobContext| {
+            let binary = binary(&graph, &params.path)?;
+            cfg(&binary, params.function)
+        })
+    });
+
+    registry.register_job("lua.decompile", move |params: DecompileParams| {
+        let files = files.clone();
+        Ok(move |context: &JobContext| {
+            let binary = binary(&files, &params.path)?;
+            let code = match params.function {
+                Some(id) => binary.decompile_function(id).ok_or_else(|| format!("no function {}", id))?,
+                None => decompile(&binary, context)?,
+            };
+            Ok(DecompileResult { functions: binary.functions.len(), code })
+        })
+    });
+}
@@ -0,0 +1,28 @@
+// Purpose: lets the interface list the Python plugins loaded at startup.
+
+use dioxus::prelude::*;
+use serde::{de::IgnoredAny, Serialize};
+
+use crate::plugin::Plugin;
+use super::RpcRegistry;
+
+#[derive(Debug, Serialize)]
+pub struct PluginInfo {
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    pub description: String,
+    pub enabled: bool,
+}
+
+pub fn register(registry: &mut RpcRegistry, plugins: Signal<Vec<Plugin>>) {
+    registry.register("plugins.list", move |_: IgnoredAny| {
+        Ok::<_, String>(plugins.read().iter().map(|plugin| PluginInfo {
+            name: plugin.name.clone(),
+            version: plugin.version.clone(),
+            author: plugin.author.clone(),
+            description: plugin.description.clone(),
+            enabled: plugin.enabled,
+        }).collect::<Vec<_>>())
+    });
+}
@@ -0,0 +1,158 @@
+// Purpose: the open project, for interface code that needs to read or change it.
+
+use dioxus::prelude::*;
+use marionette_core::mproj::{history::Command, sections::{Bookmark, ColorTag, Comment, Rename}};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use crate::states::project::ProjectState;
+use super::RpcRegistry;
+
+#[derive(Debug, Serialize)]
+pub struct ProjectInfo {
+    pub name: String,
+    pub path: Option<String>,
+    pub files: Vec<String>,
+    /// Where `files` are on disk, in the same order, for widgets that open them by path.
+    pub paths: Vec<String>,
+    pub can_undo: bool,
+    pub can_redo: bool,
+    /// Whether there are changes that are not saved yet.
+    pub dirty: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryResult {
+    /// What was undone or redone, None if there was nothing to do.
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspaceParams {
+    /// The tool's layout as JSON, stored in the project as it is.
+    pub layout: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileParams {
+    pub path: String,
+}
+
+/// An annotation of a file, None removes it. It becomes a `Command` so it can be undone.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Annotation {
+    Rename { address: u64, register: Option<u32>, name: Option<String> },
+    Comment { address: u64, text: Option<String> },
+    Bookmark { address: u64, label: Option<String> },
+    ColorTag { function: u64, block: u64, color: Option<u32> },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnnotateParams {
+    /// The file on disk, as widgets know it.
+    pub path: String,
+    pub annotation: Annotation,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnnotateResult {
+    pub description: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Annotations {
+    pub renames: Vec<Rename>,
+    pub comments: Vec<Comment>,
+    pub bookmarks: Vec<Bookmark>,
+    pub color_tags: Vec<ColorTag>,
+}
+
+/// The tool keeps one layout per project.
+const WORKSPACE: &str = "default";
+
+fn info(state: &ProjectState) -> ProjectInfo {
+    let base = state.base();
+    ProjectInfo {
+        name: state.project.project_name.clone(),
+        path: state.path.as_ref().map(|path| path.display().to_string()),
+        files: state.project.project_files.iter().map(|file| file.path.clone()).collect(),
+        paths: state.project.project_files.iter().map(|file| file.resolve(&base).display().to_string()).collect(),
+        can_undo: state.project.can_undo(),
+        can_redo: state.project.can_redo(),
+        dirty: state.dirty,
+    }
+}
+
+pub fn register(registry: &mut RpcRegistry, project_state: Signal<ProjectState>) {
+    registry.register("project.info", move |_: IgnoredAny| Ok::<_, String>(info(&project_state.read())));
+
+    // handlers are `Fn`, so write through a copy of the signal
+    registry.register("project.undo", move |_: IgnoredAny| {
+        let mut state = project_state;
+        let description = state.write().undo();
+        Ok::<_, String>(HistoryResult { description })
+    });
+
+    registry.register("project.redo", move |_: IgnoredAny| {
+        let mut state = project_state;
+        let description = state.write().redo();
+        Ok::<_, String>(HistoryResult { description })
+    });
+
+    registry.register("project.annotate", move |params: AnnotateParams| {
+        let mut state = project_state;
+        let file = state.read().project_file(&params.path)
+            .ok_or_else(|| format!("{} is not part of the project, add it to annotate it", params.path))?;
+        // `before` is filled in by the history
+        let command = match params.annotation {
+            Annotation::Rename { address, register, name } => Command::Rename { file, address, register, before: None, after: name },
+            Annotation::Comment { address, text } => Command::Comment { file, address, before: None, after: text },
+            Annotation::Bookmark { address, label } => Command::Bookmark { file, address, before: None, after: label },
+            Annotation::ColorTag { function, block, color } => Command::ColorTag { file, function, block, before: None, after: color },
+        };
+        let description = state.write().execute(command);
+        Ok::<_, String>(AnnotateResult { description })
+    });
+
+    // everything annotating the file on disk at `path`, nothing for files outside the project
+    registry.register("project.annotations", move |params: FileParams| {
+        let state = project_state.read();
+        let Some(file) = state.project_file(&params.path) else {
+            return Ok::<_, String>(Annotations::default());
+        };
+        let project = &state.project;
+        Ok(Annotations {
+            renames: project.renames.iter().filter(|rename| rename.file == file).cloned().collect(),
+            comments: project.comments.iter().filter(|comment| comment.file == file).cloned().collect(),
+            bookmarks: project.bookmarks.iter().filter(|bookmark| bookmark.file == file).cloned().collect(),
+            color_tags: project.color_tags.iter().filter(|tag| tag.file == file).cloned().collect(),
+        })
+    });
+
+    registry.register("project.save", move |_: IgnoredAny| {
+        let mut state = project_state;
+        let result = state.write().save();
+        result
+    });
+
+    // the layout the tool saved for the project, None if it never saved one
+    registry.register("project.workspace", move |_: IgnoredAny| {
+        Ok::<_, String>(project_state.read().project.workspace(WORKSPACE).map(str::to_string))
+    });
+
+    // layouts are not part of the history, see `ProjectState::save_workspace`
+    registry.register("project.set_workspace", move |params: WorkspaceParams| {
+        let mut state = project_state;
+        let saved = state.write().save_workspace(WORKSPACE, params.layout);
+        saved
+    });
+
+    // the bytes of a project file, from disk or from its embedded copy
+    registry.register("project.read_file", move |params: FileParams| {
+        let state = project_state.read();
+        let file = state.project.project_files.iter()
+            .find(|file| file.path == params.path)
+            .ok_or_else(|| format!("{} is not part of the project", params.path))?;
+        file.load(&state.base()).map_err(|error| error.to_string())
+    });
+}
@@ -0,0 +1,11 @@
+// Purpose: lets the interface read the user's preferences, e.g. the text editor's lexer.
+
+use dioxus::prelude::*;
+use serde::de::IgnoredAny;
+
+use crate::states::settings::SettingsStore;
+use super::RpcRegistry;
+
+pub fn register(registry: &mut RpcRegistry, settings: Signal<SettingsStore>) {
+    registry.register("settings.get", move |_: IgnoredAny| Ok::<_, String>(settings.read().settings.clone()));
+}
@@ -1,13 +1,17 @@
 #![allow(non_snake_case)]
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 use dioxus::prelude::*;
 use dioxus::desktop::{use_window, LogicalSize, DesktopService};
 use dioxus_html_macro::*;
+use futures::StreamExt;
 
 use crate::on_result;
 use crate::states::explorer::{ExplorerState, FileEntry};
+use crate::states::project::ProjectState;
 use crate::states::selector::SelectorState;
+use crate::states::settings::{RecentProject, Settings, SettingsStore};
 
 use crate::msgbox::{Msg, MsgButtons, MsgResult, MsgType};
 
@@ -54,115 +58,133 @@ pub fn icon_info_from_file(file: FileEntry) -> (String, String, String, String,
     (String::from("NerdFontsSymbols Nerd Font"), String::from("󰈤"), String::from("#bebebe"), String::from("1.2em"), String::from(""))
 }
 
-pub fn projects_container() -> Element {
+/// Changes the recent projects. Failing to save them only loses the change at the next launch,
+/// which the user is told about.
+fn update_recent(window: &Rc<DesktopService>, mut settings: Signal<SettingsStore>, change: impl FnOnce(&mut Settings)) {
+    if let Err(error) = settings.write().update(change) {
+        Msg::new(error.to_string(), "Recent projects not saved".to_string(), MsgType::Warning, MsgButtons::Ok).display(window);
+    }
+}
+
+/// Opens the project at `path` and switches to the tool, telling the user about anything
+/// that did not load cleanly.
+pub fn open_project(window: &Rc<DesktopService>, navigator: Navigator, path: &Path, settings: Signal<SettingsStore>, mut project_state: Signal<ProjectState>) {
+    match ProjectState::open(path) {
+        Ok((state, warnings)) => {
+            project_state.set(state);
+            update_recent(window, settings, |settings| settings.remember_project(path));
+            navigator.push(crate::Route::Tool {});
+
+            if !warnings.is_empty() {
+                Msg::new(warnings.join("\n"), "Project opened with warnings".to_string(), MsgType::Warning, MsgButtons::Ok).display(window);
+            }
+        }
+        Err(error) => Msg::new(error, "Cannot open project".to_string(), MsgType::Error, MsgButtons::Ok).display(window),
+    }
+}
+
+/// Where a new analysis of `file` is saved: next to it, named after it, without replacing
+/// an existing project.
+fn new_project_path(file: &Path) -> PathBuf {
+    let stem = file.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_else(|| "project".to_string());
+    let dir = file.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut path = dir.join(format!("{}.mproj", stem));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{}-{}.mproj", stem, n));
+        n += 1;
+    }
+    path
+}
+
+/// Creates a project over `files`, saves it next to the first of them and switches to the tool.
+pub fn new_analysis(window: &Rc<DesktopService>, navigator: Navigator, files: &[PathBuf], settings: Signal<SettingsStore>, mut project_state: Signal<ProjectState>) {
+    let Some(first) = files.first() else {
+        Msg::new("Select the files to analyze first.".to_string(), "New analysis".to_string(), MsgType::Info, MsgButtons::Ok).display(window);
+        return;
+    };
+
+    let path = new_project_path(first);
+    let name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    match ProjectState::create(&name, files, &path) {
+        Ok(state) => {
+            project_state.set(state);
+            update_recent(window, settings, |settings| settings.remember_project(&path));
+            navigator.push(crate::Route::Tool {});
+        }
+        Err(error) => Msg::new(error, "Cannot create project".to_string(), MsgType::Error, MsgButtons::Ok).display(window),
+    }
+}
+
+#[component]
+fn RecentProjectEntry(project: RecentProject) -> Element {
     let window = use_window();
-    let router = router();
-    let selector_state = use_context::<Signal<SelectorState>>();
-
-    /*let mut msgbox = Msg::new(
-        format!("Testing testing 123 awesome sauce!"),
-        "Open Project".to_string(),
-        MsgType::Warning,
-        MsgButtons::YesNo
-    );
-    msgbox.display(&window);
-
-    on_result!(msgbox, value, {
-        if value == MsgResult::Yes {
-            let mut msgbox = Msg::new(
-                format!(
-                    "lol nice",
-                ),
-                "Open Project".to_string(),
-                MsgType::Info,
-                MsgButtons::YesNo
-            );
-            msgbox.display(&window);
+    let navigator = use_navigator();
+    let settings = use_context::<Signal<SettingsStore>>();
+    let project_state = use_context::<Signal<ProjectState>>();
+
+    let missing = !project.path.exists();
+    let pinned = project.pinned;
+    let name = project.path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let (path, pin_path) = (project.path.clone(), project.path.clone());
+    let pin_window = window.clone();
+
+    rsx! {
+        div {
+            class: if missing { "project_container project_missing" } else { "project_container" },
+            title: "{project.path.display()}",
+            onclick: move |_| {
+                if !missing {
+                    open_project(&window, navigator, &path, settings, project_state);
+                    return;
+                }
+
+                let mut msgbox = Msg::new(
+                    format!("{} no longer exists.\nRemove it from the recent projects?", path.display()),
+                    "Project missing".to_string(),
+                    MsgType::Warning,
+                    MsgButtons::YesNo
+                );
+                msgbox.display(&window);
+
+                let (window, path) = (window.clone(), path.clone());
+                on_result!(msgbox, value, {
+                    if value == MsgResult::Yes {
+                        update_recent(&window, settings, |settings| settings.forget_project(&path));
+                    }
+                });
+            },
+
+            span {
+                class: if pinned { "project_pin project_pinned" } else { "project_pin" },
+                title: if pinned { "Unpin" } else { "Pin" },
+                onclick: move |evt| {
+                    evt.stop_propagation();
+                    update_recent(&pin_window, settings, |settings| settings.pin_project(&pin_path, !pinned));
+                },
+                if pinned { "󰐃" } else { "󰐄" }
+            }
+            span { class: "project_icon", if missing { "󰈅" } else { "" } }
+            p { id: "project_name", "{name}" }
         }
-    });*/
-
-    let projects = html!(
-        <div class="projects_grid_container">
-            {
-                /*database_controller.read().get_project_databases().iter().map(|proj| {
-                    let db = proj.clone();
-                    let path = proj.database.path.clone();
-                    html!(
-                        <div class="project_container" onclick = {move |_| {
-                            let active_controller = database_controller.read();
-                            let compatibility_report = active_controller.checker.check_compatibility(db.clone().database);
-                            if !compatibility_report.compatible() {
-                                if compatibility_report.has_errors() {
-                                    Msg::new(
-                                        format!(
-                                            "{}",
-                                            compatibility_report
-                                        ),
-                                        "Cannot open Project".to_string(),
-                                        MsgType::Error,
-                                        MsgButtons::Ok,
-                                        MsgSize::Fit
-                                    ).display(window);
-                                } else {
-                                    let mut msgbox = Msg::new(
-                                        format!(
-                                            "{}\nAre you sure you want to open the project?",
-                                            compatibility_report
-                                        ),
-                                        "Open Project".to_string(),
-                                        MsgType::Warning,
-                                        MsgButtons::YesNo,
-                                        MsgSize::Fit
-                                    );
-                                    msgbox.display(window);
-
-                                    let path_str = path.clone();
-                                    to_owned![selector_state];
-                                    to_owned![router];
-                                    on_result!(cx, msgbox, value, {
-                                        if value == MsgResult::Yes {
-                                            let mut selector_state = selector_state.write();
-                                            selector_state.selected_path = path_str;
-                                            router.navigate_to("/tool");
-                                        }
-                                    });
-                                }
-                            } else {
-                                let mut msgbox = Msg::new(
-                                    format!(
-                                        "{}\nAre you sure you want to open the project?",
-                                        compatibility_report
-                                    ),
-                                    "Open Project".to_string(),
-                                    MsgType::Info,
-                                    MsgButtons::YesNo,
-                                    MsgSize::Fit
-                                );
-                                msgbox.display(window);
-
-                                let path_str = path.clone();
-                                to_owned![selector_state];
-                                to_owned![router];
-                                on_result!(cx, msgbox, value, {
-                                    if value == MsgResult::Yes {
-                                        let mut selector_state = selector_state.write();
-                                        selector_state.selected_path = path_str;
-                                        router.navigate_to("/tool");
-                                    }
-                                });
-                            }
-                        }}>
-                        <span class="project_icon">""</span>
-                        <p id="project_name">{ proj.database.name.clone() }</p>
-                    </div>
-                )})*/
-                html!()
+    }
+}
+
+pub fn projects_container() -> Element {
+    let settings = use_context::<Signal<SettingsStore>>();
+    let projects = settings.read().settings.recent_projects.clone();
+
+    rsx! {
+        div {
+            class: "projects_grid_container",
+            if projects.is_empty() {
+                p { class: "projects_empty", "No recent projects. Open or create one from the Files tab." }
             }
-        </div>
-    );
-    
-    rsx!{
-        {projects}
+            for project in projects {
+                RecentProjectEntry { key: "{project.path.display()}", project: project.clone() }
+            }
+        }
     }
 }
 
@@ -342,18 +364,34 @@ pub fn ExplorerFolders() -> Element {
 pub struct ExplorerFileProps {
     pub icon_info: (String, String, String, String, String),
     pub file_name: String,
+    pub file_path: String,
     pub file_size: String,
     pub date_modified: String
 }
 
 pub fn ExplorerFile(props: ExplorerFileProps) -> Element {
     let mut selector_state = use_context::<Signal<SelectorState>>();
+    let settings = use_context::<Signal<SettingsStore>>();
+    let project_state = use_context::<Signal<ProjectState>>();
+    let window = use_window();
+    let navigator = use_navigator();
+    let selected = selector_state.read().selected_files.contains(&PathBuf::from(&props.file_path));
+    let file_path = props.file_path.clone();
+
     rsx! {
         div {
             id: "file_entry",
-            onclick: move |_| {
-                let mut selector_state = selector_state.write();
-                selector_state.selected_path = props.file_name.clone();
+            class: if selected { "file_entry_selected" } else { "" },
+            // ctrl-click picks several files for one analysis
+            onclick: move |evt| {
+                let toggle = evt.modifiers().ctrl() || evt.modifiers().meta();
+                selector_state.write().select(Path::new(&props.file_path), toggle);
+            },
+            ondoubleclick: move |_| {
+                let path = PathBuf::from(&file_path);
+                if path.extension().is_some_and(|extension| extension == "mproj") {
+                    open_project(&window, navigator, &path, settings, project_state);
+                }
             },
 
             span {
@@ -384,6 +422,7 @@ pub fn ExplorerFiles() -> Element {
                 ExplorerFile {
                     icon_info: icon_info,
                     file_name: file.file_name.clone(),
+                    file_path: file_path,
                     file_size: file.file_size.clone(),
                     date_modified: file.date_modified.clone()
                 }
@@ -479,17 +518,20 @@ pub fn BottomBarButton(props: BottomBarButtonProps) -> Element {
 
 pub fn bottom_bar_container() -> Element {
     let mut selector_state = use_context::<Signal<SelectorState>>();
+    let settings = use_context::<Signal<SettingsStore>>();
+    let project_state = use_context::<Signal<ProjectState>>();
     let navigator = use_navigator();
+    let window = use_window();
+    let new_window = window.clone();
 
     rsx! { 
         div {
             class: "bottom_bar",
             BottomBarInput {
-                text: "Path to file",
-                value: selector_state.read().selected_path.clone(),
+                text: "Project to open, or ctrl-click the files to analyze",
+                value: selector_state.read().selected_text(),
                 oninput: move |evt: FormEvent| {
-                    let mut selector_state = selector_state.write();
-                    selector_state.selected_path = evt.value().clone();
+                    selector_state.write().type_path(&evt.value());
                 }
             }
             BottomBarButton {
@@ -499,9 +541,24 @@ pub fn bottom_bar_container() -> Element {
                 }
             }
             BottomBarButton {
-                text: "Analyze",
+                text: "Open",
+                onclick: move |_| {
+                    match selector_state.read().selected_files.as_slice() {
+                        [path] => open_project(&window, navigator, path, settings, project_state),
+                        _ => Msg::new(
+                            "Select one project to open.".to_string(),
+                            "Open project".to_string(),
+                            MsgType::Info,
+                            MsgButtons::Ok
+                        ).display(&window),
+                    }
+                }
+            }
+            BottomBarButton {
+                text: "New analysis",
                 onclick: move |_| {
-                    navigator.push(crate::Route::Tool {});
+                    let files = selector_state.read().selected_files.clone();
+                    new_analysis(&new_window, navigator, &files, settings, project_state);
                 }
             }
         }
@@ -0,0 +1,276 @@
+// Purpose: the page for editing the settings. Every change is saved right away; components reading
+// the settings signal pick it up without a restart, except plugins, which are started at launch.
+
+use dioxus::prelude::*;
+
+use crate::plugin::Plugin;
+use crate::states::settings::{Settings, SettingsStore, Theme};
+use crate::updates::{ReleaseSource, UpdateSettings};
+
+const LEXERS: [&str; 3] = ["lua", "python", "general"];
+
+fn update(mut settings: Signal<SettingsStore>, mut error: Signal<Option<String>>, change: impl FnOnce(&mut Settings)) {
+    let saved = settings.write().update(change);
+    error.set(saved.err().map(|error| error.to_string()));
+}
+
+#[component]
+fn Section(title: String, children: Element) -> Element {
+    rsx! {
+        div {
+            class: "settings-section",
+            h2 { class: "settings-section-title", "{title}" }
+            {children}
+        }
+    }
+}
+
+#[component]
+fn Field(label: String, children: Element) -> Element {
+    rsx! {
+        label {
+            class: "settings-field",
+            span { class: "settings-label", "{label}" }
+            {children}
+        }
+    }
+}
+
+#[component]
+pub fn SettingsPage() -> Element {
+    let settings = use_context::<Signal<SettingsStore>>();
+    let plugins = use_context::<Signal<Vec<Plugin>>>();
+    let error = use_signal(|| None::<String>);
+
+    let current = settings.read().settings.clone();
+    let load_error = settings.read().load_error.as_ref().map(|error| format!("{}. The defaults are used and changes are not saved.", error));
+    let path = settings.read().path().map(|path| path.display().to_string()).unwrap_or_else(|| "not saved".to_string());
+
+    rsx! {
+        style { {include_str!("resources/styles/settings/settings.css")} }
+        div {
+            id: "settings",
+            div {
+                id: "settings-header",
+                span {
+                    id: "settings-back",
+                    onclick: move |_| { use_navigator().push(crate::Route::Welcome {}); },
+                    "󰁍"
+                }
+                h1 { "Settings" }
+                span { id: "settings-path", "{path}" }
+            }
+            if let Some(message) = load_error {
+                p { class: "settings-error", "{message}" }
+            }
+            if let Some(message) = error.read().as_ref() {
+                p { class: "settings-error", "{message}" }
+            }
+
+            Section {
+                title: "Appearance",
+                Field {
+                    label: "Theme",
+                    select {
+                        onchange: move |event| {
+                            let theme = if event.value() == "light" { Theme::Light } else { Theme::Dark };
+                            update(settings, error, |settings| settings.theme = theme);
+                        },
+                        option { value: "dark", selected: current.theme == Theme::Dark, "Dark" }
+                        option { value: "light", selected: current.theme == Theme::Light, "Light" }
+                    }
+                }
+                Field {
+                    label: "Tool window width",
+                    input {
+                        r#type: "number",
+                        min: "450",
+                        value: "{current.window.width}",
+                        onchange: move |event| {
+                            if let Ok(width) = event.value().parse::<u32>() {
+                                update(settings, error, |settings| settings.window.width = width.max(450));
+                            }
+                        }
+                    }
+                }
+                Field {
+                    label: "Tool window height",
+                    input {
+                        r#type: "number",
+                        min: "300",
+                        value: "{current.window.height}",
+                        onchange: move |event| {
+                            if let Ok(height) = event.value().parse::<u32>() {
+                                update(settings, error, |settings| settings.window.height = height.max(300));
+                            }
+                        }
+                    }
+                }
+            }
+
+            Section {
+                title: "Editor",
+                Field {
+                    label: "Default lexer",
+                    select {
+                        onchange: move |event| {
+                            let lexer = event.value();
+                            update(settings, error, |settings| settings.editor.default_lexer = lexer);
+                        },
+                        for lexer in LEXERS {
+                            option { value: lexer, selected: current.editor.default_lexer == lexer, "{lexer}" }
+                        }
+                    }
+                }
+            }
+
+            Section {
+                title: "Plugins",
+                if plugins.read().is_empty() {
+                    p { class: "settings-note", "No plugins are installed." }
+                }
+                for plugin in plugins.read().iter().cloned() {
+                    Field {
+                        label: "{plugin.name} {plugin.version}",
+                        input {
+                            r#type: "checkbox",
+                            checked: current.plugins.enabled.get(&plugin.name).copied().unwrap_or(plugin.enabled),
+                            onchange: move |event| {
+                                let name = plugin.name.clone();
+                                let enabled = event.checked();
+                                update(settings, error, |settings| { settings.plugins.enabled.insert(name, enabled); });
+                            }
+                        }
+                    }
+                }
+                p { class: "settings-note", "Plugins are started at launch, changes apply after a restart." }
+            }
+
+            Section {
+                title: "Updates",
+                Field {
+                    label: "Check for updates",
+                    input {
+                        r#type: "checkbox",
+                        checked: current.updates.enabled,
+                        onchange: move |event| {
+                            let enabled = event.checked();
+                            update(settings, error, |settings| settings.updates.enabled = enabled);
+                        }
+                    }
+                }
+                Field {
+                    label: "Timeout (seconds)",
+                    input {
+                        r#type: "number",
+                        min: "1",
+                        value: "{current.updates.timeout_secs}",
+                        onchange: move |event| {
+                            if let Ok(timeout) = event.value().parse::<u64>() {
+                                update(settings, error, |settings| settings.updates.timeout_secs = timeout.max(1));
+                            }
+                        }
+                    }
+                }
+                Field {
+                    label: "Check every (hours)",
+                    input {
+                        r#type: "number",
+                        min: "0",
+                        value: "{current.updates.interval_hours}",
+                        onchange: move |event| {
+                            if let Ok(interval) = event.value().parse::<u64>() {
+                                update(settings, error, |settings| settings.updates.interval_hours = interval);
+                            }
+                        }
+                    }
+                }
+                Field {
+                    label: "Release source",
+                    select {
+                        onchange: move |event| {
+                            let source = match event.value().as_str() {
+                                "file" => ReleaseSource::File { path: Default::default() },
+                                _ => UpdateSettings::default().source,
+                            };
+                            update(settings, error, |settings| settings.updates.source = source);
+                        },
+                        option { value: "github", selected: matches!(current.updates.source, ReleaseSource::GitHub { .. }), "GitHub" }
+                        option { value: "file", selected: matches!(current.updates.source, ReleaseSource::File { .. }), "Local file" }
+                    }
+                }
+                match current.updates.source.clone() {
+                    ReleaseSource::GitHub { owner, repo, api } => rsx! {
+                        Field {
+                            label: "Repository",
+                            input {
+                                value: "{owner}/{repo}",
+                                onchange: move |event| {
+                                    let value = event.value();
+                                    if let Some((owner, repo)) = value.split_once('/') {
+                                        let (owner, repo) = (owner.trim().to_string(), repo.trim().to_string());
+                                        update(settings, error, |settings| {
+                                            if let ReleaseSource::GitHub { owner: o, repo: r, .. } = &mut settings.updates.source {
+                                                (*o, *r) = (owner, repo);
+                                            }
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                        Field {
+                            label: "API mirror",
+                            input {
+                                placeholder: "https://api.github.com",
+                                value: "{api.unwrap_or_default()}",
+                                onchange: move |event| {
+                                    let value = event.value().trim().to_string();
+                                    update(settings, error, |settings| {
+                                        if let ReleaseSource::GitHub { api, .. } = &mut settings.updates.source {
+                                            *api = Some(value).filter(|value| !value.is_empty());
+                                        }
+                                    });
+                                }
+                            }
+                        }
+                    },
+                    ReleaseSource::File { path } => rsx! {
+                        Field {
+                            label: "Release file",
+                            input {
+                                placeholder: "path to a JSON release description",
+                                value: "{path.display()}",
+                                onchange: move |event| {
+                                    let value = event.value();
+                                    update(settings, error, |settings| {
+                                        settings.updates.source = ReleaseSource::File { path: value.trim().into() };
+                                    });
+                                }
+                            }
+                        }
+                    },
+                }
+            }
+
+            Section {
+                title: "Recent projects",
+                if current.recent_projects.is_empty() {
+                    p { class: "settings-note", "No projects opened yet." }
+                }
+                for project in current.recent_projects.iter() {
+                    p {
+                        class: "settings-recent",
+                        if project.pinned { "󰐃 " }
+                        "{project.path.display()}"
+                    }
+                }
+                button {
+                    class: "settings-button",
+                    disabled: current.recent_projects.iter().all(|project| project.pinned),
+                    onclick: move |_| update(settings, error, |settings| settings.recent_projects.retain(|project| project.pinned)),
+                    "Clear unpinned"
+                }
+            }
+        }
+    }
+}
@@ -1,2 +1,4 @@
 pub mod explorer;
-pub mod selector;
\ No newline at end of file
+pub mod selector;
+pub mod project;
+pub mod settings;
\ No newline at end of file
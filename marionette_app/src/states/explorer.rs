@@ -1,7 +1,5 @@
 use std::fs::*;
-
-#[cfg(target_os = "windows")]
-use std::os::windows::fs::MetadataExt;
+use std::path::PathBuf;
 
 use std::time::SystemTime;
 use chrono::*;
@@ -19,12 +17,11 @@ pub struct FileEntry {
 
 #[derive(Clone)]
 pub struct ExplorerState {
-    // example: vec!["C:", "Users", "User", "Desktop", "Marionette", "Projects", "Project1", "Analysis1", "Analysis1.db"]
-    pub path_stack: Vec<String>,
+    // example: "C:\Users\User\Desktop\Marionette\Projects" or "/home/user/marionette/projects"
+    pub path: PathBuf,
 
-    // when we press the back button we need to store the path we are leaving
-    // however once we go forward again anywhere, unless its the top of the stack, we need to clear the history
-    pub path_history: Vec<String>,
+    // when we press the up button we need to store the path we are leaving so back can return to it
+    pub path_history: Vec<PathBuf>,
 
     // example: vec!["something.txt", "something_else.txt"]
     pub current_files: Vec<FileEntry>,
@@ -33,52 +30,67 @@ pub struct ExplorerState {
     pub current_folders: Vec<FileEntry>,
 }
 
+/// Whether an entry is hidden from the explorer, like Windows hides system files.
+#[cfg(target_os = "windows")]
+fn is_system(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_SYSTEM: u32 = 4;
+    metadata.file_attributes() & FILE_ATTRIBUTE_SYSTEM != 0
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_system(_metadata: &Metadata) -> bool {
+    false
+}
+
 impl ExplorerState {
     pub fn new() -> Self {
         let mut self_ = Self {
-            path_stack: vec![],
+            path: PathBuf::new(),
             current_files: vec![],
             current_folders: vec![],
             path_history: vec![],
         };
 
-        let current_working_directory = std::env::current_dir().unwrap().into_os_string().into_string().unwrap();
+        let current_working_directory = std::env::current_dir().unwrap_or_default();
         self_.set_working_directory(current_working_directory);
         self_.refresh_directory();
         self_
     }
 
     pub fn full_path(&self) -> String {
-        let mut path = String::new();
-        for (i, path_part) in self.path_stack.iter().enumerate() {
-            path.push_str(path_part);
-            if i != self.path_stack.len() - 1 {
-                path.push('\\');
-            }
-        }
-        path
+        self.path.display().to_string()
     }
 
     pub fn refresh_directory(&mut self) {
-        let path = self.full_path();
         let mut files = vec![];
         let mut folders = vec![];
 
-        for entry in read_dir(path).unwrap() {
-            let entry = entry.unwrap();
-            let file_name = entry.file_name().into_string().unwrap();
-            let file_path = entry.path().into_os_string().into_string().unwrap();
-            let metadata = entry.metadata().unwrap();
-            let date_modified = metadata.modified().unwrap();
-            let date_modified = date_modified.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs();
-            let date_modified = DateTime::from_timestamp(date_modified as i64, 0).unwrap().format("%Y-%m-%d %H:%M:%S").to_string();
+        // a missing or unreadable folder is shown as empty, the selector reports the path to the user
+        let entries = read_dir(&self.path).map(|entries| entries.filter_map(Result::ok).collect()).unwrap_or_else(|_| vec![]);
+        for entry in entries {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if is_system(&metadata) {
+                continue;
+            }
+
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let file_path = entry.path().to_string_lossy().to_string();
+            let date_modified = metadata.modified().ok()
+                .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .and_then(|modified| DateTime::from_timestamp(modified.as_secs() as i64, 0))
+                .map(|modified| modified.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default();
 
             let file_extension = if metadata.is_dir() {
                 String::from("Folder")
             } else {
                 let mut file_extension = String::from("");
                 if let Some(extension) = entry.path().extension() {
-                    file_extension = extension.to_str().unwrap().to_string();
+                    file_extension = extension.to_string_lossy().to_string();
                 }
                 file_extension
             };
@@ -94,16 +106,6 @@ impl ExplorerState {
                 format!("{:.2} GB", file_size as f64 / 1024.0 / 1024.0 / 1024.0)
             };
 
-            // get file attributes
-            if cfg!(windows) {
-                //let is_hidden = file_attributes & 2 != 0;
-                //let is_readonly = file_attributes & 1 != 0;
-                let is_system = metadata.file_attributes() & 4 != 0;
-                if is_system {
-                    continue;
-                }
-            }
-
             if metadata.is_dir() {
                 folders.push(FileEntry {
                     file_name,
@@ -130,41 +132,27 @@ impl ExplorerState {
     }
 
     pub fn go_up(&mut self) {
-        if !self.path_stack.is_empty() {
-            let mut path_part = self.path_stack.pop().unwrap();
-            while path_part.is_empty() || path_part == "\\" {
-                path_part = self.path_stack.pop().unwrap();
-            }
-            self.path_history.push(path_part);
+        if let Some(parent) = self.path.parent() {
+            let parent = parent.to_path_buf();
+            self.path_history.push(std::mem::replace(&mut self.path, parent));
             self.refresh_directory();
         }
     }
 
     pub fn go_back(&mut self) {
-        if !self.path_history.is_empty() {
-            self.path_stack.push(self.path_history.pop().unwrap());
+        if let Some(path) = self.path_history.pop() {
+            self.path = path;
             self.refresh_directory();
         }
     }
 
     pub fn go_into_dir(&mut self, dir_name: String) {
-        self.path_stack.push(dir_name);
+        self.path.push(dir_name);
         self.refresh_directory();
     }
 
-    pub fn set_working_directory(&mut self, path: String) {
-        let mut folders = vec![];
-        let mut current_folder = String::new();
-        for c in path.chars() {
-            if c == '\\' {
-                folders.push(current_folder);
-                current_folder = String::new();
-            } else {
-                current_folder.push(c);
-            }
-        }
-        folders.push(current_folder);
-        self.path_stack = folders;
+    pub fn set_working_directory(&mut self, path: PathBuf) {
+        self.path = path;
         self.path_history = vec![];
     }
 }
@@ -187,7 +175,7 @@ impl Debug for ExplorerState {
         f.debug_struct("ExplorerState")
             .field("current_files", &self.current_files)
             .field("current_folders", &self.current_folders)
-            .field("path_stack", &self.path_stack)
+            .field("path", &self.path)
             .field("path_history", &self.path_history)
             .finish()
     }
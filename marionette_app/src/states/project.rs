@@ -0,0 +1,158 @@
+use marionette_core::byte_stream::{ByteStream, ByteStreamWrite};
+use marionette_core::mproj::{container::ProjectContainer, history::Command, integrity::FileStatus, sections::RawSection, upgrader::ProjectUpgrader, MarionetteProject};
+use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone)]
+pub struct ProjectState {
+    pub project: MarionetteProject,
+
+    // where the project was loaded from, None until it is saved
+    pub path: Option<PathBuf>,
+
+    // recorded as the author of every change made in this session
+    pub author: String,
+
+    // whether there are changes since the project was last saved
+    pub dirty: bool,
+}
+
+impl ProjectState {
+    pub fn new() -> Self {
+        let author = std::env::var("USERNAME")
+            .or_else(|_| std::env::var("USER"))
+            .unwrap_or_else(|_| "unknown".to_string());
+
+        Self {
+            project: MarionetteProject::new(),
+            path: None,
+            author,
+            dirty: false,
+        }
+    }
+
+    /// Loads the project at `path`. Projects from before the container format go through the
+    /// upgrader, which keeps a backup of the original. Anything the user should know about, like
+    /// sections that failed to load or inputs that are missing, is returned as warnings.
+    pub fn open(path: &Path) -> Result<(Self, Vec<String>), String> {
+        let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        let mut warnings = vec![];
+        let project = if ProjectContainer::is_container(&bytes) {
+            let container = ProjectContainer::parse(&bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let (project, errors) = container.project();
+            warnings.extend(errors.iter().map(|error| error.to_string()));
+            project
+        } else {
+            let (project, report) = ProjectUpgrader::new().upgrade_file(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            if let Some(backup) = report.backup {
+                warnings.push(format!("The project was upgraded from {} to {}, the original was kept as {}.", report.from, report.to, backup.display()));
+            }
+            project
+        };
+
+        let mut state = Self::new();
+        state.project = project;
+        state.path = Some(path.to_path_buf());
+
+        let base = state.base();
+        for (file, status) in state.project.project_files.iter().zip(state.project.verify_files(&base)) {
+            match status {
+                FileStatus::Missing if file.embedded.is_some() => warnings.push(format!("{} is missing, its embedded copy is used.", file.path)),
+                FileStatus::Missing => warnings.push(format!("{} is missing.", file.path)),
+                FileStatus::Changed(_) => warnings.push(format!("{} changed since it was added.", file.path)),
+                FileStatus::Verified | FileStatus::Unverified => {}
+            }
+        }
+        Ok((state, warnings))
+    }
+
+    /// Starts a project over `files` and saves it to `path`, which the file paths are kept
+    /// relative to.
+    pub fn create(name: &str, files: &[PathBuf], path: &Path) -> Result<Self, String> {
+        let mut state = Self::new();
+        state.project.project_name = name.to_string();
+        state.path = Some(path.to_path_buf());
+
+        let base = state.base();
+        for file in files {
+            state.project.add_file(file, &base, false).map_err(|error| error.to_string())?;
+        }
+        state.save()?;
+        Ok(state)
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Err("the project has no path to be saved to".to_string());
+        };
+        let mut stream = ByteStream::new(vec![]);
+        self.project.write(&mut stream).map_err(|error| error.to_string())?;
+        std::fs::write(path, stream.bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Sets the workspace layout `name` and saves it. Layouts are not part of the history, so they
+    /// are saved right away, but unsaved changes are left out of the file until the next `save`.
+    pub fn save_workspace(&mut self, name: &str, layout: String) -> Result<(), String> {
+        self.project.set_workspace(name, layout);
+        let Some(path) = &self.path else { return Ok(()) };
+        if !self.dirty {
+            return self.save();
+        }
+
+        // only the workspace section of the saved project changes
+        let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut container = ProjectContainer::parse(&bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let section = RawSection::encode(&self.project.workspaces).map_err(|error| error.to_string())?;
+        container.replace(&section).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        let mut stream = ByteStream::new(vec![]);
+        container.write(&mut stream).map_err(|error| error.to_string())?;
+        std::fs::write(path, stream.bytes).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Name of the file at `path` inside the project, if the project holds it.
+    pub fn project_file(&self, path: &str) -> Option<String> {
+        let base = self.base();
+        self.project.project_files.iter()
+            .find(|file| file.resolve(&base) == Path::new(path))
+            .map(|file| file.path.clone())
+    }
+
+    /// Directory the project's file paths are relative to.
+    pub fn base(&self) -> PathBuf {
+        self.path.as_ref().and_then(|path| path.parent()).map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    pub fn execute(&mut self, command: Command) -> String {
+        let author = self.author.clone();
+        self.dirty = true;
+        self.project.execute(command, &author).command.description()
+    }
+
+    // returns a description of what was undone, for the status line
+    pub fn undo(&mut self) -> Option<String> {
+        let description = self.project.undo().map(|entry| format!("Undo {}", entry.command.description()));
+        self.dirty |= description.is_some();
+        description
+    }
+
+    pub fn redo(&mut self) -> Option<String> {
+        let description = self.project.redo().map(|entry| format!("Redo {}", entry.command.description()));
+        self.dirty |= description.is_some();
+        description
+    }
+}
+
+impl Debug for ProjectState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProjectState")
+            .field("project_name", &self.project.project_name)
+            .field("path", &self.path)
+            .field("history", &self.project.history.len())
+            .field("dirty", &self.dirty)
+            .finish()
+    }
+}
@@ -1,26 +1,55 @@
 use crate::states::explorer::ExplorerState;
 use std::fmt::{Debug, Formatter};
+use std::path::{Path, PathBuf};
 
 #[derive(Clone)]
 pub struct SelectorState {
-    pub selected_path: String,
+    // a project to open, or the files of a new analysis; the bottom bar's input only displays it
+    pub selected_files: Vec<PathBuf>,
     pub explorer_state: ExplorerState,
 }
 
 impl SelectorState {
     pub fn new() -> Self {
         Self {
-            selected_path: String::new(),
+            selected_files: vec![],
             explorer_state: ExplorerState::new()
         }
     }
+
+    // what the bottom bar's input shows for the selection
+    pub fn selected_text(&self) -> String {
+        self.selected_files.iter()
+            .map(|file| file.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // a path typed into the bottom bar's input replaces the selection, kept exactly as typed
+    pub fn type_path(&mut self, text: &str) {
+        self.selected_files = match text.is_empty() {
+            true => vec![],
+            false => vec![PathBuf::from(text)],
+        };
+    }
+
+    // selects `path` alone, or adds it to (or removes it from) the selection when `toggle` is set
+    pub fn select(&mut self, path: &Path, toggle: bool) {
+        if !toggle {
+            self.selected_files.clear();
+        }
+        match self.selected_files.iter().position(|file| file == path) {
+            Some(index) => { self.selected_files.remove(index); }
+            None => self.selected_files.push(path.to_path_buf()),
+        }
+    }
 }
 
 impl Debug for SelectorState {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SelectorState")
-            .field("selected_path", &self.selected_path)
+            .field("selected_files", &self.selected_files)
             .field("explorer_state", &self.explorer_state)
             .finish()
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,368 @@
+// Purpose: the user's preferences, kept as TOML in the platform config dir. The file carries the
+// version of its schema; older files are brought up to date by `MIGRATIONS` when they are read,
+// and files written by a newer version are read as far as possible but never overwritten.
+// Components get the store from the `Signal<SettingsStore>` context, so reading it subscribes
+// them to changes.
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::updates::UpdateSettings;
+
+type Migration = fn(&mut Table);
+
+/// `MIGRATIONS[n]` turns a version `n` file into a version `n + 1` one.
+const MIGRATIONS: &[Migration] = &[migrate_v0, migrate_v1];
+
+pub const SETTINGS_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Version 0 files were written by hand for the update check, with nothing but an `[updates]`
+/// table, which is unchanged.
+fn migrate_v0(_: &mut Table) {}
+
+/// Version 1 kept recent projects as bare paths; version 2 remembers when they were opened and
+/// whether they are pinned.
+fn migrate_v1(table: &mut Table) {
+    let Some(Value::Array(paths)) = table.remove("recent_projects") else { return };
+    let projects = paths.into_iter()
+        .filter_map(|path| path.as_str().map(str::to_string))
+        .map(|path| {
+            let mut project = Table::new();
+            project.insert("path".to_string(), Value::String(path));
+            Value::Table(project)
+        })
+        .collect();
+    table.insert("recent_projects".to_string(), Value::Array(projects));
+}
+
+/// How many projects are remembered besides the pinned ones.
+const RECENT_PROJECTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowSettings {
+    /// Size of the tool window, in logical pixels.
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        WindowSettings { width: 1280, height: 800 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EditorSettings {
+    /// Lexer the text editor highlights with, see the "lex" method.
+    pub default_lexer: String,
+}
+
+impl Default for EditorSettings {
+    fn default() -> Self {
+        EditorSettings { default_lexer: "lua".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginSettings {
+    /// Plugins the user turned on or off by name; the rest follow their own metadata.
+    pub enabled: BTreeMap<String, bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecentProject {
+    pub path: PathBuf,
+    /// Pinned projects stay at the top of the list and are never dropped from it.
+    #[serde(default)]
+    pub pinned: bool,
+    /// Seconds since the epoch, 0 if unknown.
+    #[serde(default)]
+    pub opened_at: u64,
+}
+
+// plain values come before tables, as TOML needs them to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub theme: Theme,
+    pub window: WindowSettings,
+    pub editor: EditorSettings,
+    pub plugins: PluginSettings,
+    pub updates: UpdateSettings,
+    /// Pinned first, then most recent first.
+    pub recent_projects: Vec<RecentProject>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            theme: Theme::Dark,
+            window: WindowSettings::default(),
+            editor: EditorSettings::default(),
+            plugins: PluginSettings::default(),
+            updates: UpdateSettings::default(),
+            recent_projects: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingsErrorType {
+    Io,
+    Parse,
+    Serialize,
+    /// The file is from a newer version, or could not be read, and is kept as it is.
+    ReadOnly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsError {
+    pub description: String,
+    pub error_type: SettingsErrorType,
+}
+
+impl SettingsError {
+    pub fn new(description: String, error_type: SettingsErrorType) -> Self {
+        SettingsError { description, error_type }
+    }
+}
+
+impl Display for SettingsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "settings: {}", self.description)
+    }
+}
+
+impl Settings {
+    /// Reads a settings file of any version. The flag is set if it had to be migrated.
+    pub fn parse(text: &str) -> Result<(Settings, bool), SettingsError> {
+        let mut table: Table = toml::from_str(text)
+            .map_err(|error| SettingsError::new(error.to_string(), SettingsErrorType::Parse))?;
+
+        let version = table.get("version").and_then(Value::as_integer).unwrap_or(0).max(0) as u32;
+        for migration in MIGRATIONS.iter().skip(version as usize) {
+            migration(&mut table);
+        }
+        if version < SETTINGS_VERSION {
+            table.insert("version".to_string(), Value::Integer(SETTINGS_VERSION as i64));
+        }
+
+        let settings = Settings::deserialize(table)
+            .map_err(|error| SettingsError::new(error.to_string(), SettingsErrorType::Parse))?;
+        Ok((settings, version < SETTINGS_VERSION))
+    }
+
+    /// Moves the project at `path` to the top of the recent projects, keeping it pinned if it
+    /// was, and forgets the oldest unpinned ones past `RECENT_PROJECTS`.
+    pub fn remember_project(&mut self, path: &Path) {
+        let opened_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+        let pinned = self.recent_projects.iter().any(|project| project.path == path && project.pinned);
+        self.recent_projects.retain(|project| project.path != path);
+        self.recent_projects.insert(0, RecentProject { path: path.to_path_buf(), pinned, opened_at });
+        self.sort_recent_projects();
+
+        let mut unpinned = 0;
+        self.recent_projects.retain(|project| {
+            if project.pinned {
+                return true;
+            }
+            unpinned += 1;
+            unpinned <= RECENT_PROJECTS
+        });
+    }
+
+    pub fn pin_project(&mut self, path: &Path, pinned: bool) {
+        for project in self.recent_projects.iter_mut().filter(|project| project.path == path) {
+            project.pinned = pinned;
+        }
+        self.sort_recent_projects();
+    }
+
+    pub fn forget_project(&mut self, path: &Path) {
+        self.recent_projects.retain(|project| project.path != path);
+    }
+
+    fn sort_recent_projects(&mut self) {
+        self.recent_projects.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.opened_at.cmp(&a.opened_at)));
+    }
+
+    pub fn to_toml(&self) -> Result<String, SettingsError> {
+        toml::to_string_pretty(self).map_err(|error| SettingsError::new(error.to_string(), SettingsErrorType::Serialize))
+    }
+}
+
+#[derive(Clone)]
+pub struct SettingsStore {
+    pub settings: Settings,
+
+    // None if the platform has no config dir, in which case nothing is saved
+    path: Option<PathBuf>,
+
+    // set when the file must not be overwritten, see `SettingsErrorType::ReadOnly`
+    read_only: bool,
+
+    /// Why the file could not be loaded at launch, in which case the defaults are used and
+    /// nothing is saved over it. Shown on the settings page.
+    pub load_error: Option<SettingsError>,
+}
+
+impl SettingsStore {
+    /// Loads the settings file from the config dir. Problems are kept in `load_error` and leave
+    /// the defaults in place, since the app must start either way.
+    pub fn new() -> Self {
+        let path = dirs::config_dir().map(|dir| dir.join("marionette").join("settings.toml"));
+        match &path {
+            Some(path) => SettingsStore::open(path).unwrap_or_else(|error| SettingsStore {
+                settings: Settings::default(),
+                path: Some(path.clone()),
+                read_only: true,
+                load_error: Some(error),
+            }),
+            None => SettingsStore { settings: Settings::default(), path: None, read_only: false, load_error: None },
+        }
+    }
+
+    /// Loads the settings at `path`, migrating and saving them if they are from an older version.
+    /// A missing file gives the defaults.
+    pub fn open(path: &Path) -> Result<Self, SettingsError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(SettingsStore { settings: Settings::default(), path: Some(path.to_path_buf()), read_only: false, load_error: None });
+            }
+            Err(error) => return Err(SettingsError::new(format!("{}: {}", path.display(), error), SettingsErrorType::Io)),
+        };
+
+        let (settings, migrated) = Settings::parse(&text)
+            .map_err(|error| SettingsError::new(format!("{}: {}", path.display(), error.description), error.error_type))?;
+        let store = SettingsStore {
+            read_only: settings.version > SETTINGS_VERSION,
+            settings,
+            path: Some(path.to_path_buf()),
+            load_error: None,
+        };
+        if migrated {
+            store.save()?;
+        }
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if self.read_only {
+            return Err(SettingsError::new(
+                format!("{} was not saved, it is from a newer version or could not be read", path.display()),
+                SettingsErrorType::ReadOnly,
+            ));
+        }
+
+        let text = self.settings.to_toml()?;
+        let io_error = |error: std::io::Error| SettingsError::new(format!("{}: {}", path.display(), error), SettingsErrorType::Io);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        std::fs::write(path, text).map_err(io_error)
+    }
+
+    /// Changes the settings and saves them. The change is kept for this session even if saving fails.
+    pub fn update(&mut self, change: impl FnOnce(&mut Settings)) -> Result<(), SettingsError> {
+        change(&mut self.settings);
+        self.save()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+}
+
+impl Debug for SettingsStore {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SettingsStore")
+            .field("path", &self.path)
+            .field("read_only", &self.read_only)
+            .field("load_error", &self.load_error)
+            .field("version", &self.settings.version)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, text: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marionette_settings_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("settings.toml");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn settings_migrations() {
+        // version 0 files have no version and only the update settings
+        let path = temp_file("v0", "[updates]\nenabled = false\ntimeout_secs = 9\n");
+        let store = SettingsStore::open(&path).unwrap();
+        assert_eq!(store.settings.version, SETTINGS_VERSION);
+        assert!(!store.settings.updates.enabled);
+        assert_eq!(store.settings.updates.timeout_secs, 9);
+        assert_eq!(store.settings.window, WindowSettings::default());
+        assert!(store.settings.recent_projects.is_empty());
+        // the migrated file is saved and reads back the same
+        let (saved, migrated) = Settings::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(!migrated);
+        assert_eq!(saved, store.settings);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        // version 1 files keep recent projects as bare paths
+        let path = temp_file("v1", "version = 1\ntheme = \"light\"\nrecent_projects = [\"a.mproj\", \"b.mproj\"]\n\n[window]\nwidth = 640\nheight = 480\n");
+        let store = SettingsStore::open(&path).unwrap();
+        assert_eq!(store.settings.version, SETTINGS_VERSION);
+        assert_eq!(store.settings.theme, Theme::Light);
+        assert_eq!(store.settings.window, WindowSettings { width: 640, height: 480 });
+        assert_eq!(store.settings.recent_projects, vec![
+            RecentProject { path: "a.mproj".into(), pinned: false, opened_at: 0 },
+            RecentProject { path: "b.mproj".into(), pinned: false, opened_at: 0 },
+        ]);
+        let (saved, migrated) = Settings::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(!migrated);
+        assert_eq!(saved, store.settings);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn settings_from_newer_versions_are_kept() {
+        let text = format!("version = {}\ntheme = \"light\"\nfuture = \"setting\"\n\n[window]\nwidth = 640\nheight = 480\n", SETTINGS_VERSION + 1);
+        let path = temp_file("newer", &text);
+
+        // read as far as possible
+        let mut store = SettingsStore::open(&path).unwrap();
+        assert_eq!(store.settings.theme, Theme::Light);
+        assert_eq!(store.settings.window.width, 640);
+
+        // but never written back, even when changed
+        let saved = store.update(|settings| settings.window.width = 800);
+        assert_eq!(saved.unwrap_err().error_type, SettingsErrorType::ReadOnly);
+        assert_eq!(store.settings.window.width, 800);
+        assert_eq!(store.save().unwrap_err().error_type, SettingsErrorType::ReadOnly);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), text);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,8 +1,14 @@
 use dioxus::{prelude::*};
-use dioxus::desktop::{use_window};
+use dioxus::desktop::{use_window, use_wry_event_handler, LogicalSize};
+use dioxus::desktop::tao::event::{Event, WindowEvent};
+use futures::StreamExt;
 use pyo3::class;
 use std::rc::Rc;
-use crate::states::selector::SelectorState;
+use std::time::Duration;
+use crate::states::settings::SettingsStore;
+
+/// How long the window size must stay the same before it is saved.
+const RESIZE_SAVE_DELAY: Duration = Duration::from_millis(500);
 
 #[derive(Clone, PartialEq, Props)]
 pub struct ToolCanvasProps {
@@ -22,8 +28,33 @@ pub fn Tool() -> Element {
     let window = use_window();
     window.set_resizable(true);
 
-    let selector_state = use_context::<Signal<SelectorState>>();
-    let selected = selector_state.read().selected_path.clone();
+    // the window opens at the size it last had, which is saved once a resize has settled
+    let mut settings = use_context::<Signal<SettingsStore>>();
+    use_hook(|| {
+        let size = settings.peek().settings.window.clone();
+        window.set_inner_size(LogicalSize::new(size.width, size.height));
+    });
+    let saver = use_coroutine(move |mut sizes: UnboundedReceiver<LogicalSize<u32>>| async move {
+        while let Some(mut size) = sizes.next().await {
+            while let Ok(Some(next)) = tokio::time::timeout(RESIZE_SAVE_DELAY, sizes.next()).await {
+                size = next;
+            }
+            let saved = settings.write().update(|settings| {
+                settings.window.width = size.width;
+                settings.window.height = size.height;
+            });
+            // shown on the status line and kept by the log widget, see tool.js
+            if let Err(error) = saved {
+                eval(&format!("window.statusMessage({}, true)", serde_json::Value::from(error.to_string())));
+            }
+        }
+    });
+    let resized = window.clone();
+    use_wry_event_handler(move |event, _| {
+        if let Event::WindowEvent { event: WindowEvent::Resized(size), .. } = event {
+            saver.send(size.to_logical::<u32>(resized.scale_factor()));
+        }
+    });
 
     rsx! {
         style { {include_str!("resources/styles/tool/tool.css")} }
@@ -33,6 +64,9 @@ pub fn Tool() -> Element {
         style { {include_str!("resources/styles/tool/widgets/text_editor.css")} }
         style { {include_str!("resources/styles/tool/widgets/clock.css")} }
         style { {include_str!("resources/styles/tool/widgets/log.css")} }
+        style { {include_str!("resources/styles/tool/widgets/hex_editor.css")} }
+        style { {include_str!("resources/styles/tool/widgets/listing.css")} }
+        style { {include_str!("resources/styles/tool/workspace.css")} }
 
         script { {include_str!("resources/scripts/tool/jquery-3.7.1.min.js")} }
         script { {include_str!("resources/scripts/tool/dagre.min.js")} }
@@ -47,16 +81,29 @@ pub fn Tool() -> Element {
         script { {include_str!("resources/scripts/tool/rendering/renderer.js")} }
         script { {include_str!("resources/scripts/tool/rendering/box_renderer.js")} }
 
+        script { {include_str!("resources/scripts/tool/selection.js")} }
         script { {include_str!("resources/scripts/tool/widget.js")} }
         script { {include_str!("resources/scripts/tool/widgets/graph.js")} }
         script { {include_str!("resources/scripts/tool/widgets/clock.js")} }
         script { {include_str!("resources/scripts/tool/widgets/text_editor.js")} }
         script { {include_str!("resources/scripts/tool/widgets/log.js")} }
+        script { {include_str!("resources/scripts/tool/widgets/hex_editor.js")} }
+        script { {include_str!("resources/scripts/tool/widgets/listing.js")} }
 
         script { {include_str!("resources/scripts/tool/canvas.js")} }
+        script { {include_str!("resources/scripts/tool/workspace.js")} }
         script { {include_str!("resources/scripts/tool/toolbar.js")} }
         script { {include_str!("resources/scripts/tool/tool.js")} }
 
-        Toolbar {}
+        div {
+            id: "tool",
+            tabindex: "0",
+
+            Toolbar {}
+            // one tab per open document, see workspace.js
+            div { id: "tabs" }
+            // the outcome of the last undo, redo or save, see tool.js
+            div { id: "status" }
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,187 @@
+// Purpose: checks for new releases without ever holding up the window. The check is async and
+// time limited, its last result is cached so most launches need no network at all, and both the
+// check and where releases come from are part of the settings, so air-gapped machines can turn it
+// off or point it at a mirror or a local file.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReleaseInfo {
+    /// The release's tag, e.g. "v0.2.0".
+    pub version: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub notes: String,
+    /// Where to download it.
+    #[serde(default)]
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReleaseSource {
+    /// The latest release of a GitHub repository. `api` replaces api.github.com, for GitHub
+    /// Enterprise or another mirror of its API.
+    #[serde(rename = "github")]
+    GitHub {
+        owner: String,
+        repo: String,
+        #[serde(default)]
+        api: Option<String>,
+    },
+    /// A JSON file holding a `ReleaseInfo`, e.g. on a network share.
+    File { path: PathBuf },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdateSettings {
+    pub enabled: bool,
+    /// How long to wait for the source before giving up.
+    pub timeout_secs: u64,
+    /// How long a cached result is trusted before the source is asked again.
+    pub interval_hours: u64,
+    pub source: ReleaseSource,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        UpdateSettings {
+            enabled: true,
+            timeout_secs: 5,
+            interval_hours: 24,
+            source: ReleaseSource::GitHub {
+                owner: "matthewg-rev".to_string(),
+                repo: "marionette".to_string(),
+                api: None,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateStatus {
+    Disabled,
+    UpToDate,
+    Available(ReleaseInfo),
+    /// The source could not be reached and nothing was cached.
+    Unknown(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedCheck {
+    /// Seconds since the epoch.
+    checked_at: u64,
+    source: ReleaseSource,
+    release: ReleaseInfo,
+}
+
+fn cache_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("marionette").join("update_check.json"))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default()
+}
+
+/// Whether `latest` is a later version than `current`, comparing the numbers in them, so
+/// "v0.10.0" is newer than "0.9.1". Versions without numbers are only compared for equality.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let numbers = |version: &str| -> Vec<u64> {
+        version.trim_start_matches('v')
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    };
+    let (latest_numbers, current_numbers) = (numbers(latest), numbers(current));
+    if latest_numbers.is_empty() || current_numbers.is_empty() {
+        return latest.trim_start_matches('v') != current.trim_start_matches('v');
+    }
+    latest_numbers > current_numbers
+}
+
+fn status(release: ReleaseInfo) -> UpdateStatus {
+    match is_newer(&release.version, CURRENT_VERSION) {
+        true => UpdateStatus::Available(release),
+        false => UpdateStatus::UpToDate,
+    }
+}
+
+async fn fetch(source: &ReleaseSource) -> Result<ReleaseInfo, String> {
+    match source {
+        ReleaseSource::GitHub { owner, repo, api } => {
+            let octocrab = match api {
+                Some(api) => Arc::new(octocrab::OctocrabBuilder::new()
+                    .base_uri(api.as_str())
+                    .and_then(|builder| builder.build())
+                    .map_err(|error| error.to_string())?),
+                None => octocrab::instance(),
+            };
+            let release = octocrab.repos(owner, repo).releases().get_latest().await
+                .map_err(|error| error.to_string())?;
+            Ok(ReleaseInfo {
+                version: release.tag_name,
+                name: release.name.unwrap_or_default(),
+                notes: release.body.unwrap_or_default(),
+                url: release.html_url.to_string(),
+            })
+        }
+        ReleaseSource::File { path } => {
+            let text = tokio::fs::read_to_string(path).await.map_err(|error| format!("{}: {}", path.display(), error))?;
+            serde_json::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))
+        }
+    }
+}
+
+/// Asks the configured source for the latest release, unless a recent enough answer is cached.
+/// When the source cannot be reached in time, the cached answer is used however old it is.
+pub async fn check(settings: &UpdateSettings) -> UpdateStatus {
+    if !settings.enabled {
+        return UpdateStatus::Disabled;
+    }
+
+    // a cache filled from another source says nothing about this one
+    let cache = cache_path();
+    let cached = match &cache {
+        Some(path) => tokio::fs::read_to_string(path).await.ok(),
+        None => None,
+    };
+    let cached = cached
+        .and_then(|text| serde_json::from_str::<CachedCheck>(&text).ok())
+        .filter(|cached| cached.source == settings.source);
+
+    if let Some(cached) = &cached {
+        if now().saturating_sub(cached.checked_at) < settings.interval_hours.saturating_mul(60 * 60) {
+            return status(cached.release.clone());
+        }
+    }
+
+    let timeout = Duration::from_secs(settings.timeout_secs);
+    let fetched = match tokio::time::timeout(timeout, fetch(&settings.source)).await {
+        Ok(fetched) => fetched,
+        Err(_) => Err(format!("no answer within {} seconds", settings.timeout_secs)),
+    };
+
+    match (fetched, cached) {
+        (Ok(release), _) => {
+            if let Some(path) = cache {
+                let checked = CachedCheck { checked_at: now(), source: settings.source.clone(), release: release.clone() };
+                // failing to cache only means asking again next launch
+                if let (Some(dir), Ok(text)) = (path.parent(), serde_json::to_string(&checked)) {
+                    let _ = tokio::fs::create_dir_all(dir).await;
+                    let _ = tokio::fs::write(&path, text).await;
+                }
+            }
+            status(release)
+        }
+        (Err(_), Some(cached)) => status(cached.release),
+        (Err(error), None) => UpdateStatus::Unknown(error),
+    }
+}
@@ -0,0 +1,451 @@
+// Purpose: the subcommands of the `marionette` binary. Every command produces a `Report`
+// per chunk, holding both the text for a terminal and the JSON for scripts.
+
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+use petgraph::{dot::{Config, Dot}, graph::NodeIndex};
+use serde_json::{json, Value};
+
+use marionette_core::byte_stream::{ByteStream, ByteStreamRead, ByteStreamWrite};
+use marionette_core::{entropy::shannon, mproj::{integrity::FileStatus, sections::FileIntegrity}};
+use marionette_lua::{cfg::get_graph, lua_binary::*, optimize::PassManager};
+
+use crate::input::{Chunk, Input, Project};
+
+pub struct Report {
+    pub text: String,
+    pub json: Value,
+    /// False when the command failed or, for `verify`, found a problem.
+    pub success: bool,
+}
+
+impl Report {
+    pub fn new(text: String, json: Value) -> Report {
+        Report {
+            text,
+            json,
+            success: true,
+        }
+    }
+
+    pub fn error(message: String) -> Report {
+        Report {
+            text: format!("error: {}\n", message),
+            json: json!({ "error": message }),
+            success: false,
+        }
+    }
+}
+
+/// Runs `command`, turning a panic into an error about `name` so one malformed input does not
+/// abort the whole run.
+pub fn guarded<T>(name: &str, command: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    std::panic::catch_unwind(AssertUnwindSafe(command)).unwrap_or_else(|panic| {
+        let message = panic.downcast_ref::<&str>().map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown error".to_string());
+        Err(format!("{}: internal error: {}", name, message))
+    })
+}
+
+/// Runs `command` over the chunk at `path`, or over every chunk of the project at `path`.
+pub fn for_each_chunk(path: &Path, command: impl Fn(&Chunk) -> Result<Report, String>) -> Vec<Report> {
+    run(Input::open(path), command)
+}
+
+fn run(input: Result<Input, String>, command: impl Fn(&Chunk) -> Result<Report, String>) -> Vec<Report> {
+    let chunks = match input {
+        Ok(Input::Chunk(chunk)) => vec![Ok(chunk)],
+        Ok(Input::Project(project)) => project.chunks(),
+        Err(error) => vec![Err(error)],
+    };
+
+    chunks.into_iter()
+        .map(|chunk| chunk.and_then(|chunk| guarded(&chunk.name, || command(&chunk))).unwrap_or_else(Report::error))
+        .collect()
+}
+
+fn function<'a>(binary: &'a LuaBinary, chunk: &Chunk, id: usize) -> Result<&'a LuaFunction, String> {
+    binary.functions.get(id).ok_or_else(|| format!("{}: no function {}, the chunk has {}", chunk.name, id, binary.functions.len()))
+}
+
+/// The functions selected by `--function`, all of them by default.
+fn selected(binary: &LuaBinary, chunk: &Chunk, id: Option<usize>) -> Result<Vec<usize>, String> {
+    match id {
+        Some(id) => function(binary, chunk, id).map(|_| vec![id]),
+        None => Ok((0..binary.functions.len()).collect()),
+    }
+}
+
+fn source_name(function: &LuaFunction) -> &str {
+    function.name.trim_end_matches('\0')
+}
+
+fn write(binary: &LuaBinary) -> Result<Vec<u8>, String> {
+    let mut stream = ByteStream::new(vec![]);
+    binary.write(&mut stream).map_err(|error| error.to_string())?;
+    Ok(stream.bytes)
+}
+
+pub fn info(path: &Path) -> Vec<Report> {
+    // projects get a report of their own before the reports of their files
+    let input = Input::open(path);
+    let mut reports = match &input {
+        Ok(Input::Project(project)) => vec![project_info(path, project)],
+        _ => Vec::new(),
+    };
+    reports.extend(run(input, chunk_info));
+    reports
+}
+
+fn chunk_info(chunk: &Chunk) -> Result<Report, String> {
+    let binary = chunk.parse()?;
+    let header = &binary.header;
+    let instructions: usize = binary.functions.iter().map(|function| function.code.len()).sum();
+    let constants: usize = binary.functions.iter().map(|function| function.constants.len()).sum();
+    let source = binary.functions.first().map(source_name).unwrap_or_default();
+    let sha256 = FileIntegrity::compute(&chunk.bytes).hex();
+    let entropy = shannon(&chunk.bytes);
+    let endianness = if header.endianness == 1 { "little" } else { "big" };
+    let numbers = if header.integral_flag == 1 { "integral" } else { "floating point" };
+
+    let text = format!(
+        "{}\n  format      Lua {}.{} bytecode, format {}\n  size        {} bytes, sha256 {}\n  entropy     {:.2} bits per byte\n  \
+         header      {} endian, int {}, size_t {}, instruction {}, {}-byte {} numbers\n  \
+         functions   {} ({} instructions, {} constants)\n  debug info  {}\n  source      {:?}\n",
+        chunk.name, header.version >> 4, header.version & 0xf, header.format,
+        chunk.bytes.len(), sha256, entropy,
+        endianness, header.int_size, header.size_t_size, header.instruction_size, header.lua_number_size, numbers,
+        binary.functions.len(), instructions, constants,
+        if binary.is_stripped() { "stripped" } else { "present" }, source,
+    );
+
+    Ok(Report::new(text, json!({
+        "file": chunk.name,
+        "format": "lua51",
+        "size": chunk.bytes.len(),
+        "sha256": sha256,
+        "entropy": entropy,
+        "header": {
+            "version": header.version,
+            "format": header.format,
+            "endianness": endianness,
+            "int_size": header.int_size,
+            "size_t_size": header.size_t_size,
+            "instruction_size": header.instruction_size,
+            "number_size": header.lua_number_size,
+            "integral": header.integral_flag == 1,
+        },
+        "functions": binary.functions.len(),
+        "instructions": instructions,
+        "constants": constants,
+        "stripped": binary.is_stripped(),
+        "source": source,
+    })))
+}
+
+fn status_name(status: &FileStatus) -> &'static str {
+    match status {
+        FileStatus::Verified => "verified",
+        FileStatus::Unverified => "unverified",
+        FileStatus::Missing => "missing",
+        FileStatus::Changed(_) => "changed",
+    }
+}
+
+fn project_info(path: &Path, project: &Project) -> Report {
+    let proj = &project.project;
+    let statuses = proj.verify_files(&project.base);
+
+    let mut text = format!(
+        "{}\n  project     {:?}, version {}\n  annotations {} renames, {} comments, {} bookmarks, {} patches, {} history entries\n",
+        path.display(), proj.project_name, proj.project_version,
+        proj.renames.len(), proj.comments.len(), proj.bookmarks.len(), proj.patches.len(), proj.history.len(),
+    );
+    let mut files = Vec::new();
+    for (file, status) in proj.project_files.iter().zip(&statuses) {
+        let embedded = if file.embedded.is_some() { ", embedded" } else { "" };
+        text.push_str(&format!("  file        {} ({}{})\n", file.path, status_name(status), embedded));
+        files.push(json!({
+            "path": file.path,
+            "status": status_name(status),
+            "format": file.format,
+            "loader": file.loader,
+            "embedded": file.embedded.is_some(),
+        }));
+    }
+    for error in &project.errors {
+        text.push_str(&format!("  damaged     {}\n", error));
+    }
+
+    Report::new(text, json!({
+        "file": path.display().to_string(),
+        "project_name": proj.project_name,
+        "project_version": proj.project_version,
+        "files": files,
+        "renames": proj.renames.len(),
+        "comments": proj.comments.len(),
+        "bookmarks": proj.bookmarks.len(),
+        "patches": proj.patches.len(),
+        "history": proj.history.len(),
+        "damaged_sections": project.errors.iter().map(|error| error.to_string()).collect::<Vec<_>>(),
+    }))
+}
+
+pub fn disasm(chunk: &Chunk, id: Option<usize>) -> Result<Report, String> {
+    let binary = chunk.parse()?;
+    let mut text = format!("; {}\n", chunk.name);
+    let mut functions = Vec::new();
+
+    for id in selected(&binary, chunk, id)? {
+        let function = &binary.functions[id];
+        match binary.parent(id) {
+            Some(parent) => text.push_str(&format!("\nfunction {} in function {}\n", id, parent)),
+            None => text.push_str(&format!("\nfunction {}\n", id)),
+        }
+
+        let mut instructions = Vec::new();
+        for (pc, instruction) in function.code.iter().enumerate() {
+            let disassembly = format!("{:?}", instruction).trim_end().to_string();
+            text.push_str(&format!("{:08x}  {}\n", instruction.range.start, disassembly));
+            instructions.push(json!({
+                "pc": pc,
+                "offset": instruction.range.start,
+                "opcode": instruction.opcode.to_string(),
+                "text": disassembly,
+            }));
+        }
+        functions.push(json!({ "id": id, "parent": binary.parent(id), "instructions": instructions }));
+    }
+
+    Ok(Report::new(text, json!({ "file": chunk.name, "functions": functions })))
+}
+
+pub fn cfg(chunk: &Chunk, id: usize, dot: bool) -> Result<Report, String> {
+    let binary = chunk.parse()?;
+    let (graph, entry) = get_graph(function(&binary, chunk, id)?.clone())?;
+
+    let block_id = |node: NodeIndex| graph[node].id;
+    let mut blocks = Vec::new();
+    let mut edges = Vec::new();
+    let mut text = format!("; {} function {}\n", chunk.name, id);
+    for node in graph.node_indices() {
+        let block = &graph[node];
+        let successors: Vec<usize> = graph.neighbors(node).map(block_id).collect();
+        let instructions: Vec<String> = block.instructions.iter().map(|instruction| format!("{:?}", instruction).trim_end().to_string()).collect();
+
+        text.push_str(&format!("\nblock {} -> {:?}\n", block.id, successors));
+        for instruction in &instructions {
+            text.push_str(&format!("    {}\n", instruction));
+        }
+        edges.extend(successors.iter().map(|successor| json!([block.id, successor])));
+        blocks.push(json!({ "id": block.id, "instructions": instructions }));
+    }
+
+    let dot_text = format!("{:?}", Dot::with_config(&graph, &[Config::EdgeNoLabel]));
+    let mut json = json!({
+        "file": chunk.name,
+        "function": id,
+        "entry": entry.map(block_id),
+        "blocks": blocks,
+        "edges": edges,
+    });
+    if dot {
+        json["dot"] = json!(dot_text);
+        text = dot_text;
+    }
+    Ok(Report::new(text, json))
+}
+
+pub fn decompile(chunk: &Chunk, id: Option<usize>) -> Result<Report, String> {
+    let binary = chunk.parse()?;
+    let mut text = format!("-- {}\n", chunk.name);
+    let mut functions = Vec::new();
+    for id in selected(&binary, chunk, id)? {
+        let source = binary.decompile_function(id).unwrap_or_default();
+        text.push('\n');
+        text.push_str(&source);
+        functions.push(json!({ "id": id, "text": source }));
+    }
+    Ok(Report::new(text, json!({ "file": chunk.name, "functions": functions })))
+}
+
+pub fn verify(path: &Path) -> Vec<Report> {
+    // projects get a report of their own before the reports of their files
+    let input = Input::open(path);
+    let mut reports = match &input {
+        Ok(Input::Project(project)) => vec![verify_project(path, project)],
+        _ => Vec::new(),
+    };
+    reports.extend(run(input, verify_chunk));
+    reports
+}
+
+fn verdict(name: &str, issues: Vec<String>) -> Report {
+    let mut text = match issues.is_empty() {
+        true => format!("{}: ok\n", name),
+        false => format!("{}: {} problem(s)\n", name, issues.len()),
+    };
+    for issue in &issues {
+        text.push_str(&format!("  {}\n", issue));
+    }
+
+    Report {
+        text,
+        json: json!({ "file": name, "ok": issues.is_empty(), "issues": issues }),
+        success: issues.is_empty(),
+    }
+}
+
+fn verify_project(path: &Path, project: &Project) -> Report {
+    let mut issues: Vec<String> = project.errors.iter().map(|error| error.to_string()).collect();
+    let statuses = project.project.verify_files(&project.base);
+    for (file, status) in project.project.project_files.iter().zip(statuses) {
+        match status {
+            FileStatus::Missing if file.embedded.is_some() => issues.push(format!("{}: missing, the embedded copy is used", file.path)),
+            FileStatus::Missing => issues.push(format!("{}: missing", file.path)),
+            FileStatus::Changed(found) => issues.push(format!("{}: changed since it was added (sha256 {})", file.path, found.hex())),
+            FileStatus::Verified | FileStatus::Unverified => {}
+        }
+    }
+    verdict(&path.display().to_string(), issues)
+}
+
+fn verify_chunk(chunk: &Chunk) -> Result<Report, String> {
+    let binary = chunk.parse()?;
+    let mut issues = Vec::new();
+
+    let mut stream = ByteStream::new(chunk.bytes.clone());
+    LuaBinary::read(&mut stream).map_err(|error| format!("{}: {}", chunk.name, error))?;
+    let trailing = stream.remaining().len();
+    if trailing > 0 {
+        issues.push(format!("{} trailing bytes after the main function", trailing));
+    }
+
+    // a chunk that does not survive a round trip would be corrupted by `strip` and `reassemble`
+    let written = write(&binary)?;
+    let compared = &chunk.bytes[..chunk.bytes.len() - trailing];
+    if written != compared {
+        let offset = written.iter().zip(compared).position(|(a, b)| a != b).unwrap_or(written.len().min(compared.len()));
+        issues.push(format!("reassembling changes the chunk at offset 0x{:x}", offset));
+    }
+
+    for (id, function) in binary.functions.iter().enumerate() {
+        let operands = function.operand_words();
+        for (pc, instruction) in function.code.iter().enumerate() {
+            if operands[pc] {
+                continue;
+            }
+            if instruction.is_relative_jump() {
+                let target = pc as i64 + 1 + instruction.components.sbx() as i64;
+                if target < 0 || target >= function.code.len() as i64 {
+                    issues.push(format!("function {} pc {}: jump to {} is outside the function", id, pc, target));
+                }
+            }
+            issues.extend(operand_issues(function, pc).into_iter().map(|issue| format!("function {} pc {}: {}", id, pc, issue)));
+            if instruction.opcode == LuaOpcode::CLOSURE && binary.child(id, instruction.components.bx() as usize).is_none() {
+                issues.push(format!("function {} pc {}: closure {} does not exist", id, pc, instruction.components.bx()));
+            }
+        }
+        if !matches!(function.code.last().map(|instruction| instruction.opcode), Some(LuaOpcode::RETURN)) {
+            issues.push(format!("function {} does not end with RETURN", id));
+        }
+    }
+
+    Ok(verdict(&chunk.name, issues))
+}
+
+/// Register operands past the function's stack and constant operands past its constant pool.
+fn operand_issues(function: &LuaFunction, pc: usize) -> Vec<String> {
+    let instruction = &function.code[pc];
+    let layout = instruction.components;
+    let (a, b, c) = (layout.a() as usize, layout.b() as usize, layout.c() as usize);
+
+    let (mut registers, mut constants) = match instruction.opcode {
+        LuaOpcode::MOVE | LuaOpcode::LOADNIL | LuaOpcode::UNM | LuaOpcode::NOT | LuaOpcode::LEN | LuaOpcode::TESTSET => (vec![a, b], vec![]),
+        LuaOpcode::LOADK | LuaOpcode::GETGLOBAL | LuaOpcode::SETGLOBAL => (vec![a], vec![layout.bx() as usize]),
+        LuaOpcode::GETTABLE | LuaOpcode::SELF => (vec![a, b], vec![]),
+        LuaOpcode::CONCAT => (vec![a, b, c], vec![]),
+        LuaOpcode::JMP | LuaOpcode::EQ | LuaOpcode::LT | LuaOpcode::LE => (vec![], vec![]),
+        _ => (vec![a], vec![]),
+    };
+    let rk = match instruction.opcode {
+        LuaOpcode::GETTABLE | LuaOpcode::SELF => vec![c],
+        LuaOpcode::SETTABLE
+        | LuaOpcode::ADD
+        | LuaOpcode::SUB
+        | LuaOpcode::MUL
+        | LuaOpcode::DIV
+        | LuaOpcode::MOD
+        | LuaOpcode::POW
+        | LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE => vec![b, c],
+        _ => vec![],
+    };
+    for operand in rk {
+        match rk_constant(operand) {
+            Some(index) => constants.push(index),
+            None => registers.push(operand),
+        }
+    }
+
+    let stack = function.max_stack_size as usize;
+    let pool = function.constants.len();
+    registers.into_iter().filter(|register| *register >= stack)
+        .map(|register| format!("register {} is past the stack size {}", register, stack))
+        .chain(constants.into_iter().filter(|constant| *constant >= pool)
+            .map(|constant| format!("constant {} is past the {} constants", constant, pool)))
+        .collect()
+}
+
+/// Reads the Lua chunk at `path` for the commands that write a new one.
+fn chunk(path: &Path) -> Result<Chunk, String> {
+    match Input::open(path)? {
+        Input::Chunk(chunk) => Ok(chunk),
+        Input::Project(_) => Err(format!("{}: expected a Lua chunk, not a project", path.display())),
+    }
+}
+
+pub fn strip(path: &Path, output: &Path) -> Result<Report, String> {
+    let chunk = chunk(path)?;
+    let mut binary = chunk.parse()?;
+    binary.strip_debug();
+
+    let bytes = write(&binary)?;
+    std::fs::write(output, &bytes).map_err(|error| format!("{}: {}", output.display(), error))?;
+
+    let text = format!("{} -> {}: {} -> {} bytes\n", chunk.name, output.display(), chunk.bytes.len(), bytes.len());
+    Ok(Report::new(text, json!({
+        "file": chunk.name,
+        "output": output.display().to_string(),
+        "size_before": chunk.bytes.len(),
+        "size_after": bytes.len(),
+    })))
+}
+
+pub fn reassemble(path: &Path, output: &Path, optimize: bool) -> Result<Report, String> {
+    let chunk = chunk(path)?;
+    let mut binary = chunk.parse()?;
+
+    let passes = match optimize {
+        true => PassManager::with_default_passes().run_binary(&mut binary),
+        false => Vec::new(),
+    };
+
+    let bytes = write(&binary)?;
+    std::fs::write(output, &bytes).map_err(|error| format!("{}: {}", output.display(), error))?;
+
+    let mut text = format!("{} -> {}: {} bytes{}\n", chunk.name, output.display(), bytes.len(), if bytes == chunk.bytes { ", unchanged" } else { "" });
+    for (id, pass) in &passes {
+        text.push_str(&format!("  function {}: {}\n", id, pass));
+    }
+    Ok(Report::new(text, json!({
+        "file": chunk.name,
+        "output": output.display().to_string(),
+        "size": bytes.len(),
+        "unchanged": bytes == chunk.bytes,
+        "passes": passes.iter().map(|(id, pass)| json!({ "function": id, "pass": pass })).collect::<Vec<_>>(),
+    })))
+}
@@ -0,0 +1,73 @@
+// Purpose: opens the files given on the command line. A file is either a Lua chunk or a
+// project, in which case commands run over the chunks of every file the project holds.
+
+use std::path::{Path, PathBuf};
+
+use marionette_core::{byte_stream::{ByteStream, ByteStreamRead}, loader::Loader};
+use marionette_core::mproj::{container::{ContainerError, ProjectContainer}, upgrader::ProjectUpgrader, MarionetteProject, RawProject};
+use marionette_lua::{loader::LuaLoader, lua_binary::LuaBinary};
+
+pub struct Chunk {
+    /// The path given on the command line, or the path of the file inside a project.
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Project {
+    pub project: MarionetteProject,
+    /// Directory the project's file paths are relative to.
+    pub base: PathBuf,
+    /// Sections that could not be loaded.
+    pub errors: Vec<ContainerError>,
+}
+
+pub enum Input {
+    Chunk(Chunk),
+    Project(Box<Project>),
+}
+
+impl Input {
+    pub fn open(path: &Path) -> Result<Input, String> {
+        let bytes = std::fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+
+        let is_project = ProjectContainer::is_container(&bytes) || path.extension().is_some_and(|extension| extension == "mproj");
+        if !is_project {
+            return Ok(Input::Chunk(Chunk {
+                name: path.display().to_string(),
+                bytes,
+            }));
+        }
+
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        if ProjectContainer::is_container(&bytes) {
+            let container = ProjectContainer::parse(&bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let (project, errors) = container.project();
+            return Ok(Input::Project(Box::new(Project { project, base, errors })));
+        }
+
+        // projects from before the container format
+        let raw = RawProject::read(&mut ByteStream::new(bytes)).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let (project, _) = ProjectUpgrader::new().upgrade(raw).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Ok(Input::Project(Box::new(Project { project, base, errors: Vec::new() })))
+    }
+}
+
+impl Project {
+    /// Reads every file of the project, from disk or from its embedded copy.
+    pub fn chunks(&self) -> Vec<Result<Chunk, String>> {
+        self.project.project_files.iter().map(|file| {
+            file.load(&self.base)
+                .map(|bytes| Chunk { name: file.path.clone(), bytes })
+                .map_err(|error| error.to_string())
+        }).collect()
+    }
+}
+
+impl Chunk {
+    pub fn parse(&self) -> Result<LuaBinary, String> {
+        if LuaLoader.probe(&self.bytes) <= 0.0 {
+            return Err(format!("{}: not a Lua 5.1 chunk", self.name));
+        }
+        LuaBinary::read(&mut ByteStream::new(self.bytes.clone())).map_err(|error| format!("{}: {}", self.name, error))
+    }
+}
@@ -0,0 +1,278 @@
+// Purpose: the `marionette` command line tool, for analyzing Lua chunks and projects without the app.
+// src\main.rs
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+
+use commands::Report;
+
+mod commands;
+mod input;
+
+#[derive(Parser)]
+#[command(name = "marionette", version, about = "Batch analysis of Lua 5.1 chunks and Marionette projects")]
+struct Cli {
+    /// Print a JSON array with one object per chunk instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Summarize the header and contents of chunks, or the files of projects.
+    Info {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Disassemble chunks.
+    Disasm {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Only disassemble the function with this id.
+        #[arg(long)]
+        function: Option<usize>,
+    },
+    /// Print the control flow graph of a function.
+    Cfg {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long, default_value_t = 0)]
+        function: usize,
+        /// Print the graph in Graphviz dot format.
+        #[arg(long)]
+        dot: bool,
+    },
+    /// Print chunks as Lua-like pseudocode.
+    Decompile {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[arg(long)]
+        function: Option<usize>,
+    },
+    /// Check that chunks are well formed and that projects match their files. Fails if any is not.
+    Verify {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Remove debug information from a chunk, like `luac -s`.
+    Strip {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Parse a chunk and write it back out, optionally deobfuscated.
+    Reassemble {
+        file: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Run constant folding and the other deobfuscation passes first.
+        #[arg(long)]
+        optimize: bool,
+    },
+}
+
+fn each(files: &[PathBuf], command: impl Fn(&Path) -> Vec<Report>) -> Vec<Report> {
+    files.iter()
+        .flat_map(|path| commands::guarded(&path.display().to_string(), || Ok(command(path))).unwrap_or_else(|error| vec![Report::error(error)]))
+        .collect()
+}
+
+fn run(command: Command) -> Vec<Report> {
+    match command {
+        Command::Info { files } => each(&files, commands::info),
+        Command::Disasm { files, function } => each(&files, |path| commands::for_each_chunk(path, |chunk| commands::disasm(chunk, function))),
+        Command::Cfg { files, function, dot } => each(&files, |path| commands::for_each_chunk(path, |chunk| commands::cfg(chunk, function, dot))),
+        Command::Decompile { files, function } => each(&files, |path| commands::for_each_chunk(path, |chunk| commands::decompile(chunk, function))),
+        Command::Verify { files } => each(&files, commands::verify),
+        Command::Strip { file, output } => vec![commands::guarded(&file.display().to_string(), || commands::strip(&file, &output)).unwrap_or_else(Report::error)],
+        Command::Reassemble { file, output, optimize } => {
+            let name = file.display().to_string();
+            vec![commands::guarded(&name, || commands::reassemble(&file, &output, optimize)).unwrap_or_else(Report::error)]
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // panics are reported per file by `commands::guarded`, not printed as they happen
+    std::panic::set_hook(Box::new(|_| {}));
+    let reports = run(cli.command);
+
+    if cli.json {
+        let json: Vec<_> = reports.iter().map(|report| &report.json).collect();
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        for report in &reports {
+            // errors go to stderr so the output can still be piped into other tools
+            match report.json.get("error") {
+                Some(_) => eprint!("{}", report.text),
+                None => print!("{}", report.text),
+            }
+        }
+    }
+
+    match reports.iter().all(|report| report.success) {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = "src/resources/tests/decrypt.luac";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("marionette_cli_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn cli_commands() {
+        let cli = Cli::try_parse_from(["marionette", "--json", "cfg", FIXTURE, "--dot"]).unwrap();
+        assert!(cli.json);
+        let reports = run(cli.command);
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].text.starts_with("digraph {"));
+        assert!(reports[0].json["blocks"].as_array().unwrap().len() >= 3);
+        assert!(Cli::try_parse_from(["marionette", "info"]).is_err());
+
+        let info = run(Command::Info { files: vec![FIXTURE.into(), "missing.luac".into()] });
+        assert_eq!(info.len(), 2);
+        assert_eq!(info[0].json["functions"], 1);
+        assert_eq!(info[0].json["instructions"], 18);
+        assert_eq!(info[0].json["stripped"], false);
+        assert!(!info[1].success && info[1].json["error"].is_string());
+
+        let disasm = run(Command::Disasm { files: vec![FIXTURE.into()], function: Some(0) });
+        assert_eq!(disasm[0].json["functions"][0]["instructions"][5]["opcode"], "FORPREP");
+        assert!(run(Command::Disasm { files: vec![FIXTURE.into()], function: Some(3) })[0].text.contains("no function 3"));
+
+        let decompile = run(Command::Decompile { files: vec![FIXTURE.into()], function: None });
+        assert!(decompile[0].text.contains("goto L16"));
+
+        let verify = run(Command::Verify { files: vec![FIXTURE.into()] });
+        assert!(verify[0].success, "{}", verify[0].text);
+
+        // strip, then check the result still verifies and has lost its debug information
+        let dir = temp_dir("strip");
+        let stripped = dir.join("stripped.luac");
+        let report = commands::strip(FIXTURE.as_ref(), &stripped).unwrap();
+        assert!(report.json["size_after"].as_u64() < report.json["size_before"].as_u64());
+        let info = commands::info(&stripped);
+        assert_eq!(info[0].json["stripped"], true);
+        assert!(commands::verify(&stripped)[0].success);
+
+        let reassembled = dir.join("reassembled.luac");
+        let report = commands::reassemble(FIXTURE.as_ref(), &reassembled, false).unwrap();
+        assert_eq!(report.json["unchanged"], true);
+        assert_eq!(std::fs::read(&reassembled).unwrap(), std::fs::read(FIXTURE).unwrap());
+
+        // a corrupted jump is reported and fails the run
+        let mut bytes = std::fs::read(FIXTURE).unwrap();
+        let offset = disasm[0].json["functions"][0]["instructions"][16]["offset"].as_u64().unwrap() as usize;
+        bytes[offset + 3] = 0xff;
+        let broken = dir.join("broken.luac");
+        std::fs::write(&broken, &bytes).unwrap();
+        let verify = commands::verify(&broken);
+        assert!(!verify[0].success);
+        assert!(verify[0].json["issues"][0].as_str().unwrap().contains("outside the function"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cli_malformed_chunks() {
+        let dir = temp_dir("malformed");
+        let fixture = std::fs::read(FIXTURE).unwrap();
+        let disasm = run(Command::Disasm { files: vec![FIXTURE.into()], function: Some(0) });
+        let offset = |pc: usize| disasm[0].json["functions"][0]["instructions"][pc]["offset"].as_u64().unwrap() as usize;
+
+        // truncated chunks and an unknown opcode are errors of their file, not of the run
+        let mut files = Vec::new();
+        for length in [4, 12, 40, offset(5), fixture.len() - 1] {
+            let path = dir.join(format!("truncated_{}.luac", length));
+            std::fs::write(&path, &fixture[..length]).unwrap();
+            files.push(path);
+        }
+        let mut bytes = fixture.clone();
+        bytes[offset(3)] = 0x3f;
+        let path = dir.join("opcode.luac");
+        std::fs::write(&path, &bytes).unwrap();
+        files.push(path);
+        files.push(FIXTURE.into());
+
+        for command in [
+            Command::Info { files: files.clone() },
+            Command::Disasm { files: files.clone(), function: None },
+            Command::Decompile { files: files.clone(), function: None },
+            Command::Verify { files: files.clone() },
+        ] {
+            let reports = run(command);
+            assert_eq!(reports.len(), files.len());
+            assert!(reports[..files.len() - 1].iter().all(|report| !report.success && report.json["error"].is_string()));
+            assert!(reports[files.len() - 1].success);
+        }
+        assert!(run(Command::Verify { files: vec![dir.join("opcode.luac")] })[0].text.contains("unknown opcode"));
+
+        let panicked = commands::guarded("chunk", || -> Result<(), String> { panic!("broken") });
+        assert_eq!(panicked, Err("chunk: internal error: broken".to_string()));
+
+        // constant and register operands past the end of the pool and stack
+        let mut bytes = fixture.clone();
+        let instruction = offset(0);
+        let raw = u32::from_le_bytes(bytes[instruction..instruction + 4].try_into().unwrap());
+        // LOADK with A 255 and Bx 0x3ffff
+        let broken = (raw & 0x3f) | (0xff << 6) | (0x3ffff << 14);
+        bytes[instruction..instruction + 4].copy_from_slice(&broken.to_le_bytes());
+        let path = dir.join("operands.luac");
+        std::fs::write(&path, &bytes).unwrap();
+        let verify = commands::verify(&path);
+        assert!(!verify[0].success);
+        let issues = verify[0].json["issues"].as_array().unwrap();
+        assert!(issues.iter().any(|issue| issue.as_str().unwrap().contains("past the stack size")));
+        assert!(issues.iter().any(|issue| issue.as_str().unwrap().contains("constant 262143 is past")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cli_projects() {
+        use marionette_core::{byte_stream::{ByteStream, ByteStreamWrite}, mproj::MarionetteProject};
+
+        let dir = temp_dir("project");
+        let chunk = dir.join("decrypt.luac");
+        std::fs::copy(FIXTURE, &chunk).unwrap();
+
+        let mut project = MarionetteProject { project_name: "cli".to_string(), ..MarionetteProject::new() };
+        project.add_file(&chunk, &dir, true).unwrap();
+        let mut stream = ByteStream::new(vec![]);
+        project.write(&mut stream).unwrap();
+        let path = dir.join("cli.mproj");
+        std::fs::write(&path, &stream.bytes).unwrap();
+
+        let info = commands::info(&path);
+        assert_eq!(info.len(), 2);
+        assert_eq!(info[0].json["project_name"], "cli");
+        assert_eq!(info[0].json["files"][0]["status"], "verified");
+        assert_eq!(info[1].json["file"], "decrypt.luac");
+        assert!(commands::verify(&path).iter().all(|report| report.success));
+
+        // a missing file is reported, while the embedded copy keeps the other commands working
+        std::fs::remove_file(&chunk).unwrap();
+        let verify = commands::verify(&path);
+        assert!(!verify[0].success);
+        assert!(verify[0].text.contains("embedded copy"));
+        assert!(verify[1].success);
+        assert!(commands::strip(&path, &dir.join("out.luac")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
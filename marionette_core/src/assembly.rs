@@ -15,6 +15,31 @@ impl Range {
     pub fn size(&self) -> u64 {
         self.end - self.start
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    /// Ranges are half-open, `end` is the first address past the range.
+    pub fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+
+    pub fn contains_range(&self, other: &Range) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+
+    pub fn overlaps(&self, other: &Range) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    pub fn intersection(&self, other: &Range) -> Option<Range> {
+        let range = Range::new(self.start.max(other.start), self.end.min(other.end));
+        match range.is_empty() {
+            true => None,
+            false => Some(range),
+        }
+    }
 }
 
 pub trait Entropy {
@@ -30,7 +55,9 @@ pub trait Data {
 
 pub trait Region {
     fn range(&self) -> Range;
-    fn contains(&self, addr: u64) -> bool;
+    fn contains(&self, addr: u64) -> bool {
+        self.range().contains(addr)
+    }
 
     fn data(&self) -> &[Box<dyn Data>];
     fn raw(&self) -> &[u8];
@@ -5,6 +5,7 @@ use std::collections::VecDeque;
 
 pub mod natives;
 pub mod templated;
+pub mod inspect;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Endian {
@@ -108,9 +109,11 @@ impl ByteStream {
     /// let mut byte_stream = ByteStream::new(bytes);
     /// assert_eq!(byte_stream.is_out_of_bounds(0), false);
     /// assert_eq!(byte_stream.is_out_of_bounds(5), true);
+    /// assert_eq!(byte_stream.is_out_of_bounds(usize::MAX), true);
     /// ```
     pub fn is_out_of_bounds(&self, size: usize) -> bool {
-        self.index + size > self.bytes.len()
+        // sizes come from the data being read, so `index + size` may not fit in a usize
+        size > self.bytes.len().saturating_sub(self.index)
     }
 
     /// Returns the current index of the byte stream.
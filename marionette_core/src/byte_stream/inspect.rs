@@ -0,0 +1,60 @@
+// Purpose: decodes the bytes at one offset as each primitive type, for data inspectors
+// that show what a selection could mean before its structure is known.
+
+use std::fmt::Debug;
+
+use crate::byte_stream::{ByteStream, ByteStreamRead, Endian};
+
+/// Longest NUL terminated string looked for.
+pub const MAX_C_STRING: usize = 256;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inspection {
+    /// Type name, e.g. "u32" or "f64".
+    pub name: &'static str,
+    /// Debug formatted, so strings are quoted and extreme floats use exponents. None if too
+    /// few bytes are left, or they are not a valid value of the type.
+    pub value: Option<String>,
+    /// Number of bytes the value takes up.
+    pub size: usize,
+}
+
+fn read<T: ByteStreamRead + Debug>(name: &'static str, bytes: &[u8], endian: &Endian) -> Inspection {
+    let mut stream = ByteStream::new(bytes.to_vec());
+    stream.endianness = endian.clone();
+    match T::read(&mut stream) {
+        Ok(value) => Inspection { name, value: Some(format!("{:?}", value)), size: stream.caret() },
+        Err(_) => Inspection { name, value: None, size: 0 },
+    }
+}
+
+fn c_string(bytes: &[u8]) -> Inspection {
+    let window = &bytes[..bytes.len().min(MAX_C_STRING)];
+    match window.iter().position(|&byte| byte == 0) {
+        Some(length) => Inspection {
+            name: "c string",
+            value: Some(format!("{:?}", String::from_utf8_lossy(&window[..length]))),
+            size: length + 1,
+        },
+        None => Inspection { name: "c string", value: None, size: 0 },
+    }
+}
+
+/// Every reading of the bytes at the start of `bytes`, integers and floats in `endian` order.
+/// "string" is the length prefixed string `ByteStream` itself writes.
+pub fn inspect(bytes: &[u8], endian: Endian) -> Vec<Inspection> {
+    vec![
+        read::<u8>("u8", bytes, &endian),
+        read::<i8>("i8", bytes, &endian),
+        read::<u16>("u16", bytes, &endian),
+        read::<i16>("i16", bytes, &endian),
+        read::<u32>("u32", bytes, &endian),
+        read::<i32>("i32", bytes, &endian),
+        read::<u64>("u64", bytes, &endian),
+        read::<i64>("i64", bytes, &endian),
+        read::<f32>("f32", bytes, &endian),
+        read::<f64>("f64", bytes, &endian),
+        c_string(bytes),
+        read::<String>("string", bytes, &endian),
+    ]
+}
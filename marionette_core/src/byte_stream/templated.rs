@@ -16,7 +16,8 @@ impl<T: ByteStreamRead> ByteStreamRead for Vec<T> {
     /// ```
     fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
         let len = u64::read(stream)? as usize;
-        let mut vec = Vec::with_capacity(len);
+        // the length comes from the data, every element takes at least a byte
+        let mut vec = Vec::with_capacity(len.min(stream.bytes.len().saturating_sub(stream.index)));
         for _ in 0..len {
             vec.push(T::read(stream)?);
         }
@@ -0,0 +1,222 @@
+// Purpose: byte statistics for spotting compressed or encrypted data, e.g. packed payloads or
+// encrypted string tables. Shannon entropy is in bits per byte, from 0.0 (a single repeated
+// byte) to 8.0 (every byte value equally likely).
+
+use crate::assembly::{Assembly, Data, Entropy, Range, Region};
+
+/// Entropy above which data is usually compressed or encrypted. Code and text stay well below it.
+/// Small samples read lower than they are, so profiles should use windows of 1024 bytes or more.
+pub const HIGH_ENTROPY: f64 = 7.2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub counts: [u64; 256],
+    pub total: u64,
+}
+
+impl Histogram {
+    pub fn new(bytes: &[u8]) -> Histogram {
+        let mut histogram = Histogram {
+            counts: [0; 256],
+            total: 0,
+        };
+        for byte in bytes {
+            histogram.add(*byte);
+        }
+        histogram
+    }
+
+    pub fn add(&mut self, byte: u8) {
+        self.counts[byte as usize] += 1;
+        self.total += 1;
+    }
+
+    pub fn remove(&mut self, byte: u8) {
+        self.counts[byte as usize] -= 1;
+        self.total -= 1;
+    }
+
+    /// Number of distinct byte values.
+    pub fn unique(&self) -> usize {
+        self.counts.iter().filter(|count| **count > 0).count()
+    }
+
+    /// The `n` most frequent byte values with their counts, most frequent first.
+    pub fn most_common(&self, n: usize) -> Vec<(u8, u64)> {
+        let mut counts: Vec<_> = (0..=255u8).zip(self.counts).filter(|(_, count)| *count > 0).collect();
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Pearson's chi-square test of the bytes against a uniform distribution.
+    pub fn chi_square(&self) -> ChiSquare {
+        let expected = self.total as f64 / 256.0;
+        let statistic = match self.total {
+            0 => 0.0,
+            _ => self.counts.iter().map(|count| (*count as f64 - expected).powi(2) / expected).sum(),
+        };
+
+        ChiSquare {
+            statistic,
+            degrees_of_freedom: 255,
+            p_value: chi_square_p_value(statistic, 255.0),
+        }
+    }
+}
+
+impl Entropy for Histogram {
+    fn entropy(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+
+        let total = self.total as f64;
+        self.counts.iter()
+            .filter(|count| **count > 0)
+            .map(|count| {
+                let p = *count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: u32,
+    /// Probability of a statistic at least this large for truly random bytes.
+    pub p_value: f64,
+}
+
+impl ChiSquare {
+    /// Whether the bytes are plausibly random at the given significance level, e.g. 0.01.
+    /// Encrypted data passes, compressed data usually does not, code and text never do.
+    pub fn is_random(&self, significance: f64) -> bool {
+        self.p_value > significance && self.p_value < 1.0 - significance
+    }
+}
+
+/// Upper tail of the chi-square distribution, using the Wilson-Hilferty approximation.
+/// Accurate to a few digits for the 255 degrees of freedom of a byte histogram.
+fn chi_square_p_value(statistic: f64, degrees: f64) -> f64 {
+    if statistic <= 0.0 {
+        return 1.0;
+    }
+
+    let variance = 2.0 / (9.0 * degrees);
+    let z = ((statistic / degrees).cbrt() - (1.0 - variance)) / variance.sqrt();
+    0.5 * erfc(z / std::f64::consts::SQRT_2)
+}
+
+/// Complementary error function (Numerical Recipes `erfcc`), relative error below 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+        + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+        + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * polynomial.exp();
+    if x >= 0.0 { result } else { 2.0 - result }
+}
+
+pub fn shannon(bytes: &[u8]) -> f64 {
+    Histogram::new(bytes).entropy()
+}
+
+impl Entropy for [u8] {
+    fn entropy(&self) -> f64 {
+        shannon(self)
+    }
+}
+
+impl Entropy for dyn Data {
+    fn entropy(&self) -> f64 {
+        shannon(self.raw())
+    }
+}
+
+impl Entropy for dyn Region {
+    fn entropy(&self) -> f64 {
+        shannon(self.raw())
+    }
+}
+
+impl Entropy for dyn Assembly {
+    fn entropy(&self) -> f64 {
+        shannon(self.raw())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropyPoint {
+    pub offset: u64,
+    pub entropy: f64,
+}
+
+/// Entropy of a window sliding over a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropyProfile {
+    pub window: usize,
+    pub step: usize,
+    /// Size of the profiled bytes.
+    pub size: u64,
+    /// One point per window position. Files shorter than `window` get a single point.
+    pub points: Vec<EntropyPoint>,
+}
+
+impl EntropyProfile {
+    pub fn new(bytes: &[u8], window: usize, step: usize) -> EntropyProfile {
+        let window = window.max(1);
+        let step = step.max(1);
+        let mut profile = EntropyProfile {
+            window,
+            step,
+            size: bytes.len() as u64,
+            points: Vec::new(),
+        };
+
+        if bytes.len() <= window {
+            profile.points.push(EntropyPoint { offset: 0, entropy: shannon(bytes) });
+            return profile;
+        }
+
+        // the histogram is moved along with the window instead of being rebuilt for each position
+        let mut histogram = Histogram::new(&bytes[..window]);
+        let mut offset = 0;
+        loop {
+            profile.points.push(EntropyPoint { offset: offset as u64, entropy: histogram.entropy() });
+
+            let next = offset + step;
+            if next + window > bytes.len() {
+                break;
+            }
+            if step >= window {
+                histogram = Histogram::new(&bytes[next..next + window]);
+            } else {
+                bytes[offset..next].iter().for_each(|byte| histogram.remove(*byte));
+                bytes[offset + window..next + window].iter().for_each(|byte| histogram.add(*byte));
+            }
+            offset = next;
+        }
+        profile
+    }
+
+    /// Merged ranges of every window with an entropy of at least `threshold`.
+    pub fn above(&self, threshold: f64) -> Vec<Range> {
+        let mut ranges: Vec<Range> = Vec::new();
+        for point in self.points.iter().filter(|point| point.entropy >= threshold) {
+            let end = (point.offset + self.window as u64).min(self.size);
+            match ranges.last_mut() {
+                Some(last) if last.end >= point.offset => last.end = last.end.max(end),
+                _ => ranges.push(Range::new(point.offset, end)),
+            }
+        }
+        ranges
+    }
+
+    pub fn max(&self) -> Option<&EntropyPoint> {
+        self.points.iter().max_by(|a, b| a.entropy.total_cmp(&b.entropy))
+    }
+}
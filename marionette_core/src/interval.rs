@@ -0,0 +1,175 @@
+// Purpose: maps address ranges to values and answers which of them overlap a range or address.
+// Ranges may overlap and nest, e.g. a function region inside a section inside a file.
+//
+// Entries are kept sorted by start (longer ranges first on ties, so parents come before their
+// children) and form an implicit balanced tree: the middle entry of a slice is the root of that
+// slice. `max_end` holds the largest end of every subtree, which lets queries skip subtrees
+// that end before the queried range starts.
+
+use crate::assembly::{Assembly, Range};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalMap<T> {
+    entries: Vec<(Range, T)>,
+    max_end: Vec<u64>,
+}
+
+/// How much of a range the entries of a map cover.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coverage {
+    pub range: Range,
+    /// Bytes covered by at least one entry.
+    pub covered: u64,
+    /// The parts of `range` no entry covers, in order.
+    pub gaps: Vec<Range>,
+}
+
+impl Coverage {
+    /// Covered fraction of the range, 1.0 for an empty range.
+    pub fn ratio(&self) -> f64 {
+        match self.range.size() {
+            0 => 1.0,
+            size => self.covered as f64 / size as f64,
+        }
+    }
+}
+
+impl<T> Default for IntervalMap<T> {
+    fn default() -> Self {
+        IntervalMap::new()
+    }
+}
+
+impl<T> FromIterator<(Range, T)> for IntervalMap<T> {
+    fn from_iter<I: IntoIterator<Item = (Range, T)>>(iter: I) -> Self {
+        let mut map = IntervalMap {
+            entries: iter.into_iter().collect(),
+            max_end: Vec::new(),
+        };
+        map.entries.sort_by_key(|(range, _)| (range.start, std::cmp::Reverse(range.end)));
+        map.rebuild();
+        map
+    }
+}
+
+impl IntervalMap<usize> {
+    /// Maps the range of every region of `assembly` to the region's index.
+    pub fn from_regions(assembly: &dyn Assembly) -> IntervalMap<usize> {
+        assembly.regions().iter().enumerate().map(|(index, region)| (region.range(), index)).collect()
+    }
+}
+
+impl<T> IntervalMap<T> {
+    pub fn new() -> IntervalMap<T> {
+        IntervalMap {
+            entries: Vec::new(),
+            max_end: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every entry, ordered by start.
+    pub fn iter(&self) -> impl Iterator<Item = (&Range, &T)> {
+        self.entries.iter().map(|(range, value)| (range, value))
+    }
+
+    pub fn insert(&mut self, range: Range, value: T) {
+        let key = (range.start, std::cmp::Reverse(range.end));
+        let index = self.entries.partition_point(|(other, _)| (other.start, std::cmp::Reverse(other.end)) <= key);
+        self.entries.insert(index, (range, value));
+        self.rebuild();
+    }
+
+    /// Removes every entry `keep` returns false for.
+    pub fn retain(&mut self, mut keep: impl FnMut(&Range, &T) -> bool) {
+        self.entries.retain(|(range, value)| keep(range, value));
+        self.rebuild();
+    }
+
+    fn rebuild(&mut self) {
+        self.max_end = self.entries.iter().map(|(range, _)| range.end).collect();
+        self.build(0, self.entries.len());
+    }
+
+    fn build(&mut self, low: usize, high: usize) -> u64 {
+        if low >= high {
+            return 0;
+        }
+        let middle = low + (high - low) / 2;
+        let left = self.build(low, middle);
+        let right = self.build(middle + 1, high);
+        self.max_end[middle] = self.max_end[middle].max(left).max(right);
+        self.max_end[middle]
+    }
+
+    /// Entries overlapping `range`, ordered by start.
+    pub fn overlapping(&self, range: &Range) -> Vec<(&Range, &T)> {
+        let mut found = Vec::new();
+        self.search(0, self.entries.len(), range, &mut found);
+        found
+    }
+
+    fn search<'a>(&'a self, low: usize, high: usize, range: &Range, found: &mut Vec<(&'a Range, &'a T)>) {
+        if low >= high {
+            return;
+        }
+        let middle = low + (high - low) / 2;
+        if self.max_end[middle] <= range.start {
+            return;
+        }
+
+        self.search(low, middle, range, found);
+        let (entry, value) = &self.entries[middle];
+        // everything to the right starts at or after this entry
+        if entry.start >= range.end {
+            return;
+        }
+        if entry.overlaps(range) {
+            found.push((entry, value));
+        }
+        self.search(middle + 1, high, range, found);
+    }
+
+    /// Entries containing `addr`, outermost first.
+    pub fn at(&self, addr: u64) -> Vec<(&Range, &T)> {
+        self.overlapping(&Range::new(addr, addr.saturating_add(1)))
+    }
+
+    /// The smallest entry containing `addr`.
+    pub fn innermost(&self, addr: u64) -> Option<(&Range, &T)> {
+        self.at(addr).into_iter().min_by_key(|(range, _)| range.size())
+    }
+
+    /// The parts of `range` that no entry covers.
+    pub fn gaps(&self, range: &Range) -> Vec<Range> {
+        let mut gaps = Vec::new();
+        let mut position = range.start;
+        for (entry, _) in self.overlapping(range) {
+            if entry.start > position {
+                gaps.push(Range::new(position, entry.start));
+            }
+            position = position.max(entry.end);
+        }
+        if position < range.end {
+            gaps.push(Range::new(position, range.end));
+        }
+        gaps
+    }
+
+    pub fn coverage(&self, range: &Range) -> Coverage {
+        let gaps = self.gaps(range);
+        let uncovered: u64 = gaps.iter().map(|gap| gap.size()).sum();
+        Coverage {
+            range: range.clone(),
+            covered: range.size().saturating_sub(uncovered),
+            gaps,
+        }
+    }
+}
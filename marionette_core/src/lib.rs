@@ -4,22 +4,511 @@
 pub mod byte_stream;
 pub mod mproj;
 pub mod assembly;
+pub mod loader;
+pub mod entropy;
+pub mod interval;
 
 mod lib {}
 
 #[cfg(test)]
 mod tests {
     use std::io::Write;
-    use crate::{byte_stream::{ByteStream, ByteStreamWrite, ByteStreamRead}, mproj::RawProject};
+    use crate::{byte_stream::{ByteStream, ByteStreamWrite, ByteStreamRead}, mproj::{RawProject, sections::*, integrity::{FileStatus, IntegrityErrorType}, container::{self, ContainerErrorType, ProjectContainer}, history::Command, export::{ExportErrorType, ExportFormat}, upgrader::{ProjectUpgrader, UpgradeErrorType}}};
     use super::*;
 
+    fn sample_project() -> mproj::MarionetteProject {
+        let file = "build/main.luac".to_string();
+        mproj::MarionetteProject {
+            project_name: "Fixture Project".to_string(),
+            project_files: vec![
+                ProjectFile::new("scripts/main.lua"),
+                ProjectFile { format: Some("lua51".to_string()), loader: Some("lua".to_string()), ..ProjectFile::new(&file) },
+            ],
+            renames: vec![
+                Rename { file: file.clone(), address: 0x2c, register: None, name: "decrypt".to_string() },
+                Rename { file: file.clone(), address: 0x2c, register: Some(3), name: "key".to_string() },
+            ],
+            comments: vec![Comment { file: file.clone(), address: 0x40, text: "xor loop".to_string() }],
+            bookmarks: vec![Bookmark { file: file.clone(), address: 0x2c, label: "entry".to_string() }],
+            color_tags: vec![ColorTag { file: file.clone(), function: 1, block: 2, color: 0xff0000ff }],
+            layouts: vec![GraphLayout {
+                file,
+                function: 1,
+                nodes: vec![NodePosition { block: 0, x: 0.0, y: 0.0 }, NodePosition { block: 1, x: 120.5, y: -40.0 }],
+            }],
+            ..mproj::MarionetteProject::new()
+        }
+    }
+
     #[test]
     pub fn proj_read_write() {
+        let mut proj = sample_project();
+        proj.unknown_sections.push(RawSection { name: "future".to_string(), version: 3, payload: vec![1, 2, 3] });
+        proj.set_workspace("default", "{\"tabs\":[]}".to_string());
+        proj.set_workspace("default", "{\"tabs\":[{\"path\":\"build/main.luac\"}]}".to_string());
+        assert_eq!(proj.workspaces.len(), 1);
+        assert_eq!(proj.workspace("default"), Some("{\"tabs\":[{\"path\":\"build/main.luac\"}]}"));
+        assert_eq!(proj.workspace("other"), None);
+
         let mut stream = ByteStream::new(Vec::new());
-        let proj = mproj::MarionetteProject::new();
         proj.write(&mut stream).unwrap();
         stream = ByteStream::from(&stream);
         let raw: RawProject = RawProject::read(&mut stream).unwrap();
-        println!("{:?}", raw.project_version);
+        assert_eq!(raw.project_version, mproj::PROJECT_VERSION);
+
+        let read = mproj::MarionetteProject::read(&mut ByteStream::new(raw.raw)).unwrap();
+        assert_eq!(read, proj);
+
+        // a section from a newer build than ours is an error, not silently misread
+        let mut stream = ByteStream::new(Vec::new());
+        let newer = RawSection { version: 2, ..RawSection::encode(&proj.comments).unwrap() };
+        mproj::MarionetteProject { unknown_sections: vec![newer], ..mproj::MarionetteProject::new() }.write(&mut stream).unwrap();
+        assert!(mproj::MarionetteProject::read(&mut ByteStream::from(&stream)).is_err());
+    }
+
+    fn raw_project(fields: &[&str]) -> RawProject {
+        let mut stream = ByteStream::new(Vec::new());
+        for field in fields {
+            field.to_string().write(&mut stream).unwrap();
+        }
+        RawProject::read(&mut ByteStream::from(&stream)).unwrap()
+    }
+
+    #[test]
+    pub fn proj_upgrade() {
+        let fixture = include_bytes!("resources/tests/project_0.3.0.mproj").to_vec();
+        let raw = RawProject::read(&mut ByteStream::new(fixture)).unwrap();
+        assert_eq!(raw.project_version, "0.3.0");
+        let (proj, report) = ProjectUpgrader::new().upgrade(raw).unwrap();
+        assert_eq!(proj, sample_project());
+        assert!(report.migrations.is_empty());
+
+        // 0.2.0 had sections but no container
+        let fixture = include_bytes!("resources/tests/project_0.2.0.mproj").to_vec();
+        let raw = RawProject::read(&mut ByteStream::new(fixture)).unwrap();
+        let (proj, report) = ProjectUpgrader::new().upgrade(raw).unwrap();
+        assert_eq!(proj, sample_project());
+        assert_eq!(report.migrations, vec![("0.2.0".to_string(), "0.3.0".to_string())]);
+
+        // 0.1.0 only had paths
+        let fixture = include_bytes!("resources/tests/project_0.1.0.mproj").to_vec();
+        let raw = RawProject::read(&mut ByteStream::new(fixture)).unwrap();
+        let (proj, report) = ProjectUpgrader::new().upgrade(raw).unwrap();
+        assert_eq!(proj.project_name, "Fixture Project");
+        assert_eq!(proj.project_files, vec![ProjectFile::new("scripts/main.lua"), ProjectFile::new("build/main.luac")]);
+        assert!(proj.comments.is_empty());
+        assert_eq!(report.migrations.len(), 2);
+
+        // 0.0.1 had no file list, 0.0.5 stored the name upper cased
+        let mut upgrader = ProjectUpgrader::new();
+        upgrader.register("0.0.1", "0.0.5", Box::new(|proj| {
+            let mut stream = ByteStream::new(proj.remaining);
+            let name = String::read(&mut stream).map_err(|e| e.to_string())?;
+            Ok(raw_project(&["0.0.5", &name.to_uppercase()]))
+        }));
+        upgrader.register("0.0.5", "0.1.0", Box::new(|proj| {
+            let mut stream = ByteStream::new(Vec::new());
+            "0.1.0".to_string().write(&mut stream).map_err(|e| e.to_string())?;
+            stream.write_bytes(proj.remaining).map_err(|e| e.to_string())?;
+            Vec::<String>::new().write(&mut stream).map_err(|e| e.to_string())?;
+            RawProject::read(&mut ByteStream::from(&stream)).map_err(|e| e.to_string())
+        }));
+        assert_eq!(upgrader.path("0.0.1").unwrap().len(), 4);
+
+        let (proj, report) = upgrader.upgrade(raw_project(&["0.0.1", "old"])).unwrap();
+        assert_eq!(proj.project_name, "OLD");
+        assert_eq!(report.migrations, vec![
+            ("0.0.1".to_string(), "0.0.5".to_string()),
+            ("0.0.5".to_string(), "0.1.0".to_string()),
+            ("0.1.0".to_string(), "0.2.0".to_string()),
+            ("0.2.0".to_string(), "0.3.0".to_string()),
+        ]);
+
+        let error = upgrader.upgrade(raw_project(&["0.0.0", "old"])).unwrap_err();
+        assert_eq!(error.error_type, UpgradeErrorType::NoPath);
+
+        // a migration producing a malformed 0.1.0 project fails its validator
+        upgrader.register("0.0.2", "0.1.0", Box::new(|_| Ok(raw_project(&["0.1.0", "name", "junk"]))));
+        let error = upgrader.upgrade(raw_project(&["0.0.2"])).unwrap_err();
+        assert_eq!(error.error_type, UpgradeErrorType::ValidationFailure);
+
+        // upgrading a file keeps the original next to it
+        let dir = std::env::temp_dir().join(format!("marionette_upgrade_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("old.mproj");
+        let original = raw_project(&["0.0.1", "file"]).raw;
+        std::fs::write(&path, &original).unwrap();
+
+        let (_, report) = upgrader.upgrade_file(&path).unwrap();
+        let backup = report.backup.unwrap();
+        assert_eq!(backup, dir.join("old.mproj.bak"));
+        assert_eq!(std::fs::read(&backup).unwrap(), original);
+        let upgraded = RawProject::read(&mut ByteStream::new(std::fs::read(&path).unwrap())).unwrap();
+        assert_eq!(upgraded.project_version, mproj::PROJECT_VERSION);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    pub fn proj_container() {
+        let mut proj = sample_project();
+        proj.unknown_sections.push(RawSection { name: "future".to_string(), version: 3, payload: vec![1, 2, 3] });
+        let mut stream = ByteStream::new(Vec::new());
+        proj.write(&mut stream).unwrap();
+
+        // sections load on their own
+        let container = ProjectContainer::parse(&stream.bytes).unwrap();
+        assert_eq!(container.entries.len(), 10);
+        assert_eq!(container.section::<Comment>().unwrap(), proj.comments);
+
+        // damage the comments payload, everything else still loads
+        let entry = container.entry(Comment::NAME).unwrap().clone();
+        let mut damaged = container.clone();
+        damaged.data[entry.offset as usize] ^= 0xff;
+        assert_eq!(damaged.section::<Comment>().unwrap_err().error_type, ContainerErrorType::ChecksumMismatch);
+        assert_eq!(damaged.section::<Bookmark>().unwrap(), proj.bookmarks);
+
+        let (recovered, errors) = damaged.project();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].section, Comment::NAME);
+        assert!(recovered.comments.is_empty());
+        assert_eq!(recovered.layouts, proj.layouts);
+        assert_eq!(recovered.unknown_sections, proj.unknown_sections);
+
+        let mut bytes = ByteStream::new(Vec::new());
+        damaged.write(&mut bytes).unwrap();
+        assert!(mproj::MarionetteProject::read(&mut ByteStream::from(&bytes)).is_err());
+
+        // a table pointing past the end only loses that section
+        let mut truncated = container.clone();
+        truncated.data.truncate(truncated.entries.last().unwrap().offset as usize);
+        let (_, errors) = truncated.project();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].error_type, ContainerErrorType::Truncated);
+
+        // replacing a section carries the others over and drops its old payload
+        let workspaces = vec![WorkspaceLayout { name: "default".to_string(), layout: "{}".to_string() }];
+        let mut replaced = container.clone();
+        replaced.replace(&RawSection::encode(&workspaces).unwrap()).unwrap();
+        assert_eq!(replaced.entries.len(), 10);
+        assert_eq!(replaced.section::<WorkspaceLayout>().unwrap(), workspaces);
+        assert_eq!(replaced.section::<Bookmark>().unwrap(), proj.bookmarks);
+        assert_eq!(replaced.data.len() as u64, replaced.entries.iter().map(|entry| entry.length).sum::<u64>());
+        assert!(damaged.clone().replace(&RawSection::encode(&workspaces).unwrap()).is_err());
+
+        // a damaged section count is an error, not an allocation
+        let mut header = ByteStream::new(container::MAGIC.to_vec());
+        container::CONTAINER_VERSION.write(&mut header).unwrap();
+        container.project_version.write(&mut header).unwrap();
+        container.project_name.write(&mut header).unwrap();
+        u64::MAX.write(&mut header).unwrap();
+        let error = ProjectContainer::parse(&header.bytes).unwrap_err();
+        assert_eq!(error.error_type, ContainerErrorType::Truncated);
+
+        let section = RawSection { name: Comment::NAME.to_string(), version: Comment::VERSION, payload: u64::MAX.to_le_bytes().to_vec() };
+        assert!(section.decode::<Comment>().is_err());
+
+        assert!(!ProjectContainer::is_container(include_bytes!("resources/tests/project_0.2.0.mproj")));
+    }
+
+    #[test]
+    pub fn proj_history() {
+        let mut proj = sample_project();
+        let file = "build/main.luac".to_string();
+        let comment = |text: Option<&str>| Command::Comment { file: file.clone(), address: 0x40, before: None, after: text.map(str::to_string) };
+
+        proj.execute(comment(Some("decrypts the payload")), "alice");
+        let entry = proj.execute(comment(None), "bob").clone();
+        assert_eq!(entry.command, Command::Comment { file: file.clone(), address: 0x40, before: Some("decrypts the payload".to_string()), after: None });
+        assert_eq!(entry.author, "bob");
+        assert!(proj.comments.is_empty());
+
+        proj.undo().unwrap();
+        assert_eq!(proj.comments[0].text, "decrypts the payload");
+        proj.undo().unwrap();
+        assert_eq!(proj.comments, sample_project().comments);
+        assert!(proj.undo().is_none());
+
+        // the log, including what was undone, is saved with the project
+        let mut stream = ByteStream::new(Vec::new());
+        proj.write(&mut stream).unwrap();
+        let mut proj = mproj::MarionetteProject::read(&mut ByteStream::from(&stream)).unwrap();
+        assert_eq!(proj.history.len(), 2);
+        assert_eq!(proj.redo().unwrap().author, "alice");
+        assert_eq!(proj.comments[0].text, "decrypts the payload");
+
+        // a new command drops what is left to redo
+        proj.execute(Command::Patch { file: file.clone(), offset: 0x2c, before: None, after: Some(vec![0x1e, 0, 0x80, 0]) }, "alice");
+        assert!(!proj.can_redo());
+        assert_eq!(proj.patches.len(), 1);
+        proj.undo().unwrap();
+        assert!(proj.patches.is_empty());
+    }
+
+    #[test]
+    pub fn proj_export() {
+        let mut proj = sample_project();
+        proj.project_files[1].track(b"\x1bLua\x51", true).unwrap();
+        proj.unknown_sections.push(RawSection { name: "future".to_string(), version: 3, payload: vec![1, 2, 3] });
+        proj.execute(Command::Patch { file: "build/main.luac".to_string(), offset: 0x2c, before: None, after: Some(vec![0x1e, 0, 0x80, 0]) }, "alice");
+        proj.execute(Command::Rename { file: "build/main.luac".to_string(), address: 0x2c, register: None, before: None, after: None }, "alice");
+        proj.undo().unwrap();
+
+        for format in [ExportFormat::Json, ExportFormat::Toml] {
+            let text = proj.export(format).unwrap();
+            assert!(text.contains("1e008000"));
+            assert_eq!(mproj::MarionetteProject::import(&text, format).unwrap(), proj);
+        }
+
+        let text = proj.export(ExportFormat::Json).unwrap().replace(mproj::PROJECT_VERSION, "0.1.0");
+        assert_eq!(mproj::MarionetteProject::import(&text, ExportFormat::Json).unwrap_err().error_type, ExportErrorType::UnsupportedVersion);
+        assert_eq!(ExportFormat::from_path(std::path::Path::new("analysis.TOML")), Some(ExportFormat::Toml));
+    }
+
+    #[test]
+    pub fn proj_integrity() {
+        let dir = std::env::temp_dir().join(format!("marionette_integrity_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        let path = dir.join("build").join("main.luac");
+        std::fs::write(&path, b"\x1bLua\x51 original").unwrap();
+
+        let mut proj = mproj::MarionetteProject::new();
+        proj.add_file(&path, &dir, true).unwrap();
+        proj.add_file(&path, &dir, false).unwrap();
+        assert_eq!(proj.project_files[0].path, "build/main.luac");
+        assert_eq!(proj.project_files[0].integrity.as_ref().unwrap().size, 14);
+        assert_eq!(proj.verify_files(&dir), vec![FileStatus::Verified, FileStatus::Verified]);
+
+        // the embedded copy survives a round trip and stands in for the changed file
+        let mut stream = ByteStream::new(Vec::new());
+        proj.write(&mut stream).unwrap();
+        let mut proj = mproj::MarionetteProject::read(&mut ByteStream::from(&stream)).unwrap();
+
+        std::fs::write(&path, b"modified").unwrap();
+        assert!(matches!(proj.verify_files(&dir)[0], FileStatus::Changed(_)));
+        assert_eq!(proj.project_files[0].load(&dir).unwrap(), b"\x1bLua\x51 original");
+        assert_eq!(proj.project_files[1].load(&dir).unwrap_err().error_type, IntegrityErrorType::Changed);
+
+        // relinking only accepts the original contents
+        let moved = dir.join("moved.luac");
+        std::fs::write(&moved, b"\x1bLua\x51 original").unwrap();
+        assert!(matches!(proj.relink(1, &path, &dir), FileStatus::Changed(_)));
+        assert_eq!(proj.relink(1, &moved, &dir), FileStatus::Verified);
+        assert_eq!(proj.project_files[1].path, "moved.luac");
+
+        std::fs::remove_file(&moved).unwrap();
+        assert_eq!(proj.verify_files(&dir)[1], FileStatus::Missing);
+        assert_eq!(proj.project_files[1].load(&dir).unwrap_err().error_type, IntegrityErrorType::Missing);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    struct RawAssembly(Vec<u8>);
+
+    impl assembly::Assembly for RawAssembly {
+        fn regions(&self) -> &[Box<dyn assembly::Region>] {
+            &[]
+        }
+
+        fn raw(&self) -> &[u8] {
+            &self.0
+        }
+
+        fn text(&self) -> &str {
+            ""
+        }
+    }
+
+    struct MagicLoader(&'static str, &'static [u8], f32);
+
+    impl loader::Loader for MagicLoader {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn description(&self) -> &str {
+            "test loader"
+        }
+
+        fn probe(&self, bytes: &[u8]) -> f32 {
+            if bytes.starts_with(self.1) { self.2 } else { 0.0 }
+        }
+
+        fn load(&self, bytes: Vec<u8>) -> Result<Box<dyn assembly::Assembly>, loader::LoaderError> {
+            Ok(Box::new(RawAssembly(bytes)))
+        }
+    }
+
+    #[test]
+    fn loader_registry() {
+        use loader::{LoaderErrorType, LoaderRegistry};
+
+        let mut registry = LoaderRegistry::new();
+        registry.register(Box::new(MagicLoader("any", b"", 0.1))).unwrap();
+        registry.register(Box::new(MagicLoader("lua", b"\x1bLua", 1.0))).unwrap();
+        let error = registry.register(Box::new(MagicLoader("lua", b"", 1.0))).unwrap_err();
+        assert_eq!(error.error_type, LoaderErrorType::Duplicate);
+        assert_eq!(registry.loaders().len(), 2);
+
+        // the most confident loader wins, unless one is asked for by name
+        let names: Vec<_> = registry.probe(b"\x1bLuaQ").iter().map(|(loader, _)| loader.name()).collect();
+        assert_eq!(names, vec!["lua", "any"]);
+        assert_eq!(registry.detect(b"MZ").unwrap().name(), "any");
+        let (name, assembly) = registry.load(b"\x1bLuaQ".to_vec(), None).unwrap();
+        assert_eq!((name.as_str(), assembly.raw()), ("lua", b"\x1bLuaQ".as_slice()));
+        assert_eq!(registry.load(b"\x1bLuaQ".to_vec(), Some("any")).unwrap().0, "any");
+        assert_eq!(registry.load(vec![], Some("python")).err().unwrap().error_type, LoaderErrorType::NotFound);
+
+        let empty = LoaderRegistry::new();
+        assert_eq!(empty.load(vec![], None).err().unwrap().error_type, LoaderErrorType::NotFound);
+
+        // plugins that cannot be opened are reported, not fatal
+        let dir = std::env::temp_dir().join(format!("marionette_plugins_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("broken.{}", std::env::consts::DLL_EXTENSION)), b"not a library").unwrap();
+        std::fs::write(dir.join("readme.txt"), b"ignored").unwrap();
+        let (loaded, errors) = registry.load_plugins(&dir);
+        assert_eq!((loaded, errors.len()), (0, 1));
+        assert_eq!(errors[0].error_type, LoaderErrorType::PluginFailure);
+        assert_eq!(registry.loaders().len(), 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn entropy_statistics() {
+        use assembly::{Assembly, Entropy, Range};
+        use entropy::{EntropyProfile, Histogram, HIGH_ENTROPY};
+
+        // a xorshift stream stands in for encrypted data
+        let mut state = 0x2545f4914f6cdd1du64;
+        let random: Vec<u8> = (0..4096).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        }).collect();
+        let text = b"local function decrypt(key, data) return data end\n".repeat(80);
+
+        assert_eq!(Histogram::new(&[]).entropy(), 0.0);
+        assert_eq!(Histogram::new(&[7; 64]).entropy(), 0.0);
+        assert_eq!((0..=255u8).collect::<Vec<_>>().entropy(), 8.0);
+        assert!(random.entropy() > 7.9);
+        assert!(text.entropy() < 5.0);
+
+        let histogram = Histogram::new(b"aababc");
+        assert_eq!((histogram.total, histogram.unique()), (6, 3));
+        assert_eq!(histogram.most_common(2), vec![(b'a', 3), (b'b', 2)]);
+
+        assert!(Histogram::new(&random).chi_square().is_random(0.01));
+        let chi_square = Histogram::new(&text).chi_square();
+        assert!(chi_square.p_value < 1e-6 && !chi_square.is_random(0.01));
+
+        // an encrypted blob in the middle of a script stands out of the profile
+        let mut file = text.clone();
+        file.extend_from_slice(&random);
+        file.extend_from_slice(&text);
+        let profile = EntropyProfile::new(&file, 1024, 128);
+        assert_eq!(profile.points.len(), (file.len() - 1024) / 128 + 1);
+        let incremental: Vec<_> = profile.points.iter().map(|point| point.entropy).collect();
+        let rebuilt: Vec<_> = profile.points.iter()
+            .map(|point| file[point.offset as usize..point.offset as usize + 1024].entropy())
+            .collect();
+        assert!(incremental.iter().zip(&rebuilt).all(|(a, b)| (a - b).abs() < 1e-9));
+
+        let ranges = profile.above(HIGH_ENTROPY);
+        assert_eq!(ranges.len(), 1);
+        let (start, end) = (text.len() as u64, (text.len() + random.len()) as u64);
+        assert!(ranges[0].start >= start - 1024 && ranges[0].start <= start);
+        assert!(ranges[0].end >= end && ranges[0].end <= end + 1024);
+
+        let short = EntropyProfile::new(b"abcd", 256, 64);
+        assert_eq!(short.points.len(), 1);
+        assert_eq!(short.above(1.0), vec![Range::new(0, 4)]);
+
+        let assembly: Box<dyn Assembly> = Box::new(RawAssembly(random.clone()));
+        assert_eq!(assembly.entropy(), random.entropy());
+    }
+
+    #[test]
+    fn interval_map() {
+        use assembly::Range;
+        use interval::IntervalMap;
+
+        assert!(Range::new(0x10, 0x20).contains(0x1f) && !Range::new(0x10, 0x20).contains(0x20));
+        assert!(!Range::new(0x10, 0x20).overlaps(&Range::new(0x20, 0x30)));
+        assert_eq!(Range::new(0x10, 0x20).intersection(&Range::new(0x18, 0x30)), Some(Range::new(0x18, 0x20)));
+        assert_eq!(Range::new(0x10, 0x20).intersection(&Range::new(0x20, 0x30)), None);
+
+        // a file with a header, a function holding code and constants, and some trailing bytes
+        let mut map: IntervalMap<&str> = [
+            (Range::new(0x0c, 0x80), "function"),
+            (Range::new(0x00, 0x0c), "header"),
+            (Range::new(0x40, 0x60), "constants"),
+        ].into_iter().collect();
+        map.insert(Range::new(0x10, 0x40), "code");
+        map.insert(Range::new(0x90, 0xa0), "strings");
+        assert_eq!(map.len(), 5);
+
+        let names = |found: Vec<(&Range, &&'static str)>| found.into_iter().map(|(_, name)| *name).collect::<Vec<_>>();
+        assert_eq!(names(map.iter().collect()), vec!["header", "function", "code", "constants", "strings"]);
+        assert_eq!(names(map.at(0x20)), vec!["function", "code"]);
+        assert_eq!(map.innermost(0x44).map(|(_, name)| *name), Some("constants"));
+        assert_eq!(names(map.at(0x88)), Vec::<&str>::new());
+        assert_eq!(names(map.overlapping(&Range::new(0x3f, 0x95))), vec!["function", "code", "constants", "strings"]);
+        assert_eq!(names(map.overlapping(&Range::new(0xa0, 0xb0))), Vec::<&str>::new());
+
+        // the bytes no parser claimed
+        let coverage = map.coverage(&Range::new(0, 0xb0));
+        assert_eq!(coverage.gaps, vec![Range::new(0x80, 0x90), Range::new(0xa0, 0xb0)]);
+        assert_eq!(coverage.covered, 0x90);
+        assert_eq!(map.gaps(&Range::new(0x20, 0x30)), vec![]);
+
+        map.retain(|_, name| *name != "function");
+        assert_eq!(map.gaps(&Range::new(0, 0x80)), vec![Range::new(0x0c, 0x10), Range::new(0x60, 0x80)]);
+
+        // the tree agrees with a linear scan on many overlapping ranges
+        let ranges: Vec<_> = (0..200u64).map(|i| Range::new(i * 37 % 1000, i * 37 % 1000 + i % 50 + 1)).collect();
+        let map: IntervalMap<usize> = ranges.iter().cloned().zip(0..).collect();
+        for start in (0..1100).step_by(13) {
+            let query = Range::new(start, start + 20);
+            let mut found: Vec<_> = map.overlapping(&query).into_iter().map(|(_, index)| *index).collect();
+            let mut expected: Vec<_> = (0..ranges.len()).filter(|index| ranges[*index].overlaps(&query)).collect();
+            found.sort();
+            expected.sort();
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn inspect_and_patch() {
+        use assembly::Range;
+        use byte_stream::{Endian, inspect::inspect};
+
+        let bytes = [0x00, 0x00, 0x80, 0x3f, b'h', b'i', 0x00];
+        let value = |endian: Endian, name: &str| inspect(&bytes, endian).into_iter().find(|inspection| inspection.name == name).unwrap();
+        assert_eq!(value(Endian::Little, "u32").value.as_deref(), Some("1065353216"));
+        assert_eq!(value(Endian::Little, "f32").value.as_deref(), Some("1.0"));
+        assert_eq!(value(Endian::Big, "u16").value.as_deref(), Some("0"));
+        assert_eq!(value(Endian::Big, "u32").value.as_deref(), Some("32831"));
+        assert_eq!(value(Endian::Little, "u64").value, None);
+        assert_eq!(value(Endian::Little, "c string").value.as_deref(), Some("\"\""));
+        let text = inspect(&bytes[4..], Endian::Little);
+        assert_eq!(text.iter().find(|inspection| inspection.name == "c string").map(|inspection| (inspection.value.as_deref(), inspection.size)), Some((Some("\"hi\""), 3)));
+        // a string length that overflows the index is out of bounds, not a panic
+        let overflow = inspect(&[0xff; 16], Endian::Little);
+        assert_eq!(overflow.iter().find(|inspection| inspection.name == "u8").and_then(|inspection| inspection.value.as_deref()), Some("255"));
+
+        // patches of other files are ignored and later patches win where they overlap
+        let mut project = mproj::MarionetteProject::new();
+        project.patches = vec![
+            BytePatch { file: "a.luac".to_string(), offset: 0x0e, bytes: vec![1, 2, 3, 4] },
+            BytePatch { file: "b.luac".to_string(), offset: 0x10, bytes: vec![9] },
+            BytePatch { file: "a.luac".to_string(), offset: 0x11, bytes: vec![5] },
+        ];
+        let mut window = [0u8; 4];
+        let patched = project.apply_patches("a.luac", 0x10, &mut window);
+        assert_eq!(window, [3, 5, 0, 0]);
+        assert_eq!(patched, vec![Range::new(0x10, 0x12), Range::new(0x11, 0x12)]);
+        assert!(project.apply_patches("a.luac", 0x20, &mut window).is_empty());
     }
 }
@@ -0,0 +1,208 @@
+// Purpose: picks the parser for a file. Architecture crates implement `Loader` and register it,
+// either directly or from a dynamic library exporting a `PluginDeclaration` (see `export_plugin!`).
+
+use std::path::Path;
+
+use libloading::Library;
+
+use crate::assembly::Assembly;
+
+/// Version of the compiler that built marionette_core. Plugins are only loaded when built by the
+/// same compiler, since the registry is passed across the library boundary with the Rust ABI.
+pub static RUSTC_VERSION: &str = env!("RUSTC_VERSION");
+pub static CORE_VERSION: &str = env!("MARIONETTE_VERSION");
+
+/// Symbol a plugin library exports its `PluginDeclaration` under.
+pub const PLUGIN_SYMBOL: &[u8] = b"marionette_plugin_declaration\0";
+
+pub trait Loader: Send + Sync {
+    /// Unique name, stored in projects to reopen a file with the same loader.
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+
+    /// How confident the loader is that it can parse `bytes`, from 0.0 (not at all) to 1.0.
+    fn probe(&self, bytes: &[u8]) -> f32;
+    fn load(&self, bytes: Vec<u8>) -> Result<Box<dyn Assembly>, LoaderError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoaderErrorType {
+    /// No loader with that name, or none that recognizes the file.
+    NotFound,
+    Duplicate,
+    LoadFailure,
+    PluginFailure,
+    IncompatiblePlugin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoaderError {
+    /// The loader or plugin the error is about.
+    pub loader: String,
+    pub description: String,
+    pub error_type: LoaderErrorType,
+}
+
+impl LoaderError {
+    pub fn new(loader: &str, description: String, error_type: LoaderErrorType) -> LoaderError {
+        LoaderError {
+            loader: loader.to_string(),
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for LoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.loader, self.description)
+    }
+}
+
+pub struct PluginDeclaration {
+    pub rustc_version: &'static str,
+    pub core_version: &'static str,
+    pub register: fn(&mut LoaderRegistry),
+}
+
+/// Exports a plugin entry point from a `cdylib`. `$register` is a `fn(&mut LoaderRegistry)`
+/// that registers the plugin's loaders.
+#[macro_export]
+macro_rules! export_plugin {
+    ($register:expr) => {
+        #[doc(hidden)]
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        pub static marionette_plugin_declaration: $crate::loader::PluginDeclaration = $crate::loader::PluginDeclaration {
+            rustc_version: $crate::loader::RUSTC_VERSION,
+            core_version: $crate::loader::CORE_VERSION,
+            register: $register,
+        };
+    };
+}
+
+pub struct LoaderRegistry {
+    // declared before `libraries` so plugin loaders are dropped before their code is unloaded
+    loaders: Vec<Box<dyn Loader>>,
+    libraries: Vec<Library>,
+}
+
+impl Default for LoaderRegistry {
+    fn default() -> Self {
+        LoaderRegistry::new()
+    }
+}
+
+impl LoaderRegistry {
+    pub fn new() -> LoaderRegistry {
+        LoaderRegistry {
+            loaders: Vec::new(),
+            libraries: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, loader: Box<dyn Loader>) -> Result<(), LoaderError> {
+        if self.get(loader.name()).is_some() {
+            return Err(LoaderError::new(
+                loader.name(),
+                "a loader with this name is already registered".to_string(),
+                LoaderErrorType::Duplicate,
+            ));
+        }
+        self.loaders.push(loader);
+        Ok(())
+    }
+
+    pub fn loaders(&self) -> &[Box<dyn Loader>] {
+        &self.loaders
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Loader> {
+        self.loaders.iter().find(|loader| loader.name() == name).map(|loader| loader.as_ref())
+    }
+
+    /// Every loader that recognizes `bytes` with its confidence, most confident first.
+    pub fn probe(&self, bytes: &[u8]) -> Vec<(&dyn Loader, f32)> {
+        let mut matches: Vec<_> = self.loaders.iter()
+            .map(|loader| (loader.as_ref(), loader.probe(bytes)))
+            .filter(|(_, confidence)| *confidence > 0.0)
+            .collect();
+        // stable, so ties go to the loader registered first
+        matches.sort_by(|a, b| b.1.total_cmp(&a.1));
+        matches
+    }
+
+    pub fn detect(&self, bytes: &[u8]) -> Option<&dyn Loader> {
+        self.probe(bytes).first().map(|(loader, _)| *loader)
+    }
+
+    /// Loads `bytes` with the loader called `name`, or with the most confident one if `name` is
+    /// `None`. Returns the name of the loader that was used along with the assembly.
+    pub fn load(&self, bytes: Vec<u8>, name: Option<&str>) -> Result<(String, Box<dyn Assembly>), LoaderError> {
+        let loader = match name {
+            Some(name) => self.get(name).ok_or_else(|| {
+                LoaderError::new(name, "no loader with this name".to_string(), LoaderErrorType::NotFound)
+            })?,
+            None => self.detect(&bytes).ok_or_else(|| {
+                LoaderError::new("", "no loader recognizes the file".to_string(), LoaderErrorType::NotFound)
+            })?,
+        };
+        Ok((loader.name().to_string(), loader.load(bytes)?))
+    }
+
+    /// Loads a plugin library and lets it register its loaders.
+    pub fn load_plugin(&mut self, path: &Path) -> Result<(), LoaderError> {
+        let plugin = path.to_string_lossy().to_string();
+        let failure = |error: libloading::Error| LoaderError::new(&plugin, error.to_string(), LoaderErrorType::PluginFailure);
+
+        // SAFETY: loading a library runs its initializers, plugins are trusted like any other code
+        // the user installs. The declaration is only used after its versions are checked.
+        let library = unsafe { Library::new(path) }.map_err(failure)?;
+        let declaration = unsafe { library.get::<*const PluginDeclaration>(PLUGIN_SYMBOL) }.map_err(failure)?;
+        let declaration = unsafe { &**declaration };
+
+        if declaration.rustc_version != RUSTC_VERSION || declaration.core_version != CORE_VERSION {
+            return Err(LoaderError::new(
+                &plugin,
+                format!(
+                    "built with rustc {} for marionette_core {}, expected rustc {} and marionette_core {}",
+                    declaration.rustc_version, declaration.core_version, RUSTC_VERSION, CORE_VERSION,
+                ),
+                LoaderErrorType::IncompatiblePlugin,
+            ));
+        }
+
+        let register = declaration.register;
+        self.libraries.push(library);
+        register(self);
+        Ok(())
+    }
+
+    /// Loads every dynamic library in `directory`. Returns the number of plugins loaded
+    /// and the errors of those that failed.
+    pub fn load_plugins(&mut self, directory: &Path) -> (usize, Vec<LoaderError>) {
+        let entries = match std::fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) => {
+                let directory = directory.to_string_lossy();
+                return (0, vec![LoaderError::new(&directory, error.to_string(), LoaderErrorType::PluginFailure)]);
+            }
+        };
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION))
+            .collect();
+        paths.sort();
+
+        let mut loaded = 0;
+        let mut errors = Vec::new();
+        for path in paths {
+            match self.load_plugin(&path) {
+                Ok(()) => loaded += 1,
+                Err(error) => errors.push(error),
+            }
+        }
+        (loaded, errors)
+    }
+}
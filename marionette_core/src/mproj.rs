@@ -1,9 +1,25 @@
 use std::fmt::{Debug};
 
+use serde::{Deserialize, Serialize};
+
+use history::HistoryEntry;
+use sections::{Bookmark, BytePatch, ColorTag, Comment, GraphLayout, ProjectFile, RawSection, Rename, WorkspaceLayout};
+
+use crate::assembly::Range;
+
 pub mod upgrader;
 pub mod reader;
 pub mod writer;
+pub mod sections;
+pub mod integrity;
+pub mod container;
+pub mod history;
+pub mod export;
 
+/// Format version written by this build. Older projects go through `upgrader::ProjectUpgrader`.
+pub const PROJECT_VERSION: &str = "0.3.0";
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct RawProject {
     // Metadata
     pub project_version: String,
@@ -12,21 +28,84 @@ pub struct RawProject {
     pub raw: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MarionetteProject {
     // Metadata
     pub project_version: String,
 
     pub project_name: String,
-    pub project_files: Vec<String>,
+    pub project_files: Vec<ProjectFile>,
+
+    // Analysis state
+    pub renames: Vec<Rename>,
+    pub comments: Vec<Comment>,
+    pub bookmarks: Vec<Bookmark>,
+    pub color_tags: Vec<ColorTag>,
+    pub layouts: Vec<GraphLayout>,
+    pub patches: Vec<BytePatch>,
+
+    /// Interface state, kept out of the history.
+    pub workspaces: Vec<WorkspaceLayout>,
+
+    /// Every change made to the analysis state, see `history`.
+    pub history: Vec<HistoryEntry>,
+
+    /// Sections written by a newer build, kept so saving does not drop them.
+    pub unknown_sections: Vec<RawSection>,
 }
 
 impl MarionetteProject {
     pub fn new() -> MarionetteProject {
         MarionetteProject {
-            project_version: env!("CARGO_PKG_VERSION").to_string(),
+            project_version: PROJECT_VERSION.to_string(),
             project_name: "New Project".to_string(),
             project_files: Vec::new(),
+            renames: Vec::new(),
+            comments: Vec::new(),
+            bookmarks: Vec::new(),
+            color_tags: Vec::new(),
+            layouts: Vec::new(),
+            patches: Vec::new(),
+            workspaces: Vec::new(),
+            history: Vec::new(),
+            unknown_sections: Vec::new(),
+        }
+    }
+
+    /// Writes the patches of `file` over `bytes`, a window of the file starting at `offset`.
+    /// Patches apply in order, so later ones win where they overlap. Returns the patched
+    /// parts of the window, as file offsets.
+    pub fn apply_patches(&self, file: &str, offset: u64, bytes: &mut [u8]) -> Vec<Range> {
+        let window = Range::new(offset, offset + bytes.len() as u64);
+        let mut patched = Vec::new();
+        for patch in self.patches.iter().filter(|patch| patch.file == file) {
+            let range = Range::new(patch.offset, patch.offset + patch.bytes.len() as u64);
+            let Some(overlap) = range.intersection(&window) else { continue };
+            let source = (overlap.start - patch.offset) as usize..(overlap.end - patch.offset) as usize;
+            let target = (overlap.start - offset) as usize..(overlap.end - offset) as usize;
+            bytes[target].copy_from_slice(&patch.bytes[source]);
+            patched.push(overlap);
+        }
+        patched
+    }
+
+    /// The saved layout of the workspace called `name`, if there is one.
+    pub fn workspace(&self, name: &str) -> Option<&str> {
+        self.workspaces.iter().find(|workspace| workspace.name == name).map(|workspace| workspace.layout.as_str())
+    }
+
+    /// Saves `layout` as the workspace called `name`, replacing what was saved under that name.
+    pub fn set_workspace(&mut self, name: &str, layout: String) {
+        match self.workspaces.iter_mut().find(|workspace| workspace.name == name) {
+            Some(workspace) => workspace.layout = layout,
+            None => self.workspaces.push(WorkspaceLayout { name: name.to_string(), layout }),
         }
     }
-}
\ No newline at end of file
+}
+
+impl Default for MarionetteProject {
+    fn default() -> Self {
+        MarionetteProject::new()
+    }
+}
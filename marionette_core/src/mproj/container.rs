@@ -0,0 +1,258 @@
+// Purpose: the on-disk layout of a project since 0.3.0.
+// A header, a table of sections and then the section payloads:
+//
+//   magic "MPRJ" | container version u32 | project version | project name
+//   table: count u64, then { name, version u32, offset u64, length u64, crc32 u32 } per section
+//   payloads, `offset` counted from the end of the table
+//
+// Only the header and the table are parsed up front. A section is checked and decoded when it
+// is asked for, so a damaged section does not take the rest of the project with it.
+
+use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamRead, ByteStreamWrite};
+use crate::mproj::{sections::{ProjectSection, RawSection}, MarionetteProject};
+
+pub const MAGIC: &[u8; 4] = b"MPRJ";
+pub const CONTAINER_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContainerErrorType {
+    BadMagic,
+    UnsupportedVersion,
+    Truncated,
+    ChecksumMismatch,
+    Malformed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerError {
+    /// The section the error is about, empty for the header and the table.
+    pub section: String,
+    pub description: String,
+    pub error_type: ContainerErrorType,
+}
+
+impl ContainerError {
+    pub fn new(section: &str, description: String, error_type: ContainerErrorType) -> ContainerError {
+        ContainerError {
+            section: section.to_string(),
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for ContainerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.section.is_empty() {
+            true => write!(f, "{}", self.description),
+            false => write!(f, "section {}: {}", self.section, self.description),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SectionEntry {
+    pub name: String,
+    pub version: u32,
+    pub offset: u64,
+    pub length: u64,
+    pub crc32: u32,
+}
+
+impl ByteStreamRead for SectionEntry {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(SectionEntry {
+            name: String::read(stream)?,
+            version: u32::read(stream)?,
+            offset: u64::read(stream)?,
+            length: u64::read(stream)?,
+            crc32: u32::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for SectionEntry {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.name.write(stream)?;
+        self.version.write(stream)?;
+        self.offset.write(stream)?;
+        self.length.write(stream)?;
+        self.crc32.write(stream)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectContainer {
+    pub project_version: String,
+    pub project_name: String,
+    pub entries: Vec<SectionEntry>,
+    /// The section payloads, `SectionEntry::offset` is relative to the start of this.
+    pub data: Vec<u8>,
+}
+
+impl ProjectContainer {
+    /// Whether `bytes` start like a container, as opposed to a pre 0.3.0 project.
+    pub fn is_container(bytes: &[u8]) -> bool {
+        bytes.starts_with(MAGIC)
+    }
+
+    /// Parses the header and the section table. Payloads are left alone until requested.
+    pub fn parse(bytes: &[u8]) -> Result<ProjectContainer, ContainerError> {
+        if !ProjectContainer::is_container(bytes) {
+            return Err(ContainerError::new("", "not a project container".to_string(), ContainerErrorType::BadMagic));
+        }
+
+        let truncated = |error: ByteStreamError| ContainerError::new("", error.to_string(), ContainerErrorType::Truncated);
+        let mut stream = ByteStream::new(bytes[MAGIC.len()..].to_vec());
+        let version = u32::read(&mut stream).map_err(truncated)?;
+        if version > CONTAINER_VERSION {
+            return Err(ContainerError::new(
+                "",
+                format!("container version {} is newer than {}", version, CONTAINER_VERSION),
+                ContainerErrorType::UnsupportedVersion,
+            ));
+        }
+
+        let project_version = String::read(&mut stream).map_err(truncated)?;
+        let project_name = String::read(&mut stream).map_err(truncated)?;
+        let entries = Vec::<SectionEntry>::read(&mut stream).map_err(truncated)?;
+
+        Ok(ProjectContainer {
+            project_version,
+            project_name,
+            entries,
+            data: stream.remaining(),
+        })
+    }
+
+    pub fn from_project(project: &MarionetteProject) -> Result<ProjectContainer, ByteStreamError> {
+        let mut container = ProjectContainer {
+            project_version: project.project_version.clone(),
+            project_name: project.project_name.clone(),
+            entries: Vec::new(),
+            data: Vec::new(),
+        };
+
+        let sections = [
+            RawSection::encode(&project.project_files)?,
+            RawSection::encode(&project.renames)?,
+            RawSection::encode(&project.comments)?,
+            RawSection::encode(&project.bookmarks)?,
+            RawSection::encode(&project.color_tags)?,
+            RawSection::encode(&project.layouts)?,
+            RawSection::encode(&project.patches)?,
+            RawSection::encode(&project.workspaces)?,
+            RawSection::encode(&project.history)?,
+        ];
+        for section in sections.iter().chain(project.unknown_sections.iter()) {
+            container.push(section);
+        }
+        Ok(container)
+    }
+
+    /// Appends a section, replacing any section with the same name.
+    pub fn push(&mut self, section: &RawSection) {
+        self.entries.retain(|entry| entry.name != section.name);
+        self.entries.push(SectionEntry {
+            name: section.name.clone(),
+            version: section.version,
+            offset: self.data.len() as u64,
+            length: section.payload.len() as u64,
+            crc32: crc32fast::hash(&section.payload),
+        });
+        self.data.extend_from_slice(&section.payload);
+    }
+
+    /// Replaces a section, or appends it, and drops the old payload instead of leaving it behind
+    /// like `push` does. Fails if another section is damaged, since it could not be carried over.
+    pub fn replace(&mut self, section: &RawSection) -> Result<(), ContainerError> {
+        let sections = self.entries.iter()
+            .filter(|entry| entry.name != section.name)
+            .map(|entry| self.raw_section(entry))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.entries.clear();
+        self.data.clear();
+        for kept in sections.iter().chain(std::iter::once(section)) {
+            self.push(kept);
+        }
+        Ok(())
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&SectionEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Returns the payload of a section after checking its bounds and checksum.
+    pub fn raw_section(&self, entry: &SectionEntry) -> Result<RawSection, ContainerError> {
+        let start = entry.offset as usize;
+        let end = start.checked_add(entry.length as usize).filter(|end| *end <= self.data.len());
+        let Some(end) = end else {
+            return Err(ContainerError::new(
+                &entry.name,
+                format!("payload 0x{:x}+0x{:x} is past the end of the file", entry.offset, entry.length),
+                ContainerErrorType::Truncated,
+            ));
+        };
+
+        let payload = &self.data[start..end];
+        let crc32 = crc32fast::hash(payload);
+        if crc32 != entry.crc32 {
+            return Err(ContainerError::new(
+                &entry.name,
+                format!("checksum is {:08x}, expected {:08x}", crc32, entry.crc32),
+                ContainerErrorType::ChecksumMismatch,
+            ));
+        }
+
+        Ok(RawSection {
+            name: entry.name.clone(),
+            version: entry.version,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Decodes one section. A section that is not in the container reads as empty.
+    pub fn section<T: ProjectSection>(&self) -> Result<Vec<T>, ContainerError> {
+        match self.entry(T::NAME) {
+            Some(entry) => self.raw_section(entry)?.decode::<T>().map_err(|error| {
+                ContainerError::new(T::NAME, error.to_string(), ContainerErrorType::Malformed)
+            }),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Decodes every section. Sections that fail to load are left empty and reported,
+    /// the rest of the project is still returned.
+    pub fn project(&self) -> (MarionetteProject, Vec<ContainerError>) {
+        let mut project = MarionetteProject {
+            project_version: self.project_version.clone(),
+            project_name: self.project_name.clone(),
+            ..MarionetteProject::new()
+        };
+
+        let mut errors = Vec::new();
+        for entry in &self.entries {
+            let loaded = self.raw_section(entry).and_then(|section| {
+                project.load_section(section).map_err(|error| {
+                    ContainerError::new(&entry.name, error.to_string(), ContainerErrorType::Malformed)
+                })
+            });
+            if let Err(error) = loaded {
+                errors.push(error);
+            }
+        }
+        (project, errors)
+    }
+}
+
+impl ByteStreamWrite for ProjectContainer {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        stream.write_bytes_slice(MAGIC)?;
+        CONTAINER_VERSION.write(stream)?;
+        self.project_version.write(stream)?;
+        self.project_name.write(stream)?;
+        self.entries.write(stream)?;
+        stream.write_bytes_slice(&self.data)
+    }
+}
@@ -0,0 +1,125 @@
+// Purpose: a text form of `MarionetteProject` that can be reviewed and diffed.
+// Exporting and importing again gives back the same project. Bytes (hashes, embedded files,
+// patches) are written as hex strings.
+
+use std::path::Path;
+
+use crate::mproj::{MarionetteProject, PROJECT_VERSION};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Json,
+    Toml,
+}
+
+impl ExportFormat {
+    /// Picks the format from the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(ExportFormat::Json),
+            "toml" => Some(ExportFormat::Toml),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportErrorType {
+    SerializeFailure,
+    ParseFailure,
+    UnsupportedVersion,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportError {
+    pub description: String,
+    pub error_type: ExportErrorType,
+}
+
+impl ExportError {
+    pub fn new(description: String, error_type: ExportErrorType) -> ExportError {
+        ExportError {
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.description)
+    }
+}
+
+impl MarionetteProject {
+    pub fn export(&self, format: ExportFormat) -> Result<String, ExportError> {
+        let serialize = |description: String| ExportError::new(description, ExportErrorType::SerializeFailure);
+        match format {
+            ExportFormat::Json => serde_json::to_string_pretty(self).map_err(|error| serialize(error.to_string())),
+            ExportFormat::Toml => toml::to_string_pretty(self).map_err(|error| serialize(error.to_string())),
+        }
+    }
+
+    /// Reads an exported project. Only exports of the current format version are accepted,
+    /// older projects should be upgraded in their binary form first.
+    pub fn import(text: &str, format: ExportFormat) -> Result<MarionetteProject, ExportError> {
+        let parse = |description: String| ExportError::new(description, ExportErrorType::ParseFailure);
+        let project: MarionetteProject = match format {
+            ExportFormat::Json => serde_json::from_str(text).map_err(|error| parse(error.to_string()))?,
+            ExportFormat::Toml => toml::from_str(text).map_err(|error| parse(error.to_string()))?,
+        };
+
+        if project.project_version != PROJECT_VERSION {
+            return Err(ExportError::new(
+                format!("exported project has version {}, expected {}", project.project_version, PROJECT_VERSION),
+                ExportErrorType::UnsupportedVersion,
+            ));
+        }
+        Ok(project)
+    }
+}
+
+/// `#[serde(with = "hex")]` for `Vec<u8>` fields.
+pub(crate) mod hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn encode(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+        if !text.len().is_multiple_of(2) || !text.is_ascii() {
+            return Err(format!("invalid hex string {:?}", text));
+        }
+        (0..text.len()).step_by(2)
+            .map(|index| u8::from_str_radix(&text[index..index + 2], 16).map_err(|error| error.to_string()))
+            .collect()
+    }
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        decode(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+/// `#[serde(with = "hex_option", default)]` for `Option<Vec<u8>>` fields.
+pub(crate) mod hex_option {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&super::hex::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => super::hex::decode(&text).map(Some).map_err(D::Error::custom),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,325 @@
+// Purpose: undo/redo for the analysis state of a project.
+// Every change to renames, comments, bookmarks, color tags, layouts and patches goes through
+// a `Command` holding the value before and after it, so it can be applied in either direction.
+// The log is stored in the project, undone entries included, so redo survives a reload.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamErrorType, ByteStreamRead, ByteStreamWrite};
+use crate::mproj::{export::hex_option, sections::*, MarionetteProject};
+
+/// A change to one annotation. `None` means the annotation does not exist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Command {
+    Rename { file: String, address: u64, register: Option<u32>, before: Option<String>, after: Option<String> },
+    Comment { file: String, address: u64, before: Option<String>, after: Option<String> },
+    Bookmark { file: String, address: u64, before: Option<String>, after: Option<String> },
+    ColorTag { file: String, function: u64, block: u64, before: Option<u32>, after: Option<u32> },
+    Layout { file: String, function: u64, before: Option<Vec<NodePosition>>, after: Option<Vec<NodePosition>> },
+    Patch {
+        file: String,
+        offset: u64,
+        #[serde(with = "hex_option", default)]
+        before: Option<Vec<u8>>,
+        #[serde(with = "hex_option", default)]
+        after: Option<Vec<u8>>,
+    },
+}
+
+/// Sets, replaces or removes the item of `items` matching `key`.
+fn set<T, V>(items: &mut Vec<T>, key: impl Fn(&T) -> bool, value: Option<V>, update: impl Fn(&mut T, V), create: impl Fn(V) -> T) {
+    let index = items.iter().position(key);
+    match (index, value) {
+        (Some(index), Some(value)) => update(&mut items[index], value),
+        (Some(index), None) => {
+            items.remove(index);
+        }
+        (None, Some(value)) => items.push(create(value)),
+        (None, None) => {}
+    }
+}
+
+impl Command {
+    pub fn description(&self) -> String {
+        let verb = |before: bool, after: bool| match (before, after) {
+            (false, _) => "add",
+            (true, true) => "change",
+            (true, false) => "remove",
+        };
+
+        match self {
+            Command::Rename { address, register: None, before, after, .. } => format!("{} function name at 0x{:x}", verb(before.is_some(), after.is_some()), address),
+            Command::Rename { address, register: Some(register), before, after, .. } => format!("{} name of r{} in 0x{:x}", verb(before.is_some(), after.is_some()), register, address),
+            Command::Comment { address, before, after, .. } => format!("{} comment at 0x{:x}", verb(before.is_some(), after.is_some()), address),
+            Command::Bookmark { address, before, after, .. } => format!("{} bookmark at 0x{:x}", verb(before.is_some(), after.is_some()), address),
+            Command::ColorTag { function, block, before, after, .. } => format!("{} color of block {} in function {}", verb(before.is_some(), after.is_some()), block, function),
+            Command::Layout { function, before, after, .. } => format!("{} layout of function {}", verb(before.is_some(), after.is_some()), function),
+            Command::Patch { offset, before, after, .. } => format!("{} patch at 0x{:x}", verb(before.is_some(), after.is_some()), offset),
+        }
+    }
+
+    /// Fills in `before` with the current state of `project`.
+    fn capture(&mut self, project: &MarionetteProject) {
+        match self {
+            Command::Rename { file, address, register, before, .. } => {
+                *before = project.renames.iter()
+                    .find(|rename| rename.file == *file && rename.address == *address && rename.register == *register)
+                    .map(|rename| rename.name.clone());
+            }
+            Command::Comment { file, address, before, .. } => {
+                *before = project.comments.iter()
+                    .find(|comment| comment.file == *file && comment.address == *address)
+                    .map(|comment| comment.text.clone());
+            }
+            Command::Bookmark { file, address, before, .. } => {
+                *before = project.bookmarks.iter()
+                    .find(|bookmark| bookmark.file == *file && bookmark.address == *address)
+                    .map(|bookmark| bookmark.label.clone());
+            }
+            Command::ColorTag { file, function, block, before, .. } => {
+                *before = project.color_tags.iter()
+                    .find(|tag| tag.file == *file && tag.function == *function && tag.block == *block)
+                    .map(|tag| tag.color);
+            }
+            Command::Layout { file, function, before, .. } => {
+                *before = project.layouts.iter()
+                    .find(|layout| layout.file == *file && layout.function == *function)
+                    .map(|layout| layout.nodes.clone());
+            }
+            Command::Patch { file, offset, before, .. } => {
+                *before = project.patches.iter()
+                    .find(|patch| patch.file == *file && patch.offset == *offset)
+                    .map(|patch| patch.bytes.clone());
+            }
+        }
+    }
+
+    /// Applies the command, or reverts it if `undo` is set.
+    fn apply(&self, project: &mut MarionetteProject, undo: bool) {
+        match self {
+            Command::Rename { file, address, register, before, after } => set(
+                &mut project.renames,
+                |rename| rename.file == *file && rename.address == *address && rename.register == *register,
+                if undo { before.clone() } else { after.clone() },
+                |rename, name| rename.name = name,
+                |name| Rename { file: file.clone(), address: *address, register: *register, name },
+            ),
+            Command::Comment { file, address, before, after } => set(
+                &mut project.comments,
+                |comment| comment.file == *file && comment.address == *address,
+                if undo { before.clone() } else { after.clone() },
+                |comment, text| comment.text = text,
+                |text| Comment { file: file.clone(), address: *address, text },
+            ),
+            Command::Bookmark { file, address, before, after } => set(
+                &mut project.bookmarks,
+                |bookmark| bookmark.file == *file && bookmark.address == *address,
+                if undo { before.clone() } else { after.clone() },
+                |bookmark, label| bookmark.label = label,
+                |label| Bookmark { file: file.clone(), address: *address, label },
+            ),
+            Command::ColorTag { file, function, block, before, after } => set(
+                &mut project.color_tags,
+                |tag| tag.file == *file && tag.function == *function && tag.block == *block,
+                if undo { *before } else { *after },
+                |tag, color| tag.color = color,
+                |color| ColorTag { file: file.clone(), function: *function, block: *block, color },
+            ),
+            Command::Layout { file, function, before, after } => set(
+                &mut project.layouts,
+                |layout| layout.file == *file && layout.function == *function,
+                if undo { before.clone() } else { after.clone() },
+                |layout, nodes| layout.nodes = nodes,
+                |nodes| GraphLayout { file: file.clone(), function: *function, nodes },
+            ),
+            Command::Patch { file, offset, before, after } => set(
+                &mut project.patches,
+                |patch| patch.file == *file && patch.offset == *offset,
+                if undo { before.clone() } else { after.clone() },
+                |patch, bytes| patch.bytes = bytes,
+                |bytes| BytePatch { file: file.clone(), offset: *offset, bytes },
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub command: Command,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    pub author: String,
+    /// Undone entries are kept at the end of the log until a new command replaces them.
+    pub undone: bool,
+}
+
+impl ProjectSection for HistoryEntry {
+    const NAME: &'static str = "history";
+    const VERSION: u32 = 1;
+}
+
+impl MarionetteProject {
+    /// Applies `command` and records it. The `before` values of the command are filled in
+    /// from the project, so callers only need to provide `after`.
+    /// Anything that was undone can no longer be redone.
+    pub fn execute(&mut self, mut command: Command, author: &str) -> &HistoryEntry {
+        command.capture(self);
+        command.apply(self, false);
+
+        self.history.retain(|entry| !entry.undone);
+        self.history.push(HistoryEntry {
+            command,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0),
+            author: author.to_string(),
+            undone: false,
+        });
+        self.history.last().unwrap()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.history.iter().any(|entry| !entry.undone)
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.history.iter().any(|entry| entry.undone)
+    }
+
+    /// Reverts the last applied command and returns its entry.
+    pub fn undo(&mut self) -> Option<&HistoryEntry> {
+        let index = self.history.iter().rposition(|entry| !entry.undone)?;
+        let command = self.history[index].command.clone();
+        command.apply(self, true);
+        self.history[index].undone = true;
+        Some(&self.history[index])
+    }
+
+    /// Applies the first undone command again and returns its entry.
+    pub fn redo(&mut self) -> Option<&HistoryEntry> {
+        let index = self.history.iter().position(|entry| entry.undone)?;
+        let command = self.history[index].command.clone();
+        command.apply(self, false);
+        self.history[index].undone = false;
+        Some(&self.history[index])
+    }
+}
+
+impl ByteStreamRead for Command {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        let command = match u8::read(stream)? {
+            0 => Command::Rename {
+                file: String::read(stream)?,
+                address: u64::read(stream)?,
+                register: Option::<u32>::read(stream)?,
+                before: Option::<String>::read(stream)?,
+                after: Option::<String>::read(stream)?,
+            },
+            1 => Command::Comment {
+                file: String::read(stream)?,
+                address: u64::read(stream)?,
+                before: Option::<String>::read(stream)?,
+                after: Option::<String>::read(stream)?,
+            },
+            2 => Command::Bookmark {
+                file: String::read(stream)?,
+                address: u64::read(stream)?,
+                before: Option::<String>::read(stream)?,
+                after: Option::<String>::read(stream)?,
+            },
+            3 => Command::ColorTag {
+                file: String::read(stream)?,
+                function: u64::read(stream)?,
+                block: u64::read(stream)?,
+                before: Option::<u32>::read(stream)?,
+                after: Option::<u32>::read(stream)?,
+            },
+            4 => Command::Layout {
+                file: String::read(stream)?,
+                function: u64::read(stream)?,
+                before: Option::<Vec<NodePosition>>::read(stream)?,
+                after: Option::<Vec<NodePosition>>::read(stream)?,
+            },
+            5 => Command::Patch {
+                file: String::read(stream)?,
+                offset: u64::read(stream)?,
+                before: Option::<Vec<u8>>::read(stream)?,
+                after: Option::<Vec<u8>>::read(stream)?,
+            },
+            kind => return Err(ByteStreamError::new(stream, format!("Unknown command kind {}", kind), ByteStreamErrorType::ReadFailure)),
+        };
+        Ok(command)
+    }
+}
+
+impl ByteStreamWrite for Command {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        match self {
+            Command::Rename { file, address, register, before, after } => {
+                0u8.write(stream)?;
+                file.write(stream)?;
+                address.write(stream)?;
+                register.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+            Command::Comment { file, address, before, after } => {
+                1u8.write(stream)?;
+                file.write(stream)?;
+                address.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+            Command::Bookmark { file, address, before, after } => {
+                2u8.write(stream)?;
+                file.write(stream)?;
+                address.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+            Command::ColorTag { file, function, block, before, after } => {
+                3u8.write(stream)?;
+                file.write(stream)?;
+                function.write(stream)?;
+                block.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+            Command::Layout { file, function, before, after } => {
+                4u8.write(stream)?;
+                file.write(stream)?;
+                function.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+            Command::Patch { file, offset, before, after } => {
+                5u8.write(stream)?;
+                file.write(stream)?;
+                offset.write(stream)?;
+                before.write(stream)?;
+                after.write(stream)
+            }
+        }
+    }
+}
+
+impl ByteStreamRead for HistoryEntry {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(HistoryEntry {
+            command: Command::read(stream)?,
+            timestamp: u64::read(stream)?,
+            author: String::read(stream)?,
+            undone: bool::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for HistoryEntry {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.command.write(stream)?;
+        self.timestamp.write(stream)?;
+        self.author.write(stream)?;
+        self.undone.write(stream)
+    }
+}
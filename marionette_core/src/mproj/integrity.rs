@@ -0,0 +1,188 @@
+// Purpose: keep the inputs of a project identifiable when the project moves.
+// Every file records its SHA-256 and size, and may carry a compressed copy of itself,
+// so a project can tell missing or modified inputs apart and still open without them.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use sha2::{Digest, Sha256};
+
+use crate::mproj::{sections::{FileIntegrity, ProjectFile}, MarionetteProject};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileStatus {
+    /// The file on disk matches the recorded hash.
+    Verified,
+    /// The file exists but nothing was recorded to compare it with.
+    Unverified,
+    Missing,
+    /// The file on disk is not the one that was added.
+    Changed(FileIntegrity),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityErrorType {
+    Missing,
+    Changed,
+    CorruptEmbedded,
+    Io,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityError {
+    pub path: String,
+    pub description: String,
+    pub error_type: IntegrityErrorType,
+}
+
+impl IntegrityError {
+    pub fn new(path: &str, description: String, error_type: IntegrityErrorType) -> IntegrityError {
+        IntegrityError {
+            path: path.to_string(),
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.description)
+    }
+}
+
+impl FileIntegrity {
+    pub fn compute(bytes: &[u8]) -> FileIntegrity {
+        FileIntegrity {
+            sha256: Sha256::digest(bytes).to_vec(),
+            size: bytes.len() as u64,
+        }
+    }
+
+    pub fn hex(&self) -> String {
+        self.sha256.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl ProjectFile {
+    /// Where the file is expected to be for a project stored in `base`.
+    pub fn resolve(&self, base: &Path) -> PathBuf {
+        base.join(&self.path)
+    }
+
+    /// Records the hash and size of `bytes`, and keeps a compressed copy of them if `embed` is set.
+    pub fn track(&mut self, bytes: &[u8], embed: bool) -> Result<(), IntegrityError> {
+        self.integrity = Some(FileIntegrity::compute(bytes));
+        self.embedded = None;
+
+        if embed {
+            let io = |error: std::io::Error| IntegrityError::new(&self.path, error.to_string(), IntegrityErrorType::Io);
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(bytes).map_err(io)?;
+            self.embedded = Some(encoder.finish().map_err(io)?);
+        }
+        Ok(())
+    }
+
+    /// Decompresses the embedded copy, checking it against the recorded hash.
+    pub fn embedded_bytes(&self) -> Result<Option<Vec<u8>>, IntegrityError> {
+        let Some(embedded) = &self.embedded else {
+            return Ok(None);
+        };
+
+        let mut bytes = Vec::new();
+        DeflateDecoder::new(embedded.as_slice()).read_to_end(&mut bytes).map_err(|error| {
+            IntegrityError::new(&self.path, error.to_string(), IntegrityErrorType::CorruptEmbedded)
+        })?;
+
+        if let Some(integrity) = &self.integrity {
+            if FileIntegrity::compute(&bytes) != *integrity {
+                return Err(IntegrityError::new(
+                    &self.path,
+                    "embedded copy does not match the recorded hash".to_string(),
+                    IntegrityErrorType::CorruptEmbedded,
+                ));
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    pub fn status(&self, base: &Path) -> FileStatus {
+        match std::fs::read(self.resolve(base)) {
+            Ok(bytes) => self.compare(&bytes),
+            Err(_) => FileStatus::Missing,
+        }
+    }
+
+    fn compare(&self, bytes: &[u8]) -> FileStatus {
+        let found = FileIntegrity::compute(bytes);
+        match &self.integrity {
+            Some(integrity) if *integrity == found => FileStatus::Verified,
+            Some(_) => FileStatus::Changed(found),
+            None => FileStatus::Unverified,
+        }
+    }
+
+    /// Reads the file from disk, or from the embedded copy if the file on disk is missing or changed.
+    pub fn load(&self, base: &Path) -> Result<Vec<u8>, IntegrityError> {
+        let error = match std::fs::read(self.resolve(base)) {
+            Ok(bytes) => match self.compare(&bytes) {
+                FileStatus::Changed(found) => IntegrityError::new(
+                    &self.path,
+                    format!("file changed since it was added (sha256 {})", found.hex()),
+                    IntegrityErrorType::Changed,
+                ),
+                _ => return Ok(bytes),
+            },
+            Err(error) => IntegrityError::new(&self.path, error.to_string(), IntegrityErrorType::Missing),
+        };
+
+        self.embedded_bytes()?.ok_or(error)
+    }
+}
+
+/// `path` as stored in a project kept in `base`: relative when possible, with forward slashes
+/// so the project opens on any platform.
+fn project_path(path: &Path, base: &Path) -> String {
+    path.strip_prefix(base).unwrap_or(path).to_string_lossy().replace('\\', "/")
+}
+
+impl MarionetteProject {
+    /// Adds the file at `path` to a project stored in `base`, recording its hash.
+    /// Returns the index of the new file.
+    pub fn add_file(&mut self, path: &Path, base: &Path, embed: bool) -> Result<usize, IntegrityError> {
+        let mut file = ProjectFile::new(&project_path(path, base));
+
+        let bytes = std::fs::read(path).map_err(|error| {
+            IntegrityError::new(&file.path, error.to_string(), IntegrityErrorType::Missing)
+        })?;
+        file.track(&bytes, embed)?;
+
+        self.project_files.push(file);
+        Ok(self.project_files.len() - 1)
+    }
+
+    /// Checks every file of a project stored in `base`.
+    pub fn verify_files(&self, base: &Path) -> Vec<FileStatus> {
+        self.project_files.iter().map(|file| file.status(base)).collect()
+    }
+
+    /// Points a missing or moved file at `path`. The file is only relinked if it matches the
+    /// recorded hash (or if there is none); the status of `path` is returned either way.
+    pub fn relink(&mut self, index: usize, path: &Path, base: &Path) -> FileStatus {
+        let Some(file) = self.project_files.get_mut(index) else {
+            return FileStatus::Missing;
+        };
+
+        let status = match std::fs::read(path) {
+            Ok(bytes) => file.compare(&bytes),
+            Err(_) => FileStatus::Missing,
+        };
+
+        if matches!(status, FileStatus::Verified | FileStatus::Unverified) {
+            file.path = project_path(path, base);
+        }
+        status
+    }
+}
@@ -1,11 +1,27 @@
-use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamErrorType, ByteStreamRead, ByteStreamWrite};
+use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamErrorType, ByteStreamRead};
 use crate::mproj::RawProject;
+use crate::mproj::container::ProjectContainer;
+use crate::mproj::history::HistoryEntry;
+use crate::mproj::sections::{Bookmark, BytePatch, ColorTag, Comment, GraphLayout, ProjectFile, ProjectSection, RawSection, Rename, WorkspaceLayout};
 
 use super::MarionetteProject;
 
 impl ByteStreamRead for RawProject {
     fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
         let raw = stream.bytes.clone();
+
+        // projects before 0.3.0 start with the version string, later ones with a container header
+        if ProjectContainer::is_container(&stream.remaining()) {
+            let container = ProjectContainer::parse(&stream.remaining()).map_err(|error| {
+                ByteStreamError::new(stream, error.to_string(), ByteStreamErrorType::ReadFailure)
+            })?;
+            return Ok(RawProject {
+                project_version: container.project_version,
+                raw,
+                remaining: container.data,
+            });
+        }
+
         let project_version = String::read(stream)?;
         let remaining = stream.remaining();
         Ok(RawProject {
@@ -16,26 +32,38 @@ impl ByteStreamRead for RawProject {
     }
 }
 
+impl MarionetteProject {
+    /// Fills in the field a section stores. Sections of unknown names are kept as they are.
+    pub(crate) fn load_section(&mut self, section: RawSection) -> Result<(), ByteStreamError> {
+        match section.name.as_str() {
+            name if name == ProjectFile::NAME => self.project_files = section.decode()?,
+            name if name == Rename::NAME => self.renames = section.decode()?,
+            name if name == Comment::NAME => self.comments = section.decode()?,
+            name if name == Bookmark::NAME => self.bookmarks = section.decode()?,
+            name if name == ColorTag::NAME => self.color_tags = section.decode()?,
+            name if name == GraphLayout::NAME => self.layouts = section.decode()?,
+            name if name == BytePatch::NAME => self.patches = section.decode()?,
+            name if name == WorkspaceLayout::NAME => self.workspaces = section.decode()?,
+            name if name == HistoryEntry::NAME => self.history = section.decode()?,
+            _ => self.unknown_sections.push(section),
+        }
+        Ok(())
+    }
+}
+
 impl ByteStreamRead for MarionetteProject {
+    /// Reads a whole project, failing if any section is damaged.
+    /// Use `ProjectContainer::project` to recover what is left of a damaged project.
     fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
-        let project_version = String::read(stream)?;
-        
-        let project_name = String::read(stream);
-        if project_name.is_err() {
-            return Err(ByteStreamError::new(stream, "Failed to read project name".to_string(), ByteStreamErrorType::ReadFailure));
-        }
-        let project_name = project_name.unwrap();
-        
-        let project_files = Vec::<String>::read(stream);
-        if project_files.is_err() {
-            return Err(ByteStreamError::new(stream, "Failed to read project files".to_string(), ByteStreamErrorType::ReadFailure));
-        }
-        let project_files = project_files.unwrap();
+        let bytes = stream.read_bytes(stream.bytes.len() - stream.caret())?;
+        let container = ProjectContainer::parse(&bytes).map_err(|error| {
+            ByteStreamError::new(stream, error.to_string(), ByteStreamErrorType::ReadFailure)
+        })?;
 
-        Ok(MarionetteProject {
-            project_version,
-            project_name,
-            project_files,
-        })
+        let (project, errors) = container.project();
+        if let Some(error) = errors.first() {
+            return Err(ByteStreamError::new(stream, error.to_string(), ByteStreamErrorType::ReadFailure));
+        }
+        Ok(project)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,426 @@
+// Purpose: the data a project keeps next to its files, stored as versioned sections.
+// Every section is a list of one item type, written as `RawSection { name, version, payload }`
+// so a reader can skip (and keep) sections it does not know about.
+
+use serde::{Deserialize, Serialize};
+
+use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamErrorType, ByteStreamRead, ByteStreamWrite};
+use crate::mproj::export::{hex, hex_option};
+
+/// An item type stored as a list in its own project section.
+pub trait ProjectSection: ByteStreamRead + ByteStreamWrite {
+    const NAME: &'static str;
+    /// Bumped whenever the encoding of the item changes.
+    const VERSION: u32;
+
+    /// Reads an item written with an older `VERSION` of the section.
+    fn read_version(stream: &mut ByteStream, _version: u32) -> Result<Self, ByteStreamError> {
+        Self::read(stream)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawSection {
+    pub name: String,
+    pub version: u32,
+    #[serde(with = "hex")]
+    pub payload: Vec<u8>,
+}
+
+impl RawSection {
+    pub fn encode<T: ProjectSection>(items: &[T]) -> Result<RawSection, ByteStreamError> {
+        // same layout as Vec<T>
+        let mut stream = ByteStream::new(Vec::new());
+        (items.len() as u64).write(&mut stream)?;
+        for item in items {
+            item.write(&mut stream)?;
+        }
+        Ok(RawSection {
+            name: T::NAME.to_string(),
+            version: T::VERSION,
+            payload: stream.bytes,
+        })
+    }
+
+    pub fn decode<T: ProjectSection>(&self) -> Result<Vec<T>, ByteStreamError> {
+        let mut stream = ByteStream::new(self.payload.clone());
+        if self.version > T::VERSION {
+            return Err(ByteStreamError::new(
+                &mut stream,
+                format!("Section {} has unsupported version {}", self.name, self.version),
+                ByteStreamErrorType::ReadFailure,
+            ));
+        }
+
+        let length = u64::read(&mut stream)? as usize;
+        let mut items = Vec::with_capacity(length.min(self.payload.len()));
+        for _ in 0..length {
+            items.push(T::read_version(&mut stream, self.version)?);
+        }
+        if !stream.remaining().is_empty() {
+            return Err(ByteStreamError::new(
+                &mut stream,
+                format!("Trailing bytes in section {}", self.name),
+                ByteStreamErrorType::ReadFailure,
+            ));
+        }
+        Ok(items)
+    }
+}
+
+impl ByteStreamRead for RawSection {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(RawSection {
+            name: String::read(stream)?,
+            version: u32::read(stream)?,
+            payload: Vec::<u8>::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for RawSection {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.name.write(stream)?;
+        self.version.write(stream)?;
+        self.payload.write(stream)
+    }
+}
+
+/// A file of the project and how it should be opened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectFile {
+    /// Relative to the project file's directory, unless the file lives outside of it.
+    pub path: String,
+    /// Detected or user-chosen format, e.g. "lua51".
+    pub format: Option<String>,
+    /// Name of the loader used to open the file, `None` to pick one automatically.
+    pub loader: Option<String>,
+    /// Hash and size of the file when it was added, `None` for files added before version 2.
+    pub integrity: Option<FileIntegrity>,
+    /// Deflate compressed copy of the file.
+    #[serde(with = "hex_option", default)]
+    pub embedded: Option<Vec<u8>>,
+}
+
+impl ProjectFile {
+    pub fn new(path: &str) -> ProjectFile {
+        ProjectFile {
+            path: path.to_string(),
+            format: None,
+            loader: None,
+            integrity: None,
+            embedded: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileIntegrity {
+    #[serde(with = "hex")]
+    pub sha256: Vec<u8>,
+    pub size: u64,
+}
+
+/// A user-given name for a function, or for a local of that function when `register` is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rename {
+    pub file: String,
+    /// Address of the function.
+    pub address: u64,
+    pub register: Option<u32>,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Comment {
+    pub file: String,
+    pub address: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub file: String,
+    pub address: u64,
+    pub label: String,
+}
+
+/// A color given to a basic block of a function's control flow graph.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ColorTag {
+    pub file: String,
+    pub function: u64,
+    pub block: u64,
+    /// 0xRRGGBBAA
+    pub color: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodePosition {
+    pub block: u64,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Node positions of a control flow graph the user arranged by hand.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GraphLayout {
+    pub file: String,
+    pub function: u64,
+    pub nodes: Vec<NodePosition>,
+}
+
+/// How the interface arranged its tabs and widgets, restored when the project is opened again.
+/// `layout` is the interface's own JSON; the core only keeps it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceLayout {
+    pub name: String,
+    pub layout: String,
+}
+
+/// Bytes written over a file at `offset`. The original bytes stay in the file itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BytePatch {
+    pub file: String,
+    pub offset: u64,
+    #[serde(with = "hex")]
+    pub bytes: Vec<u8>,
+}
+
+impl ProjectSection for ProjectFile {
+    const NAME: &'static str = "files";
+    const VERSION: u32 = 2;
+
+    fn read_version(stream: &mut ByteStream, version: u32) -> Result<Self, ByteStreamError> {
+        match version {
+            // no integrity information
+            1 => Ok(ProjectFile {
+                path: String::read(stream)?,
+                format: Option::<String>::read(stream)?,
+                loader: Option::<String>::read(stream)?,
+                integrity: None,
+                embedded: None,
+            }),
+            _ => ProjectFile::read(stream),
+        }
+    }
+}
+
+impl ProjectSection for Rename {
+    const NAME: &'static str = "renames";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for Comment {
+    const NAME: &'static str = "comments";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for Bookmark {
+    const NAME: &'static str = "bookmarks";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for ColorTag {
+    const NAME: &'static str = "color_tags";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for GraphLayout {
+    const NAME: &'static str = "layouts";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for BytePatch {
+    const NAME: &'static str = "patches";
+    const VERSION: u32 = 1;
+}
+
+impl ProjectSection for WorkspaceLayout {
+    const NAME: &'static str = "workspaces";
+    const VERSION: u32 = 1;
+}
+
+impl ByteStreamRead for ProjectFile {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(ProjectFile {
+            path: String::read(stream)?,
+            format: Option::<String>::read(stream)?,
+            loader: Option::<String>::read(stream)?,
+            integrity: Option::<FileIntegrity>::read(stream)?,
+            embedded: Option::<Vec<u8>>::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for ProjectFile {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.path.write(stream)?;
+        self.format.write(stream)?;
+        self.loader.write(stream)?;
+        self.integrity.write(stream)?;
+        self.embedded.write(stream)
+    }
+}
+
+impl ByteStreamRead for FileIntegrity {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(FileIntegrity {
+            sha256: Vec::<u8>::read(stream)?,
+            size: u64::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for FileIntegrity {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.sha256.write(stream)?;
+        self.size.write(stream)
+    }
+}
+
+impl ByteStreamRead for Rename {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(Rename {
+            file: String::read(stream)?,
+            address: u64::read(stream)?,
+            register: Option::<u32>::read(stream)?,
+            name: String::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for Rename {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.address.write(stream)?;
+        self.register.write(stream)?;
+        self.name.write(stream)
+    }
+}
+
+impl ByteStreamRead for Comment {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(Comment {
+            file: String::read(stream)?,
+            address: u64::read(stream)?,
+            text: String::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for Comment {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.address.write(stream)?;
+        self.text.write(stream)
+    }
+}
+
+impl ByteStreamRead for Bookmark {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(Bookmark {
+            file: String::read(stream)?,
+            address: u64::read(stream)?,
+            label: String::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for Bookmark {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.address.write(stream)?;
+        self.label.write(stream)
+    }
+}
+
+impl ByteStreamRead for ColorTag {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(ColorTag {
+            file: String::read(stream)?,
+            function: u64::read(stream)?,
+            block: u64::read(stream)?,
+            color: u32::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for ColorTag {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.function.write(stream)?;
+        self.block.write(stream)?;
+        self.color.write(stream)
+    }
+}
+
+impl ByteStreamRead for NodePosition {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(NodePosition {
+            block: u64::read(stream)?,
+            x: f64::read(stream)?,
+            y: f64::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for NodePosition {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.block.write(stream)?;
+        self.x.write(stream)?;
+        self.y.write(stream)
+    }
+}
+
+impl ByteStreamRead for GraphLayout {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(GraphLayout {
+            file: String::read(stream)?,
+            function: u64::read(stream)?,
+            nodes: Vec::<NodePosition>::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for GraphLayout {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.function.write(stream)?;
+        self.nodes.write(stream)
+    }
+}
+
+impl ByteStreamRead for BytePatch {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(BytePatch {
+            file: String::read(stream)?,
+            offset: u64::read(stream)?,
+            bytes: Vec::<u8>::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for BytePatch {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.file.write(stream)?;
+        self.offset.write(stream)?;
+        self.bytes.write(stream)
+    }
+}
+
+impl ByteStreamRead for WorkspaceLayout {
+    fn read(stream: &mut ByteStream) -> Result<Self, ByteStreamError> {
+        Ok(WorkspaceLayout {
+            name: String::read(stream)?,
+            layout: String::read(stream)?,
+        })
+    }
+}
+
+impl ByteStreamWrite for WorkspaceLayout {
+    fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
+        self.name.write(stream)?;
+        self.layout.write(stream)
+    }
+}
@@ -1,21 +1,293 @@
-use std::collections::HashMap;
-use crate::{byte_stream::{ByteStream, ByteStreamRead}, mproj::{MarionetteProject, RawProject}};
+use std::{collections::{HashMap, HashSet, VecDeque}, path::{Path, PathBuf}};
+use crate::{byte_stream::{ByteStream, ByteStreamError, ByteStreamRead, ByteStreamWrite}, mproj::{sections::{ProjectFile, RawSection}, MarionetteProject, RawProject, PROJECT_VERSION}};
+
+/// Rewrites a project of one format version into the next one.
+pub type Migration = Box<dyn Fn(RawProject) -> Result<RawProject, String>>;
+
+/// Checks that a project really is in the format it claims to be.
+pub type Validator = Box<dyn Fn(&RawProject) -> Result<(), String>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpgradeErrorType {
+    NoPath,
+    MigrationFailure,
+    ValidationFailure,
+    ReadFailure,
+    IoFailure,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpgradeError {
+    pub from: String,
+    pub to: String,
+    pub description: String,
+    pub error_type: UpgradeErrorType,
+}
+
+impl UpgradeError {
+    pub fn new(from: &str, to: &str, description: String, error_type: UpgradeErrorType) -> UpgradeError {
+        UpgradeError {
+            from: from.to_string(),
+            to: to.to_string(),
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} -> {}: {}", self.from, self.to, self.description)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpgradeReport {
+    pub from: String,
+    pub to: String,
+    /// The (from, to) migrations that ran, in order. Empty if the project was already current.
+    pub migrations: Vec<(String, String)>,
+    /// Where `upgrade_file` saved the original file, if it had to rewrite it.
+    pub backup: Option<PathBuf>,
+}
 
 pub struct ProjectUpgrader {
-    upgrade_functions: HashMap<(String, String), Box<dyn Fn(RawProject) -> MarionetteProject>>
+    upgrade_functions: HashMap<(String, String), Migration>,
+    validators: HashMap<String, Validator>,
+}
+
+impl Default for ProjectUpgrader {
+    fn default() -> Self {
+        ProjectUpgrader::new()
+    }
 }
 
 impl ProjectUpgrader {
     pub fn new() -> ProjectUpgrader {
-        let mut upgrade_functions: HashMap<(String, String), Box<dyn Fn(RawProject) -> MarionetteProject>> = HashMap::new();
-        
-        upgrade_functions.insert((env!("CARGO_PKG_VERSION").to_string(), env!("CARGO_PKG_VERSION").to_string()), Box::new(|proj| {
-            let mut stream = ByteStream::new(proj.raw);
-            MarionetteProject::read(&mut stream).unwrap()
+        let mut upgrader = ProjectUpgrader {
+            upgrade_functions: HashMap::new(),
+            validators: HashMap::new(),
+        };
+
+        upgrader.register_validator(PROJECT_VERSION, Box::new(|proj| {
+            let mut stream = ByteStream::new(proj.raw.clone());
+            MarionetteProject::read(&mut stream).map_err(|error| error.to_string())?;
+            match stream.remaining().len() {
+                0 => Ok(()),
+                trailing => Err(format!("{} trailing bytes after the project", trailing)),
+            }
         }));
 
-        ProjectUpgrader {
-            upgrade_functions,
+        upgrader.register_validator("0.1.0", Box::new(|proj| read_0_1_0(proj).map(|_| ())));
+        upgrader.register("0.1.0", "0.2.0", Box::new(migrate_0_1_0));
+        upgrader.register_validator("0.2.0", Box::new(|proj| read_0_2_0(proj).map(|_| ())));
+        upgrader.register("0.2.0", "0.3.0", Box::new(migrate_0_2_0));
+
+        upgrader
+    }
+
+    pub fn register(&mut self, from: &str, to: &str, migration: Migration) {
+        self.upgrade_functions.insert((from.to_string(), to.to_string()), migration);
+    }
+
+    pub fn register_validator(&mut self, version: &str, validator: Validator) {
+        self.validators.insert(version.to_string(), validator);
+    }
+
+    /// Finds the shortest chain of migrations from `from` to the current version.
+    pub fn path(&self, from: &str) -> Option<Vec<(String, String)>> {
+        let mut previous: HashMap<String, String> = HashMap::new();
+        let mut visited: HashSet<String> = HashSet::from([from.to_string()]);
+        let mut queue = VecDeque::from([from.to_string()]);
+
+        while let Some(version) = queue.pop_front() {
+            if version == PROJECT_VERSION {
+                let mut path = Vec::new();
+                let mut current = version;
+                while let Some(before) = previous.get(&current) {
+                    path.push((before.clone(), current.clone()));
+                    current = before.clone();
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            // sorted so the chosen path does not depend on hash order
+            let mut next: Vec<&String> = self.upgrade_functions.keys()
+                .filter(|(source, _)| *source == version)
+                .map(|(_, target)| target)
+                .collect();
+            next.sort();
+
+            for target in next {
+                if visited.insert(target.clone()) {
+                    previous.insert(target.clone(), version.clone());
+                    queue.push_back(target.clone());
+                }
+            }
+        }
+
+        None
+    }
+
+    fn validate(&self, proj: &RawProject, from: &str) -> Result<(), UpgradeError> {
+        match self.validators.get(&proj.project_version) {
+            Some(validator) => validator(proj).map_err(|description| {
+                UpgradeError::new(from, &proj.project_version, description, UpgradeErrorType::ValidationFailure)
+            }),
+            None => Ok(()),
         }
     }
-}
\ No newline at end of file
+
+    /// Migrates `proj` to the current format and reads it.
+    /// The input and the output of every migration are validated.
+    pub fn upgrade(&self, proj: RawProject) -> Result<(MarionetteProject, UpgradeReport), UpgradeError> {
+        let from = proj.project_version.clone();
+        let path = self.path(&from).ok_or_else(|| UpgradeError::new(
+            &from,
+            PROJECT_VERSION,
+            format!("no migration path from {} to {}", from, PROJECT_VERSION),
+            UpgradeErrorType::NoPath,
+        ))?;
+
+        self.validate(&proj, &from)?;
+
+        let mut proj = proj;
+        for (source, target) in &path {
+            let migration = &self.upgrade_functions[&(source.clone(), target.clone())];
+            let migrated = migration(proj).map_err(|description| {
+                UpgradeError::new(source, target, description, UpgradeErrorType::MigrationFailure)
+            })?;
+
+            // re-read the bytes rather than trusting the fields the migration filled in
+            let mut stream = ByteStream::new(migrated.raw);
+            proj = RawProject::read(&mut stream).map_err(|error| {
+                UpgradeError::new(source, target, error.to_string(), UpgradeErrorType::ReadFailure)
+            })?;
+
+            if proj.project_version != *target {
+                return Err(UpgradeError::new(
+                    source,
+                    target,
+                    format!("migration produced version {}", proj.project_version),
+                    UpgradeErrorType::ValidationFailure,
+                ));
+            }
+            self.validate(&proj, source)?;
+        }
+
+        let mut stream = ByteStream::new(proj.raw);
+        let project = MarionetteProject::read(&mut stream).map_err(|error| {
+            UpgradeError::new(&from, PROJECT_VERSION, error.to_string(), UpgradeErrorType::ReadFailure)
+        })?;
+
+        Ok((project, UpgradeReport {
+            from,
+            to: PROJECT_VERSION.to_string(),
+            migrations: path,
+            backup: None,
+        }))
+    }
+
+    /// Upgrades the project stored at `path`. If any migration ran, the original file is kept
+    /// next to it as `<name>.bak` (or `<name>.bak.N` if that exists) and the upgraded project
+    /// is written in its place.
+    pub fn upgrade_file(&self, path: &Path) -> Result<(MarionetteProject, UpgradeReport), UpgradeError> {
+        let io = |error: std::io::Error| UpgradeError::new("", PROJECT_VERSION, format!("{}: {}", path.display(), error), UpgradeErrorType::IoFailure);
+
+        let bytes = std::fs::read(path).map_err(io)?;
+        let mut stream = ByteStream::new(bytes.clone());
+        let proj = RawProject::read(&mut stream).map_err(|error| {
+            UpgradeError::new("", PROJECT_VERSION, error.to_string(), UpgradeErrorType::ReadFailure)
+        })?;
+
+        let (project, mut report) = self.upgrade(proj)?;
+        if report.migrations.is_empty() {
+            return Ok((project, report));
+        }
+
+        let backup = backup_path(path);
+        std::fs::write(&backup, &bytes).map_err(io)?;
+        report.backup = Some(backup);
+
+        let mut stream = ByteStream::new(vec![]);
+        project.write(&mut stream).map_err(|error| {
+            UpgradeError::new(&report.from, PROJECT_VERSION, error.to_string(), UpgradeErrorType::IoFailure)
+        })?;
+        std::fs::write(path, stream.bytes).map_err(io)?;
+
+        Ok((project, report))
+    }
+}
+
+/// 0.1.0 stored the project name followed by a list of file paths, and nothing else.
+fn read_0_1_0(proj: &RawProject) -> Result<(String, Vec<String>), String> {
+    let mut stream = ByteStream::new(proj.remaining.clone());
+    let name = String::read(&mut stream).map_err(|error| error.to_string())?;
+    let files = Vec::<String>::read(&mut stream).map_err(|error| error.to_string())?;
+    match stream.remaining().len() {
+        0 => Ok((name, files)),
+        trailing => Err(format!("{} trailing bytes after the project", trailing)),
+    }
+}
+
+/// 0.2.0 moved the files into a section and added the analysis sections, which start empty.
+fn migrate_0_1_0(proj: RawProject) -> Result<RawProject, String> {
+    let (name, files) = read_0_1_0(&proj)?;
+    let files: Vec<ProjectFile> = files.iter().map(|path| ProjectFile::new(path)).collect();
+
+    let mut stream = ByteStream::new(Vec::new());
+    let write = |stream: &mut ByteStream| -> Result<(), ByteStreamError> {
+        "0.2.0".to_string().write(stream)?;
+        name.write(stream)?;
+        vec![RawSection::encode(&files)?].write(stream)
+    };
+    write(&mut stream).map_err(|error| error.to_string())?;
+    RawProject::read(&mut ByteStream::new(stream.bytes)).map_err(|error| error.to_string())
+}
+
+/// 0.2.0 stored the project name followed by its sections, without a table or checksums.
+fn read_0_2_0(proj: &RawProject) -> Result<MarionetteProject, String> {
+    let mut stream = ByteStream::new(proj.remaining.clone());
+    let mut project = MarionetteProject {
+        project_version: proj.project_version.clone(),
+        project_name: String::read(&mut stream).map_err(|error| error.to_string())?,
+        ..MarionetteProject::new()
+    };
+
+    let sections = Vec::<RawSection>::read(&mut stream).map_err(|error| error.to_string())?;
+    if !stream.remaining().is_empty() {
+        return Err(format!("{} trailing bytes after the project", stream.remaining().len()));
+    }
+    for section in sections {
+        project.load_section(section).map_err(|error| error.to_string())?;
+    }
+    Ok(project)
+}
+
+/// 0.3.0 moved the sections into a container.
+fn migrate_0_2_0(proj: RawProject) -> Result<RawProject, String> {
+    let project = MarionetteProject {
+        project_version: "0.3.0".to_string(),
+        ..read_0_2_0(&proj)?
+    };
+
+    let mut stream = ByteStream::new(Vec::new());
+    project.write(&mut stream).map_err(|error| error.to_string())?;
+    RawProject::read(&mut ByteStream::new(stream.bytes)).map_err(|error| error.to_string())
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".bak");
+    let mut backup = PathBuf::from(&name);
+
+    let mut index = 1;
+    while backup.exists() {
+        let mut numbered = name.clone();
+        numbered.push(format!(".{}", index));
+        backup = PathBuf::from(numbered);
+        index += 1;
+    }
+    backup
+}
@@ -1,11 +1,9 @@
 use crate::mproj::MarionetteProject;
-use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamErrorType, ByteStreamRead, ByteStreamWrite};
+use crate::mproj::container::ProjectContainer;
+use crate::byte_stream::{ByteStream, ByteStreamError, ByteStreamWrite};
 
 impl ByteStreamWrite for MarionetteProject {
     fn write(&self, stream: &mut ByteStream) -> Result<(), ByteStreamError> {
-        self.project_version.write(stream)?;
-        self.project_name.write(stream)?;
-        self.project_files.write(stream)?;
-        Ok(())
+        ProjectContainer::from_project(self)?.write(stream)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,235 @@
+// Purpose: exposes a `LuaBinary` through the architecture independent traits of
+// `marionette_core::assembly`, so generic tooling (hex views, entropy, listings) can work on it.
+// Regions are the header and, for every prototype, its code, constants and debug information.
+
+use marionette_core::{assembly::*, byte_stream::*};
+
+use crate::lua_binary::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LuaRegionKind {
+    Header,
+    /// The instructions of a prototype, including the instruction count.
+    Code(usize),
+    /// The constant pool of a prototype, including the constant count.
+    Constants(usize),
+    /// Line info, locals and upvalue names of a prototype.
+    Debug(usize),
+}
+
+pub struct LuaData {
+    pub range: Range,
+    pub raw: Vec<u8>,
+    pub text: String,
+}
+
+impl Data for LuaData {
+    fn range(&self) -> Range {
+        self.range.clone()
+    }
+
+    fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+pub struct LuaRegion {
+    pub kind: LuaRegionKind,
+    pub range: Range,
+    pub raw: Vec<u8>,
+    pub text: String,
+    pub data: Vec<Box<dyn Data>>,
+}
+
+impl Region for LuaRegion {
+    fn range(&self) -> Range {
+        self.range.clone()
+    }
+
+    fn data(&self) -> &[Box<dyn Data>] {
+        &self.data
+    }
+
+    fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+pub struct LuaAssembly {
+    pub binary: LuaBinary,
+    pub kinds: Vec<LuaRegionKind>,
+    raw: Vec<u8>,
+    text: String,
+    regions: Vec<Box<dyn Region>>,
+}
+
+impl Assembly for LuaAssembly {
+    fn regions(&self) -> &[Box<dyn Region>] {
+        &self.regions
+    }
+
+    fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+fn slice(raw: &[u8], range: &Range) -> Vec<u8> {
+    let end = (range.end as usize).min(raw.len());
+    let start = (range.start as usize).min(end);
+    raw[start..end].to_vec()
+}
+
+fn constant_text(constant: &LuaConstantType) -> String {
+    match constant {
+        LuaConstantType::Nil(_) => "nil".to_string(),
+        LuaConstantType::Boolean(_, value) => value.to_string(),
+        LuaConstantType::Number(_, value) => value.to_string(),
+        LuaConstantType::String(_, value) => format!("{:?}", value.trim_end_matches('\0')),
+    }
+}
+
+impl LuaAssembly {
+    /// Builds the assembly of `binary` as it would be written out. The binary is serialized and
+    /// parsed again, so ranges always match `raw()`, even after the binary was edited.
+    pub fn new(binary: &LuaBinary) -> Result<LuaAssembly, ByteStreamError> {
+        let mut stream = ByteStream::new(vec![]);
+        binary.write(&mut stream)?;
+        LuaAssembly::from_bytes(stream.bytes)
+    }
+
+    pub fn from_bytes(raw: Vec<u8>) -> Result<LuaAssembly, ByteStreamError> {
+        let binary = LuaBinary::read(&mut ByteStream::new(raw.clone()))?;
+        let mut assembly = LuaAssembly {
+            binary,
+            kinds: Vec::new(),
+            raw,
+            text: String::new(),
+            regions: Vec::new(),
+        };
+
+        let header = assembly.header_region();
+        assembly.push(header);
+        for id in 0..assembly.binary.functions.len() {
+            for region in assembly.function_regions(id) {
+                assembly.push(region);
+            }
+        }
+        Ok(assembly)
+    }
+
+    pub fn region(&self, kind: LuaRegionKind) -> Option<&dyn Region> {
+        let index = self.kinds.iter().position(|other| *other == kind)?;
+        Some(self.regions[index].as_ref())
+    }
+
+    /// The region containing `addr`.
+    pub fn region_at(&self, addr: u64) -> Option<(LuaRegionKind, &dyn Region)> {
+        let index = self.regions.iter().position(|region| region.contains(addr))?;
+        Some((self.kinds[index], self.regions[index].as_ref()))
+    }
+
+    fn push(&mut self, region: LuaRegion) {
+        self.text.push_str(&region.text);
+        self.text.push('\n');
+        self.kinds.push(region.kind);
+        self.regions.push(Box::new(region));
+    }
+
+    fn data(&self, range: Range, text: String) -> Box<dyn Data> {
+        Box::new(LuaData {
+            raw: slice(&self.raw, &range),
+            range,
+            text,
+        })
+    }
+
+    fn build_region(&self, kind: LuaRegionKind, range: Range, title: String, data: Vec<Box<dyn Data>>) -> LuaRegion {
+        let mut text = format!("; {}\n", title);
+        for item in &data {
+            text.push_str(&format!("{:08x}  {}\n", item.range().start, item.text()));
+        }
+
+        LuaRegion {
+            kind,
+            raw: slice(&self.raw, &range),
+            range,
+            text,
+            data,
+        }
+    }
+
+    fn header_region(&self) -> LuaRegion {
+        let header = &self.binary.header;
+        let endianness = if header.endianness == 1 { "little" } else { "big" };
+        let numbers = if header.integral_flag == 1 { "integral" } else { "floating point" };
+        let description = format!(
+            "Lua {}.{} bytecode, format {}, {} endian, int {}, size_t {}, instruction {}, {}-byte {} numbers",
+            header.version >> 4, header.version & 0xf, header.format, endianness,
+            header.int_size, header.size_t_size, header.instruction_size, header.lua_number_size, numbers,
+        );
+
+        let data = vec![self.data(header.range.clone(), description)];
+        self.build_region(LuaRegionKind::Header, header.range.clone(), "header".to_string(), data)
+    }
+
+    fn function_regions(&self, id: usize) -> Vec<LuaRegion> {
+        let function = &self.binary.functions[id];
+        let int_size = self.binary.header.int_size as u64;
+        let mut regions = Vec::new();
+
+        // the counts in front of each list are not items of their own, so the boundaries
+        // are found from the items around them
+        let code_end = match (function.code.first(), function.code.last()) {
+            (Some(first), Some(last)) => {
+                let range = Range::new(first.range.start - int_size, last.range.end);
+                let data = function.code.iter()
+                    .map(|instruction| self.data(instruction.range.clone(), format!("{:?}", instruction).trim_end().to_string()))
+                    .collect();
+                let end = range.end;
+                regions.push(self.build_region(LuaRegionKind::Code(id), range, format!("function {} code", id), data));
+                end
+            }
+            _ => return regions,
+        };
+
+        let constants_end = function.constants.last().map(|constant| constant.range.end).unwrap_or(code_end + int_size);
+        let data = function.constants.iter().enumerate()
+            .map(|(index, constant)| self.data(constant.range.clone(), format!("K{} = {}", index, constant_text(&constant.constant))))
+            .collect();
+        regions.push(self.build_region(LuaRegionKind::Constants(id), Range::new(code_end, constants_end), format!("function {} constants", id), data));
+
+        let debug_start = self.binary.children(id).iter()
+            .map(|child| self.binary.functions[*child].range.end)
+            .max()
+            .unwrap_or(constants_end + int_size);
+
+        let mut data = Vec::new();
+        let lines_start = debug_start + int_size;
+        for (pc, line) in function.line_info.iter().enumerate() {
+            let start = lines_start + pc as u64 * int_size;
+            data.push(self.data(Range::new(start, start + int_size), format!("line {} = {}", pc, line)));
+        }
+        for (index, local) in function.locals.iter().enumerate() {
+            let text = format!("local {} {:?} pc {}..{}", index, local.name.trim_end_matches('\0'), local.start_pc, local.end_pc);
+            data.push(self.data(local.range.clone(), text));
+        }
+        for (index, upvalue) in function.upvalues.iter().enumerate() {
+            data.push(self.data(upvalue.range.clone(), format!("upvalue {} {:?}", index, upvalue.name.trim_end_matches('\0'))));
+        }
+        regions.push(self.build_region(LuaRegionKind::Debug(id), Range::new(debug_start, function.range.end), format!("function {} debug", id), data));
+
+        regions
+    }
+}
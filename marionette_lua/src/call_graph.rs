@@ -0,0 +1,382 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use petgraph::{graph::NodeIndex, prelude::StableDiGraph, visit::EdgeRef, Direction};
+use crate::{cfg::instruction_successors, lua_binary::*};
+
+/// How a function reaches another one.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum CallKind {
+    /// The caller instantiates the callee with `CLOSURE`.
+    Closure,
+    Call,
+    TailCall,
+}
+
+/// What a `CALL`/`TAILCALL` site was resolved to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum CallTarget {
+    /// A prototype of the binary.
+    Function(usize),
+    /// A global that was never assigned a known closure (e.g. `print`).
+    Global(String),
+    /// Anything we could not track: table fields, call results, parameters...
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CallSite {
+    pub caller: usize,
+    pub pc: u64,
+    pub kind: CallKind,
+    pub target: CallTarget,
+}
+
+/// The symbolic content of a register, tracked while scanning a function.
+#[derive(Debug, PartialEq, Clone)]
+enum Source {
+    Closure(usize),
+    Upvalue(usize),
+    Global(String),
+}
+
+/// Everything a function tells us about closures flowing around the binary.
+#[derive(Default)]
+struct Facts {
+    // (name, value assigned in this function)
+    global_assignments: Vec<(String, Source)>,
+    // (upvalue index of this function, value stored through SETUPVAL)
+    upvalue_assignments: Vec<(usize, Source)>,
+    // (child prototype, upvalue index of the child, value captured from this function)
+    captures: Vec<(usize, usize, Source)>,
+    // (pc, kind, value held by the called register)
+    calls: Vec<(u64, CallKind, Option<Source>)>,
+    // (pc, created prototype)
+    closures: Vec<(u64, usize)>,
+}
+
+/// Interprocedural call graph of a `LuaBinary`.
+/// Nodes are prototype ids (indexes into `LuaBinary::functions`), edges are the call sites.
+#[derive(Debug)]
+pub struct CallGraph {
+    pub graph: StableDiGraph<usize, CallSite>,
+    pub nodes: Vec<NodeIndex>,
+
+    /// Call sites whose target could not be resolved to a prototype.
+    pub unresolved: Vec<CallSite>,
+}
+
+impl CallGraph {
+    pub fn build(binary: &LuaBinary) -> CallGraph {
+        let facts: Vec<Facts> = (0..binary.functions.len())
+            .map(|id| collect_facts(binary, id))
+            .collect();
+
+        // Resolve upvalues and globals to sets of prototypes. Assignments can depend on each
+        // other (a global assigned from an upvalue captured from another global...), so iterate
+        // until nothing changes.
+        let mut upvalues: Vec<Vec<BTreeSet<usize>>> = binary.functions.iter()
+            .map(|function| vec![BTreeSet::new(); function.num_upvalues as usize])
+            .collect();
+        let mut globals: HashMap<String, BTreeSet<usize>> = HashMap::new();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (id, fact) in facts.iter().enumerate() {
+                for (name, source) in &fact.global_assignments {
+                    let values = resolve(id, source, &upvalues, &globals);
+                    let entry = globals.entry(name.clone()).or_default();
+                    let before = entry.len();
+                    entry.extend(values);
+                    changed |= entry.len() != before;
+                }
+
+                for (index, source) in &fact.upvalue_assignments {
+                    let values = resolve(id, source, &upvalues, &globals);
+                    if let Some(entry) = upvalues[id].get_mut(*index) {
+                        let before = entry.len();
+                        entry.extend(values);
+                        changed |= entry.len() != before;
+                    }
+                }
+
+                for (child, index, source) in &fact.captures {
+                    let values = resolve(id, source, &upvalues, &globals);
+                    if let Some(entry) = upvalues[*child].get_mut(*index) {
+                        let before = entry.len();
+                        entry.extend(values);
+                        changed |= entry.len() != before;
+                    }
+                }
+            }
+        }
+
+        let mut graph = StableDiGraph::new();
+        let nodes: Vec<NodeIndex> = (0..binary.functions.len())
+            .map(|id| graph.add_node(id))
+            .collect();
+        let mut unresolved = Vec::new();
+
+        for (id, fact) in facts.iter().enumerate() {
+            for &(pc, child) in &fact.closures {
+                graph.add_edge(nodes[id], nodes[child], CallSite {
+                    caller: id,
+                    pc,
+                    kind: CallKind::Closure,
+                    target: CallTarget::Function(child),
+                });
+            }
+
+            for (pc, kind, source) in &fact.calls {
+                let targets = match source {
+                    Some(source) => resolve(id, source, &upvalues, &globals),
+                    None => BTreeSet::new(),
+                };
+
+                if targets.is_empty() {
+                    let target = match source {
+                        Some(Source::Global(name)) => CallTarget::Global(name.clone()),
+                        _ => CallTarget::Unknown,
+                    };
+                    unresolved.push(CallSite { caller: id, pc: *pc, kind: *kind, target });
+                    continue;
+                }
+
+                for target in targets {
+                    graph.add_edge(nodes[id], nodes[target], CallSite {
+                        caller: id,
+                        pc: *pc,
+                        kind: *kind,
+                        target: CallTarget::Function(target),
+                    });
+                }
+            }
+        }
+
+        CallGraph {
+            graph,
+            nodes,
+            unresolved,
+        }
+    }
+
+    fn neighbors(&self, id: usize, direction: Direction) -> Vec<usize> {
+        let node = match self.nodes.get(id) {
+            Some(node) => *node,
+            None => return vec![],
+        };
+
+        let ids: BTreeSet<usize> = self.graph.edges_directed(node, direction)
+            .filter(|edge| edge.weight().kind != CallKind::Closure)
+            .map(|edge| match direction {
+                Direction::Outgoing => self.graph[edge.target()],
+                Direction::Incoming => self.graph[edge.source()],
+            })
+            .collect();
+        ids.into_iter().collect()
+    }
+
+    /// Returns the functions that call the function `id`.
+    pub fn callers(&self, id: usize) -> Vec<usize> {
+        self.neighbors(id, Direction::Incoming)
+    }
+
+    /// Returns the functions called by the function `id`.
+    pub fn callees(&self, id: usize) -> Vec<usize> {
+        self.neighbors(id, Direction::Outgoing)
+    }
+
+    /// Returns every resolved site inside the function `id`, including closure creations.
+    pub fn sites(&self, id: usize) -> Vec<&CallSite> {
+        match self.nodes.get(id) {
+            Some(node) => {
+                let mut sites: Vec<&CallSite> = self.graph.edges_directed(*node, Direction::Outgoing)
+                    .map(|edge| edge.weight())
+                    .collect();
+                sites.sort_by_key(|site| site.pc);
+                sites
+            },
+            None => vec![],
+        }
+    }
+
+    /// Returns the call sites inside the function `id` that could not be resolved.
+    pub fn unresolved_sites(&self, id: usize) -> Vec<&CallSite> {
+        self.unresolved.iter().filter(|site| site.caller == id).collect()
+    }
+}
+
+fn resolve(
+    function: usize,
+    source: &Source,
+    upvalues: &[Vec<BTreeSet<usize>>],
+    globals: &HashMap<String, BTreeSet<usize>>
+) -> BTreeSet<usize> {
+    match source {
+        Source::Closure(id) => BTreeSet::from([*id]),
+        Source::Upvalue(index) => upvalues[function].get(*index).cloned().unwrap_or_default(),
+        Source::Global(name) => globals.get(name).cloned().unwrap_or_default(),
+    }
+}
+
+fn constant_name(function: &LuaFunction, index: u32) -> Option<String> {
+    match function.constants.get(index as usize).map(|constant| &constant.constant) {
+        Some(LuaConstantType::String(_, value)) => Some(value.trim_end_matches('\0').to_string()),
+        _ => None,
+    }
+}
+
+/// Number of upvalue pseudo-instructions following the `CLOSURE` at `pc`.
+fn closure_upvalues(binary: &LuaBinary, id: usize, instruction: &LuaInstruction) -> usize {
+    binary.child(id, instruction.components.bx() as usize)
+        .map(|child| binary.functions[child].num_upvalues as usize)
+        .unwrap_or(0)
+}
+
+fn successors(binary: &LuaBinary, id: usize, pc: usize) -> Vec<usize> {
+    let code = &binary.functions[id].code;
+    if code[pc].opcode == LuaOpcode::CLOSURE {
+        let next = pc + 1 + closure_upvalues(binary, id, &code[pc]);
+        return if next < code.len() { vec![next] } else { vec![] };
+    }
+    instruction_successors(code, pc)
+}
+
+fn kill(registers: &mut [Option<Source>], from: usize, to: usize) {
+    let to = to.min(registers.len());
+    for register in registers.iter_mut().take(to).skip(from) {
+        *register = None;
+    }
+}
+
+fn set(registers: &mut [Option<Source>], index: usize, value: Option<Source>) {
+    if let Some(register) = registers.get_mut(index) {
+        *register = value;
+    }
+}
+
+fn get(registers: &[Option<Source>], index: usize) -> Option<Source> {
+    registers.get(index).cloned().flatten()
+}
+
+/// Applies the instruction at `pc` to the register state.
+fn transfer(binary: &LuaBinary, id: usize, pc: usize, registers: &mut [Option<Source>]) {
+    let function = &binary.functions[id];
+    let instruction = &function.code[pc];
+    let layout = instruction.components;
+    let a = layout.a() as usize;
+    let top = registers.len();
+
+    match instruction.opcode {
+        LuaOpcode::MOVE => set(registers, a, get(registers, layout.b() as usize)),
+        LuaOpcode::GETUPVAL => set(registers, a, Some(Source::Upvalue(layout.b() as usize))),
+        LuaOpcode::GETGLOBAL => set(registers, a, constant_name(function, layout.bx()).map(Source::Global)),
+        LuaOpcode::CLOSURE => set(registers, a, binary.child(id, layout.bx() as usize).map(Source::Closure)),
+        LuaOpcode::LOADNIL => kill(registers, a, layout.b() as usize + 1),
+        LuaOpcode::SELF => {
+            let object = get(registers, layout.b() as usize);
+            set(registers, a, None);
+            set(registers, a + 1, object);
+        },
+        LuaOpcode::CALL | LuaOpcode::VARARG => kill(registers, a, top),
+        LuaOpcode::TFORLOOP => kill(registers, a + 2, top),
+        LuaOpcode::FORLOOP | LuaOpcode::FORPREP => kill(registers, a, a + 4),
+        LuaOpcode::SETGLOBAL
+        | LuaOpcode::SETUPVAL
+        | LuaOpcode::SETTABLE
+        | LuaOpcode::SETLIST
+        | LuaOpcode::JMP
+        | LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE
+        | LuaOpcode::TEST
+        | LuaOpcode::RETURN
+        | LuaOpcode::TAILCALL
+        | LuaOpcode::CLOSE => {},
+        _ => set(registers, a, None),
+    }
+}
+
+/// Runs a forward dataflow over the function `id` and records the facts visible at each pc.
+fn collect_facts(binary: &LuaBinary, id: usize) -> Facts {
+    let function = &binary.functions[id];
+    let mut facts = Facts::default();
+    if function.code.is_empty() {
+        return facts;
+    }
+
+    // Registers are tracked up to the declared stack size; instructions referencing registers
+    // above it (malformed chunks) simply read nothing.
+    let size = function.max_stack_size.max(1) as usize;
+    let mut states: Vec<Option<Vec<Option<Source>>>> = vec![None; function.code.len()];
+    states[0] = Some(vec![None; size]);
+
+    let mut worklist = VecDeque::from([0usize]);
+    while let Some(pc) = worklist.pop_front() {
+        let mut registers = match &states[pc] {
+            Some(state) => state.clone(),
+            None => continue,
+        };
+        transfer(binary, id, pc, &mut registers);
+
+        for next in successors(binary, id, pc) {
+            let merged = match &states[next] {
+                None => registers.clone(),
+                Some(existing) => existing.iter()
+                    .zip(registers.iter())
+                    .map(|(old, new)| if old == new { old.clone() } else { None })
+                    .collect(),
+            };
+
+            if states[next].as_ref() != Some(&merged) {
+                states[next] = Some(merged);
+                worklist.push_back(next);
+            }
+        }
+    }
+
+    for (pc, instruction) in function.code.iter().enumerate() {
+        let registers = match &states[pc] {
+            Some(state) => state,
+            None => continue, // unreachable, or an upvalue pseudo-instruction
+        };
+        let layout = instruction.components;
+        let a = layout.a() as usize;
+
+        match instruction.opcode {
+            LuaOpcode::SETGLOBAL => {
+                if let (Some(name), Some(source)) = (constant_name(function, layout.bx()), get(registers, a)) {
+                    facts.global_assignments.push((name, source));
+                }
+            },
+            LuaOpcode::SETUPVAL => {
+                if let Some(source) = get(registers, a) {
+                    facts.upvalue_assignments.push((layout.b() as usize, source));
+                }
+            },
+            LuaOpcode::CLOSURE => {
+                let child = match binary.child(id, layout.bx() as usize) {
+                    Some(child) => child,
+                    None => continue,
+                };
+                facts.closures.push((pc as u64, child));
+
+                let count = binary.functions[child].num_upvalues as usize;
+                for (index, pseudo) in function.code.iter().skip(pc + 1).take(count).enumerate() {
+                    let source = match pseudo.opcode {
+                        LuaOpcode::MOVE => get(registers, pseudo.components.b() as usize),
+                        LuaOpcode::GETUPVAL => Some(Source::Upvalue(pseudo.components.b() as usize)),
+                        _ => None,
+                    };
+                    if let Some(source) = source {
+                        facts.captures.push((child, index, source));
+                    }
+                }
+            },
+            LuaOpcode::CALL => facts.calls.push((pc as u64, CallKind::Call, get(registers, a))),
+            LuaOpcode::TAILCALL => facts.calls.push((pc as u64, CallKind::TailCall, get(registers, a))),
+            _ => {},
+        }
+    }
+
+    facts
+}
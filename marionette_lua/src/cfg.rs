@@ -65,6 +65,47 @@ impl<T: Debug> Debug for Block<T> {
     }
 }
 
+/// Returns the pcs that may execute directly after the instruction at `pc`.
+/// Targets outside of `code` are dropped, and `CLOSURE` is treated as a plain
+/// instruction, so callers that care about its upvalue pseudo-instructions must skip them.
+pub fn instruction_successors(code: &[LuaInstruction], pc: usize) -> Vec<usize> {
+    let instruction = match code.get(pc) {
+        Some(instruction) => instruction,
+        None => return vec![],
+    };
+
+    let jump = |s_bx: i32| -> Option<usize> {
+        let target = pc as i64 + 1 + s_bx as i64;
+        if target >= 0 && (target as usize) < code.len() {
+            Some(target as usize)
+        } else {
+            None
+        }
+    };
+
+    let mut successors = match instruction.opcode {
+        LuaOpcode::RETURN | LuaOpcode::TAILCALL => vec![],
+        LuaOpcode::JMP | LuaOpcode::FORPREP => jump(instruction.components.sbx()).into_iter().collect(),
+        LuaOpcode::FORLOOP => {
+            let mut targets = vec![pc + 1];
+            targets.extend(jump(instruction.components.sbx()));
+            targets
+        },
+        LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE
+        | LuaOpcode::TEST
+        | LuaOpcode::TESTSET
+        | LuaOpcode::TFORLOOP => vec![pc + 1, pc + 2],
+        LuaOpcode::LOADBOOL if instruction.components.c() != 0 => vec![pc + 2],
+        _ => vec![pc + 1],
+    };
+
+    successors.retain(|&target| target < code.len());
+    successors.dedup();
+    successors
+}
+
 pub fn build_control_flow_graph<T, F1, F2, F3>(
     instructions: &Vec<T>,
     is_branching: F1,
@@ -225,11 +266,48 @@ pub fn get_graph(function: LuaFunction) -> Result<(StableDiGraph<Block<LuaInstru
         }
     });
 
+    Ok((graph, root))
+}
+/// How control reaches one block from another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    /// The only way out of the source block: a jump, or falling through.
+    Unconditional,
+    /// Taken when the test ending the source block holds, e.g. the loop continuing.
+    True,
+    /// Taken when it does not.
+    False,
+}
 
-    let dot = petgraph::dot::Dot::new(&graph);
-    println!("{:?}", dot);
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Unconditional => "unconditional",
+            EdgeKind::True => "true",
+            EdgeKind::False => "false",
+        }
+    }
+}
 
-    // let formatted_cfg = format_cfg(&graph);
+/// Classifies the edge from `source` to `target`, two blocks of the same `get_graph` result.
+pub fn edge_kind(source: &Block<LuaInstruction>, target: &Block<LuaInstruction>) -> EdgeKind {
+    let last = match source.instructions.last() {
+        Some(last) => last,
+        None => return EdgeKind::Unconditional,
+    };
+    let next = target.id as u64 == last.pc + 1;
 
-    Ok((graph, root))
-}
\ No newline at end of file
+    match last.opcode {
+        // the comparison or test matching A runs the next instruction, usually a jump,
+        // otherwise the next instruction is skipped
+        LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE
+        | LuaOpcode::TEST
+        | LuaOpcode::TESTSET
+        | LuaOpcode::TFORLOOP => if next { EdgeKind::True } else { EdgeKind::False },
+        // jumps back while the loop continues and falls through once it is done
+        LuaOpcode::FORLOOP => if next { EdgeKind::False } else { EdgeKind::True },
+        _ => EdgeKind::Unconditional,
+    }
+}
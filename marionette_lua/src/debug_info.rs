@@ -0,0 +1,381 @@
+// Purpose: removes debug information from a binary like `luac -s`, and synthesizes it back
+// for stripped chunks: local names guessed from what registers hold, upvalue names taken
+// from the enclosing function, and line numbers pointing into a listing.
+
+use std::collections::HashMap;
+use marionette_core::assembly::Range;
+use crate::{
+    lua_binary::*,
+    optimize::{dead_stores::definitions, reachable, constants::constant_value},
+    emulator::value::LuaValue,
+};
+
+/// Maps (function id, pc) to a line of some listing.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct LineMap {
+    pub lines: HashMap<(usize, u64), u32>,
+}
+
+impl LineMap {
+    pub fn new() -> LineMap {
+        LineMap::default()
+    }
+
+    /// Collects the `--[function:pc]` markers of a listing; the line holding a marker
+    /// (counting from 1) becomes the line of that instruction.
+    pub fn from_listing(listing: &str) -> LineMap {
+        let mut map = LineMap::new();
+        for (index, text) in listing.lines().enumerate() {
+            for marker in text.split("--[").skip(1) {
+                let location = match marker.split_once(']') {
+                    Some((location, _)) => location,
+                    None => continue,
+                };
+
+                if let Some((function, pc)) = location.split_once(':') {
+                    if let (Ok(function), Ok(pc)) = (function.trim().parse(), pc.trim().parse()) {
+                        map.lines.insert((function, pc), index as u32 + 1);
+                    }
+                }
+            }
+        }
+        map
+    }
+}
+
+/// What `inject_debug` fills in. Only missing information is synthesized, except for
+/// line numbers, which always follow `lines` when it is given.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DebugOptions {
+    /// Chunk name stored in the main function, e.g. `@script.lua`.
+    pub source: Option<String>,
+    pub lines: Option<LineMap>,
+    pub locals: bool,
+    pub upvalues: bool,
+}
+
+impl DebugOptions {
+    pub fn new() -> DebugOptions {
+        DebugOptions {
+            source: None,
+            lines: None,
+            locals: true,
+            upvalues: true,
+        }
+    }
+}
+
+impl Default for DebugOptions {
+    fn default() -> Self {
+        DebugOptions::new()
+    }
+}
+
+/// Names in dumps carry their NUL terminator.
+fn dumped(name: &str) -> String {
+    format!("{}\0", name)
+}
+
+fn undumped(name: &str) -> &str {
+    name.strip_suffix('\0').unwrap_or(name)
+}
+
+/// Turns a constant into something usable as part of a variable name.
+fn identifier(text: &str) -> Option<String> {
+    let text: String = text.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(24)
+        .collect();
+    let text = text.trim_matches('_');
+
+    if text.is_empty() || text.starts_with(|c: char| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+impl LuaFunction {
+    /// Drops line info, local and upvalue names and the source name, like `luac -s`.
+    pub fn strip_debug(&mut self) {
+        self.name = String::new();
+
+        self.line_info = vec![];
+        self.line_info_size = 0;
+
+        self.locals = vec![];
+        self.local_size = 0;
+
+        self.upvalues = vec![];
+        self.upvalue_size = 0;
+    }
+
+    pub fn is_stripped(&self) -> bool {
+        self.line_info.is_empty() && self.locals.is_empty() && self.upvalues.is_empty()
+    }
+
+    /// Name of the local held by `register` at `pc`, resolved the way `luaF_getlocalname` does.
+    pub fn local_name(&self, register: usize, pc: u64) -> Option<&str> {
+        let mut remaining = register + 1;
+        for local in &self.locals {
+            if local.start_pc as u64 > pc {
+                break;
+            }
+            if pc < local.end_pc as u64 {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Some(undumped(&local.name));
+                }
+            }
+        }
+        None
+    }
+
+    /// Guesses a name for every register from the first value stored into it.
+    fn register_names(&self, upvalue_names: &[String]) -> Vec<Option<(String, u32)>> {
+        let mut names: Vec<Option<(String, u32)>> = vec![None; self.max_stack_size as usize];
+        let operands = self.operand_words();
+        let reached = reachable(self, &operands);
+
+        let constant = |index: usize| self.constants.get(index).map(|constant| constant_value(&constant.constant));
+        let constant_name = |index: usize| match constant(index) {
+            Some(LuaValue::String(text)) => identifier(&String::from_utf8_lossy(&text)),
+            _ => None,
+        };
+
+        for (pc, instruction) in self.code.iter().enumerate() {
+            if operands[pc] || !reached[pc] {
+                continue;
+            }
+
+            let layout = instruction.components;
+            let a = layout.a() as usize;
+
+            // registers whose role is fixed by the instruction
+            let fixed: Vec<(usize, String)> = match instruction.opcode {
+                LuaOpcode::FORPREP => vec![
+                    (a, "(for index)".to_string()),
+                    (a + 1, "(for limit)".to_string()),
+                    (a + 2, "(for step)".to_string()),
+                    (a + 3, format!("i_{}", a + 3)),
+                ],
+                LuaOpcode::TFORLOOP => {
+                    let mut fixed = vec![
+                        (a, "(for generator)".to_string()),
+                        (a + 1, "(for state)".to_string()),
+                        (a + 2, "(for control)".to_string()),
+                    ];
+                    for register in a + 3..a + 3 + layout.c() as usize {
+                        let name = if register == a + 3 { "k" } else { "v" };
+                        fixed.push((register, format!("{}_{}", name, register)));
+                    }
+                    fixed
+                },
+                _ => vec![],
+            };
+
+            for (register, name) in fixed {
+                if let Some(slot) = names.get_mut(register) {
+                    if slot.is_none() {
+                        *slot = Some((name, pc as u32 + 1));
+                    }
+                }
+            }
+
+            for register in definitions(instruction) {
+                if names.get(register).map(|slot| slot.is_some()).unwrap_or(true) {
+                    continue;
+                }
+
+                let key = rk_constant(layout.c() as usize);
+                let base = match instruction.opcode {
+                    LuaOpcode::GETGLOBAL => constant_name(layout.bx() as usize),
+                    LuaOpcode::GETTABLE if key.is_some() => key.and_then(constant_name),
+                    LuaOpcode::SELF if register == a && key.is_some() => key.and_then(constant_name),
+                    LuaOpcode::SELF => Some("self".to_string()),
+                    LuaOpcode::GETUPVAL => upvalue_names.get(layout.b() as usize).and_then(|name| identifier(name)),
+                    LuaOpcode::LOADK => match constant(layout.bx() as usize) {
+                        Some(LuaValue::String(_)) => Some("str".to_string()),
+                        Some(LuaValue::Number(_)) => Some("num".to_string()),
+                        _ => None,
+                    },
+                    LuaOpcode::LOADBOOL => Some("flag".to_string()),
+                    LuaOpcode::NEWTABLE => Some("tbl".to_string()),
+                    LuaOpcode::CLOSURE => Some("fn".to_string()),
+                    LuaOpcode::CALL => Some("result".to_string()),
+                    LuaOpcode::CONCAT => Some("str".to_string()),
+                    _ => None,
+                };
+
+                let name = format!("{}_{}", base.unwrap_or_else(|| "var".to_string()), register);
+                names[register] = Some((name, pc as u32 + 1));
+            }
+        }
+
+        names
+    }
+
+    /// Generates one local per register, live until the end of the function. Registers are
+    /// bound to locals by declaration order, so starts are made non-decreasing by register.
+    pub fn generate_locals(&mut self, upvalue_names: &[String]) {
+        let mut names = self.register_names(upvalue_names);
+        let used = names.iter().rposition(|name| name.is_some()).map(|last| last + 1).unwrap_or(0);
+        names.truncate(used.max(self.num_parameters as usize));
+        let end = self.code.len() as u32;
+
+        let mut locals = Vec::new();
+        let mut start = 0;
+        for (register, name) in names.into_iter().enumerate() {
+            let (name, first) = match (register < self.num_parameters as usize, name) {
+                (true, _) => (format!("param_{}", register), 0),
+                (false, Some(named)) => named,
+                (false, None) => (format!("var_{}", register), start),
+            };
+
+            start = start.max(first.min(end));
+            locals.push(LuaLocal {
+                raw: vec![],
+                range: Range::new(0, 0),
+
+                name: dumped(&name),
+                start_pc: start,
+                end_pc: end,
+            });
+        }
+
+        self.locals = locals;
+        self.local_size = self.locals.len() as u64;
+    }
+
+    /// Sets the line of every instruction found in `lines`; the others take the line of the
+    /// instruction before them. Functions missing from `lines` are left alone.
+    pub fn apply_lines(&mut self, id: usize, lines: &LineMap) {
+        let found: Vec<Option<u32>> = (0..self.code.len())
+            .map(|pc| lines.lines.get(&(id, pc as u64)).copied())
+            .collect();
+
+        let first = match found.iter().flatten().next() {
+            Some(first) => *first,
+            None => return,
+        };
+
+        let mut current = first;
+        self.line_info = found.iter().map(|line| {
+            if let Some(line) = line {
+                current = *line;
+            }
+            current
+        }).collect();
+        self.line_info_size = self.line_info.len() as u64;
+
+        if id != 0 {
+            self.first_line = *self.line_info.iter().min().unwrap() as u64;
+            self.last_line = *self.line_info.iter().max().unwrap() as u64;
+        }
+    }
+}
+
+impl LuaBinary {
+    /// Strips every prototype, like `luac -s`.
+    pub fn strip_debug(&mut self) {
+        for function in self.functions.iter_mut() {
+            function.strip_debug();
+        }
+    }
+
+    pub fn is_stripped(&self) -> bool {
+        self.functions.iter().all(|function| function.is_stripped())
+    }
+
+    /// A disassembly with one instruction per line, each tagged with a `--[function:pc]`
+    /// marker that `LineMap::from_listing` understands.
+    pub fn listing(&self) -> String {
+        let mut listing = String::new();
+        for (id, function) in self.functions.iter().enumerate() {
+            let parent = match self.parent(id) {
+                Some(parent) => format!(" in function {}", parent),
+                None => String::new(),
+            };
+            listing.push_str(&format!("function {}{} ({} instructions)\n", id, parent, function.code.len()));
+
+            for (pc, instruction) in function.code.iter().enumerate() {
+                listing.push_str(&format!("    {:<40} --[{}:{}]\n", format!("{:?}", instruction), id, pc));
+            }
+            listing.push('\n');
+        }
+        listing
+    }
+
+    /// Synthesizes debug information, see `DebugOptions`.
+    pub fn inject_debug(&mut self, options: &DebugOptions) {
+        if let Some(source) = &options.source {
+            if let Some(root) = self.functions.first_mut() {
+                root.name = dumped(source);
+            }
+        }
+
+        // parents come before their children in `functions`, so their names are ready
+        for id in 0..self.functions.len() {
+            if options.upvalues && self.functions[id].upvalues.is_empty() && self.functions[id].num_upvalues > 0 {
+                let names = self.upvalue_names(id);
+                let function = &mut self.functions[id];
+                function.upvalues = names.iter().map(|name| LuaUpvalue {
+                    raw: vec![],
+                    range: Range::new(0, 0),
+
+                    name: dumped(name),
+                }).collect();
+                function.upvalue_size = function.upvalues.len() as u64;
+            }
+
+            if options.locals && self.functions[id].locals.is_empty() {
+                let upvalue_names: Vec<String> = self.functions[id].upvalues.iter()
+                    .map(|upvalue| undumped(&upvalue.name).to_string())
+                    .collect();
+                self.functions[id].generate_locals(&upvalue_names);
+            }
+
+            if let Some(lines) = &options.lines {
+                self.functions[id].apply_lines(id, lines);
+            }
+        }
+    }
+
+    /// Names the upvalues of `id` after what its parent's `CLOSURE` captures.
+    fn upvalue_names(&self, id: usize) -> Vec<String> {
+        let count = self.functions[id].num_upvalues as usize;
+        let mut names: Vec<String> = (0..count).map(|index| format!("upval_{}", index)).collect();
+
+        let parent = match self.parent(id) {
+            Some(parent) => parent,
+            None => return names,
+        };
+        let index = self.children(parent).iter().position(|child| *child == id).unwrap();
+
+        let function = &self.functions[parent];
+        let closure = function.code.iter().position(|instruction| {
+            instruction.opcode == LuaOpcode::CLOSURE && instruction.components.bx() as usize == index
+        });
+
+        if let Some(pc) = closure {
+            for (upvalue, name) in names.iter_mut().enumerate() {
+                let pseudo = match function.code.get(pc + 1 + upvalue) {
+                    Some(pseudo) => pseudo.components,
+                    None => break,
+                };
+
+                let captured = match pseudo.opcode() {
+                    LuaOpcode::MOVE => function.local_name(pseudo.b() as usize, pc as u64),
+                    LuaOpcode::GETUPVAL => function.upvalues.get(pseudo.b() as usize).map(|upvalue| undumped(&upvalue.name)),
+                    _ => None,
+                };
+
+                if let Some(captured) = captured.filter(|captured| !captured.starts_with('(')) {
+                    *name = captured.to_string();
+                }
+            }
+        }
+
+        names
+    }
+}
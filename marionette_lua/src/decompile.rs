@@ -0,0 +1,245 @@
+// Purpose: renders prototypes as Lua-like pseudocode, one statement per instruction.
+// Control flow is not structured: jumps become `goto` to labels placed at their targets,
+// so the output reads like Lua but is meant for reading, not for compiling.
+
+use crate::lua_binary::*;
+use crate::listing::constant_text;
+
+/// Number of values a `SETLIST` stores per block (`LFIELDS_PER_FLUSH`).
+const FIELDS_PER_FLUSH: u32 = 50;
+
+struct Decompiler<'a> {
+    binary: &'a LuaBinary,
+    id: usize,
+    function: &'a LuaFunction,
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+impl<'a> Decompiler<'a> {
+    fn register(&self, register: u32, pc: u64) -> String {
+        match self.function.local_name(register as usize, pc) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("r{}", register),
+        }
+    }
+
+    /// Registers `first..=last`, or `first, ...` when the count is only known at runtime.
+    fn registers(&self, first: u32, last: Option<u32>, pc: u64) -> String {
+        match last {
+            Some(last) => (first..=last).map(|register| self.register(register, pc)).collect::<Vec<_>>().join(", "),
+            None => format!("{}, ...", self.register(first, pc)),
+        }
+    }
+
+    fn constant(&self, index: u32) -> String {
+        constant_text(self.function, index)
+    }
+
+    /// A string constant usable as a bare name, e.g. a global or a field.
+    fn name(&self, index: u32) -> Option<String> {
+        match self.function.constants.get(index as usize).map(|constant| &constant.constant) {
+            Some(LuaConstantType::String(_, value)) if is_identifier(value.trim_end_matches('\0')) => {
+                Some(value.trim_end_matches('\0').to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn rk(&self, operand: u32, pc: u64) -> String {
+        match rk_constant(operand as usize) {
+            Some(index) => self.constant(index as u32),
+            None => self.register(operand, pc),
+        }
+    }
+
+    fn upvalue(&self, index: u32) -> String {
+        match self.function.upvalues.get(index as usize) {
+            Some(upvalue) if !upvalue.name.trim_end_matches('\0').is_empty() => upvalue.name.trim_end_matches('\0').to_string(),
+            _ => format!("u{}", index),
+        }
+    }
+
+    fn global(&self, index: u32) -> String {
+        self.name(index).unwrap_or_else(|| format!("_G[{}]", self.constant(index)))
+    }
+
+    fn index(&self, table: String, key: u32, pc: u64) -> String {
+        match rk_constant(key as usize).map(|index| index as u32) {
+            Some(index) => match self.name(index) {
+                Some(name) => format!("{}.{}", table, name),
+                None => format!("{}[{}]", table, self.constant(index)),
+            },
+            None => format!("{}[{}]", table, self.register(key, pc)),
+        }
+    }
+
+    fn arguments(&self, a: u32, b: u32, pc: u64) -> String {
+        match b {
+            0 => self.registers(a + 1, None, pc),
+            1 => String::new(),
+            _ => self.registers(a + 1, Some(a + b - 1), pc),
+        }
+    }
+
+    fn statement(&self, pc: usize) -> String {
+        let instruction = &self.function.code[pc];
+        let layout = &instruction.components;
+        let (a, b, c) = (layout.a() as u32, layout.b() as u32, layout.c() as u32);
+        let at = pc as u64;
+        let r = |register: u32| self.register(register, at);
+        let rk = |operand: u32| self.rk(operand, at);
+        let label = |target: usize| format!("goto L{}", target);
+        let jump = (pc as i64 + 1 + layout.sbx() as i64).max(0) as usize;
+
+        match instruction.opcode {
+            LuaOpcode::MOVE => format!("{} = {}", r(a), r(b)),
+            LuaOpcode::LOADK => format!("{} = {}", r(a), self.constant(layout.bx())),
+            LuaOpcode::LOADBOOL => match c {
+                0 => format!("{} = {}", r(a), b != 0),
+                _ => format!("{} = {}; {}", r(a), b != 0, label(pc + 2)),
+            },
+            LuaOpcode::LOADNIL => format!("{} = nil", self.registers(a, Some(b.max(a)), at)),
+            LuaOpcode::GETUPVAL => format!("{} = {}", r(a), self.upvalue(b)),
+            LuaOpcode::GETGLOBAL => format!("{} = {}", r(a), self.global(layout.bx())),
+            LuaOpcode::GETTABLE => format!("{} = {}", r(a), self.index(r(b), c, at)),
+            LuaOpcode::SETGLOBAL => format!("{} = {}", self.global(layout.bx()), r(a)),
+            LuaOpcode::SETUPVAL => format!("{} = {}", self.upvalue(b), r(a)),
+            LuaOpcode::SETTABLE => format!("{} = {}", self.index(r(a), b, at), rk(c)),
+            LuaOpcode::NEWTABLE => format!("{} = {{}}", r(a)),
+            LuaOpcode::SELF => format!("{} = {}; {} = {}", r(a + 1), r(b), r(a), self.index(r(b), c, at)),
+            LuaOpcode::ADD => format!("{} = {} + {}", r(a), rk(b), rk(c)),
+            LuaOpcode::SUB => format!("{} = {} - {}", r(a), rk(b), rk(c)),
+            LuaOpcode::MUL => format!("{} = {} * {}", r(a), rk(b), rk(c)),
+            LuaOpcode::DIV => format!("{} = {} / {}", r(a), rk(b), rk(c)),
+            LuaOpcode::MOD => format!("{} = {} % {}", r(a), rk(b), rk(c)),
+            LuaOpcode::POW => format!("{} = {} ^ {}", r(a), rk(b), rk(c)),
+            LuaOpcode::UNM => format!("{} = -{}", r(a), r(b)),
+            LuaOpcode::NOT => format!("{} = not {}", r(a), r(b)),
+            LuaOpcode::LEN => format!("{} = #{}", r(a), r(b)),
+            LuaOpcode::CONCAT => format!("{} = {}", r(a), (b..=c).map(r).collect::<Vec<_>>().join(" .. ")),
+            LuaOpcode::JMP => label(jump),
+            // the next instruction is skipped when the comparison differs from A
+            LuaOpcode::EQ => format!("if {} {} {} then {} end", rk(b), if a != 0 { "~=" } else { "==" }, rk(c), label(pc + 2)),
+            LuaOpcode::LT | LuaOpcode::LE => {
+                let operator = if instruction.opcode == LuaOpcode::LT { "<" } else { "<=" };
+                match a {
+                    0 => format!("if {} {} {} then {} end", rk(b), operator, rk(c), label(pc + 2)),
+                    _ => format!("if not ({} {} {}) then {} end", rk(b), operator, rk(c), label(pc + 2)),
+                }
+            }
+            LuaOpcode::TEST => format!("if {}{} then {} end", if c != 0 { "not " } else { "" }, r(a), label(pc + 2)),
+            LuaOpcode::TESTSET => format!(
+                "if {}{} then {} else {} = {} end",
+                if c != 0 { "not " } else { "" }, r(b), label(pc + 2), r(a), r(b),
+            ),
+            LuaOpcode::CALL => {
+                let call = format!("{}({})", r(a), self.arguments(a, b, at));
+                match c {
+                    0 => format!("{} = {}", self.registers(a, None, at), call),
+                    1 => call,
+                    _ => format!("{} = {}", self.registers(a, Some(a + c - 2), at), call),
+                }
+            }
+            LuaOpcode::TAILCALL => format!("return {}({})", r(a), self.arguments(a, b, at)),
+            LuaOpcode::RETURN => match b {
+                0 => format!("return {}", self.registers(a, None, at)),
+                1 => "return".to_string(),
+                _ => format!("return {}", self.registers(a, Some(a + b - 2), at)),
+            },
+            LuaOpcode::FORLOOP => format!(
+                "{} = {} + {}; if {} <= {} then {} = {}; {} end",
+                r(a), r(a), r(a + 2), r(a), r(a + 1), r(a + 3), r(a), label(jump),
+            ),
+            LuaOpcode::FORPREP => format!("{} = {} - {}; {}", r(a), r(a), r(a + 2), label(jump)),
+            LuaOpcode::TFORLOOP => format!(
+                "{} = {}({}, {}); if {} ~= nil then {} = {} else {} end",
+                self.registers(a + 3, Some(a + 2 + c.max(1)), at), r(a), r(a + 1), r(a + 2),
+                r(a + 3), r(a + 2), r(a + 3), label(pc + 2),
+            ),
+            LuaOpcode::SETLIST => {
+                // with C = 0 the block number is stored in the next word
+                let block = match c {
+                    0 => self.function.code.get(pc + 1).map(|word| word.word(&self.binary.header)).unwrap_or(1),
+                    _ => c,
+                };
+                let values = match b {
+                    0 => self.registers(a + 1, None, at),
+                    _ => self.registers(a + 1, Some(a + b), at),
+                };
+                format!("{}[{}...] = {}", r(a), (block.max(1) - 1) * FIELDS_PER_FLUSH + 1, values)
+            }
+            LuaOpcode::CLOSE => format!("-- close upvalues from {}", r(a)),
+            LuaOpcode::CLOSURE => match self.binary.child(self.id, layout.bx() as usize) {
+                Some(child) => format!("{} = function{}", r(a), child),
+                None => format!("{} = closure({})", r(a), layout.bx()),
+            },
+            LuaOpcode::VARARG => match b {
+                0 => format!("{} = ...", self.registers(a, None, at)),
+                _ => format!("{} = ...", self.registers(a, Some(a + b.max(2) - 2), at)),
+            },
+        }
+    }
+
+    fn decompile(&self) -> String {
+        let operands = self.function.operand_words();
+        let mut targets: Vec<usize> = Vec::new();
+        for (pc, instruction) in self.function.code.iter().enumerate() {
+            if operands[pc] {
+                continue;
+            }
+            if instruction.is_relative_jump() {
+                targets.push((pc as i64 + 1 + instruction.components.sbx() as i64).max(0) as usize);
+            }
+            if instruction.skips_next() || matches!(instruction.opcode, LuaOpcode::EQ | LuaOpcode::LT | LuaOpcode::LE) {
+                targets.push(pc + 2);
+            }
+        }
+        targets.sort();
+        targets.dedup();
+
+        let parameters = (0..self.function.num_parameters as u32).map(|register| self.register(register, 0));
+        let mut parameters: Vec<String> = parameters.collect();
+        if self.function.is_vararg != 0 {
+            parameters.push("...".to_string());
+        }
+
+        let mut text = match self.binary.parent(self.id) {
+            Some(parent) => format!("-- function {} in function {}\n", self.id, parent),
+            None => format!("-- function {}\n", self.id),
+        };
+        text.push_str(&format!("function function{}({})\n", self.id, parameters.join(", ")));
+        for (pc, instruction) in self.function.code.iter().enumerate() {
+            if targets.binary_search(&pc).is_ok() {
+                text.push_str(&format!("::L{}::\n", pc));
+            }
+            let statement = match operands[pc] {
+                true => format!("-- operand {:?}", instruction),
+                false => self.statement(pc),
+            };
+            text.push_str(&format!("    {:<48} -- {}\n", statement, pc));
+        }
+        text.push_str("end\n");
+        text
+    }
+}
+
+impl LuaBinary {
+    /// Pseudocode of the prototype `id`, see the module documentation.
+    pub fn decompile_function(&self, id: usize) -> Option<String> {
+        let function = self.functions.get(id)?;
+        Some(Decompiler { binary: self, id, function }.decompile())
+    }
+
+    /// Pseudocode of every prototype, parents before their children.
+    pub fn decompile(&self) -> String {
+        (0..self.functions.len())
+            .filter_map(|id| self.decompile_function(id))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
@@ -0,0 +1,759 @@
+// Purpose: executes Lua 5.1 bytecode straight from a `LuaBinary`, one instruction at a time.
+// The emulator has no metatables, coroutines or garbage collection: it is meant for running
+// small self-contained routines (string decryption, constant tables...) under inspection.
+
+pub mod value;
+pub mod host;
+
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+use crate::lua_binary::*;
+use value::*;
+use host::LuaHost;
+
+/// Number of list items flushed by one `SETLIST` (`LFIELDS_PER_FLUSH`).
+const FIELDS_PER_FLUSH: usize = 50;
+
+/// Maximum depth of nested calls before we report a stack overflow (`LUAI_MAXCCALLS`).
+const MAX_FRAMES: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmulatorError {
+    pub function: usize,
+    pub pc: u64,
+    pub description: String,
+}
+
+impl std::fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "function {} pc {}: {}", self.function, self.pc, self.description)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepResult {
+    /// An instruction was executed and the program is still running.
+    Running,
+    /// Execution stopped before the instruction at (function, pc).
+    Breakpoint(usize, u64),
+    /// The entry function returned these values.
+    Finished(Vec<LuaValue>),
+}
+
+/// What the caller does with the results of a call once they are delivered.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Resume {
+    Call,
+    // the call was the iterator of the `TFORLOOP` whose A operand is stored here
+    TForLoop(usize),
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    pub closure: Rc<LuaClosure>,
+    /// Absolute stack index of register 0.
+    pub base: usize,
+    /// Index of the next instruction to execute.
+    pub pc: usize,
+    pub varargs: Vec<LuaValue>,
+    /// One past the last value produced by a multiple-results instruction.
+    pub top: usize,
+
+    // where and how the results of this frame are delivered
+    return_slot: usize,
+    wanted: Option<usize>,
+    resume: Resume,
+}
+
+pub struct Emulator<H: LuaHost> {
+    pub binary: LuaBinary,
+    pub host: H,
+
+    pub stack: Vec<LuaValue>,
+    pub frames: Vec<Frame>,
+    open_upvalues: Vec<Rc<RefCell<LuaUpvalueCell>>>,
+
+    /// Locations (function id, pc) where `run` stops before executing the instruction.
+    pub breakpoints: HashSet<(usize, u64)>,
+    /// Upper bound on executed instructions, to get out of obfuscator busy loops.
+    pub step_limit: Option<u64>,
+    pub steps: u64,
+
+    result: Option<Vec<LuaValue>>,
+}
+
+impl<H: LuaHost> Emulator<H> {
+    pub fn new(binary: LuaBinary, host: H) -> Emulator<H> {
+        Emulator {
+            binary,
+            host,
+
+            stack: Vec::new(),
+            frames: Vec::new(),
+            open_upvalues: Vec::new(),
+
+            breakpoints: HashSet::new(),
+            step_limit: None,
+            steps: 0,
+
+            result: None,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, function: usize, pc: u64) {
+        self.breakpoints.insert((function, pc));
+    }
+
+    pub fn remove_breakpoint(&mut self, function: usize, pc: u64) {
+        self.breakpoints.remove(&(function, pc));
+    }
+
+    /// Prepares a call to the prototype `function` with `args`.
+    /// Upvalues of the entry function start as `nil`.
+    pub fn start(&mut self, function: usize, args: Vec<LuaValue>) -> Result<(), EmulatorError> {
+        let prototype = self.binary.functions.get(function).ok_or_else(|| EmulatorError {
+            function,
+            pc: 0,
+            description: "no such function".to_string(),
+        })?;
+
+        let upvalues = (0..prototype.num_upvalues)
+            .map(|_| Rc::new(RefCell::new(LuaUpvalueCell::Closed(LuaValue::Nil))))
+            .collect();
+        let closure = Rc::new(LuaClosure { function, upvalues });
+
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+        self.steps = 0;
+        self.result = None;
+
+        let nargs = args.len();
+        self.stack.push(LuaValue::Function(closure));
+        self.stack.extend(args);
+
+        self.call(0, nargs, None, Resume::Call).map_err(|description| EmulatorError {
+            function,
+            pc: 0,
+            description,
+        })
+    }
+
+    /// Runs `function` to completion, ignoring breakpoints.
+    pub fn execute(&mut self, function: usize, args: Vec<LuaValue>) -> Result<Vec<LuaValue>, EmulatorError> {
+        self.start(function, args)?;
+        loop {
+            if let StepResult::Finished(values) = self.step()? {
+                return Ok(values);
+            }
+        }
+    }
+
+    /// Runs until a breakpoint is reached or the entry function returns.
+    /// At least one instruction is executed, so calling `run` again continues past a breakpoint.
+    pub fn run(&mut self) -> Result<StepResult, EmulatorError> {
+        let mut first = true;
+        loop {
+            if !first {
+                if let Some((function, pc)) = self.location() {
+                    if self.breakpoints.contains(&(function, pc)) {
+                        return Ok(StepResult::Breakpoint(function, pc));
+                    }
+                }
+            }
+            first = false;
+
+            match self.step()? {
+                StepResult::Running => continue,
+                result => return Ok(result),
+            }
+        }
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<StepResult, EmulatorError> {
+        if let Some(result) = &self.result {
+            return Ok(StepResult::Finished(result.clone()));
+        }
+
+        let (function, pc) = self.location().ok_or_else(|| EmulatorError {
+            function: 0,
+            pc: 0,
+            description: "emulator was not started".to_string(),
+        })?;
+
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(EmulatorError { function, pc, description: format!("step limit of {} reached", limit) });
+            }
+        }
+
+        self.execute_instruction().map_err(|description| EmulatorError { function, pc, description })?;
+        self.steps += 1;
+
+        Ok(match &self.result {
+            Some(result) => StepResult::Finished(result.clone()),
+            None => StepResult::Running,
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// The (function id, pc) of the next instruction to execute.
+    pub fn location(&self) -> Option<(usize, u64)> {
+        self.frames.last().map(|frame| (frame.closure.function, frame.pc as u64))
+    }
+
+    /// The register file of the current frame.
+    pub fn registers(&self) -> &[LuaValue] {
+        match self.frames.last() {
+            Some(frame) => {
+                let size = self.binary.functions[frame.closure.function].max_stack_size as usize;
+                let end = (frame.base + size).min(self.stack.len());
+                &self.stack[frame.base..end]
+            },
+            None => &[],
+        }
+    }
+
+    pub fn register(&self, index: usize) -> LuaValue {
+        self.registers().get(index).cloned().unwrap_or(LuaValue::Nil)
+    }
+
+    /// The current values of the upvalues of the current frame.
+    pub fn upvalues(&self) -> Vec<LuaValue> {
+        match self.frames.last() {
+            Some(frame) => frame.closure.upvalues.iter().map(|cell| self.read_upvalue(cell)).collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn varargs(&self) -> &[LuaValue] {
+        match self.frames.last() {
+            Some(frame) => &frame.varargs,
+            None => &[],
+        }
+    }
+
+    fn read_upvalue(&self, cell: &Rc<RefCell<LuaUpvalueCell>>) -> LuaValue {
+        match &*cell.borrow() {
+            LuaUpvalueCell::Open(index) => self.stack.get(*index).cloned().unwrap_or(LuaValue::Nil),
+            LuaUpvalueCell::Closed(value) => value.clone(),
+        }
+    }
+
+    fn write_upvalue(&mut self, cell: &Rc<RefCell<LuaUpvalueCell>>, value: LuaValue) {
+        let mut cell = cell.borrow_mut();
+        match &mut *cell {
+            LuaUpvalueCell::Open(index) => self.stack[*index] = value,
+            LuaUpvalueCell::Closed(closed) => *closed = value,
+        }
+    }
+
+    fn find_upvalue(&mut self, index: usize) -> Rc<RefCell<LuaUpvalueCell>> {
+        for cell in &self.open_upvalues {
+            if matches!(&*cell.borrow(), LuaUpvalueCell::Open(open) if *open == index) {
+                return cell.clone();
+            }
+        }
+
+        let cell = Rc::new(RefCell::new(LuaUpvalueCell::Open(index)));
+        self.open_upvalues.push(cell.clone());
+        cell
+    }
+
+    /// Closes every open upvalue pointing at or above the stack index `level`.
+    fn close_upvalues(&mut self, level: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|cell| {
+            let index = match &*cell.borrow() {
+                LuaUpvalueCell::Open(index) => *index,
+                LuaUpvalueCell::Closed(_) => return false,
+            };
+            if index < level {
+                return true;
+            }
+
+            let value = stack.get(index).cloned().unwrap_or(LuaValue::Nil);
+            *cell.borrow_mut() = LuaUpvalueCell::Closed(value);
+            false
+        });
+    }
+
+    fn ensure_stack(&mut self, size: usize) {
+        if self.stack.len() < size {
+            self.stack.resize(size, LuaValue::Nil);
+        }
+    }
+
+    /// Calls the value at `slot` with the `nargs` values above it.
+    fn call(&mut self, slot: usize, nargs: usize, wanted: Option<usize>, resume: Resume) -> Result<(), String> {
+        self.ensure_stack(slot + nargs + 1);
+        match self.stack[slot].clone() {
+            LuaValue::Function(closure) => {
+                if self.frames.len() >= MAX_FRAMES {
+                    return Err("stack overflow".to_string());
+                }
+
+                let prototype = &self.binary.functions[closure.function];
+                let parameters = prototype.num_parameters as usize;
+                let size = prototype.max_stack_size as usize;
+                let is_vararg = prototype.is_vararg;
+                let base = slot + 1;
+
+                let varargs = if is_vararg != 0 && nargs > parameters {
+                    self.stack[base + parameters..base + nargs].to_vec()
+                } else {
+                    vec![]
+                };
+
+                self.stack.truncate(base + nargs.min(parameters));
+                self.ensure_stack(base + size.max(parameters));
+
+                // VARARG_NEEDSARG: the 5.0 compatible `arg` table lives in the first free register
+                if is_vararg & 4 != 0 {
+                    let mut arg = LuaTable::new();
+                    for (index, value) in varargs.iter().enumerate() {
+                        arg.set(LuaValue::Number((index + 1) as f64), value.clone())?;
+                    }
+                    arg.set_str("n", LuaValue::Number(varargs.len() as f64));
+                    self.ensure_stack(base + parameters + 1);
+                    self.stack[base + parameters] = LuaValue::table(arg);
+                }
+
+                self.frames.push(Frame {
+                    closure,
+                    base,
+                    pc: 0,
+                    varargs,
+                    top: base,
+
+                    return_slot: slot,
+                    wanted,
+                    resume,
+                });
+                Ok(())
+            },
+            LuaValue::Native(native) => {
+                let args = self.stack[slot + 1..slot + 1 + nargs].to_vec();
+                let results = self.host.call(&native, args).map_err(|error| format!("{}: {}", native.name, error))?;
+                self.deliver(slot, results, wanted, resume)
+            },
+            other => Err(format!("attempt to call a {} value", other.type_name())),
+        }
+    }
+
+    /// Copies `results` into the current frame at `slot`, or finishes the program if no frame is left.
+    fn deliver(&mut self, slot: usize, results: Vec<LuaValue>, wanted: Option<usize>, resume: Resume) -> Result<(), String> {
+        if self.frames.is_empty() {
+            self.result = Some(results);
+            return Ok(());
+        }
+
+        let count = wanted.unwrap_or(results.len());
+        let mut results = results.into_iter();
+
+        let (base, size) = {
+            let frame = self.frames.last().unwrap();
+            (frame.base, self.binary.functions[frame.closure.function].max_stack_size as usize)
+        };
+
+        // drop whatever the callee left above the caller's registers
+        self.stack.truncate((base + size).max(slot + count));
+        self.ensure_stack((base + size).max(slot + count));
+        for index in 0..count {
+            self.stack[slot + index] = results.next().unwrap_or(LuaValue::Nil);
+        }
+
+        let frame = self.frames.last_mut().unwrap();
+        frame.top = slot + count;
+
+        if let Resume::TForLoop(a) = resume {
+            let control = self.stack[base + a + 3].clone();
+            if control.is_nil() {
+                frame.pc += 1;
+            } else {
+                self.stack[base + a + 2] = control;
+            }
+        }
+        Ok(())
+    }
+
+    fn constant(&self, function: usize, index: usize) -> Result<LuaValue, String> {
+        let constant = self.binary.functions[function].constants.get(index)
+            .ok_or_else(|| format!("constant {} out of range", index))?;
+
+        Ok(match &constant.constant {
+            LuaConstantType::Nil(_) => LuaValue::Nil,
+            LuaConstantType::Boolean(_, value) => LuaValue::Boolean(*value),
+            LuaConstantType::Number(_, value) => LuaValue::Number(*value),
+            // dumped strings carry their NUL terminator
+            LuaConstantType::String(_, value) => {
+                LuaValue::string(value.strip_suffix('\0').unwrap_or(value).as_bytes())
+            },
+        })
+    }
+
+    fn global_name(&self, function: usize, index: usize) -> Result<String, String> {
+        match self.constant(function, index)? {
+            LuaValue::String(name) => Ok(String::from_utf8_lossy(&name).to_string()),
+            other => Err(format!("global name is a {} constant", other.type_name())),
+        }
+    }
+
+    fn index(&mut self, object: &LuaValue, key: &LuaValue) -> Result<LuaValue, String> {
+        match object {
+            LuaValue::Table(table) => Ok(table.borrow().get(key)),
+            // strings share the `string` library as their `__index`
+            LuaValue::String(_) => match self.host.get_global("string") {
+                LuaValue::Table(library) => Ok(library.borrow().get(key)),
+                _ => Ok(LuaValue::Nil),
+            },
+            other => Err(format!("attempt to index a {} value", other.type_name())),
+        }
+    }
+
+    fn new_index(&mut self, object: &LuaValue, key: LuaValue, value: LuaValue) -> Result<(), String> {
+        match object {
+            LuaValue::Table(table) => table.borrow_mut().set(key, value),
+            other => Err(format!("attempt to index a {} value", other.type_name())),
+        }
+    }
+
+    fn execute_instruction(&mut self) -> Result<(), String> {
+        let (closure, base, pc) = {
+            let frame = self.frames.last().unwrap();
+            (frame.closure.clone(), frame.base, frame.pc)
+        };
+        let function = closure.function;
+
+        let (opcode, layout, raw_next) = {
+            let code = &self.binary.functions[function].code;
+            let instruction = code.get(pc).ok_or_else(|| "pc out of range".to_string())?;
+            (instruction.opcode, instruction.components, code.get(pc + 1).map(|word| word.word(&self.binary.header)))
+        };
+        self.frames.last_mut().unwrap().pc += 1;
+
+        let a = layout.a() as usize;
+        let b = layout.b() as usize;
+        let c = layout.c() as usize;
+        let size = self.binary.functions[function].max_stack_size as usize;
+
+        // register operands past the stack size only come from corrupt or hand-made chunks
+        macro_rules! slot {
+            ($index:expr) => {{
+                let index = $index;
+                if index >= size {
+                    return Err(format!("register {} past the stack size {}", index, size));
+                }
+                base + index
+            }};
+        }
+        // the registers `first..first + count`, which may be empty
+        macro_rules! slots {
+            ($first:expr, $count:expr) => {{
+                let (first, count) = ($first, $count);
+                if first + count > size {
+                    return Err(format!("registers {}..{} past the stack size {}", first, first + count, size));
+                }
+                base + first
+            }};
+        }
+        macro_rules! reg {
+            ($index:expr) => { self.stack[slot!($index)].clone() };
+        }
+        macro_rules! rk {
+            ($index:expr) => {
+                match rk_constant($index) {
+                    Some(index) => self.constant(function, index)?,
+                    None => reg!($index),
+                }
+            };
+        }
+        macro_rules! jump {
+            ($offset:expr) => {{
+                let frame = self.frames.last_mut().unwrap();
+                frame.pc = (frame.pc as i64 + $offset as i64) as usize;
+            }};
+        }
+
+        match opcode {
+            LuaOpcode::MOVE => {
+                let value = reg!(b);
+                self.stack[slot!(a)] = value;
+            },
+            LuaOpcode::LOADK => self.stack[slot!(a)] = self.constant(function, layout.bx() as usize)?,
+            LuaOpcode::LOADBOOL => {
+                self.stack[slot!(a)] = LuaValue::Boolean(b != 0);
+                if c != 0 {
+                    jump!(1);
+                }
+            },
+            LuaOpcode::LOADNIL => {
+                for index in a..=b {
+                    self.stack[slot!(index)] = LuaValue::Nil;
+                }
+            },
+            LuaOpcode::GETUPVAL => {
+                let cell = closure.upvalues.get(b).ok_or_else(|| format!("upvalue {} out of range", b))?;
+                self.stack[slot!(a)] = self.read_upvalue(cell);
+            },
+            LuaOpcode::GETGLOBAL => {
+                let name = self.global_name(function, layout.bx() as usize)?;
+                self.stack[slot!(a)] = self.host.get_global(&name);
+            },
+            LuaOpcode::GETTABLE => {
+                let object = reg!(b);
+                let key = rk!(c);
+                self.stack[slot!(a)] = self.index(&object, &key)?;
+            },
+            LuaOpcode::SETGLOBAL => {
+                let name = self.global_name(function, layout.bx() as usize)?;
+                let value = reg!(a);
+                self.host.set_global(&name, value);
+            },
+            LuaOpcode::SETUPVAL => {
+                let cell = closure.upvalues.get(b).ok_or_else(|| format!("upvalue {} out of range", b))?;
+                let value = reg!(a);
+                self.write_upvalue(cell, value);
+            },
+            LuaOpcode::SETTABLE => {
+                let object = reg!(a);
+                let key = rk!(b);
+                let value = rk!(c);
+                self.new_index(&object, key, value)?;
+            },
+            LuaOpcode::NEWTABLE => self.stack[slot!(a)] = LuaValue::table(LuaTable::new()),
+            LuaOpcode::SELF => {
+                let object = reg!(b);
+                let key = rk!(c);
+                let ra = slots!(a, 2);
+                self.stack[ra + 1] = object.clone();
+                self.stack[ra] = self.index(&object, &key)?;
+            },
+            LuaOpcode::ADD
+            | LuaOpcode::SUB
+            | LuaOpcode::MUL
+            | LuaOpcode::DIV
+            | LuaOpcode::MOD
+            | LuaOpcode::POW => {
+                let left = rk!(b);
+                let right = rk!(c);
+                self.stack[slot!(a)] = arithmetic(opcode, &left, &right)?;
+            },
+            LuaOpcode::UNM => {
+                let value = reg!(b);
+                self.stack[slot!(a)] = arithmetic(opcode, &value, &value)?;
+            },
+            LuaOpcode::NOT => {
+                let value = LuaValue::Boolean(!reg!(b).is_truthy());
+                self.stack[slot!(a)] = value;
+            },
+            LuaOpcode::LEN => {
+                self.stack[slot!(a)] = match reg!(b) {
+                    LuaValue::String(value) => LuaValue::Number(value.len() as f64),
+                    LuaValue::Table(table) => LuaValue::Number(table.borrow().len() as f64),
+                    other => return Err(format!("attempt to get length of a {} value", other.type_name())),
+                };
+            },
+            LuaOpcode::CONCAT => {
+                let mut result = Vec::new();
+                for index in b..=c {
+                    let value = reg!(index);
+                    match value.to_bytes() {
+                        Some(bytes) => result.extend(bytes),
+                        None => return Err(format!("attempt to concatenate a {} value", value.type_name())),
+                    }
+                }
+                self.stack[slot!(a)] = LuaValue::String(Rc::new(result));
+            },
+            LuaOpcode::JMP => jump!(layout.sbx()),
+            LuaOpcode::EQ | LuaOpcode::LT | LuaOpcode::LE => {
+                let left = rk!(b);
+                let right = rk!(c);
+                if compare(opcode, &left, &right)? != (a != 0) {
+                    jump!(1);
+                }
+            },
+            LuaOpcode::TEST => {
+                if reg!(a).is_truthy() != (c != 0) {
+                    jump!(1);
+                }
+            },
+            LuaOpcode::TESTSET => {
+                let value = reg!(b);
+                if value.is_truthy() == (c != 0) {
+                    self.stack[slot!(a)] = value;
+                } else {
+                    jump!(1);
+                }
+            },
+            LuaOpcode::CALL => {
+                let top = self.frames.last().unwrap().top;
+                let ra = if b == 0 { slot!(a) } else { slots!(a, b) };
+                let nargs = if b == 0 { top.saturating_sub(ra + 1) } else { b - 1 };
+                let wanted = if c == 0 { None } else { Some(c - 1) };
+                self.call(ra, nargs, wanted, Resume::Call)?;
+            },
+            LuaOpcode::TAILCALL => {
+                let top = self.frames.last().unwrap().top;
+                let ra = if b == 0 { slot!(a) } else { slots!(a, b) };
+                let nargs = if b == 0 { top.saturating_sub(ra + 1) } else { b - 1 };
+                let callee: Vec<LuaValue> = self.stack[ra..ra + 1 + nargs].to_vec();
+
+                self.close_upvalues(base);
+                let frame = self.frames.pop().unwrap();
+                let slot = frame.return_slot;
+
+                self.stack.truncate(slot);
+                self.stack.extend(callee);
+                self.call(slot, nargs, frame.wanted, frame.resume)?;
+            },
+            LuaOpcode::RETURN => {
+                let top = self.frames.last().unwrap().top;
+                let ra = slots!(a, b.saturating_sub(1));
+                let end = if b == 0 { top.max(ra) } else { ra + b - 1 };
+                let results = self.stack[ra..end].to_vec();
+
+                self.close_upvalues(base);
+                let frame = self.frames.pop().unwrap();
+                self.deliver(frame.return_slot, results, frame.wanted, frame.resume)?;
+            },
+            LuaOpcode::FORLOOP => {
+                let ra = slots!(a, 4);
+                let step = number(&reg!(a + 2), "'for' step")?;
+                let index = number(&reg!(a), "'for' initial value")? + step;
+                let limit = number(&reg!(a + 1), "'for' limit")?;
+
+                if (step > 0.0 && index <= limit) || (step <= 0.0 && limit <= index) {
+                    jump!(layout.sbx());
+                    self.stack[ra] = LuaValue::Number(index);
+                    self.stack[ra + 3] = LuaValue::Number(index);
+                }
+            },
+            LuaOpcode::FORPREP => {
+                let initial = number(&reg!(a), "'for' initial value")?;
+                number(&reg!(a + 1), "'for' limit")?;
+                let step = number(&reg!(a + 2), "'for' step")?;
+
+                self.stack[slot!(a)] = LuaValue::Number(initial - step);
+                jump!(layout.sbx());
+            },
+            LuaOpcode::TFORLOOP => {
+                let slot = slots!(a, 3) + 3;
+                self.ensure_stack(slot + 3);
+                self.stack[slot] = reg!(a);
+                self.stack[slot + 1] = reg!(a + 1);
+                self.stack[slot + 2] = reg!(a + 2);
+                self.call(slot, 2, Some(c), Resume::TForLoop(a))?;
+            },
+            LuaOpcode::SETLIST => {
+                let top = self.frames.last().unwrap().top;
+                let ra = if b == 0 { slot!(a) } else { slots!(a, b + 1) };
+                let count = if b == 0 { top.saturating_sub(ra + 1) } else { b };
+                let block = if c == 0 {
+                    // the block number does not fit in C and is stored as the next "instruction"
+                    jump!(1);
+                    raw_next.ok_or_else(|| "SETLIST is missing its extra argument".to_string())? as usize
+                } else {
+                    c
+                };
+                // blocks count from 1, only a corrupt extra argument is 0
+                let first = block.checked_sub(1).ok_or_else(|| "SETLIST block 0".to_string())? * FIELDS_PER_FLUSH;
+
+                let table = match reg!(a) {
+                    LuaValue::Table(table) => table,
+                    other => return Err(format!("SETLIST on a {} value", other.type_name())),
+                };
+                let mut table = table.borrow_mut();
+                for index in 1..=count {
+                    let key = LuaValue::Number((first + index) as f64);
+                    table.set(key, self.stack[ra + index].clone())?;
+                }
+            },
+            LuaOpcode::CLOSE => self.close_upvalues(slot!(a)),
+            LuaOpcode::CLOSURE => {
+                let child = self.binary.child(function, layout.bx() as usize)
+                    .ok_or_else(|| format!("CLOSURE of unknown prototype {}", layout.bx()))?;
+                let count = self.binary.functions[child].num_upvalues as usize;
+
+                let mut upvalues = Vec::with_capacity(count);
+                for index in 0..count {
+                    let pseudo = self.binary.functions[function].code.get(pc + 1 + index)
+                        .map(|instruction| instruction.components)
+                        .ok_or_else(|| "CLOSURE is missing its upvalue instructions".to_string())?;
+
+                    match pseudo.opcode() {
+                        LuaOpcode::MOVE => upvalues.push(self.find_upvalue(slot!(pseudo.b() as usize))),
+                        LuaOpcode::GETUPVAL => {
+                            let cell = closure.upvalues.get(pseudo.b() as usize)
+                                .ok_or_else(|| format!("upvalue {} out of range", pseudo.b()))?;
+                            upvalues.push(cell.clone());
+                        },
+                        other => return Err(format!("unexpected {} in CLOSURE upvalues", other.to_string())),
+                    }
+                }
+
+                jump!(count);
+                self.stack[slot!(a)] = LuaValue::Function(Rc::new(LuaClosure { function: child, upvalues }));
+            },
+            LuaOpcode::VARARG => {
+                let varargs = self.frames.last().unwrap().varargs.clone();
+                let count = if b == 0 { varargs.len() } else { b - 1 };
+                let ra = if b == 0 { slot!(a) } else { slots!(a, count) };
+                self.ensure_stack(ra + count);
+                for index in 0..count {
+                    self.stack[ra + index] = varargs.get(index).cloned().unwrap_or(LuaValue::Nil);
+                }
+                if b == 0 {
+                    self.frames.last_mut().unwrap().top = ra + count;
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+fn number(value: &LuaValue, what: &str) -> Result<f64, String> {
+    value.to_number().ok_or_else(|| format!("{} must be a number", what))
+}
+
+/// Evaluates an arithmetic opcode (`UNM` ignores `right`).
+pub fn arithmetic(opcode: LuaOpcode, left: &LuaValue, right: &LuaValue) -> Result<LuaValue, String> {
+    let operand = |value: &LuaValue| value.to_number()
+        .ok_or_else(|| format!("attempt to perform arithmetic on a {} value", value.type_name()));
+    let l = operand(left)?;
+    let r = operand(right)?;
+
+    Ok(LuaValue::Number(match opcode {
+        LuaOpcode::ADD => l + r,
+        LuaOpcode::SUB => l - r,
+        LuaOpcode::MUL => l * r,
+        LuaOpcode::DIV => l / r,
+        LuaOpcode::MOD => l - (l / r).floor() * r,
+        LuaOpcode::POW => l.powf(r),
+        LuaOpcode::UNM => -l,
+        _ => return Err(format!("{} is not an arithmetic opcode", opcode.to_string())),
+    }))
+}
+
+/// Evaluates `EQ`, `LT` or `LE` without metamethods.
+pub fn compare(opcode: LuaOpcode, left: &LuaValue, right: &LuaValue) -> Result<bool, String> {
+    if opcode == LuaOpcode::EQ {
+        return Ok(left.raw_equals(right));
+    }
+
+    let ordering = match (left, right) {
+        (LuaValue::Number(l), LuaValue::Number(r)) => l.partial_cmp(r),
+        (LuaValue::String(l), LuaValue::String(r)) => Some(l.cmp(r)),
+        _ => return Err(format!("attempt to compare {} with {}", left.type_name(), right.type_name())),
+    };
+
+    Ok(match (opcode, ordering) {
+        (_, None) => false, // NaN
+        (LuaOpcode::LT, Some(ordering)) => ordering.is_lt(),
+        (_, Some(ordering)) => ordering.is_le(),
+    })
+}
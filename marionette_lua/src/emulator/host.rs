@@ -0,0 +1,362 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use crate::emulator::value::*;
+
+/// Longest string the string and table libraries build, so a script cannot exhaust memory.
+const MAX_STRING: usize = 64 << 20;
+/// Most values `unpack` returns, `LUAI_MAXCSTACK` in luaconf.h.
+const MAX_UNPACK: usize = 8000;
+
+/// The environment the emulated code runs in.
+/// Globals are owned by the host so callers can pre-seed them with stubs, and every native
+/// call goes through `call` so the host can trace or intercept it.
+pub trait LuaHost {
+    fn get_global(&mut self, name: &str) -> LuaValue;
+    fn set_global(&mut self, name: &str, value: LuaValue);
+
+    fn call(&mut self, native: &LuaNative, args: Vec<LuaValue>) -> Result<Vec<LuaValue>, String> {
+        (native.function)(args)
+    }
+}
+
+/// A host with a subset of the Lua 5.1 standard library: the pure functions string
+/// decryption routines typically depend on. Nothing here touches the file system or the OS.
+pub struct DefaultHost {
+    pub globals: HashMap<String, LuaValue>,
+
+    /// Everything passed to `print`, one entry per call.
+    pub output: Rc<RefCell<Vec<String>>>,
+
+    /// Every native call made by the emulated code, in order.
+    pub trace: Vec<(String, Vec<LuaValue>)>,
+}
+
+impl LuaHost for DefaultHost {
+    fn get_global(&mut self, name: &str) -> LuaValue {
+        self.globals.get(name).cloned().unwrap_or(LuaValue::Nil)
+    }
+
+    fn set_global(&mut self, name: &str, value: LuaValue) {
+        if value.is_nil() {
+            self.globals.remove(name);
+        } else {
+            self.globals.insert(name.to_string(), value);
+        }
+    }
+
+    fn call(&mut self, native: &LuaNative, args: Vec<LuaValue>) -> Result<Vec<LuaValue>, String> {
+        self.trace.push((native.name.clone(), args.clone()));
+        (native.function)(args)
+    }
+}
+
+impl Default for DefaultHost {
+    fn default() -> Self {
+        DefaultHost::new()
+    }
+}
+
+fn arg(args: &[LuaValue], index: usize) -> LuaValue {
+    args.get(index).cloned().unwrap_or(LuaValue::Nil)
+}
+
+fn number_arg(args: &[LuaValue], index: usize, function: &str) -> Result<f64, String> {
+    arg(args, index).to_number()
+        .ok_or_else(|| format!("bad argument #{} to '{}' (number expected)", index + 1, function))
+}
+
+fn optional_number_arg(args: &[LuaValue], index: usize, default: f64, function: &str) -> Result<f64, String> {
+    if arg(args, index).is_nil() {
+        return Ok(default);
+    }
+    number_arg(args, index, function)
+}
+
+fn string_arg(args: &[LuaValue], index: usize, function: &str) -> Result<Vec<u8>, String> {
+    arg(args, index).to_bytes()
+        .ok_or_else(|| format!("bad argument #{} to '{}' (string expected)", index + 1, function))
+}
+
+fn table_arg(args: &[LuaValue], index: usize, function: &str) -> Result<Rc<RefCell<LuaTable>>, String> {
+    match arg(args, index) {
+        LuaValue::Table(table) => Ok(table),
+        _ => Err(format!("bad argument #{} to '{}' (table expected)", index + 1, function)),
+    }
+}
+
+/// Converts Lua's 1-based, possibly negative string positions into a byte range.
+fn string_range(length: usize, i: f64, j: f64) -> std::ops::Range<usize> {
+    let length = length as i64;
+    let position = |value: f64| -> i64 {
+        let value = value as i64;
+        if value < 0 { (length + value + 1).max(0) } else { value }
+    };
+
+    let start = position(i).max(1);
+    let end = position(j).min(length);
+    if start > end {
+        return 0..0;
+    }
+    (start - 1) as usize..end as usize
+}
+
+pub fn tostring(value: &LuaValue) -> String {
+    match value {
+        LuaValue::String(bytes) => String::from_utf8_lossy(bytes).to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn string_library() -> LuaTable {
+    let mut string = LuaTable::new();
+
+    string.set_str("len", LuaValue::native("string.len", |args| {
+        Ok(vec![LuaValue::Number(string_arg(&args, 0, "len")?.len() as f64)])
+    }));
+
+    string.set_str("sub", LuaValue::native("string.sub", |args| {
+        let value = string_arg(&args, 0, "sub")?;
+        let range = string_range(
+            value.len(),
+            optional_number_arg(&args, 1, 1.0, "sub")?,
+            optional_number_arg(&args, 2, -1.0, "sub")?
+        );
+        Ok(vec![LuaValue::string(&value[range])])
+    }));
+
+    string.set_str("byte", LuaValue::native("string.byte", |args| {
+        let value = string_arg(&args, 0, "byte")?;
+        let i = optional_number_arg(&args, 1, 1.0, "byte")?;
+        let j = optional_number_arg(&args, 2, i, "byte")?;
+        Ok(value[string_range(value.len(), i, j)].iter().map(|byte| LuaValue::Number(*byte as f64)).collect())
+    }));
+
+    string.set_str("char", LuaValue::native("string.char", |args| {
+        let mut bytes = Vec::with_capacity(args.len());
+        for index in 0..args.len() {
+            let value = number_arg(&args, index, "char")?;
+            if !(0.0..=255.0).contains(&value) {
+                return Err(format!("bad argument #{} to 'char' (invalid value)", index + 1));
+            }
+            bytes.push(value as u8);
+        }
+        Ok(vec![LuaValue::string(&bytes)])
+    }));
+
+    string.set_str("rep", LuaValue::native("string.rep", |args| {
+        let value = string_arg(&args, 0, "rep")?;
+        let count = number_arg(&args, 1, "rep")?.max(0.0) as usize;
+        if count.saturating_mul(value.len()) > MAX_STRING {
+            return Err("bad argument #2 to 'rep' (resulting string too large)".to_string());
+        }
+        Ok(vec![LuaValue::string(&value.repeat(count))])
+    }));
+
+    string.set_str("reverse", LuaValue::native("string.reverse", |args| {
+        let mut value = string_arg(&args, 0, "reverse")?;
+        value.reverse();
+        Ok(vec![LuaValue::string(&value)])
+    }));
+
+    string.set_str("lower", LuaValue::native("string.lower", |args| {
+        Ok(vec![LuaValue::string(&string_arg(&args, 0, "lower")?.to_ascii_lowercase())])
+    }));
+
+    string.set_str("upper", LuaValue::native("string.upper", |args| {
+        Ok(vec![LuaValue::string(&string_arg(&args, 0, "upper")?.to_ascii_uppercase())])
+    }));
+
+    string
+}
+
+fn table_library() -> LuaTable {
+    let mut table = LuaTable::new();
+
+    table.set_str("concat", LuaValue::native("table.concat", |args| {
+        let list = table_arg(&args, 0, "concat")?;
+        let separator = if arg(&args, 1).is_nil() { vec![] } else { string_arg(&args, 1, "concat")? };
+        let list = list.borrow();
+        let first = optional_number_arg(&args, 2, 1.0, "concat")? as usize;
+        let last = optional_number_arg(&args, 3, list.len() as f64, "concat")? as usize;
+
+        let mut result = Vec::new();
+        for index in first..=last {
+            if index > first {
+                result.extend_from_slice(&separator);
+            }
+            match list.get(&LuaValue::Number(index as f64)).to_bytes() {
+                Some(bytes) => result.extend(bytes),
+                None => return Err(format!("invalid value (at index {}) in table for 'concat'", index)),
+            }
+            if result.len() > MAX_STRING {
+                return Err("resulting string too large for 'concat'".to_string());
+            }
+        }
+        Ok(vec![LuaValue::string(&result)])
+    }));
+
+    table.set_str("insert", LuaValue::native("table.insert", |args| {
+        let list = table_arg(&args, 0, "insert")?;
+        let mut list = list.borrow_mut();
+        let length = list.len();
+        match args.len() {
+            2 => list.set(LuaValue::Number((length + 1) as f64), arg(&args, 1))?,
+            3 => {
+                let position = number_arg(&args, 1, "insert")? as usize;
+                for index in (position..=length).rev() {
+                    let value = list.get(&LuaValue::Number(index as f64));
+                    list.set(LuaValue::Number((index + 1) as f64), value)?;
+                }
+                list.set(LuaValue::Number(position as f64), arg(&args, 2))?;
+            },
+            _ => return Err("wrong number of arguments to 'insert'".to_string()),
+        }
+        Ok(vec![])
+    }));
+
+    table
+}
+
+fn math_library() -> LuaTable {
+    let mut math = LuaTable::new();
+
+    math.set_str("floor", LuaValue::native("math.floor", |args| {
+        Ok(vec![LuaValue::Number(number_arg(&args, 0, "floor")?.floor())])
+    }));
+    math.set_str("ceil", LuaValue::native("math.ceil", |args| {
+        Ok(vec![LuaValue::Number(number_arg(&args, 0, "ceil")?.ceil())])
+    }));
+    math.set_str("abs", LuaValue::native("math.abs", |args| {
+        Ok(vec![LuaValue::Number(number_arg(&args, 0, "abs")?.abs())])
+    }));
+    math.set_str("fmod", LuaValue::native("math.fmod", |args| {
+        Ok(vec![LuaValue::Number(number_arg(&args, 0, "fmod")? % number_arg(&args, 1, "fmod")?)])
+    }));
+    math.set_str("max", LuaValue::native("math.max", |args| {
+        let mut max = number_arg(&args, 0, "max")?;
+        for index in 1..args.len() {
+            max = max.max(number_arg(&args, index, "max")?);
+        }
+        Ok(vec![LuaValue::Number(max)])
+    }));
+    math.set_str("min", LuaValue::native("math.min", |args| {
+        let mut min = number_arg(&args, 0, "min")?;
+        for index in 1..args.len() {
+            min = min.min(number_arg(&args, index, "min")?);
+        }
+        Ok(vec![LuaValue::Number(min)])
+    }));
+    math.set_str("huge", LuaValue::Number(f64::INFINITY));
+    math.set_str("pi", LuaValue::Number(std::f64::consts::PI));
+
+    math
+}
+
+impl DefaultHost {
+    pub fn new() -> DefaultHost {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let mut host = DefaultHost {
+            globals: HashMap::new(),
+            output: output.clone(),
+            trace: Vec::new(),
+        };
+
+        host.set_global("string", LuaValue::table(string_library()));
+        host.set_global("table", LuaValue::table(table_library()));
+        host.set_global("math", LuaValue::table(math_library()));
+
+        host.set_global("print", LuaValue::native("print", move |args| {
+            let line: Vec<String> = args.iter().map(tostring).collect();
+            output.borrow_mut().push(line.join("\t"));
+            Ok(vec![])
+        }));
+
+        host.set_global("type", LuaValue::native("type", |args| {
+            Ok(vec![LuaValue::string(arg(&args, 0).type_name().as_bytes())])
+        }));
+
+        host.set_global("tostring", LuaValue::native("tostring", |args| {
+            Ok(vec![LuaValue::string(tostring(&arg(&args, 0)).as_bytes())])
+        }));
+
+        host.set_global("tonumber", LuaValue::native("tonumber", |args| {
+            let value = arg(&args, 0);
+            let base = optional_number_arg(&args, 1, 10.0, "tonumber")?;
+            if !(2.0..=36.0).contains(&base) {
+                return Err("bad argument #2 to 'tonumber' (base out of range)".to_string());
+            }
+            let base = base as u32;
+            let number = match (&value, base) {
+                (_, 10) => value.to_number(),
+                (LuaValue::String(text), _) => std::str::from_utf8(text).ok()
+                    .and_then(|text| i64::from_str_radix(text.trim(), base).ok())
+                    .map(|number| number as f64),
+                _ => None,
+            };
+            Ok(vec![number.map(LuaValue::Number).unwrap_or(LuaValue::Nil)])
+        }));
+
+        host.set_global("select", LuaValue::native("select", |args| {
+            match arg(&args, 0) {
+                LuaValue::String(selector) if selector.as_slice() == b"#" => {
+                    Ok(vec![LuaValue::Number((args.len() - 1) as f64)])
+                },
+                _ => {
+                    let index = number_arg(&args, 0, "select")?;
+                    if index < 1.0 {
+                        return Err("bad argument #1 to 'select' (index out of range)".to_string());
+                    }
+                    Ok(args.into_iter().skip(index as usize).collect())
+                },
+            }
+        }));
+
+        host.set_global("unpack", LuaValue::native("unpack", |args| {
+            let list = table_arg(&args, 0, "unpack")?;
+            let list = list.borrow();
+            let first = optional_number_arg(&args, 1, 1.0, "unpack")? as usize;
+            let last = optional_number_arg(&args, 2, list.len() as f64, "unpack")? as usize;
+            if last >= first && last - first >= MAX_UNPACK {
+                return Err("too many results to unpack".to_string());
+            }
+            Ok((first..=last).map(|index| list.get(&LuaValue::Number(index as f64))).collect())
+        }));
+
+        host.set_global("rawget", LuaValue::native("rawget", |args| {
+            Ok(vec![table_arg(&args, 0, "rawget")?.borrow().get(&arg(&args, 1))])
+        }));
+
+        host.set_global("rawset", LuaValue::native("rawset", |args| {
+            let table = table_arg(&args, 0, "rawset")?;
+            table.borrow_mut().set(arg(&args, 1), arg(&args, 2))?;
+            Ok(vec![LuaValue::Table(table)])
+        }));
+
+        let next = LuaValue::native("next", |args| {
+            let table = table_arg(&args, 0, "next")?;
+            let entry = table.borrow().next(&arg(&args, 1))?;
+            Ok(match entry {
+                Some((key, value)) => vec![key, value],
+                None => vec![LuaValue::Nil],
+            })
+        });
+        host.set_global("next", next.clone());
+
+        host.set_global("pairs", LuaValue::native("pairs", move |args| {
+            let table = table_arg(&args, 0, "pairs")?;
+            Ok(vec![next.clone(), LuaValue::Table(table), LuaValue::Nil])
+        }));
+
+        let inext = LuaValue::native("ipairs_iterator", |args| {
+            let table = table_arg(&args, 0, "ipairs")?;
+            let index = number_arg(&args, 1, "ipairs")? + 1.0;
+            let value = table.borrow().get(&LuaValue::Number(index));
+            Ok(if value.is_nil() { vec![LuaValue::Nil] } else { vec![LuaValue::Number(index), value] })
+        });
+        host.set_global("ipairs", LuaValue::native("ipairs", move |args| {
+            let table = table_arg(&args, 0, "ipairs")?;
+            Ok(vec![inext.clone(), LuaValue::Table(table), LuaValue::Number(0.0)])
+        }));
+
+        host
+    }
+}
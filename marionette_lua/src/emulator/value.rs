@@ -0,0 +1,349 @@
+use std::{cell::RefCell, collections::HashMap, fmt::Debug, rc::Rc};
+
+/// A runtime value of the emulator.
+/// Strings are byte strings, like in Lua, so decrypted data does not have to be valid UTF-8.
+#[derive(Clone)]
+pub enum LuaValue {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Rc<Vec<u8>>),
+    Table(Rc<RefCell<LuaTable>>),
+    Function(Rc<LuaClosure>),
+    Native(Rc<LuaNative>),
+}
+
+impl LuaValue {
+    pub fn string(value: &[u8]) -> LuaValue {
+        LuaValue::String(Rc::new(value.to_vec()))
+    }
+
+    pub fn table(table: LuaTable) -> LuaValue {
+        LuaValue::Table(Rc::new(RefCell::new(table)))
+    }
+
+    pub fn native(name: &str, function: impl Fn(Vec<LuaValue>) -> Result<Vec<LuaValue>, String> + 'static) -> LuaValue {
+        LuaValue::Native(Rc::new(LuaNative {
+            name: name.to_string(),
+            function: Box::new(function),
+        }))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, LuaValue::Nil)
+    }
+
+    /// Everything except `nil` and `false` is true.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, LuaValue::Nil | LuaValue::Boolean(false))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LuaValue::Nil => "nil",
+            LuaValue::Boolean(_) => "boolean",
+            LuaValue::Number(_) => "number",
+            LuaValue::String(_) => "string",
+            LuaValue::Table(_) => "table",
+            LuaValue::Function(_) | LuaValue::Native(_) => "function",
+        }
+    }
+
+    /// Converts the value to a number the way Lua's arithmetic does (strings are coerced).
+    pub fn to_number(&self) -> Option<f64> {
+        match self {
+            LuaValue::Number(value) => Some(*value),
+            LuaValue::String(value) => parse_number(value),
+            _ => None,
+        }
+    }
+
+    /// Converts the value to a string the way `CONCAT` does (numbers are formatted).
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            LuaValue::String(value) => Some(value.as_ref().clone()),
+            LuaValue::Number(value) => Some(number_to_string(*value).into_bytes()),
+            _ => None,
+        }
+    }
+
+    /// Raw equality (no metamethods): by value for primitives, by identity for objects.
+    pub fn raw_equals(&self, other: &LuaValue) -> bool {
+        match (self, other) {
+            (LuaValue::Nil, LuaValue::Nil) => true,
+            (LuaValue::Boolean(a), LuaValue::Boolean(b)) => a == b,
+            (LuaValue::Number(a), LuaValue::Number(b)) => a == b,
+            (LuaValue::String(a), LuaValue::String(b)) => a == b,
+            (LuaValue::Table(a), LuaValue::Table(b)) => Rc::ptr_eq(a, b),
+            (LuaValue::Function(a), LuaValue::Function(b)) => Rc::ptr_eq(a, b),
+            (LuaValue::Native(a), LuaValue::Native(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for LuaValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.raw_equals(other)
+    }
+}
+
+impl Debug for LuaValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LuaValue::Nil => write!(f, "nil"),
+            LuaValue::Boolean(value) => write!(f, "{}", value),
+            LuaValue::Number(value) => write!(f, "{}", number_to_string(*value)),
+            LuaValue::String(value) => write!(f, "{:?}", String::from_utf8_lossy(value)),
+            LuaValue::Table(table) => write!(f, "table: {:p}", Rc::as_ptr(table)),
+            LuaValue::Function(closure) => write!(f, "function<{}>: {:p}", closure.function, Rc::as_ptr(closure)),
+            LuaValue::Native(native) => write!(f, "builtin<{}>: {:p}", native.name, Rc::as_ptr(native)),
+        }
+    }
+}
+
+/// Formats a number like Lua 5.1's `LUAI_NUMFFORMAT` (`%.14g`).
+pub fn number_to_string(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if value == value.trunc() && value.abs() < 1e15 {
+        return format!("{}", value as i64);
+    }
+
+    let scientific = format!("{:.13e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    let trim = |digits: String| -> String {
+        if digits.contains('.') {
+            digits.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            digits
+        }
+    };
+
+    if !(-4..14).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim(mantissa.to_string()), sign, exponent.abs())
+    } else {
+        let decimals = (13 - exponent).max(0) as usize;
+        trim(format!("{:.*}", decimals, value))
+    }
+}
+
+/// Parses a number the way `tonumber`/arithmetic coercion does in Lua 5.1.
+pub fn parse_number(value: &[u8]) -> Option<f64> {
+    let text = std::str::from_utf8(value).ok()?.trim();
+    if text.is_empty() {
+        return None;
+    }
+
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        let parsed = u64::from_str_radix(hex, 16).ok()? as f64;
+        return Some(if negative { -parsed } else { parsed });
+    }
+
+    // Rust accepts "inf", "nan" and friends, Lua does not.
+    if text.chars().any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E') {
+        return None;
+    }
+    text.parse::<f64>().ok()
+}
+
+/// A hashable view of a table key.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum LuaKey {
+    Boolean(bool),
+    Number(u64),
+    String(Rc<Vec<u8>>),
+    Object(usize),
+}
+
+impl LuaKey {
+    fn from(value: &LuaValue) -> Option<LuaKey> {
+        match value {
+            LuaValue::Nil => None,
+            LuaValue::Boolean(value) => Some(LuaKey::Boolean(*value)),
+            LuaValue::Number(value) if value.is_nan() => None,
+            // -0.0 and 0.0 are the same key
+            LuaValue::Number(value) => Some(LuaKey::Number((value + 0.0).to_bits())),
+            LuaValue::String(value) => Some(LuaKey::String(value.clone())),
+            LuaValue::Table(table) => Some(LuaKey::Object(Rc::as_ptr(table) as *const u8 as usize)),
+            LuaValue::Function(closure) => Some(LuaKey::Object(Rc::as_ptr(closure) as *const u8 as usize)),
+            LuaValue::Native(native) => Some(LuaKey::Object(Rc::as_ptr(native) as *const u8 as usize)),
+        }
+    }
+}
+
+/// A Lua table without metatables.
+/// The hash part remembers insertion order so `next` is deterministic between runs.
+#[derive(Default, Debug)]
+pub struct LuaTable {
+    pub array: Vec<LuaValue>,
+    hash: Vec<(LuaValue, LuaValue)>,
+    index: HashMap<LuaKey, usize>,
+}
+
+impl LuaTable {
+    pub fn new() -> LuaTable {
+        LuaTable::default()
+    }
+
+    fn array_index(key: &LuaValue) -> Option<usize> {
+        match key {
+            LuaValue::Number(value) if *value >= 1.0 && value.fract() == 0.0 && *value <= usize::MAX as f64 => {
+                Some(*value as usize - 1)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, key: &LuaValue) -> LuaValue {
+        if let Some(index) = LuaTable::array_index(key) {
+            if let Some(value) = self.array.get(index) {
+                return value.clone();
+            }
+        }
+
+        match LuaKey::from(key).and_then(|key| self.index.get(&key)) {
+            Some(slot) => self.hash[*slot].1.clone(),
+            None => LuaValue::Nil,
+        }
+    }
+
+    pub fn get_str(&self, key: &str) -> LuaValue {
+        self.get(&LuaValue::string(key.as_bytes()))
+    }
+
+    pub fn set(&mut self, key: LuaValue, value: LuaValue) -> Result<(), String> {
+        if let Some(index) = LuaTable::array_index(&key) {
+            if index < self.array.len() {
+                self.array[index] = value;
+                if index == self.array.len() - 1 {
+                    while matches!(self.array.last(), Some(LuaValue::Nil)) {
+                        self.array.pop();
+                    }
+                }
+                return Ok(());
+            }
+
+            if index == self.array.len() && !value.is_nil() {
+                self.array.push(value);
+                self.migrate();
+                return Ok(());
+            }
+        }
+
+        let hashed = match LuaKey::from(&key) {
+            Some(hashed) => hashed,
+            None => return Err(format!("table index is {}", if key.is_nil() { "nil" } else { "NaN" })),
+        };
+
+        match self.index.get(&hashed) {
+            Some(slot) => self.hash[*slot].1 = value,
+            None if !value.is_nil() => {
+                self.index.insert(hashed, self.hash.len());
+                self.hash.push((key, value));
+            },
+            None => {},
+        }
+        Ok(())
+    }
+
+    pub fn set_str(&mut self, key: &str, value: LuaValue) {
+        self.set(LuaValue::string(key.as_bytes()), value).unwrap();
+    }
+
+    /// Moves integer keys that became contiguous from the hash part into the array part.
+    fn migrate(&mut self) {
+        loop {
+            let key = LuaValue::Number((self.array.len() + 1) as f64);
+            let slot = match LuaKey::from(&key).and_then(|key| self.index.get(&key)) {
+                Some(slot) => *slot,
+                None => break,
+            };
+
+            let value = std::mem::replace(&mut self.hash[slot].1, LuaValue::Nil);
+            if value.is_nil() {
+                break;
+            }
+            self.array.push(value);
+        }
+    }
+
+    /// The border used by the length operator.
+    pub fn len(&self) -> usize {
+        self.array.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty() && self.hash.iter().all(|(_, value)| value.is_nil())
+    }
+
+    /// Returns the entry following `key` in traversal order, like Lua's `next`.
+    pub fn next(&self, key: &LuaValue) -> Result<Option<(LuaValue, LuaValue)>, String> {
+        let start = if key.is_nil() {
+            0
+        } else if let Some(index) = LuaTable::array_index(key).filter(|index| *index < self.array.len()) {
+            index + 1
+        } else {
+            match LuaKey::from(key).and_then(|key| self.index.get(&key)) {
+                Some(slot) => self.array.len() + slot + 1,
+                None => return Err("invalid key to 'next'".to_string()),
+            }
+        };
+
+        for position in start..self.array.len() + self.hash.len() {
+            if position < self.array.len() {
+                if !self.array[position].is_nil() {
+                    return Ok(Some((LuaValue::Number((position + 1) as f64), self.array[position].clone())));
+                }
+            } else {
+                let (key, value) = &self.hash[position - self.array.len()];
+                if !value.is_nil() {
+                    return Ok(Some((key.clone(), value.clone())));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// A captured variable. It points into the emulator stack until the declaring frame
+/// closes it, after which it owns the value.
+#[derive(Debug, Clone)]
+pub enum LuaUpvalueCell {
+    Open(usize),
+    Closed(LuaValue),
+}
+
+/// A Lua function instance: a prototype of the binary plus its upvalues.
+#[derive(Debug)]
+pub struct LuaClosure {
+    pub function: usize,
+    pub upvalues: Vec<Rc<RefCell<LuaUpvalueCell>>>,
+}
+
+/// A function implemented by the host.
+pub struct LuaNative {
+    pub name: String,
+    #[allow(clippy::type_complexity)]
+    pub function: Box<dyn Fn(Vec<LuaValue>) -> Result<Vec<LuaValue>, String>>,
+}
+
+impl Debug for LuaNative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LuaNative")
+            .field("name", &self.name)
+            .finish()
+    }
+}
@@ -1,9 +1,197 @@
 pub mod lua_binary;
 pub mod cfg;
+pub mod call_graph;
+pub mod emulator;
+pub mod optimize;
+pub mod patch;
+pub mod debug_info;
+pub mod assembly;
+pub mod loader;
+pub mod decompile;
+pub mod listing;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use marionette_core::{assembly::Range, byte_stream::{ByteStream, ByteStreamRead, ByteStreamWrite}};
+    use lua_binary::*;
+
+    fn header() -> LuaHeader {
+        LuaHeader {
+            raw: vec![],
+            range: Range::new(0, 0),
+
+            signature: 0x61754c1b,
+            version: 0x51,
+            format: 0,
+            endianness: 1,
+            int_size: 4,
+            size_t_size: 4,
+            instruction_size: 4,
+            lua_number_size: 8,
+            integral_flag: 0
+        }
+    }
+
+    fn string(value: &str) -> LuaConstantType {
+        LuaConstantType::String(vec![], format!("{}\0", value))
+    }
+
+    fn function(code: Vec<LuaLayout>, constants: Vec<LuaConstantType>, functions: Vec<LuaFunction>, num_upvalues: u8) -> LuaFunction {
+        let code: Vec<LuaInstruction> = code.into_iter().enumerate().map(|(pc, components)| LuaInstruction {
+            raw: vec![],
+            range: Range::new(0, 0),
+
+            opcode: components.opcode(),
+            components,
+            pc: pc as u64,
+
+            jump_target: None
+        }).collect();
+        let constants: Vec<LuaConstant> = constants.into_iter().map(|constant| LuaConstant {
+            raw: vec![],
+            range: Range::new(0, 0),
+            constant
+        }).collect();
+
+        LuaFunction {
+            raw: vec![],
+            range: Range::new(0, 0),
+
+            name: String::new(),
+            first_line: 0,
+            last_line: 0,
+
+            num_upvalues,
+            num_parameters: 0,
+            is_vararg: 2,
+            max_stack_size: 8,
+
+            code_size: code.len() as u64,
+            code,
+
+            constant_size: constants.len() as u64,
+            constants,
+
+            function_size: functions.len() as u64,
+            functions,
+
+            line_info_size: 0,
+            line_info: vec![],
+
+            local_size: 0,
+            locals: vec![],
+
+            upvalue_size: 0,
+            upvalues: vec![]
+        }
+    }
+
+    /// Serializes a synthesized main chunk and parses it back, so ranges and ids are real.
+    fn binary(root: LuaFunction) -> LuaBinary {
+        let mut stream = ByteStream::new(vec![]);
+        stream.add_context(header());
+        header().write(&mut stream).unwrap();
+        root.write(&mut stream).unwrap();
+
+        let mut stream = ByteStream::new(stream.bytes);
+        LuaBinary::read(&mut stream).unwrap()
+    }
+
+    /// local function helper() end
+    /// function global_fn() helper() end
+    /// global_fn()
+    /// print("x")
+    fn call_graph_binary() -> LuaBinary {
+        let helper = function(vec![LuaLayout::AB(LuaOpcode::RETURN, 0, 1)], vec![], vec![], 0);
+        let global_fn = function(vec![
+            LuaLayout::AB(LuaOpcode::GETUPVAL, 0, 0),
+            LuaLayout::ABC(LuaOpcode::CALL, 0, 1, 1),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ], vec![], vec![], 1);
+
+        binary(function(vec![
+            LuaLayout::ABx(LuaOpcode::CLOSURE, 0, 0),
+            LuaLayout::ABx(LuaOpcode::CLOSURE, 1, 1),
+            LuaLayout::AB(LuaOpcode::MOVE, 0, 0),
+            LuaLayout::ABx(LuaOpcode::SETGLOBAL, 1, 0),
+            LuaLayout::ABx(LuaOpcode::GETGLOBAL, 1, 0),
+            LuaLayout::ABC(LuaOpcode::CALL, 1, 1, 1),
+            LuaLayout::ABx(LuaOpcode::GETGLOBAL, 1, 1),
+            LuaLayout::ABx(LuaOpcode::LOADK, 2, 2),
+            LuaLayout::ABC(LuaOpcode::CALL, 1, 2, 1),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ], vec![string("global_fn"), string("print"), string("x")], vec![helper, global_fn], 0))
+    }
+
+    /// local s = "Ifmmp" local r = ""
+    /// for i = 1, #s do r = r .. string.char(s:byte(i) - 1) end
+    /// return r
+    fn decryption_binary() -> LuaBinary {
+        let mut root = function(vec![
+            LuaLayout::ABx(LuaOpcode::LOADK, 0, 0),
+            LuaLayout::ABx(LuaOpcode::LOADK, 1, 1),
+            LuaLayout::ABx(LuaOpcode::LOADK, 2, 2),
+            LuaLayout::AB(LuaOpcode::LEN, 2, 2),
+            LuaLayout::ABx(LuaOpcode::LOADK, 3, 1),
+            LuaLayout::AsBx(LuaOpcode::FORPREP, 1, 10),
+            LuaLayout::AB(LuaOpcode::MOVE, 5, 0),
+            LuaLayout::ABx(LuaOpcode::GETGLOBAL, 6, 3),
+            LuaLayout::ABC(LuaOpcode::GETTABLE, 6, 6, 260),
+            LuaLayout::ABx(LuaOpcode::LOADK, 7, 2),
+            LuaLayout::ABC(LuaOpcode::SELF, 7, 7, 261),
+            LuaLayout::AB(LuaOpcode::MOVE, 9, 4),
+            LuaLayout::ABC(LuaOpcode::CALL, 7, 3, 2),
+            LuaLayout::ABC(LuaOpcode::SUB, 7, 7, 257),
+            LuaLayout::ABC(LuaOpcode::CALL, 6, 2, 2),
+            LuaLayout::ABC(LuaOpcode::CONCAT, 0, 5, 6),
+            LuaLayout::AsBx(LuaOpcode::FORLOOP, 1, -11),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 2),
+        ], vec![
+            string(""),
+            LuaConstantType::Number(vec![], 1.0),
+            string("Ifmmp"),
+            string("string"),
+            string("char"),
+            string("byte"),
+        ], vec![], 0);
+        root.max_stack_size = 10;
+        binary(root)
+    }
+
+    /// local x = (10 + 32) * 2
+    /// local a, b
+    /// if x == 84 then a, b = "Hel", "lo" else a, b = "bad", "bad" end
+    /// return a .. b
+    fn obfuscated_binary() -> LuaBinary {
+        let mut root = function(vec![
+            LuaLayout::ABx(LuaOpcode::LOADK, 0, 0),
+            LuaLayout::ABx(LuaOpcode::LOADK, 1, 1),
+            LuaLayout::ABC(LuaOpcode::ADD, 0, 0, 1),
+            LuaLayout::ABC(LuaOpcode::MUL, 0, 0, 258),
+            LuaLayout::ABC(LuaOpcode::EQ, 0, 0, 259),
+            LuaLayout::SBx(LuaOpcode::JMP, 3),
+            LuaLayout::ABx(LuaOpcode::LOADK, 2, 4),
+            LuaLayout::ABx(LuaOpcode::LOADK, 3, 5),
+            LuaLayout::SBx(LuaOpcode::JMP, 2),
+            LuaLayout::ABx(LuaOpcode::LOADK, 2, 6),
+            LuaLayout::ABx(LuaOpcode::LOADK, 3, 6),
+            LuaLayout::ABC(LuaOpcode::CONCAT, 1, 2, 3),
+            LuaLayout::AB(LuaOpcode::RETURN, 1, 2),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ], vec![
+            LuaConstantType::Number(vec![], 10.0),
+            LuaConstantType::Number(vec![], 32.0),
+            LuaConstantType::Number(vec![], 2.0),
+            LuaConstantType::Number(vec![], 84.0),
+            string("Hel"),
+            string("lo"),
+            string("bad"),
+        ], vec![], 0);
+        root.line_info = (1..=14).collect();
+        root.line_info_size = 14;
+        binary(root)
+    }
 
     #[test]
     fn lua_deserialization_tests() {
@@ -38,4 +226,429 @@ mod tests {
             let graph = cfg::get_graph(function.clone());
         }
     }
+
+    #[test]
+    fn prototype_tree_tests() {
+        let binary = call_graph_binary();
+        assert_eq!(binary.functions.len(), 3);
+        assert_eq!(binary.root(), Some(0));
+        assert_eq!(binary.children(0), &[1, 2]);
+        assert_eq!(binary.parent(2), Some(0));
+        assert_eq!(binary.prototypes[2].depth, 1);
+        assert_eq!(binary.child(0, 1), Some(2));
+    }
+
+    #[test]
+    fn call_graph_tests() {
+        let binary = call_graph_binary();
+        let graph = call_graph::CallGraph::build(&binary);
+
+        assert_eq!(graph.callees(0), vec![2]);
+        assert_eq!(graph.callees(2), vec![1]);
+        assert_eq!(graph.callers(1), vec![2]);
+        assert_eq!(graph.callers(2), vec![0]);
+
+        let unresolved = graph.unresolved_sites(0);
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].pc, 8);
+        assert_eq!(unresolved[0].target, call_graph::CallTarget::Global("print".to_string()));
+
+        let creations: Vec<usize> = graph.sites(0).iter()
+            .filter(|site| site.kind == call_graph::CallKind::Closure)
+            .filter_map(|site| match site.target {
+                call_graph::CallTarget::Function(id) => Some(id),
+                _ => None
+            })
+            .collect();
+        assert_eq!(creations, vec![1, 2]);
+    }
+
+    #[test]
+    fn emulator_tests() {
+        use emulator::{Emulator, StepResult, host::{DefaultHost, LuaHost}, value::LuaValue};
+
+        let mut emulator = Emulator::new(decryption_binary(), DefaultHost::new());
+        let result = emulator.execute(0, vec![]).unwrap();
+        assert_eq!(result, vec![LuaValue::string(b"Hello")]);
+        assert_eq!(emulator.host.trace.iter().filter(|(name, _)| name == "string.char").count(), 5);
+
+        // stop on every iteration of the loop body, then single-step into it
+        emulator.start(0, vec![]).unwrap();
+        emulator.add_breakpoint(0, 15);
+        assert_eq!(emulator.run().unwrap(), StepResult::Breakpoint(0, 15));
+        assert_eq!(emulator.register(4), LuaValue::Number(1.0));
+        assert_eq!(emulator.register(6), LuaValue::string(b"H"));
+
+        assert_eq!(emulator.step().unwrap(), StepResult::Running);
+        assert_eq!(emulator.location(), Some((0, 16)));
+        assert_eq!(emulator.register(0), LuaValue::string(b"H"));
+
+        assert_eq!(emulator.run().unwrap(), StepResult::Breakpoint(0, 15));
+        assert_eq!(emulator.register(4), LuaValue::Number(2.0));
+
+        emulator.remove_breakpoint(0, 15);
+        assert_eq!(emulator.run().unwrap(), StepResult::Finished(vec![LuaValue::string(b"Hello")]));
+
+        emulator.step_limit = Some(10);
+        let error = emulator.execute(0, vec![]).unwrap_err();
+        assert_eq!(error.pc, 9);
+
+        let mut host = DefaultHost::new();
+        let LuaValue::Native(tonumber) = host.get_global("tonumber") else { panic!("tonumber is not a native") };
+        let mut call = |base: f64| host.call(&tonumber, vec![LuaValue::string(b"10"), LuaValue::Number(base)]);
+        assert_eq!(call(16.0).unwrap(), vec![LuaValue::Number(16.0)]);
+        assert_eq!(call(1.0).unwrap_err(), "bad argument #2 to 'tonumber' (base out of range)");
+        assert!(call(37.0).is_err());
+
+        let LuaValue::Table(string) = host.get_global("string") else { panic!("string is not a table") };
+        let rep = string.borrow().get(&LuaValue::string(b"rep"));
+        let LuaValue::Native(rep) = rep else { panic!("string.rep is not a native") };
+        assert_eq!(host.call(&rep, vec![LuaValue::string(b"ab"), LuaValue::Number(2.0)]).unwrap(), vec![LuaValue::string(b"abab")]);
+        assert!(host.call(&rep, vec![LuaValue::string(b"x"), LuaValue::Number(1e18)]).is_err());
+        let LuaValue::Native(unpack) = host.get_global("unpack") else { panic!("unpack is not a native") };
+        let list = LuaValue::table(emulator::value::LuaTable::new());
+        assert!(host.call(&unpack, vec![list, LuaValue::Number(1.0), LuaValue::Number(1e18)]).is_err());
+    }
+
+    #[test]
+    fn emulator_operand_range_tests() {
+        use emulator::{Emulator, host::DefaultHost};
+
+        // corrupt operands fail the step instead of panicking
+        let run = |code: Vec<LuaLayout>| Emulator::new(binary(function(code, vec![], vec![], 0)), DefaultHost::new()).execute(0, vec![]);
+        let mut patched = decryption_binary();
+        patched.functions[0].code[0] = function(vec![LuaLayout::AB(LuaOpcode::MOVE, 250, 0)], vec![], vec![], 0).code[0].clone();
+        let error = Emulator::new(patched, DefaultHost::new()).execute(0, vec![]).unwrap_err();
+        assert_eq!(error.pc, 0);
+        assert!(error.description.contains("register 250 past the stack size 10"), "{}", error.description);
+
+        assert!(run(vec![LuaLayout::AB(LuaOpcode::LOADNIL, 0, 200), LuaLayout::AB(LuaOpcode::RETURN, 0, 1)]).is_err());
+        assert!(run(vec![LuaLayout::ABC(LuaOpcode::CONCAT, 0, 1, 100), LuaLayout::AB(LuaOpcode::RETURN, 0, 1)]).is_err());
+        assert!(run(vec![LuaLayout::ABC(LuaOpcode::SELF, 7, 0, 0), LuaLayout::AB(LuaOpcode::RETURN, 0, 1)]).is_err());
+        assert!(run(vec![LuaLayout::AB(LuaOpcode::RETURN, 0, 100)]).is_err());
+        assert!(run(vec![LuaLayout::AB(LuaOpcode::RETURN, 200, 1)]).is_err());
+
+        // SETLIST with C = 0 and a block number of 0 in its extra word
+        assert!(run(vec![
+            LuaLayout::ABC(LuaOpcode::NEWTABLE, 0, 0, 0),
+            LuaLayout::ABC(LuaOpcode::SETLIST, 0, 1, 0),
+            LuaLayout::ABC(LuaOpcode::MOVE, 0, 0, 0),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ]).is_err());
+    }
+
+    #[test]
+    fn optimization_tests() {
+        use emulator::{Emulator, host::DefaultHost, value::LuaValue};
+        use optimize::PassManager;
+
+        let mut binary = obfuscated_binary();
+        let expected = Emulator::new(binary.clone(), DefaultHost::new()).execute(0, vec![]).unwrap();
+        assert_eq!(expected, vec![LuaValue::string(b"Hello")]);
+
+        let applied = PassManager::with_default_passes().run_binary(&mut binary);
+        assert!(applied.contains(&(0, "opaque predicates")));
+
+        let function = &binary.functions[0];
+        let opcodes: Vec<LuaOpcode> = function.code.iter().map(|instruction| instruction.opcode).collect();
+        assert_eq!(opcodes, vec![LuaOpcode::LOADK, LuaOpcode::RETURN, LuaOpcode::RETURN]);
+        assert_eq!(function.constants.len(), 1);
+        // refreshed after the passes, so the constant carries the bytes it is written as
+        assert!(matches!(&function.constants[0].constant, LuaConstantType::String(raw, value) if value == "Hello\0" && !raw.is_empty()));
+        assert_eq!(function.line_info, vec![12, 13, 14]);
+
+        // the simplified function survives a round trip and still computes the same thing
+        let mut stream = ByteStream::new(vec![]);
+        binary.write(&mut stream).unwrap();
+        let mut stream = ByteStream::new(stream.bytes);
+        let binary = LuaBinary::read(&mut stream).unwrap();
+        assert_eq!(binary.functions[0].code_size, 3);
+        assert_eq!(Emulator::new(binary, DefaultHost::new()).execute(0, vec![]).unwrap(), expected);
+    }
+
+    #[test]
+    fn optimization_operand_range_tests() {
+        use optimize::PassManager;
+
+        // operands past the constant pool and the register file are left alone
+        let mut binary = binary(function(vec![
+            LuaLayout::ABx(LuaOpcode::LOADK, 0, 69634),
+            LuaLayout::ABC(LuaOpcode::SELF, 1, 293, 0),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ], vec![string("unused")], vec![], 0));
+        PassManager::with_default_passes().run_binary(&mut binary);
+        assert_eq!(binary.functions[0].code[0].components, LuaLayout::ABx(LuaOpcode::LOADK, 0, 69634));
+        assert!(binary.functions[0].constants.is_empty());
+    }
+
+    #[test]
+    fn patch_tests() {
+        use emulator::{Emulator, host::DefaultHost, value::LuaValue};
+        use patch::{LuaInstructionBuilder, PatchErrorType};
+
+        let mut binary = decryption_binary();
+        let original = binary.functions[0].clone();
+        let function = &mut binary.functions[0];
+        function.line_info = (1..=18).collect();
+        function.line_info_size = 18;
+        function.locals = vec![LuaLocal { raw: vec![], range: Range::new(0, 0), name: "r\0".to_string(), start_pc: 1, end_pc: 18 }];
+        function.local_size = 1;
+
+        // at the head of the loop body, which FORLOOP jumps back to
+        let nil = LuaInstructionBuilder::new(LuaOpcode::LOADNIL).a(9).b(9).build().unwrap();
+        function.insert_instruction(6, nil).unwrap();
+        assert_eq!(function.code_size, 19);
+        assert_eq!(function.code[5].components, LuaLayout::AsBx(LuaOpcode::FORPREP, 1, 11));
+        assert_eq!(function.code[17].components, LuaLayout::AsBx(LuaOpcode::FORLOOP, 1, -12));
+        assert_eq!(function.code[17].jump_target, Some(6));
+        assert_eq!(function.line_info[6], 7);
+        assert_eq!(function.line_info[7], 7);
+        assert_eq!((function.locals[0].start_pc, function.locals[0].end_pc), (1, 19));
+
+        let result = Emulator::new(binary.clone(), DefaultHost::new()).execute(0, vec![]).unwrap();
+        assert_eq!(result, vec![LuaValue::string(b"Hello")]);
+
+        let function = &mut binary.functions[0];
+        let removed = function.remove_instruction(6).unwrap();
+        assert_eq!(removed[0].opcode, LuaOpcode::LOADNIL);
+        let components = |function: &LuaFunction| function.code.iter().map(|instruction| instruction.components).collect::<Vec<_>>();
+        assert_eq!(components(function), components(&original));
+
+        let error = LuaInstructionBuilder::new(LuaOpcode::LOADK).a(0).c(1).build().unwrap_err();
+        assert_eq!(error.error_type, PatchErrorType::InvalidOperand);
+        let error = LuaInstructionBuilder::new(LuaOpcode::JMP).sbx(200000).build().unwrap_err();
+        assert_eq!(error.error_type, PatchErrorType::JumpOverflow);
+
+        // the JMP after EQ is part of the comparison
+        let mut binary = obfuscated_binary();
+        let jump = LuaInstructionBuilder::new(LuaOpcode::JMP).build().unwrap();
+        let error = binary.functions[0].insert_instruction(5, jump).unwrap_err();
+        assert_eq!(error.error_type, PatchErrorType::SplitsInstruction);
+
+        // code inserted before FORLOOP ends the body; FORPREP still enters at the FORLOOP
+        let mut binary = decryption_binary();
+        let nil = LuaInstructionBuilder::new(LuaOpcode::LOADNIL).a(9).b(9).build().unwrap();
+        binary.functions[0].insert_instruction(16, nil).unwrap();
+        assert_eq!(binary.functions[0].code[5].components, LuaLayout::AsBx(LuaOpcode::FORPREP, 1, 11));
+        assert_eq!(binary.functions[0].code[17].components, LuaLayout::AsBx(LuaOpcode::FORLOOP, 1, -12));
+        let result = Emulator::new(binary.clone(), DefaultHost::new()).execute(0, vec![]).unwrap();
+        assert_eq!(result, vec![LuaValue::string(b"Hello")]);
+
+        // the constants after the code only move once the binary is refreshed
+        let original = decryption_binary();
+        let before = (original.functions[0].range.end, original.functions[0].constants[0].range.start);
+        assert_eq!((binary.functions[0].range.end, binary.functions[0].constants[0].range.start), before);
+        binary.refresh().unwrap();
+        assert_eq!(binary.functions[0].range.end, before.0 + 4);
+        assert_eq!(binary.functions[0].constants[0].range.start, before.1 + 4);
+        assert_eq!(binary.functions[0].code[17].components, LuaLayout::AsBx(LuaOpcode::FORLOOP, 1, -12));
+        assert_eq!(binary.range.end, original.range.end + 4);
+
+        // the MOVE after the second CLOSURE is its upvalue, not an instruction
+        let mut binary = call_graph_binary();
+        let function = &mut binary.functions[0];
+        let nil = LuaInstructionBuilder::new(LuaOpcode::LOADNIL).a(0).b(0).build().unwrap();
+        let error = function.replace_instruction(2, nil.clone()).unwrap_err();
+        assert_eq!(error.error_type, PatchErrorType::SplitsInstruction);
+        let error = function.replace_instruction(1, nil.clone()).unwrap_err();
+        assert_eq!(error.error_type, PatchErrorType::SplitsInstruction);
+        assert_eq!(function.code[1].opcode, LuaOpcode::CLOSURE);
+        assert_eq!(function.replace_instruction(4, nil).unwrap().opcode, LuaOpcode::GETGLOBAL);
+    }
+
+    #[test]
+    fn debug_info_tests() {
+        use debug_info::{DebugOptions, LineMap};
+
+        let roundtrip = |binary: &LuaBinary| {
+            let mut stream = ByteStream::new(vec![]);
+            binary.write(&mut stream).unwrap();
+            let mut stream = ByteStream::new(stream.bytes);
+            LuaBinary::read(&mut stream).unwrap()
+        };
+
+        let mut binary = call_graph_binary();
+        binary.functions[0].name = "@test.lua\0".to_string();
+        binary.functions[0].line_info = vec![1; 10];
+        binary.functions[0].line_info_size = 10;
+
+        binary.strip_debug();
+        let stripped = roundtrip(&binary);
+        assert!(stripped.is_stripped());
+        assert_eq!(stripped.functions[0].name, "");
+
+        let mut options = DebugOptions::new();
+        options.source = Some("@listing".to_string());
+        options.lines = Some(LineMap::from_listing(&binary.listing()));
+        binary.inject_debug(&options);
+
+        let root = &binary.functions[0];
+        assert_eq!(root.name, "@listing\0");
+        assert_eq!(root.locals[0].name, "fn_0\0");
+        assert_eq!(root.locals[1].name, "fn_1\0");
+        assert_eq!(root.local_name(0, 2), Some("fn_0"));
+        // the listing starts with a header line for the function
+        assert_eq!(root.line_info[0], 2);
+        assert_eq!(root.line_info[9], 11);
+
+        // global_fn captures helper from register 0 of the main chunk
+        assert_eq!(binary.functions[2].upvalues[0].name, "fn_0\0");
+        assert_eq!(binary.functions[2].locals[0].name, "fn_0_0\0");
+
+        let injected = roundtrip(&binary);
+        for (written, read) in binary.functions.iter().zip(injected.functions.iter()) {
+            assert_eq!(written.name, read.name);
+            assert_eq!(written.line_info, read.line_info);
+            assert_eq!(written.locals.iter().map(|local| (&local.name, local.start_pc, local.end_pc)).collect::<Vec<_>>(),
+                read.locals.iter().map(|local| (&local.name, local.start_pc, local.end_pc)).collect::<Vec<_>>());
+            assert_eq!(written.upvalues.iter().map(|upvalue| &upvalue.name).collect::<Vec<_>>(),
+                read.upvalues.iter().map(|upvalue| &upvalue.name).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn assembly_tests() {
+        use assembly::{LuaAssembly, LuaRegionKind};
+        use debug_info::DebugOptions;
+        use marionette_core::assembly::Assembly;
+
+        let mut binary = call_graph_binary();
+        binary.inject_debug(&DebugOptions::default());
+        binary.functions[0].line_info = (1..=10).collect();
+        binary.functions[0].line_info_size = 10;
+
+        let assembly = LuaAssembly::new(&binary).unwrap();
+        assert_eq!(assembly.regions().len(), 1 + 3 * 3);
+        assert!(assembly.text().starts_with("; header\n"));
+
+        // nested prototypes sit between the constants and the debug information of their parent,
+        // so regions never overlap but are not in file order
+        let mut ranges: Vec<_> = assembly.regions().iter().map(|region| region.range()).collect();
+        ranges.sort_by_key(|range| range.start);
+        assert!(ranges.windows(2).all(|pair| pair[0].end <= pair[1].start));
+
+        for region in assembly.regions() {
+            assert_eq!(region.raw(), &assembly.raw()[region.range().start as usize..region.range().end as usize]);
+            for data in region.data() {
+                assert!(region.range().start <= data.range().start && data.range().end <= region.range().end);
+                assert_eq!(data.raw(), &assembly.raw()[data.range().start as usize..data.range().end as usize]);
+            }
+        }
+
+        let code = assembly.region(LuaRegionKind::Code(0)).unwrap();
+        assert_eq!(code.data().len(), 10);
+        assert_eq!(code.data()[9].raw(), &[0x1e, 0x00, 0x80, 0x00]);
+        assert!(code.data()[9].text().contains("RETURN"));
+        assert_eq!(assembly.region_at(code.data()[3].range().start).unwrap().0, LuaRegionKind::Code(0));
+
+        let constants = assembly.region(LuaRegionKind::Constants(0)).unwrap();
+        assert_eq!(constants.data()[1].text(), "K1 = \"print\"");
+
+        let debug = assembly.region(LuaRegionKind::Debug(0)).unwrap();
+        assert_eq!(debug.data()[9].text(), "line 9 = 10");
+        assert_eq!(debug.data()[9].raw(), &[10, 0, 0, 0]);
+        assert!(debug.text().contains("local 0"));
+        assert_eq!(debug.range().end, assembly.raw().len() as u64);
+
+        // line numbers are ints, as wide as the header says
+        binary.header.int_size = 8;
+        let mut stream = ByteStream::new(vec![]);
+        binary.write(&mut stream).unwrap();
+        let wide = LuaBinary::read(&mut ByteStream::new(stream.bytes)).unwrap();
+        let assembly = LuaAssembly::new(&wide).unwrap();
+        let debug = assembly.region(LuaRegionKind::Debug(0)).unwrap();
+        assert_eq!(debug.data()[9].raw(), &[10, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(debug.data().windows(2).all(|pair| pair[0].range().end <= pair[1].range().start));
+    }
+
+    #[test]
+    fn loader_tests() {
+        use marionette_core::{byte_stream::ByteStreamWrite, loader::{LoaderErrorType, LoaderRegistry}};
+
+        let mut registry = LoaderRegistry::new();
+        loader::register(&mut registry).unwrap();
+
+        let mut stream = ByteStream::new(vec![]);
+        call_graph_binary().write(&mut stream).unwrap();
+        let (name, assembly) = registry.load(stream.bytes.clone(), None).unwrap();
+        assert_eq!(name, "lua51");
+        assert_eq!(assembly.raw(), stream.bytes.as_slice());
+        assert_eq!(assembly.regions().len(), 10);
+
+        // Lua 5.3 has the same signature but is not ours to parse
+        assert!(registry.detect(b"\x1bLua\x53\x00").is_none());
+        let error = registry.load(b"\x1bLua\x51\x00".to_vec(), Some("lua51")).err().unwrap();
+        assert_eq!(error.error_type, LoaderErrorType::LoadFailure);
+    }
+
+    #[test]
+    fn decompile_tests() {
+        use debug_info::DebugOptions;
+
+        let binary = call_graph_binary();
+        let text = binary.decompile_function(0).unwrap();
+        assert!(text.starts_with("-- function 0\nfunction function0(...)\n"));
+        assert!(text.contains("r0 = function1"));
+        assert!(text.contains("global_fn = r1"));
+        assert!(text.contains("r1 = print"));
+        assert!(text.contains("r1(r2)"));
+        // the upvalue pseudo-instruction of the second closure is not a statement
+        assert!(text.contains("-- operand"));
+        assert!(binary.decompile_function(2).unwrap().contains("r0 = u0"));
+        assert!(binary.decompile_function(3).is_none());
+        assert_eq!(binary.decompile().matches("\nend\n").count(), 3);
+
+        // the block number after a SETLIST with C = 0 is read in the chunk's byte order
+        let mut setlist = self::binary(function(vec![
+            LuaLayout::ABC(LuaOpcode::NEWTABLE, 0, 0, 0),
+            LuaLayout::ABC(LuaOpcode::SETLIST, 0, 1, 0),
+            LuaLayout::ABC(LuaOpcode::MOVE, 0, 0, 0),
+            LuaLayout::AB(LuaOpcode::RETURN, 0, 1),
+        ], vec![], vec![], 0));
+        setlist.functions[0].code[2].raw = vec![3, 0, 0, 0];
+        assert!(setlist.decompile_function(0).unwrap().contains("r0[101...]"));
+        setlist.header.endianness = 0;
+        setlist.functions[0].code[2].raw = vec![0, 0, 0, 3];
+        assert!(setlist.decompile_function(0).unwrap().contains("r0[101...]"));
+
+        // jumps get labels and locals their names once debug information exists
+        let mut binary = decryption_binary();
+        binary.inject_debug(&DebugOptions::default());
+        let text = binary.decompile();
+        assert!(text.contains("::L16::"));
+        assert!(text.contains("goto L16"));
+        assert!(text.contains("goto L6"));
+        assert!(text.contains(".char"));
+        assert!(text.contains(":byte") || text.contains(".byte"));
+    }
+
+    #[test]
+    fn listing_tests() {
+        use listing::TokenKind;
+
+        let binary = decryption_binary();
+        let function = &binary.functions[0];
+        let text = |pc: usize| function.listing(pc).unwrap().iter().map(|token| token.text.clone()).collect::<Vec<_>>().join(" ");
+        assert_eq!(text(2), "0002 LOADK r2 K2 ; \"Ifmmp\"");
+        assert_eq!(text(5), "0005 FORPREP r1 L16");
+        assert_eq!(text(16), "0016 FORLOOP r1 L6");
+        // the RK operand of GETTABLE is a constant, its other operands registers
+        let kinds: Vec<TokenKind> = function.listing(8).unwrap().iter().map(|token| token.kind).collect();
+        assert_eq!(kinds, [TokenKind::Pc, TokenKind::Opcode, TokenKind::Register, TokenKind::Register, TokenKind::Constant, TokenKind::Comment]);
+        assert!(function.listing(18).is_none());
+
+        // the loop: entry and body fall into the FORLOOP block, which loops back or exits
+        let (graph, _) = cfg::get_graph(function.clone()).unwrap();
+        let mut edges: Vec<(usize, usize, cfg::EdgeKind)> = graph.edge_indices().map(|edge| {
+            let (source, target) = graph.edge_endpoints(edge).unwrap();
+            (graph[source].id, graph[target].id, cfg::edge_kind(&graph[source], &graph[target]))
+        }).collect();
+        edges.sort_by_key(|&(source, target, _)| (source, target));
+        assert_eq!(edges, [
+            (0, 16, cfg::EdgeKind::Unconditional),
+            (6, 16, cfg::EdgeKind::Unconditional),
+            (16, 6, cfg::EdgeKind::True),
+            (16, 17, cfg::EdgeKind::False),
+        ]);
+    }
 }
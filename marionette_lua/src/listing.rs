@@ -0,0 +1,149 @@
+// Purpose: splits instructions into typed tokens for display, e.g. for syntax colored graph
+// blocks. Each operand is classified by what it refers to for its opcode, so a viewer can
+// color registers, constants and jump targets differently without knowing Lua's opcodes.
+
+use crate::lua_binary::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Pc,
+    Opcode,
+    Register,
+    Constant,
+    Upvalue,
+    Number,
+    /// The pc a jump goes to, written as a label `L{pc}` like in decompiled code.
+    Target,
+    /// Values and names the operands refer to, e.g. the text of a string constant.
+    Comment,
+}
+
+impl TokenKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Pc => "pc",
+            TokenKind::Opcode => "opcode",
+            TokenKind::Register => "register",
+            TokenKind::Constant => "constant",
+            TokenKind::Upvalue => "upvalue",
+            TokenKind::Number => "number",
+            TokenKind::Target => "target",
+            TokenKind::Comment => "comment",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub text: String,
+    pub kind: TokenKind,
+}
+
+impl Token {
+    fn new(text: String, kind: TokenKind) -> Self {
+        Token { text, kind }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register,
+    /// A register, or a constant from `RK_CONSTANT` up.
+    Rk,
+    Constant,
+    Upvalue,
+    Number,
+    Target,
+}
+
+/// What the A, B (or Bx) and C operands of `opcode` refer to, None for unused ones.
+fn operands(opcode: LuaOpcode) -> [Option<Operand>; 3] {
+    use Operand::*;
+    match opcode {
+        LuaOpcode::MOVE | LuaOpcode::LOADNIL | LuaOpcode::UNM | LuaOpcode::NOT | LuaOpcode::LEN => [Some(Register), Some(Register), None],
+        LuaOpcode::LOADK | LuaOpcode::GETGLOBAL | LuaOpcode::SETGLOBAL => [Some(Register), Some(Constant), None],
+        LuaOpcode::LOADBOOL | LuaOpcode::NEWTABLE | LuaOpcode::CALL | LuaOpcode::TAILCALL | LuaOpcode::SETLIST => [Some(Register), Some(Number), Some(Number)],
+        LuaOpcode::GETUPVAL | LuaOpcode::SETUPVAL => [Some(Register), Some(Upvalue), None],
+        LuaOpcode::GETTABLE | LuaOpcode::SELF => [Some(Register), Some(Register), Some(Rk)],
+        LuaOpcode::SETTABLE
+        | LuaOpcode::ADD
+        | LuaOpcode::SUB
+        | LuaOpcode::MUL
+        | LuaOpcode::DIV
+        | LuaOpcode::MOD
+        | LuaOpcode::POW => [Some(Register), Some(Rk), Some(Rk)],
+        LuaOpcode::CONCAT => [Some(Register), Some(Register), Some(Register)],
+        LuaOpcode::JMP => [None, Some(Target), None],
+        LuaOpcode::EQ | LuaOpcode::LT | LuaOpcode::LE => [Some(Number), Some(Rk), Some(Rk)],
+        LuaOpcode::TEST | LuaOpcode::TFORLOOP => [Some(Register), None, Some(Number)],
+        LuaOpcode::TESTSET => [Some(Register), Some(Register), Some(Number)],
+        LuaOpcode::RETURN | LuaOpcode::VARARG | LuaOpcode::CLOSURE => [Some(Register), Some(Number), None],
+        LuaOpcode::FORLOOP | LuaOpcode::FORPREP => [Some(Register), Some(Target), None],
+        LuaOpcode::CLOSE => [Some(Register), None, None],
+    }
+}
+
+/// Readable text of a constant, e.g. `"print"` for a string.
+pub(crate) fn constant_text(function: &LuaFunction, index: u32) -> String {
+    match function.constants.get(index as usize).map(|constant| &constant.constant) {
+        Some(LuaConstantType::Nil(_)) => "nil".to_string(),
+        Some(LuaConstantType::Boolean(_, value)) => value.to_string(),
+        Some(LuaConstantType::Number(_, value)) => value.to_string(),
+        Some(LuaConstantType::String(_, value)) => format!("{:?}", value.trim_end_matches('\0')),
+        None => format!("K{}", index),
+    }
+}
+
+impl LuaFunction {
+    /// Tokens of the instruction at `pc`: its pc, opcode, operands and a comment with the
+    /// constants and local names they refer to, if any.
+    pub fn listing(&self, pc: usize) -> Option<Vec<Token>> {
+        let instruction = self.code.get(pc)?;
+        let components = &instruction.components;
+        let mut tokens = vec![
+            Token::new(format!("{:04}", pc), TokenKind::Pc),
+            Token::new(instruction.opcode.to_string(), TokenKind::Opcode),
+        ];
+        let mut comments = Vec::new();
+
+        let [a, b, c] = operands(instruction.opcode);
+        let b_value = match components {
+            LuaLayout::ABx(..) => components.bx(),
+            _ => components.b() as u32,
+        };
+        let values = [components.a() as u32, b_value, components.c() as u32];
+
+        for (operand, value) in [a, b, c].into_iter().zip(values) {
+            let Some(operand) = operand else { continue };
+            let token = match operand {
+                Operand::Rk if rk_constant(value as usize).is_some() => {
+                    let index = value - RK_CONSTANT as u32;
+                    comments.push(constant_text(self, index));
+                    Token::new(format!("K{}", index), TokenKind::Constant)
+                }
+                Operand::Register | Operand::Rk => {
+                    if let Some(name) = self.local_name(value as usize, instruction.pc).filter(|name| !name.is_empty()) {
+                        comments.push(name.to_string());
+                    }
+                    Token::new(format!("r{}", value), TokenKind::Register)
+                }
+                Operand::Constant => {
+                    comments.push(constant_text(self, value));
+                    Token::new(format!("K{}", value), TokenKind::Constant)
+                }
+                Operand::Upvalue => Token::new(format!("u{}", value), TokenKind::Upvalue),
+                Operand::Number => Token::new(value.to_string(), TokenKind::Number),
+                Operand::Target => {
+                    let target = pc as i64 + 1 + components.sbx() as i64;
+                    Token::new(format!("L{}", target), TokenKind::Target)
+                }
+            };
+            tokens.push(token);
+        }
+
+        if !comments.is_empty() {
+            tokens.push(Token::new(format!("; {}", comments.join(", ")), TokenKind::Comment));
+        }
+        Some(tokens)
+    }
+}
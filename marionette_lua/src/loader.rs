@@ -0,0 +1,39 @@
+// Purpose: registers Lua 5.1 bytecode with `marionette_core::loader`, opening it as a `LuaAssembly`.
+
+use marionette_core::{assembly::Assembly, loader::*};
+
+use crate::assembly::LuaAssembly;
+
+const SIGNATURE: &[u8] = b"\x1bLua";
+const VERSION: u8 = 0x51;
+
+pub struct LuaLoader;
+
+impl Loader for LuaLoader {
+    fn name(&self) -> &str {
+        "lua51"
+    }
+
+    fn description(&self) -> &str {
+        "Lua 5.1 bytecode"
+    }
+
+    fn probe(&self, bytes: &[u8]) -> f32 {
+        // other Lua versions share the signature but not the layout
+        match bytes.strip_prefix(SIGNATURE) {
+            Some([VERSION, ..]) => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn load(&self, bytes: Vec<u8>) -> Result<Box<dyn Assembly>, LoaderError> {
+        let assembly = LuaAssembly::from_bytes(bytes).map_err(|error| {
+            LoaderError::new(self.name(), error.to_string(), LoaderErrorType::LoadFailure)
+        })?;
+        Ok(Box::new(assembly))
+    }
+}
+
+pub fn register(registry: &mut LoaderRegistry) -> Result<(), LoaderError> {
+    registry.register(Box::new(LuaLoader))
+}
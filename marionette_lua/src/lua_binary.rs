@@ -201,6 +201,13 @@ impl ToString for LuaOpcode {
     }
 }
 
+impl LuaOpcode {
+    /// Returns the operand layout of the opcode, with zeroed operands.
+    pub fn layout(&self) -> Option<LuaLayout> {
+        OPCODE_LAYOUT.get(self).copied()
+    }
+}
+
 impl TryFrom<String> for LuaOpcode {
     type Error = ();
     fn try_from(value: String) -> Result<Self, ()> {
@@ -263,67 +270,77 @@ impl TryFrom<String> for LuaOpcode {
     }
 }
 
-impl From<u8> for LuaOpcode {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for LuaOpcode {
+    type Error = ();
+    fn try_from(value: u8) -> Result<Self, ()> {
         match value {
-            0 => LuaOpcode::MOVE,
-            1 => LuaOpcode::LOADK,
-            2 => LuaOpcode::LOADBOOL,
-            3 => LuaOpcode::LOADNIL,
-            4 => LuaOpcode::GETUPVAL,
+            0 => Ok(LuaOpcode::MOVE),
+            1 => Ok(LuaOpcode::LOADK),
+            2 => Ok(LuaOpcode::LOADBOOL),
+            3 => Ok(LuaOpcode::LOADNIL),
+            4 => Ok(LuaOpcode::GETUPVAL),
 
-            5 => LuaOpcode::GETGLOBAL,
-            6 => LuaOpcode::GETTABLE,
+            5 => Ok(LuaOpcode::GETGLOBAL),
+            6 => Ok(LuaOpcode::GETTABLE),
 
-            7 => LuaOpcode::SETGLOBAL,
-            8 => LuaOpcode::SETUPVAL,
-            9 => LuaOpcode::SETTABLE,
+            7 => Ok(LuaOpcode::SETGLOBAL),
+            8 => Ok(LuaOpcode::SETUPVAL),
+            9 => Ok(LuaOpcode::SETTABLE),
 
-            10 => LuaOpcode::NEWTABLE,
+            10 => Ok(LuaOpcode::NEWTABLE),
 
-            11 => LuaOpcode::SELF,
+            11 => Ok(LuaOpcode::SELF),
 
-            12 => LuaOpcode::ADD,
-            13 => LuaOpcode::SUB,
-            14 => LuaOpcode::MUL,
-            15 => LuaOpcode::DIV,
-            16 => LuaOpcode::MOD,
-            17 => LuaOpcode::POW,
-            18 => LuaOpcode::UNM,
-            19 => LuaOpcode::NOT,
-            20 => LuaOpcode::LEN,
+            12 => Ok(LuaOpcode::ADD),
+            13 => Ok(LuaOpcode::SUB),
+            14 => Ok(LuaOpcode::MUL),
+            15 => Ok(LuaOpcode::DIV),
+            16 => Ok(LuaOpcode::MOD),
+            17 => Ok(LuaOpcode::POW),
+            18 => Ok(LuaOpcode::UNM),
+            19 => Ok(LuaOpcode::NOT),
+            20 => Ok(LuaOpcode::LEN),
 
-            21 => LuaOpcode::CONCAT,
+            21 => Ok(LuaOpcode::CONCAT),
 
-            22 => LuaOpcode::JMP,
+            22 => Ok(LuaOpcode::JMP),
 
-            23 => LuaOpcode::EQ,
-            24 => LuaOpcode::LT,
-            25 => LuaOpcode::LE,
+            23 => Ok(LuaOpcode::EQ),
+            24 => Ok(LuaOpcode::LT),
+            25 => Ok(LuaOpcode::LE),
 
-            26 => LuaOpcode::TEST,
-            27 => LuaOpcode::TESTSET,
+            26 => Ok(LuaOpcode::TEST),
+            27 => Ok(LuaOpcode::TESTSET),
 
-            28 => LuaOpcode::CALL,
-            29 => LuaOpcode::TAILCALL,
-            30 => LuaOpcode::RETURN,
+            28 => Ok(LuaOpcode::CALL),
+            29 => Ok(LuaOpcode::TAILCALL),
+            30 => Ok(LuaOpcode::RETURN),
 
-            31 => LuaOpcode::FORLOOP,
-            32 => LuaOpcode::FORPREP,
+            31 => Ok(LuaOpcode::FORLOOP),
+            32 => Ok(LuaOpcode::FORPREP),
 
-            33 => LuaOpcode::TFORLOOP,
+            33 => Ok(LuaOpcode::TFORLOOP),
 
-            34 => LuaOpcode::SETLIST,
+            34 => Ok(LuaOpcode::SETLIST),
 
-            35 => LuaOpcode::CLOSE,
-            36 => LuaOpcode::CLOSURE,
+            35 => Ok(LuaOpcode::CLOSE),
+            36 => Ok(LuaOpcode::CLOSURE),
 
-            37 => LuaOpcode::VARARG,
-            _ => panic!("unknown opcode: {}", value)
+            37 => Ok(LuaOpcode::VARARG),
+            _ => Err(())
         }
     }
 }
 
+/// RK operands at or above this refer to constant `operand - RK_CONSTANT` instead of a
+/// register (`BITRK` in lopcodes.h), so at most this many constants can be RK operands.
+pub const RK_CONSTANT: usize = 256;
+
+/// The constant an RK operand refers to, None if it is a register.
+pub fn rk_constant(operand: usize) -> Option<usize> {
+    operand.checked_sub(RK_CONSTANT)
+}
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum LuaLayout {
     // opcode A
@@ -342,6 +359,65 @@ pub enum LuaLayout {
     ABC(LuaOpcode, u8, u16, u16),
 }
 
+impl LuaLayout {
+    pub fn opcode(&self) -> LuaOpcode {
+        match *self {
+            LuaLayout::A(opcode, _) => opcode,
+            LuaLayout::SBx(opcode, _) => opcode,
+            LuaLayout::AB(opcode, _, _) => opcode,
+            LuaLayout::AC(opcode, _, _) => opcode,
+            LuaLayout::ABx(opcode, _, _) => opcode,
+            LuaLayout::AsBx(opcode, _, _) => opcode,
+            LuaLayout::ABC(opcode, _, _, _) => opcode
+        }
+    }
+
+    /// Returns the A operand, or 0 if the layout has none.
+    pub fn a(&self) -> u8 {
+        match *self {
+            LuaLayout::A(_, a)
+            | LuaLayout::AB(_, a, _)
+            | LuaLayout::AC(_, a, _)
+            | LuaLayout::ABx(_, a, _)
+            | LuaLayout::AsBx(_, a, _)
+            | LuaLayout::ABC(_, a, _, _) => a,
+            LuaLayout::SBx(_, _) => 0
+        }
+    }
+
+    /// Returns the B operand, or 0 if the layout has none.
+    pub fn b(&self) -> u16 {
+        match *self {
+            LuaLayout::AB(_, _, b) | LuaLayout::ABC(_, _, b, _) => b,
+            _ => 0
+        }
+    }
+
+    /// Returns the C operand, or 0 if the layout has none.
+    pub fn c(&self) -> u16 {
+        match *self {
+            LuaLayout::AC(_, _, c) | LuaLayout::ABC(_, _, _, c) => c,
+            _ => 0
+        }
+    }
+
+    /// Returns the Bx operand, or 0 if the layout has none.
+    pub fn bx(&self) -> u32 {
+        match *self {
+            LuaLayout::ABx(_, _, bx) => bx,
+            _ => 0
+        }
+    }
+
+    /// Returns the sBx operand, or 0 if the layout has none.
+    pub fn sbx(&self) -> i32 {
+        match *self {
+            LuaLayout::SBx(_, sbx) | LuaLayout::AsBx(_, _, sbx) => sbx,
+            _ => 0
+        }
+    }
+}
+
 #[derive(PartialEq, Clone)]
 pub struct LuaInstruction {
     pub raw: Vec<u8>,
@@ -354,6 +430,27 @@ pub struct LuaInstruction {
     pub jump_target: Option<usize>,
 }
 
+impl LuaInstruction {
+    /// The instruction as one 32-bit word, reading `raw` in the byte order of `header`. Used for
+    /// the word after a `SETLIST` with C = 0, which holds a plain block number.
+    pub fn word(&self, header: &LuaHeader) -> u32 {
+        let mut stream = match self.raw.len() {
+            4 => {
+                let mut stream = ByteStream::new(self.raw.clone());
+                stream.endianness = if header.endianness == 0 { Endian::Big } else { Endian::Little };
+                stream
+            }
+            // not read from a file, the writer always lays words out little-endian
+            _ => {
+                let mut stream = ByteStream::new(vec![]);
+                self.components.write(&mut stream).unwrap();
+                stream
+            }
+        };
+        u32::read(&mut stream).unwrap()
+    }
+}
+
 impl Debug for LuaInstruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = self.opcode.to_string();
@@ -514,21 +611,85 @@ impl LuaFunction {
     }
 }
 
+/// A node of the prototype tree of a `LuaBinary`.
+/// The id of a prototype is its index in `LuaBinary::functions`, which are stored in pre-order
+/// (the main chunk first, then every nested prototype before its next sibling).
+#[derive(Debug, PartialEq, Clone)]
+pub struct LuaPrototype {
+    pub id: usize,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub depth: usize,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct LuaBinary {
     pub raw: Vec<u8>,
     pub range: Range,
 
     pub header: LuaHeader,
-    pub functions: Vec<LuaFunction>
+    pub functions: Vec<LuaFunction>,
+    pub prototypes: Vec<LuaPrototype>,
 }
 
 impl LuaBinary {
+    /// Writes the binary and reads it back, so the `raw` and `range` of everything in it describe
+    /// the current contents again. The edits in `patch` only lay out the instructions they move,
+    /// the offsets of constants, prototypes, debug information and the functions themselves are
+    /// stale until this runs.
+    pub fn refresh(&mut self) -> Result<(), ByteStreamError> {
+        let mut written = ByteStream::new(vec![]);
+        self.write(&mut written)?;
+
+        // keep the offsets relative to wherever the binary started
+        let start = self.range.start as usize;
+        let mut bytes = vec![0; start];
+        bytes.extend(written.bytes);
+        let mut stream = ByteStream::new(bytes);
+        stream.index = start;
+        *self = LuaBinary::read(&mut stream)?;
+        Ok(())
+    }
+
     pub fn update_targets(&mut self) {
         for function in self.functions.iter_mut() {
             function.update_targets();
         }
     }
+
+    /// Returns the id of the main chunk, if the binary has one.
+    pub fn root(&self) -> Option<usize> {
+        self.prototypes.first().map(|prototype| prototype.id)
+    }
+
+    /// Returns the id of the prototype that declares the function `id`.
+    pub fn parent(&self, id: usize) -> Option<usize> {
+        self.prototypes.get(id).and_then(|prototype| prototype.parent)
+    }
+
+    /// Returns the ids of the prototypes declared directly inside the function `id`.
+    pub fn children(&self, id: usize) -> &[usize] {
+        match self.prototypes.get(id) {
+            Some(prototype) => &prototype.children,
+            None => &[]
+        }
+    }
+
+    /// Resolves the `Bx` operand of a `CLOSURE` in the function `id` to a prototype id.
+    pub fn child(&self, id: usize, index: usize) -> Option<usize> {
+        self.children(id).get(index).copied()
+    }
+
+    /// Rebuilds the prototype `id` with its nested prototypes taken from `functions`,
+    /// so edits made to any prototype of the flat list end up in the written binary.
+    pub fn nested(&self, id: usize) -> LuaFunction {
+        let mut function = self.functions[id].clone();
+        if self.prototypes.len() == self.functions.len() {
+            function.functions = self.children(id).iter().map(|child| self.nested(*child)).collect();
+            function.function_size = function.functions.len() as u64;
+        }
+        function
+    }
 }
 
 impl ByteStreamRead for LuaHeader {
@@ -597,7 +758,11 @@ impl ByteStreamRead for LuaLayout {
         }
 
         let raw = u32::read(stream)?;
-        let opcode = LuaOpcode::from((raw & 0x3F) as u8);
+        let opcode = LuaOpcode::try_from((raw & 0x3F) as u8).map_err(|_| ByteStreamError::new(
+            stream,
+            format!("unknown opcode: {}", raw & 0x3F),
+            ByteStreamErrorType::ReadFailure)
+        )?;
         let a = ((raw >> 6) & 0xFF) as u8;
 
         match OPCODE_LAYOUT.get(&opcode) {
@@ -655,7 +820,7 @@ impl ByteStreamRead for LuaInstruction {
         }
 
         let start = stream.caret();
-        let raw = stream.peek(4).unwrap();
+        let raw = stream.peek(4)?;
         let components = LuaLayout::read(stream)?;
         let opcode = match components {
             LuaLayout::A(opcode, _) => opcode,
@@ -712,11 +877,11 @@ impl ByteStreamRead for LuaConstantType {
                 }
 
                 if number_size == 4 {
-                    raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+                    raw.extend_from_slice(stream.peek(4)?.as_slice());
                     let value = f32::read(stream)?;
                     return Ok(LuaConstantType::Number(raw, value as f64));
                 } else if number_size == 8 {
-                    raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+                    raw.extend_from_slice(stream.peek(8)?.as_slice());
                     let value = f64::read(stream)?;
                     return Ok(LuaConstantType::Number(raw, value));
                 } else {
@@ -739,10 +904,10 @@ impl ByteStreamRead for LuaConstantType {
 
                 let mut bytes: Vec<u8> = Vec::new();
                 let size = if size_t_size == 4 {
-                    raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+                    raw.extend_from_slice(stream.peek(4)?.as_slice());
                     u32::read(stream)? as u64
                 } else if size_t_size == 8 {
-                    raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+                    raw.extend_from_slice(stream.peek(8)?.as_slice());
                     u64::read(stream)?
                 } else {
                     return Err(ByteStreamError::new(
@@ -766,7 +931,12 @@ impl ByteStreamRead for LuaConstantType {
                 }
 
                 raw.extend_from_slice(bytes.as_slice());
-                return Ok(LuaConstantType::String(raw, String::from_utf8(bytes).unwrap()));
+                let value = String::from_utf8(bytes).map_err(|_| ByteStreamError::new(
+                    stream,
+                    "string constant is not valid UTF-8".to_string(),
+                    ByteStreamErrorType::ReadFailure)
+                )?;
+                return Ok(LuaConstantType::String(raw, value));
             },
             _ => {
                 return Err(ByteStreamError::new(
@@ -809,10 +979,10 @@ impl ByteStreamRead for LuaLocal {
         let mut raw = Vec::new();
 
         let name_size = if size_t_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if size_t_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -830,12 +1000,16 @@ impl ByteStreamRead for LuaLocal {
             );
         }
 
-        let name_bytes = stream.peek(name_size as usize).unwrap().to_vec();
+        let name_bytes = stream.peek(name_size as usize)?.to_vec();
         raw.extend_from_slice(name_bytes.as_slice());
-        let name = String::from_utf8(name_bytes).unwrap();
+        let name = String::from_utf8(name_bytes).map_err(|_| ByteStreamError::new(
+            stream,
+            "name is not valid UTF-8".to_string(),
+            ByteStreamErrorType::ReadFailure)
+        )?;
         stream.skip(name_size as usize)?;
 
-        let start_and_end_bytes = stream.peek(8).unwrap().to_vec();
+        let start_and_end_bytes = stream.peek(8)?.to_vec();
         raw.extend_from_slice(start_and_end_bytes.as_slice());
 
         let start_pc = u32::read(stream)?;
@@ -863,10 +1037,10 @@ impl ByteStreamRead for LuaUpvalue {
         let mut raw = Vec::new();
 
         let name_size = if size_t_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if size_t_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -884,9 +1058,13 @@ impl ByteStreamRead for LuaUpvalue {
             );
         }
 
-        let name_bytes = stream.peek(name_size as usize).unwrap().to_vec();
+        let name_bytes = stream.peek(name_size as usize)?.to_vec();
         raw.extend_from_slice(name_bytes.as_slice());
-        let name = String::from_utf8(name_bytes).unwrap();
+        let name = String::from_utf8(name_bytes).map_err(|_| ByteStreamError::new(
+            stream,
+            "name is not valid UTF-8".to_string(),
+            ByteStreamErrorType::ReadFailure)
+        )?;
         stream.skip(name_size as usize)?;
 
         let end = stream.caret();
@@ -919,10 +1097,10 @@ impl ByteStreamRead for LuaFunction {
         }
 
         let name_size = if size_t_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if size_t_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -940,16 +1118,20 @@ impl ByteStreamRead for LuaFunction {
             );
         }
 
-        let name_bytes = stream.peek(name_size as usize).unwrap().to_vec();
+        let name_bytes = stream.peek(name_size as usize)?.to_vec();
         raw.extend_from_slice(name_bytes.as_slice());
-        let name = String::from_utf8(name_bytes).unwrap();
+        let name = String::from_utf8(name_bytes).map_err(|_| ByteStreamError::new(
+            stream,
+            "name is not valid UTF-8".to_string(),
+            ByteStreamErrorType::ReadFailure)
+        )?;
         stream.skip(name_size as usize)?;
 
         let first_line = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -960,10 +1142,10 @@ impl ByteStreamRead for LuaFunction {
         };
 
         let last_line = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -973,17 +1155,17 @@ impl ByteStreamRead for LuaFunction {
             );
         };
 
-        raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+        raw.extend_from_slice(stream.peek(4)?.as_slice());
         let num_upvalues = u8::read(stream)?;
         let num_parameters = u8::read(stream)?;
         let is_vararg = u8::read(stream)?;
         let max_stack_size = u8::read(stream)?;
 
         let code_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1001,10 +1183,10 @@ impl ByteStreamRead for LuaFunction {
         }
 
         let constant_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1021,10 +1203,10 @@ impl ByteStreamRead for LuaFunction {
         }
 
         let function_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1041,10 +1223,10 @@ impl ByteStreamRead for LuaFunction {
         }
 
         let line_info_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1054,17 +1236,18 @@ impl ByteStreamRead for LuaFunction {
             );
         };
 
+        // the lines are ints too, so as wide as the header says
         let mut line_info = Vec::new();
         for _ in 0..line_info_size {
-            let line = u32::read(stream)?;
+            let line = if int_size == 8 { u64::read(stream)? as u32 } else { u32::read(stream)? };
             line_info.push(line);
         }
 
         let local_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1081,10 +1264,10 @@ impl ByteStreamRead for LuaFunction {
         }
 
         let upvalue_size = if int_size == 4 {
-            raw.extend_from_slice(stream.peek(4).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(4)?.as_slice());
             u32::read(stream)? as u64
         } else if int_size == 8 {
-            raw.extend_from_slice(stream.peek(8).unwrap().as_slice());
+            raw.extend_from_slice(stream.peek(8)?.as_slice());
             u64::read(stream)?
         } else {
             return Err(ByteStreamError::new(
@@ -1144,22 +1327,46 @@ impl ByteStreamRead for LuaBinary {
         let start = header.range.start;
         let end = entry.range.end;
 
-        fn add_functions(function: LuaFunction, functions: &mut Vec<LuaFunction>) {
+        fn add_functions(
+            function: LuaFunction,
+            parent: Option<usize>,
+            functions: &mut Vec<LuaFunction>,
+            prototypes: &mut Vec<LuaPrototype>
+        ) {
+            let id = functions.len();
+            let depth = match parent {
+                Some(parent) => prototypes[parent].depth + 1,
+                None => 0
+            };
+
+            if let Some(parent) = parent {
+                prototypes[parent].children.push(id);
+            }
+
             functions.push(function.clone());
+            prototypes.push(LuaPrototype {
+                id,
+                parent,
+                children: Vec::new(),
+                depth
+            });
+
             for f in function.functions {
-                add_functions(f, functions);
+                add_functions(f, Some(id), functions, prototypes);
             }
         }
 
         let mut functions = Vec::new();
-        add_functions(entry, &mut functions);
+        let mut prototypes = Vec::new();
+        add_functions(entry, None, &mut functions, &mut prototypes);
 
         Ok(LuaBinary {
             raw: vec![],
             range: Range::new(start, end),
 
-            header: header,
-            functions: functions
+            header,
+            functions,
+            prototypes
         })
     }
 }
@@ -1170,8 +1377,8 @@ impl ByteStreamWrite for LuaBinary {
         stream.add_context(self.header.clone());
 
         LuaHeader::write(&self.header, stream)?;
-        let root = &self.functions[0];
-        LuaFunction::write(root, stream)?;
+        let root = self.nested(0);
+        LuaFunction::write(&root, stream)?;
         Ok(())
     }
 }
@@ -1385,7 +1592,11 @@ impl ByteStreamWrite for LuaFunction {
         }
 
         for line in &self.line_info {
-            stream.write_bytes_slice(&line.to_le_bytes())?;
+            if int_size == 8 {
+                stream.write_bytes_slice(&(*line as u64).to_le_bytes())?;
+            } else {
+                stream.write_bytes_slice(&line.to_le_bytes())?;
+            }
         }
 
         if int_size == 4 {
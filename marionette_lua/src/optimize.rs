@@ -0,0 +1,133 @@
+// Purpose: simplification passes over `LuaFunction`, used to undo constant obfuscation.
+// Every pass rewrites a function in place; `PassManager` runs them until none of them
+// changes anything. Edits go through the `patch` API, which keeps jumps, line info and
+// locals consistent.
+
+pub mod constants;
+pub mod dead_stores;
+pub mod opaque_predicates;
+pub mod unreachable;
+
+use crate::{cfg::instruction_successors, lua_binary::*, patch::LuaInstructionBuilder};
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    /// Simplifies `function` in place and returns whether anything changed.
+    fn run(&self, function: &mut LuaFunction) -> bool;
+}
+
+pub struct PassManager {
+    pub passes: Vec<Box<dyn Pass>>,
+    /// Upper bound on rounds over all passes, in case two passes keep undoing each other.
+    pub max_iterations: usize,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new()
+    }
+}
+
+impl PassManager {
+    pub fn new() -> PassManager {
+        PassManager {
+            passes: Vec::new(),
+            max_iterations: 32,
+        }
+    }
+
+    /// The deobfuscation pipeline: fold constants, decide opaque predicates, then clean up.
+    pub fn with_default_passes() -> PassManager {
+        let mut manager = PassManager::new();
+        manager.add(constants::ConstantFolding);
+        manager.add(opaque_predicates::OpaquePredicates);
+        manager.add(unreachable::UnreachableCode);
+        manager.add(dead_stores::DeadStores);
+        manager
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Runs the passes over `function` until a fixpoint is reached.
+    /// Returns the names of the passes that changed something, in the order they ran.
+    pub fn run(&self, function: &mut LuaFunction) -> Vec<&'static str> {
+        let mut applied = Vec::new();
+        for _ in 0..self.max_iterations {
+            let mut changed = false;
+            for pass in &self.passes {
+                if pass.run(function) {
+                    applied.push(pass.name());
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+        applied
+    }
+
+    /// Runs the passes over every prototype of `binary`.
+    pub fn run_binary(&self, binary: &mut LuaBinary) -> Vec<(usize, &'static str)> {
+        let mut applied = Vec::new();
+        for (id, function) in binary.functions.iter_mut().enumerate() {
+            applied.extend(self.run(function).into_iter().map(|name| (id, name)));
+        }
+        // the passes move code around, so the offsets after it have to be recomputed; a binary
+        // that cannot be written keeps its old ones, and fails again when it is saved
+        if !applied.is_empty() {
+            let _ = binary.refresh();
+        }
+        applied
+    }
+}
+
+/// Like `cfg::instruction_successors`, but steps over operand words.
+pub fn successors(function: &LuaFunction, operands: &[bool], pc: usize) -> Vec<usize> {
+    let mut successors: Vec<usize> = instruction_successors(&function.code, pc).into_iter()
+        .map(|mut target| {
+            while operands.get(target) == Some(&true) {
+                target += 1;
+            }
+            target
+        })
+        .filter(|target| *target < function.code.len())
+        .collect();
+    successors.dedup();
+    successors
+}
+
+/// Marks the instructions reachable from the entry of the function.
+/// Operand words are reachable when the instruction owning them is.
+pub fn reachable(function: &LuaFunction, operands: &[bool]) -> Vec<bool> {
+    let mut reached = vec![false; function.code.len()];
+    let mut pending = if function.code.is_empty() { vec![] } else { vec![0] };
+
+    while let Some(pc) = pending.pop() {
+        if reached[pc] {
+            continue;
+        }
+        reached[pc] = true;
+
+        let mut operand = pc + 1;
+        while operands.get(operand) == Some(&true) {
+            reached[operand] = true;
+            operand += 1;
+        }
+
+        pending.extend(successors(function, operands, pc));
+    }
+    reached
+}
+
+/// Replaces the instruction at `pc`. Returns false, leaving the code alone, if an operand does not fit.
+fn rewrite_instruction(function: &mut LuaFunction, pc: usize, components: LuaLayout) -> bool {
+    match LuaInstructionBuilder::from_layout(components).build() {
+        Ok(instruction) => function.replace_instruction(pc, instruction).is_ok(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,409 @@
+use crate::{
+    emulator::{arithmetic, value::LuaValue},
+    lua_binary::*,
+    optimize::*,
+    patch::MAX_BX,
+};
+
+/// Number of registers tracked; RK operands cannot address more.
+const REGISTERS: usize = 256;
+
+/// What is known about a register before an instruction executes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Known {
+    Constant(LuaValue),
+    Varying,
+}
+
+pub type RegisterState = Vec<Known>;
+
+/// Converts a constant of the pool to a value. Dumped strings carry their NUL terminator.
+pub fn constant_value(constant: &LuaConstantType) -> LuaValue {
+    match constant {
+        LuaConstantType::Nil(_) => LuaValue::Nil,
+        LuaConstantType::Boolean(_, value) => LuaValue::Boolean(*value),
+        LuaConstantType::Number(_, value) => LuaValue::Number(*value),
+        LuaConstantType::String(_, value) => LuaValue::string(value.strip_suffix('\0').unwrap_or(value).as_bytes()),
+    }
+}
+
+/// Converts a value back to a constant, if it can be stored in the pool.
+pub fn value_constant(value: &LuaValue) -> Option<LuaConstantType> {
+    match value {
+        LuaValue::Nil => Some(LuaConstantType::Nil(vec![])),
+        LuaValue::Boolean(value) => Some(LuaConstantType::Boolean(vec![], *value)),
+        LuaValue::Number(value) if value.is_finite() => Some(LuaConstantType::Number(vec![], *value)),
+        // `LuaConstantType` stores text, so decrypted binary strings are left alone
+        LuaValue::String(bytes) => std::str::from_utf8(bytes).ok()
+            .map(|text| LuaConstantType::String(vec![], format!("{}\0", text))),
+        _ => None,
+    }
+}
+
+/// Registers captured by closures can change behind our back, so nothing is assumed about them.
+fn escaped_registers(function: &LuaFunction, operands: &[bool]) -> Vec<bool> {
+    let mut escaped = vec![false; REGISTERS];
+    for (pc, instruction) in function.code.iter().enumerate() {
+        if operands[pc] && instruction.opcode == LuaOpcode::MOVE {
+            escaped[instruction.components.b() as usize % REGISTERS] = true;
+        }
+    }
+    escaped
+}
+
+/// Forward constant propagation. Returns the register state before every instruction,
+/// or `None` for instructions that are never reached.
+pub fn analyze(function: &LuaFunction) -> Vec<Option<RegisterState>> {
+    let operands = function.operand_words();
+    let escaped = escaped_registers(function, &operands);
+
+    let mut states: Vec<Option<RegisterState>> = vec![None; function.code.len()];
+    if function.code.is_empty() {
+        return states;
+    }
+
+    states[0] = Some(vec![Known::Varying; REGISTERS]);
+    let mut pending = vec![0];
+    while let Some(pc) = pending.pop() {
+        let mut state = states[pc].clone().unwrap();
+        transfer(function, &function.code[pc], &mut state, &escaped);
+
+        for successor in successors(function, &operands, pc) {
+            let merged = match &states[successor] {
+                None => state.clone(),
+                Some(existing) => existing.iter().zip(state.iter())
+                    .map(|(a, b)| if a == b { a.clone() } else { Known::Varying })
+                    .collect(),
+            };
+
+            if states[successor].as_ref() != Some(&merged) {
+                states[successor] = Some(merged);
+                pending.push(successor);
+            }
+        }
+    }
+    states
+}
+
+/// Reads an RK operand.
+pub fn rk(function: &LuaFunction, state: &RegisterState, operand: u16) -> Known {
+    match rk_constant(operand as usize) {
+        Some(index) => match function.constants.get(index) {
+            Some(constant) => known(constant_value(&constant.constant)),
+            None => Known::Varying,
+        },
+        None => state[operand as usize].clone(),
+    }
+}
+
+// NaN never equals itself, which would keep the analysis from converging
+fn known(value: LuaValue) -> Known {
+    match value {
+        LuaValue::Number(number) if number.is_nan() => Known::Varying,
+        value => Known::Constant(value),
+    }
+}
+
+/// The value the instruction stores into register A, when it only depends on known values.
+pub fn evaluate(function: &LuaFunction, state: &RegisterState, instruction: &LuaInstruction) -> Option<LuaValue> {
+    let layout = instruction.components;
+    let register = |index: u16| state.get(index as usize).cloned().unwrap_or(Known::Varying);
+
+    let value = match instruction.opcode {
+        LuaOpcode::MOVE => register(layout.b()),
+        LuaOpcode::LOADK => function.constants.get(layout.bx() as usize)
+            .map(|constant| known(constant_value(&constant.constant)))
+            .unwrap_or(Known::Varying),
+        LuaOpcode::LOADBOOL if layout.c() == 0 => Known::Constant(LuaValue::Boolean(layout.b() != 0)),
+        LuaOpcode::ADD
+        | LuaOpcode::SUB
+        | LuaOpcode::MUL
+        | LuaOpcode::DIV
+        | LuaOpcode::MOD
+        | LuaOpcode::POW => match (rk(function, state, layout.b()), rk(function, state, layout.c())) {
+            (Known::Constant(left), Known::Constant(right)) => match arithmetic(instruction.opcode, &left, &right) {
+                Ok(value) => known(value),
+                Err(_) => Known::Varying,
+            },
+            _ => Known::Varying,
+        },
+        LuaOpcode::UNM => match register(layout.b()) {
+            Known::Constant(value) => arithmetic(LuaOpcode::UNM, &value, &value).map(known).unwrap_or(Known::Varying),
+            Known::Varying => Known::Varying,
+        },
+        LuaOpcode::NOT => match register(layout.b()) {
+            Known::Constant(value) => Known::Constant(LuaValue::Boolean(!value.is_truthy())),
+            Known::Varying => Known::Varying,
+        },
+        LuaOpcode::LEN => match register(layout.b()) {
+            Known::Constant(LuaValue::String(value)) => Known::Constant(LuaValue::Number(value.len() as f64)),
+            _ => Known::Varying,
+        },
+        LuaOpcode::CONCAT => {
+            let mut result = Vec::new();
+            for index in layout.b()..=layout.c() {
+                match register(index) {
+                    Known::Constant(value) => result.extend(value.to_bytes()?),
+                    Known::Varying => return None,
+                }
+            }
+            Known::Constant(LuaValue::string(&result))
+        },
+        _ => Known::Varying,
+    };
+
+    match value {
+        Known::Constant(value) => Some(value),
+        Known::Varying => None,
+    }
+}
+
+fn transfer(function: &LuaFunction, instruction: &LuaInstruction, state: &mut RegisterState, escaped: &[bool]) {
+    let layout = instruction.components;
+    let a = layout.a() as usize;
+
+    let value = match evaluate(function, state, instruction) {
+        Some(value) => known(value),
+        None => Known::Varying,
+    };
+
+    let set = |state: &mut RegisterState, register: usize, value: Known| {
+        if register < REGISTERS {
+            state[register] = if escaped[register] { Known::Varying } else { value };
+        }
+    };
+    let clobber_from = |state: &mut RegisterState, register: usize| {
+        for known in state.iter_mut().skip(register) {
+            *known = Known::Varying;
+        }
+    };
+
+    match instruction.opcode {
+        LuaOpcode::LOADNIL => {
+            for register in a..=layout.b() as usize {
+                set(state, register, Known::Constant(LuaValue::Nil));
+            }
+        },
+        LuaOpcode::SELF => {
+            let object = state.get(layout.b() as usize).cloned().unwrap_or(Known::Varying);
+            set(state, a + 1, object);
+            set(state, a, Known::Varying);
+        },
+        LuaOpcode::FORLOOP => {
+            set(state, a, Known::Varying);
+            set(state, a + 3, Known::Varying);
+        },
+        LuaOpcode::CALL | LuaOpcode::VARARG | LuaOpcode::TFORLOOP => clobber_from(state, a),
+        LuaOpcode::SETGLOBAL
+        | LuaOpcode::SETUPVAL
+        | LuaOpcode::SETTABLE
+        | LuaOpcode::SETLIST
+        | LuaOpcode::JMP
+        | LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE
+        | LuaOpcode::TEST
+        | LuaOpcode::CLOSE
+        | LuaOpcode::RETURN
+        | LuaOpcode::TAILCALL => {},
+        // everything else writes register A only
+        _ => set(state, a, value),
+    }
+}
+
+/// Propagates constants through registers and folds arithmetic, `CONCAT`, `LEN` and `NOT`
+/// over them into `LOADK`/`LOADBOOL`/`LOADNIL`. Constants nothing refers to anymore are dropped.
+pub struct ConstantFolding;
+
+impl Pass for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant folding"
+    }
+
+    fn run(&self, function: &mut LuaFunction) -> bool {
+        let states = analyze(function);
+        let operands = function.operand_words();
+        let mut changed = false;
+
+        for pc in 0..function.code.len() {
+            let state = match &states[pc] {
+                Some(state) if !operands[pc] => state,
+                _ => continue,
+            };
+
+            let instruction = function.code[pc].clone();
+            let layout = instruction.components;
+            let a = layout.a();
+
+            let folds = matches!(instruction.opcode,
+                LuaOpcode::MOVE
+                | LuaOpcode::ADD
+                | LuaOpcode::SUB
+                | LuaOpcode::MUL
+                | LuaOpcode::DIV
+                | LuaOpcode::MOD
+                | LuaOpcode::POW
+                | LuaOpcode::UNM
+                | LuaOpcode::NOT
+                | LuaOpcode::LEN
+                | LuaOpcode::CONCAT
+            );
+
+            if folds {
+                if let Some(replacement) = evaluate(function, state, &instruction).and_then(|value| load(function, a, &value)) {
+                    changed |= rewrite_instruction(function, pc, replacement);
+                    continue;
+                }
+            }
+
+            // operands that can be read from the constant pool directly
+            let (b, c) = match instruction.opcode {
+                LuaOpcode::ADD
+                | LuaOpcode::SUB
+                | LuaOpcode::MUL
+                | LuaOpcode::DIV
+                | LuaOpcode::MOD
+                | LuaOpcode::POW
+                | LuaOpcode::EQ
+                | LuaOpcode::LT
+                | LuaOpcode::LE
+                | LuaOpcode::SETTABLE => (propagate(function, state, layout.b()), propagate(function, state, layout.c())),
+                LuaOpcode::GETTABLE | LuaOpcode::SELF => (layout.b(), propagate(function, state, layout.c())),
+                _ => continue,
+            };
+
+            if b != layout.b() || c != layout.c() {
+                changed |= rewrite_instruction(function, pc, LuaLayout::ABC(instruction.opcode, a, b, c));
+            }
+        }
+
+        prune_constants(function) || changed
+    }
+}
+
+/// An instruction loading `value` into register `a`.
+fn load(function: &mut LuaFunction, a: u8, value: &LuaValue) -> Option<LuaLayout> {
+    Some(match value {
+        LuaValue::Nil => LuaLayout::AB(LuaOpcode::LOADNIL, a, a as u16),
+        LuaValue::Boolean(value) => LuaLayout::ABC(LuaOpcode::LOADBOOL, a, *value as u16, 0),
+        value => {
+            let index = function.add_constant(value_constant(value)?);
+            if index > MAX_BX as usize {
+                return None;
+            }
+            LuaLayout::ABx(LuaOpcode::LOADK, a, index as u32)
+        },
+    })
+}
+
+/// Rewrites a register RK operand holding a known constant into a constant operand.
+fn propagate(function: &mut LuaFunction, state: &RegisterState, operand: u16) -> u16 {
+    if rk_constant(operand as usize).is_some() {
+        return operand;
+    }
+
+    let constant = match &state[operand as usize] {
+        Known::Constant(value) => value_constant(value),
+        Known::Varying => None,
+    };
+
+    match constant {
+        Some(constant) => {
+            let index = function.add_constant(constant);
+            if index >= RK_CONSTANT {
+                return operand;
+            }
+            (index + RK_CONSTANT) as u16
+        },
+        None => operand,
+    }
+}
+
+/// Every instruction operand that refers to the constant pool, as (pc, operand) pairs.
+/// Operand 0 is Bx, 1 is B and 2 is C.
+fn constant_references(function: &LuaFunction) -> Vec<(usize, u8, usize)> {
+    let operands = function.operand_words();
+    let mut references = Vec::new();
+
+    for (pc, instruction) in function.code.iter().enumerate() {
+        if operands[pc] {
+            continue;
+        }
+
+        let layout = instruction.components;
+        let mut rk = |operand: u8, value: u16| {
+            if let Some(index) = rk_constant(value as usize) {
+                references.push((pc, operand, index));
+            }
+        };
+
+        match instruction.opcode {
+            LuaOpcode::LOADK | LuaOpcode::GETGLOBAL | LuaOpcode::SETGLOBAL => {
+                references.push((pc, 0, layout.bx() as usize));
+            },
+            LuaOpcode::GETTABLE | LuaOpcode::SELF => rk(2, layout.c()),
+            LuaOpcode::SETTABLE
+            | LuaOpcode::ADD
+            | LuaOpcode::SUB
+            | LuaOpcode::MUL
+            | LuaOpcode::DIV
+            | LuaOpcode::MOD
+            | LuaOpcode::POW
+            | LuaOpcode::EQ
+            | LuaOpcode::LT
+            | LuaOpcode::LE => {
+                rk(1, layout.b());
+                rk(2, layout.c());
+            },
+            _ => {},
+        }
+    }
+    references
+}
+
+/// Drops constants no instruction refers to and renumbers the remaining ones.
+fn prune_constants(function: &mut LuaFunction) -> bool {
+    let references = constant_references(function);
+    let mut used = vec![false; function.constants.len()];
+    for (_, _, index) in &references {
+        if let Some(used) = used.get_mut(*index) {
+            *used = true;
+        }
+    }
+
+    if used.iter().all(|used| *used) {
+        return false;
+    }
+
+    let mut map = vec![0; used.len()];
+    let mut next = 0;
+    for (index, used) in used.iter().enumerate() {
+        map[index] = next;
+        if *used {
+            next += 1;
+        }
+    }
+
+    for (pc, operand, index) in references {
+        // references past the end of the pool are left for the VM to reject
+        let Some(&index) = map.get(index) else {
+            continue;
+        };
+        let layout = function.code[pc].components;
+        let components = match (operand, layout) {
+            (0, LuaLayout::ABx(opcode, a, _)) => LuaLayout::ABx(opcode, a, index as u32),
+            (1, LuaLayout::ABC(opcode, a, _, c)) => LuaLayout::ABC(opcode, a, (index + RK_CONSTANT) as u16, c),
+            (2, LuaLayout::ABC(opcode, a, b, _)) => LuaLayout::ABC(opcode, a, b, (index + RK_CONSTANT) as u16),
+            _ => continue,
+        };
+        rewrite_instruction(function, pc, components);
+    }
+
+    let mut index = 0;
+    function.constants.retain(|_| {
+        index += 1;
+        used[index - 1]
+    });
+    function.constant_size = function.constants.len() as u64;
+    true
+}
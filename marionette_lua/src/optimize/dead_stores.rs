@@ -0,0 +1,179 @@
+use crate::{lua_binary::*, optimize::*};
+
+const REGISTERS: usize = 256;
+
+/// Registers read by an instruction. `from` means every register from there on,
+/// used by instructions taking a variable number of values.
+#[derive(Debug, Default)]
+struct Uses {
+    registers: Vec<usize>,
+    from: Option<usize>,
+}
+
+fn uses(instruction: &LuaInstruction) -> Uses {
+    let layout = instruction.components;
+    let (a, b, c) = (layout.a() as usize, layout.b() as usize, layout.c() as usize);
+    let rk = |operand: usize| if rk_constant(operand).is_some() { vec![] } else { vec![operand] };
+    let range = |from: usize, count: usize| Uses { registers: (from..from + count).collect(), from: None };
+    let open = |from: usize| Uses { registers: vec![], from: Some(from) };
+
+    match instruction.opcode {
+        LuaOpcode::MOVE | LuaOpcode::UNM | LuaOpcode::NOT | LuaOpcode::LEN | LuaOpcode::TESTSET => range(b, 1),
+        LuaOpcode::GETTABLE | LuaOpcode::SELF => Uses { registers: [vec![b], rk(c)].concat(), from: None },
+        LuaOpcode::SETGLOBAL | LuaOpcode::SETUPVAL | LuaOpcode::TEST => range(a, 1),
+        LuaOpcode::SETTABLE => Uses { registers: [vec![a], rk(b), rk(c)].concat(), from: None },
+        LuaOpcode::ADD
+        | LuaOpcode::SUB
+        | LuaOpcode::MUL
+        | LuaOpcode::DIV
+        | LuaOpcode::MOD
+        | LuaOpcode::POW
+        | LuaOpcode::EQ
+        | LuaOpcode::LT
+        | LuaOpcode::LE => Uses { registers: [rk(b), rk(c)].concat(), from: None },
+        LuaOpcode::CONCAT => range(b, (c + 1).saturating_sub(b)),
+        LuaOpcode::CALL | LuaOpcode::TAILCALL => if b == 0 { open(a) } else { range(a, b) },
+        LuaOpcode::RETURN => if b == 0 { open(a) } else { range(a, b - 1) },
+        LuaOpcode::SETLIST => if b == 0 { open(a) } else { range(a, b + 1) },
+        LuaOpcode::FORLOOP | LuaOpcode::FORPREP | LuaOpcode::TFORLOOP => range(a, 3),
+        _ => Uses::default(),
+    }
+}
+
+/// Registers an instruction always overwrites.
+pub(crate) fn definitions(instruction: &LuaInstruction) -> Vec<usize> {
+    let layout = instruction.components;
+    let (a, b, c) = (layout.a() as usize, layout.b() as usize, layout.c() as usize);
+
+    match instruction.opcode {
+        LuaOpcode::MOVE
+        | LuaOpcode::LOADK
+        | LuaOpcode::LOADBOOL
+        | LuaOpcode::GETUPVAL
+        | LuaOpcode::GETGLOBAL
+        | LuaOpcode::GETTABLE
+        | LuaOpcode::NEWTABLE
+        | LuaOpcode::ADD
+        | LuaOpcode::SUB
+        | LuaOpcode::MUL
+        | LuaOpcode::DIV
+        | LuaOpcode::MOD
+        | LuaOpcode::POW
+        | LuaOpcode::UNM
+        | LuaOpcode::NOT
+        | LuaOpcode::LEN
+        | LuaOpcode::CONCAT
+        | LuaOpcode::CLOSURE => vec![a],
+        LuaOpcode::SELF => vec![a, a + 1],
+        LuaOpcode::LOADNIL => (a..=b).collect(),
+        LuaOpcode::CALL if c > 0 => (a..a + c - 1).collect(),
+        LuaOpcode::VARARG if b > 0 => (a..a + b - 1).collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether removing the instruction only loses the values it writes.
+fn is_pure(instruction: &LuaInstruction) -> bool {
+    match instruction.opcode {
+        LuaOpcode::MOVE
+        | LuaOpcode::LOADK
+        | LuaOpcode::LOADNIL
+        | LuaOpcode::GETUPVAL
+        | LuaOpcode::NOT
+        | LuaOpcode::NEWTABLE => true,
+        LuaOpcode::LOADBOOL => instruction.components.c() == 0,
+        _ => false,
+    }
+}
+
+/// Removes side-effect free instructions whose results are never read.
+/// Registers captured by closures are treated as always live.
+pub struct DeadStores;
+
+impl Pass for DeadStores {
+    fn name(&self) -> &'static str {
+        "dead stores"
+    }
+
+    fn run(&self, function: &mut LuaFunction) -> bool {
+        let length = function.code.len();
+        let operands = function.operand_words();
+
+        let mut escaped = vec![false; REGISTERS];
+        for (pc, instruction) in function.code.iter().enumerate() {
+            if operands[pc] && instruction.opcode == LuaOpcode::MOVE {
+                escaped[instruction.components.b() as usize % REGISTERS] = true;
+            }
+        }
+
+        let successors: Vec<Vec<usize>> = (0..length).map(|pc| successors(function, &operands, pc)).collect();
+
+        // live registers after each instruction, computed backwards until nothing changes
+        let mut live_in = vec![escaped.clone(); length];
+        let mut live_out = vec![escaped.clone(); length];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for pc in (0..length).rev() {
+                if operands[pc] {
+                    continue;
+                }
+
+                let mut out = escaped.clone();
+                for successor in &successors[pc] {
+                    for (register, live) in live_in[*successor].iter().enumerate() {
+                        out[register] |= *live;
+                    }
+                }
+
+                let instruction = &function.code[pc];
+                let mut into = out.clone();
+                for register in definitions(instruction) {
+                    if register < REGISTERS && !escaped[register] {
+                        into[register] = false;
+                    }
+                }
+
+                let uses = uses(instruction);
+                for register in uses.registers {
+                    if register < REGISTERS {
+                        into[register] = true;
+                    }
+                }
+                if let Some(from) = uses.from {
+                    for live in into.iter_mut().skip(from) {
+                        *live = true;
+                    }
+                }
+
+                if into != live_in[pc] || out != live_out[pc] {
+                    live_in[pc] = into;
+                    live_out[pc] = out;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut removed = vec![false; length];
+        for pc in 0..length.saturating_sub(1) {
+            let instruction = &function.code[pc];
+            if operands[pc] || !is_pure(instruction) {
+                continue;
+            }
+            if pc > 0 && !operands[pc - 1] && function.code[pc - 1].skips_next() {
+                continue;
+            }
+
+            let definitions = definitions(instruction);
+            if definitions.iter().all(|register| *register < REGISTERS && !live_out[pc][*register]) {
+                removed[pc] = true;
+            }
+        }
+
+        if !removed.iter().any(|removed| *removed) {
+            return false;
+        }
+
+        function.remove_instructions(&removed).is_ok()
+    }
+}
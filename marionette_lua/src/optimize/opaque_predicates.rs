@@ -0,0 +1,64 @@
+use crate::{
+    emulator::compare,
+    lua_binary::*,
+    optimize::{constants::*, *},
+};
+
+/// Replaces conditionals whose outcome is known from constant propagation.
+/// A test that always skips becomes `JMP 1`, one that never skips becomes `JMP 0`;
+/// the unreachable code pass then drops the dead branch and the empty jumps.
+pub struct OpaquePredicates;
+
+impl Pass for OpaquePredicates {
+    fn name(&self) -> &'static str {
+        "opaque predicates"
+    }
+
+    fn run(&self, function: &mut LuaFunction) -> bool {
+        let states = analyze(function);
+        let operands = function.operand_words();
+        let mut changed = false;
+
+        for pc in 0..function.code.len() {
+            let state = match &states[pc] {
+                Some(state) if !operands[pc] => state,
+                _ => continue,
+            };
+
+            let instruction = function.code[pc].clone();
+            let layout = instruction.components;
+            let register = |index: u16| match state.get(index as usize) {
+                Some(Known::Constant(value)) => Some(value.clone()),
+                _ => None,
+            };
+
+            // Some(true) when the next instruction is skipped
+            let skips = match instruction.opcode {
+                LuaOpcode::EQ | LuaOpcode::LT | LuaOpcode::LE => {
+                    match (rk(function, state, layout.b()), rk(function, state, layout.c())) {
+                        (Known::Constant(left), Known::Constant(right)) => compare(instruction.opcode, &left, &right)
+                            .ok()
+                            .map(|result| result != (layout.a() != 0)),
+                        _ => None,
+                    }
+                },
+                LuaOpcode::TEST => register(layout.a() as u16).map(|value| value.is_truthy() != (layout.c() != 0)),
+                LuaOpcode::TESTSET => match register(layout.b()) {
+                    // the copy happens on the path that falls through
+                    Some(value) if value.is_truthy() == (layout.c() != 0) => {
+                        changed |= rewrite_instruction(function, pc, LuaLayout::AB(LuaOpcode::MOVE, layout.a(), layout.b()));
+                        continue;
+                    },
+                    Some(_) => Some(true),
+                    None => None,
+                },
+                _ => None,
+            };
+
+            if let Some(skips) = skips {
+                changed |= rewrite_instruction(function, pc, LuaLayout::SBx(LuaOpcode::JMP, skips as i32));
+            }
+        }
+        changed
+    }
+}
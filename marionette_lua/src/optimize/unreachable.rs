@@ -0,0 +1,38 @@
+use crate::{lua_binary::*, optimize::*};
+
+/// Removes instructions that cannot be reached from the entry point, and jumps to the
+/// next instruction. The final `RETURN` is always kept, the 5.1 verifier requires it.
+pub struct UnreachableCode;
+
+impl Pass for UnreachableCode {
+    fn name(&self) -> &'static str {
+        "unreachable code"
+    }
+
+    fn run(&self, function: &mut LuaFunction) -> bool {
+        let length = function.code.len();
+        if length < 2 {
+            return false;
+        }
+
+        let operands = function.operand_words();
+        let reached = reachable(function, &operands);
+
+        let mut removed: Vec<bool> = reached.iter().map(|reached| !reached).collect();
+        for pc in 0..length {
+            let instruction = &function.code[pc];
+            let empty_jump = instruction.opcode == LuaOpcode::JMP && instruction.components.sbx() == 0;
+            let after_skip = pc > 0 && !operands[pc - 1] && function.code[pc - 1].skips_next();
+            if empty_jump && !operands[pc] && !after_skip {
+                removed[pc] = true;
+            }
+        }
+        removed[length - 1] = false;
+
+        if !removed.iter().any(|removed| *removed) {
+            return false;
+        }
+
+        function.remove_instructions(&removed).is_ok()
+    }
+}
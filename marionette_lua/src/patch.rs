@@ -0,0 +1,489 @@
+// Purpose: instruction level editing of a `LuaFunction`.
+// Lua 5.1 jumps are relative, so inserting or removing a single instruction shifts the
+// target of every jump crossing it. The functions here rebuild the code from a list of
+// kept, removed and inserted words, then relocate jumps, line info, locals, pcs and the ranges
+// of the instructions. Everything after the code moves too; `LuaBinary::refresh` recomputes
+// those offsets once the edits are done.
+
+use marionette_core::{assembly::Range, byte_stream::{ByteStream, ByteStreamWrite}};
+use crate::lua_binary::*;
+
+/// Bounds of the operand fields of a 32 bit Lua 5.1 instruction.
+pub const MAX_A: u32 = 0xFF;
+pub const MAX_B: u32 = 0x1FF;
+pub const MAX_C: u32 = 0x1FF;
+pub const MAX_BX: u32 = 0x3FFFF;
+pub const MAX_SBX: i32 = 131071;
+
+/// Size in bytes of an encoded instruction.
+const INSTRUCTION_SIZE: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchErrorType {
+    OutOfRange,
+    InvalidOperand,
+    JumpOverflow,
+    /// The edit would separate an instruction from the word it relies on: the upvalue
+    /// pseudo-instructions of a `CLOSURE`, the extra argument of a `SETLIST`, or the
+    /// jump a conditional skips over.
+    SplitsInstruction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchError {
+    pub pc: usize,
+    pub description: String,
+    pub error_type: PatchErrorType,
+}
+
+impl PatchError {
+    pub fn new(pc: usize, description: String, error_type: PatchErrorType) -> PatchError {
+        PatchError {
+            pc,
+            description,
+            error_type,
+        }
+    }
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "pc {}: {}", self.pc, self.description)
+    }
+}
+
+/// Builds a `LuaInstruction`, checking the operands against the layout of its opcode.
+///
+/// ```
+/// use marionette_lua::{lua_binary::LuaOpcode, patch::LuaInstructionBuilder};
+///
+/// let load = LuaInstructionBuilder::new(LuaOpcode::LOADK).a(0).bx(1).build().unwrap();
+/// assert_eq!(load.raw, vec![0x01, 0x40, 0x00, 0x00]);
+///
+/// assert!(LuaInstructionBuilder::new(LuaOpcode::JMP).a(1).build().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct LuaInstructionBuilder {
+    opcode: LuaOpcode,
+    a: Option<u32>,
+    b: Option<u32>,
+    c: Option<u32>,
+    bx: Option<u32>,
+    sbx: Option<i32>,
+}
+
+impl LuaInstructionBuilder {
+    pub fn new(opcode: LuaOpcode) -> LuaInstructionBuilder {
+        LuaInstructionBuilder {
+            opcode,
+            a: None,
+            b: None,
+            c: None,
+            bx: None,
+            sbx: None,
+        }
+    }
+
+    /// Starts from already decoded operands.
+    pub fn from_layout(layout: LuaLayout) -> LuaInstructionBuilder {
+        let builder = LuaInstructionBuilder::new(layout.opcode());
+        match layout {
+            LuaLayout::A(_, a) => builder.a(a as u32),
+            LuaLayout::SBx(_, sbx) => builder.sbx(sbx),
+            LuaLayout::AB(_, a, b) => builder.a(a as u32).b(b as u32),
+            LuaLayout::AC(_, a, c) => builder.a(a as u32).c(c as u32),
+            LuaLayout::ABx(_, a, bx) => builder.a(a as u32).bx(bx),
+            LuaLayout::AsBx(_, a, sbx) => builder.a(a as u32).sbx(sbx),
+            LuaLayout::ABC(_, a, b, c) => builder.a(a as u32).b(b as u32).c(c as u32),
+        }
+    }
+
+    pub fn a(mut self, a: u32) -> LuaInstructionBuilder {
+        self.a = Some(a);
+        self
+    }
+
+    pub fn b(mut self, b: u32) -> LuaInstructionBuilder {
+        self.b = Some(b);
+        self
+    }
+
+    pub fn c(mut self, c: u32) -> LuaInstructionBuilder {
+        self.c = Some(c);
+        self
+    }
+
+    pub fn bx(mut self, bx: u32) -> LuaInstructionBuilder {
+        self.bx = Some(bx);
+        self
+    }
+
+    pub fn sbx(mut self, sbx: i32) -> LuaInstructionBuilder {
+        self.sbx = Some(sbx);
+        self
+    }
+
+    /// Unset operands default to 0. Operands the layout does not have, or values that do
+    /// not fit their field, are an `InvalidOperand` error.
+    pub fn build(&self) -> Result<LuaInstruction, PatchError> {
+        let name = self.opcode.to_string();
+        let invalid = |description: String| PatchError::new(0, description, PatchErrorType::InvalidOperand);
+
+        let layout = self.opcode.layout().ok_or_else(|| invalid(format!("{} has no known layout", name)))?;
+        let (has_a, has_b, has_c, has_bx, has_sbx) = match layout {
+            LuaLayout::A(..) => (true, false, false, false, false),
+            LuaLayout::SBx(..) => (false, false, false, false, true),
+            LuaLayout::AB(..) => (true, true, false, false, false),
+            LuaLayout::AC(..) => (true, false, true, false, false),
+            LuaLayout::ABx(..) => (true, false, false, true, false),
+            LuaLayout::AsBx(..) => (true, false, false, false, true),
+            LuaLayout::ABC(..) => (true, true, true, false, false),
+        };
+
+        let field = |value: Option<u32>, present: bool, max: u32, operand: &str| -> Result<u32, PatchError> {
+            match value {
+                Some(_) if !present => Err(invalid(format!("{} has no {} operand", name, operand))),
+                Some(value) if value > max => Err(invalid(format!("{} {} of {} does not fit in {} bits", name, operand, value, max.count_ones()))),
+                Some(value) => Ok(value),
+                None => Ok(0),
+            }
+        };
+
+        let a = field(self.a, has_a, MAX_A, "A")? as u8;
+        let b = field(self.b, has_b, MAX_B, "B")? as u16;
+        let c = field(self.c, has_c, MAX_C, "C")? as u16;
+        let bx = field(self.bx, has_bx, MAX_BX, "Bx")?;
+        let sbx = match self.sbx {
+            Some(_) if !has_sbx => return Err(invalid(format!("{} has no sBx operand", name))),
+            Some(sbx) if !(-MAX_SBX..=MAX_SBX).contains(&sbx) => {
+                return Err(PatchError::new(0, format!("{} sBx of {} is out of range", name, sbx), PatchErrorType::JumpOverflow));
+            },
+            Some(sbx) => sbx,
+            None => 0,
+        };
+
+        let components = match layout {
+            LuaLayout::A(..) => LuaLayout::A(self.opcode, a),
+            LuaLayout::SBx(..) => LuaLayout::SBx(self.opcode, sbx),
+            LuaLayout::AB(..) => LuaLayout::AB(self.opcode, a, b),
+            LuaLayout::AC(..) => LuaLayout::AC(self.opcode, a, c),
+            LuaLayout::ABx(..) => LuaLayout::ABx(self.opcode, a, bx),
+            LuaLayout::AsBx(..) => LuaLayout::AsBx(self.opcode, a, sbx),
+            LuaLayout::ABC(..) => LuaLayout::ABC(self.opcode, a, b, c),
+        };
+
+        Ok(LuaInstruction {
+            raw: encode(&components),
+            range: Range::new(0, INSTRUCTION_SIZE),
+
+            opcode: self.opcode,
+            components,
+            pc: 0,
+
+            jump_target: None,
+        })
+    }
+}
+
+fn encode(components: &LuaLayout) -> Vec<u8> {
+    let mut stream = ByteStream::new(vec![]);
+    components.write(&mut stream).unwrap();
+    stream.bytes
+}
+
+impl LuaInstruction {
+    /// Whether the instruction conditionally skips the one after it.
+    pub fn skips_next(&self) -> bool {
+        match self.opcode {
+            LuaOpcode::EQ
+            | LuaOpcode::LT
+            | LuaOpcode::LE
+            | LuaOpcode::TEST
+            | LuaOpcode::TESTSET
+            | LuaOpcode::TFORLOOP => true,
+            LuaOpcode::LOADBOOL => self.components.c() != 0,
+            _ => false,
+        }
+    }
+
+    /// Whether the instruction has a relative sBx jump.
+    pub fn is_relative_jump(&self) -> bool {
+        matches!(self.opcode, LuaOpcode::JMP | LuaOpcode::FORLOOP | LuaOpcode::FORPREP)
+    }
+}
+
+/// One word of the code being rebuilt.
+enum Word {
+    Kept(usize),
+    Removed(usize),
+    Inserted(LuaInstruction),
+}
+
+impl LuaFunction {
+    /// Marks the words of `code` that are operands rather than instructions: the upvalue
+    /// pseudo-instructions following a `CLOSURE`, and the block number following a `SETLIST` with C = 0.
+    pub fn operand_words(&self) -> Vec<bool> {
+        let mut operands = vec![false; self.code.len()];
+        let mut pc = 0;
+        while pc < self.code.len() {
+            let instruction = &self.code[pc];
+            let count = match instruction.opcode {
+                LuaOpcode::CLOSURE => self.functions.get(instruction.components.bx() as usize)
+                    .map(|child| child.num_upvalues as usize)
+                    .unwrap_or(0),
+                LuaOpcode::SETLIST if instruction.components.c() == 0 => 1,
+                _ => 0,
+            };
+
+            for operand in operands.iter_mut().skip(pc + 1).take(count) {
+                *operand = true;
+            }
+            pc += count + 1;
+        }
+        operands
+    }
+
+    /// Returns the index of `constant` in the constant pool, appending it if needed.
+    pub fn add_constant(&mut self, constant: LuaConstantType) -> usize {
+        let same = |other: &LuaConstantType| match (other, &constant) {
+            (LuaConstantType::Nil(_), LuaConstantType::Nil(_)) => true,
+            (LuaConstantType::Boolean(_, a), LuaConstantType::Boolean(_, b)) => a == b,
+            (LuaConstantType::Number(_, a), LuaConstantType::Number(_, b)) => a.to_bits() == b.to_bits(),
+            (LuaConstantType::String(_, a), LuaConstantType::String(_, b)) => a == b,
+            _ => false,
+        };
+
+        if let Some(index) = self.constants.iter().position(|existing| same(&existing.constant)) {
+            return index;
+        }
+
+        self.constants.push(LuaConstant {
+            raw: vec![],
+            range: Range::new(0, 0),
+            constant,
+        });
+        self.constant_size = self.constants.len() as u64;
+        self.constants.len() - 1
+    }
+
+    /// Whether the word at `pc` must stay right after the instruction before it.
+    fn is_bound(&self, operands: &[bool], pc: usize) -> bool {
+        if operands.get(pc) == Some(&true) {
+            return true;
+        }
+        pc > 0 && pc <= self.code.len() && !operands[pc - 1] && self.code[pc - 1].skips_next()
+    }
+
+    /// Inserts `instruction` before the one at `pc` (or at the end when `pc` is the code size).
+    /// Jumps to `pc` now reach the inserted instruction, which takes the line of the one it precedes.
+    /// The exception is a loop entry: the `FORPREP` of a `FORLOOP`, or the `JMP` into a `TFORLOOP`,
+    /// still reaches the loop instruction, so code inserted before it runs once per iteration
+    /// as the end of the body rather than also on entry.
+    /// The sBx of the inserted instruction is used as is, relative to its new position.
+    pub fn insert_instruction(&mut self, pc: usize, instruction: LuaInstruction) -> Result<(), PatchError> {
+        self.insert_instructions(pc, vec![instruction])
+    }
+
+    pub fn insert_instructions(&mut self, pc: usize, instructions: Vec<LuaInstruction>) -> Result<(), PatchError> {
+        if pc > self.code.len() {
+            return Err(PatchError::new(pc, format!("pc is past the end of the code ({})", self.code.len()), PatchErrorType::OutOfRange));
+        }
+        if self.is_bound(&self.operand_words(), pc) {
+            return Err(PatchError::new(pc, "inserting here separates an instruction from its operand".to_string(), PatchErrorType::SplitsInstruction));
+        }
+
+        let mut words: Vec<Word> = (0..pc).map(Word::Kept).collect();
+        words.extend(instructions.into_iter().map(Word::Inserted));
+        words.extend((pc..self.code.len()).map(Word::Kept));
+        self.rebuild(words)
+    }
+
+    /// Removes the instruction at `pc` together with its operand words, and returns them.
+    /// Jumps to a removed instruction now reach the one that followed it.
+    pub fn remove_instruction(&mut self, pc: usize) -> Result<Vec<LuaInstruction>, PatchError> {
+        if pc >= self.code.len() {
+            return Err(PatchError::new(pc, format!("pc is past the end of the code ({})", self.code.len()), PatchErrorType::OutOfRange));
+        }
+
+        let operands = self.operand_words();
+        if self.is_bound(&operands, pc) {
+            return Err(PatchError::new(pc, "removing this separates an instruction from its operand".to_string(), PatchErrorType::SplitsInstruction));
+        }
+
+        let mut removed = vec![false; self.code.len()];
+        removed[pc] = true;
+        let mut operand = pc + 1;
+        while operands.get(operand) == Some(&true) {
+            removed[operand] = true;
+            operand += 1;
+        }
+
+        let words: Vec<LuaInstruction> = self.code[pc..operand].to_vec();
+        self.remove_instructions(&removed)?;
+        Ok(words)
+    }
+
+    /// Removes every instruction marked in `removed` at once. Unlike `remove_instruction`,
+    /// the caller is responsible for not separating instructions from their operands.
+    pub fn remove_instructions(&mut self, removed: &[bool]) -> Result<(), PatchError> {
+        let words = (0..self.code.len())
+            .map(|pc| if removed.get(pc) == Some(&true) { Word::Removed(pc) } else { Word::Kept(pc) })
+            .collect();
+        self.rebuild(words)
+    }
+
+    /// Replaces the instruction at `pc`, keeping its line, and returns the previous one.
+    /// The sBx of `instruction` is relative to `pc`. Operand words can't be replaced, and the
+    /// replacement must take the same operand words as the instruction it replaces.
+    pub fn replace_instruction(&mut self, pc: usize, instruction: LuaInstruction) -> Result<LuaInstruction, PatchError> {
+        if pc >= self.code.len() {
+            return Err(PatchError::new(pc, format!("pc is past the end of the code ({})", self.code.len()), PatchErrorType::OutOfRange));
+        }
+
+        let operands = self.operand_words();
+        if operands[pc] {
+            return Err(PatchError::new(pc, "this is an operand of the instruction before it".to_string(), PatchErrorType::SplitsInstruction));
+        }
+
+        let range = self.code[pc].range.clone();
+        let mut instruction = instruction;
+        instruction.pc = pc as u64;
+        instruction.range = range;
+        instruction.jump_target = None;
+
+        let previous = std::mem::replace(&mut self.code[pc], instruction);
+        if self.operand_words() != operands {
+            self.code[pc] = previous;
+            return Err(PatchError::new(pc, "the replacement takes different operand words".to_string(), PatchErrorType::SplitsInstruction));
+        }
+        self.update_targets();
+        Ok(previous)
+    }
+
+    /// Whether the jump at `pc` to `target` enters a loop, jumping straight to its loop instruction.
+    fn is_loop_entry(&self, pc: usize, target: usize) -> bool {
+        let Some(loop_instruction) = self.code.get(target) else {
+            return false;
+        };
+        match (self.code[pc].opcode, loop_instruction.opcode) {
+            (LuaOpcode::FORPREP, LuaOpcode::FORLOOP) => true,
+            // the JMP after TFORLOOP goes back to the body, right after the entry jump
+            (LuaOpcode::JMP, LuaOpcode::TFORLOOP) => self.code.get(target + 1).is_some_and(|back| {
+                back.opcode == LuaOpcode::JMP && target as i64 + 2 + back.components.sbx() as i64 == pc as i64 + 1
+            }),
+            _ => false,
+        }
+    }
+
+    /// Lays out `words` as the new code. Nothing is modified if a jump no longer fits.
+    /// Only the instructions get new ranges, see `LuaBinary::refresh` for the rest.
+    fn rebuild(&mut self, words: Vec<Word>) -> Result<(), PatchError> {
+        let length = self.code.len();
+
+        // new position of every old pc: the first word emitted since the previous kept
+        // instruction, so jumps land on instructions inserted before their old target
+        let mut map = vec![0; length + 1];
+        // and of the instruction itself, for loop entries
+        let mut exact = vec![0; length + 1];
+        let mut position = 0;
+        let mut group = 0;
+        for word in &words {
+            match word {
+                Word::Kept(pc) => {
+                    map[*pc] = group;
+                    exact[*pc] = position;
+                    position += 1;
+                    group = position;
+                },
+                Word::Removed(pc) => {
+                    map[*pc] = group;
+                    exact[*pc] = group;
+                },
+                Word::Inserted(_) => position += 1,
+            }
+        }
+        map[length] = group;
+        exact[length] = group;
+
+        let mut offsets = Vec::with_capacity(words.len());
+        let mut new_pc = 0;
+        for word in &words {
+            let offset = match word {
+                Word::Kept(pc) if self.code[*pc].is_relative_jump() => {
+                    let sbx = self.code[*pc].components.sbx();
+                    let target = (*pc as i64 + 1 + sbx as i64).clamp(0, length as i64) as usize;
+                    let target = if self.is_loop_entry(*pc, target) { exact[target] } else { map[target] };
+                    let offset = target as i64 - (new_pc as i64 + 1);
+                    if offset.abs() > MAX_SBX as i64 {
+                        return Err(PatchError::new(*pc, format!("jump of {} no longer fits in sBx", offset), PatchErrorType::JumpOverflow));
+                    }
+                    Some(offset as i32)
+                },
+                _ => None,
+            };
+            if !matches!(word, Word::Removed(_)) {
+                new_pc += 1;
+            }
+            offsets.push(offset);
+        }
+
+        let start = self.code.first().map(|instruction| instruction.range.start).unwrap_or(0);
+        let has_lines = self.line_info.len() == length;
+        let line = |pc: usize| self.line_info.get(pc).copied().unwrap_or(0);
+
+        // an inserted word takes the line of the instruction it precedes
+        let mut lines = vec![0; words.len()];
+        let mut next_line = line(length.saturating_sub(1));
+        for (index, word) in words.iter().enumerate().rev() {
+            match word {
+                Word::Kept(pc) | Word::Removed(pc) => next_line = line(*pc),
+                Word::Inserted(_) => {},
+            }
+            lines[index] = next_line;
+        }
+
+        let mut old_code: Vec<Option<LuaInstruction>> = std::mem::take(&mut self.code).into_iter().map(Some).collect();
+        let mut code = Vec::with_capacity(words.len());
+        let mut line_info = Vec::with_capacity(words.len());
+        for ((word, offset), line) in words.into_iter().zip(offsets).zip(lines) {
+            let mut instruction = match word {
+                Word::Kept(pc) => old_code[pc].take().unwrap(),
+                Word::Removed(_) => continue,
+                Word::Inserted(instruction) => instruction,
+            };
+
+            if let Some(offset) = offset {
+                let components = match instruction.components {
+                    LuaLayout::SBx(opcode, _) => LuaLayout::SBx(opcode, offset),
+                    LuaLayout::AsBx(opcode, a, _) => LuaLayout::AsBx(opcode, a, offset),
+                    components => components,
+                };
+                instruction.components = components;
+            }
+
+            let index = code.len() as u64;
+            instruction.raw = encode(&instruction.components);
+            instruction.range = Range::new(start + index * INSTRUCTION_SIZE, start + (index + 1) * INSTRUCTION_SIZE);
+            instruction.pc = index;
+            instruction.jump_target = None;
+            code.push(instruction);
+            line_info.push(line);
+        }
+
+        self.code = code;
+        self.code_size = self.code.len() as u64;
+
+        if has_lines {
+            self.line_info = line_info;
+            self.line_info_size = self.line_info.len() as u64;
+        }
+
+        for local in self.locals.iter_mut() {
+            local.start_pc = map[(local.start_pc as usize).min(length)] as u32;
+            local.end_pc = map[(local.end_pc as usize).min(length)] as u32;
+        }
+
+        self.update_targets();
+        Ok(())
+    }
+}